@@ -0,0 +1,119 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+use crate::package::PaxPackage;
+
+/// Label for files without an extension
+const NO_EXTENSION: &str = "(none)";
+
+/// Label for files at the package root
+const ROOT_DIRECTORY: &str = "(root)";
+
+/// Where the bytes of a package go
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SizeAnalysis {
+    /// Size of the package file
+    pub compressed_size: u64,
+    /// Total size of every file in the archive
+    pub uncompressed_size: u64,
+    /// Uncompressed bytes per file extension
+    pub by_extension: BTreeMap<String, u64>,
+    /// Uncompressed bytes per top-level directory
+    pub by_directory: BTreeMap<String, u64>,
+}
+
+impl SizeAnalysis {
+    /// Compressed size as a fraction of the uncompressed size
+    pub fn ratio(&self) -> f64 {
+        if self.uncompressed_size == 0 {
+            return 0.0;
+        }
+        self.compressed_size as f64 / self.uncompressed_size as f64
+    }
+}
+
+/// Break down the size of a package from its archive entries
+pub fn analyze_package(package_path: &Path) -> Result<SizeAnalysis> {
+    let package = PaxPackage::open(package_path)?;
+    let mut analysis = SizeAnalysis {
+        compressed_size: package.size()?,
+        ..Default::default()
+    };
+
+    for entry in package.entries()? {
+        let entry = entry?;
+        if !entry.is_file() {
+            continue;
+        }
+
+        let extension = entry.path.extension()
+            .map(|extension| extension.to_string_lossy().to_string())
+            .unwrap_or_else(|| NO_EXTENSION.to_string());
+        let mut components = entry.path.components().filter(|component| matches!(component, Component::Normal(_)));
+        let directory = match (components.next(), components.next()) {
+            (Some(first), Some(_)) => first.as_os_str().to_string_lossy().to_string(),
+            _ => ROOT_DIRECTORY.to_string(),
+        };
+
+        analysis.uncompressed_size += entry.size;
+        *analysis.by_extension.entry(extension).or_default() += entry.size;
+        *analysis.by_directory.entry(directory).or_default() += entry.size;
+    }
+
+    Ok(analysis)
+}
+
+/// Show a package's compression ratio and size breakdown (CLI entry point)
+pub fn analyze_cmd(package_path: &str) -> Result<()> {
+    println!("PAXBuild - Analyzing package size");
+    println!("Package: {}", package_path);
+
+    let analysis = analyze_package(Path::new(package_path))?;
+    println!("Compressed size: {} bytes", analysis.compressed_size);
+    println!("Uncompressed size: {} bytes", analysis.uncompressed_size);
+    println!("Compression ratio: {:.1}%", analysis.ratio() * 100.0);
+
+    for (title, sizes) in [("By top-level directory:", &analysis.by_directory), ("By extension:", &analysis.by_extension)] {
+        println!("{}", title);
+        let mut sizes: Vec<(&String, &u64)> = sizes.iter().collect();
+        sizes.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+        for (name, size) in sizes {
+            let share = if analysis.uncompressed_size == 0 { 0.0 } else { *size as f64 * 100.0 / analysis.uncompressed_size as f64 };
+            println!("  {:<24} {:>12} bytes {:>5.1}%", name, size, share);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_analysis_totals_match_contents() {
+        let temp_dir = TempDir::new().unwrap();
+        let metadata = test_support::metadata("sizes", "1.0.0");
+        let package_path = test_support::write_package(temp_dir.path(), &metadata, &[
+            ("usr/bin/sizes", "0123456789"),
+            ("usr/lib/libsizes.so", "01234"),
+            ("usr/share/doc/README.md", "012"),
+            ("LICENSE", "01"),
+        ]);
+        let metadata_size = serde_yaml::to_string(&metadata).unwrap().len() as u64;
+
+        let analysis = analyze_package(&package_path).unwrap();
+        assert_eq!(analysis.compressed_size, std::fs::metadata(&package_path).unwrap().len());
+        assert_eq!(analysis.uncompressed_size, 20 + metadata_size);
+        assert_eq!(analysis.by_directory["usr"], 18);
+        assert_eq!(analysis.by_directory[ROOT_DIRECTORY], 2);
+        assert_eq!(analysis.by_directory[".pax"], metadata_size);
+        assert_eq!(analysis.by_extension[NO_EXTENSION], 12);
+        assert_eq!(analysis.by_extension["so"], 5);
+        assert_eq!(analysis.by_extension["md"], 3);
+        assert_eq!(analysis.by_extension["yaml"], metadata_size);
+        assert_eq!(analysis.by_extension.values().sum::<u64>(), analysis.uncompressed_size);
+    }
+}
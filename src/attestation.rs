@@ -0,0 +1,174 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Digest};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::builder::ArchBuildReport;
+use crate::crypto;
+use crate::recipe::BuildRecipe;
+
+/// Where the source of one built package came from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourceRef {
+    /// Source URL or local path
+    pub url: String,
+    /// Expected source hash from the recipe, if it declares one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+}
+
+/// Provenance of a built package: what went in, who built it, and what came out
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attestation {
+    /// Package file name
+    pub package: String,
+    /// Target architecture
+    pub arch: String,
+    /// SHA256 of the package file
+    pub output_sha256: String,
+    /// Recipe path or URL
+    pub recipe: String,
+    /// SHA256 of the recipe's canonical YAML, the same bytes a recipe signature covers
+    pub recipe_sha256: String,
+    pub source: SourceRef,
+    /// Tool and version that produced the package
+    pub builder: String,
+    /// Build completion time (seconds since the Unix epoch)
+    pub timestamp: u64,
+}
+
+impl Attestation {
+    /// Describe one package from a build of `recipe`
+    pub fn new(recipe_path: &str, recipe: &BuildRecipe, recipe_sha256: &str, package: &ArchBuildReport) -> Result<Self> {
+        let (url, hash) = recipe.source_for_arch(&package.arch)?;
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)
+            .with_context(|| "System clock is before the Unix epoch")?
+            .as_secs();
+
+        Ok(Attestation {
+            package: package.path.file_name().unwrap_or_default().to_string_lossy().to_string(),
+            arch: package.arch.clone(),
+            output_sha256: package.sha256.clone(),
+            recipe: recipe_path.to_string(),
+            recipe_sha256: recipe_sha256.to_string(),
+            source: SourceRef { url: url.to_string(), hash: hash.map(str::to_string) },
+            builder: format!("paxbuild {}", env!("CARGO_PKG_VERSION")),
+            timestamp,
+        })
+    }
+
+    /// Serialize the attestation as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self)
+            .with_context(|| "Failed to serialize attestation")
+    }
+}
+
+/// SHA256 of a recipe's canonical YAML serialization
+pub fn recipe_hash(recipe: &BuildRecipe) -> Result<String> {
+    Ok(hex::encode(Sha256::digest(recipe.to_yaml()?.as_bytes())))
+}
+
+/// Attestation path written alongside a package
+pub fn attestation_path(package_path: &Path) -> PathBuf {
+    let mut path = package_path.as_os_str().to_owned();
+    path.push(".attestation.json");
+    PathBuf::from(path)
+}
+
+/// Write an attestation next to its package with an armored signature at `<attestation>.asc`
+///
+/// Returns the attestation path.
+pub fn write_attestation(attestation: &Attestation, package_path: &Path, private_key_path: &Path) -> Result<PathBuf> {
+    let json = attestation.to_json()?;
+    let path = attestation_path(package_path);
+    fs::write(&path, &json)
+        .with_context(|| format!("Failed to write attestation to: {}", path.display()))?;
+
+    let signature = crypto::sign_bytes(json.as_bytes(), private_key_path)?;
+    let signature_path = format!("{}.asc", path.display());
+    fs::write(&signature_path, crypto::armor_signature(&signature))
+        .with_context(|| format!("Failed to write attestation signature to: {}", signature_path))?;
+
+    Ok(path)
+}
+
+/// Check a package's attestation signature and that it describes this exact package file
+pub fn verify_attestation(package_path: &Path, public_key_path: &Path) -> Result<Attestation> {
+    let path = attestation_path(package_path);
+    let json = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read attestation: {}", path.display()))?;
+    let signature_path = format!("{}.asc", path.display());
+    let armored = fs::read_to_string(&signature_path)
+        .with_context(|| format!("Failed to read attestation signature: {}", signature_path))?;
+
+    crypto::verify_bytes(json.as_bytes(), &crypto::dearmor_signature(&armored)?, public_key_path)
+        .with_context(|| format!("Attestation signature is invalid: {}", path.display()))?;
+
+    let attestation: Attestation = serde_json::from_str(&json)
+        .with_context(|| format!("Failed to parse attestation: {}", path.display()))?;
+    let package_hash = crate::package::PaxPackage::open(package_path)?.calculate_hash()?;
+    if attestation.output_sha256 != package_hash {
+        anyhow::bail!("Attestation does not match package: expected {}, got {}", attestation.output_sha256, package_hash);
+    }
+
+    Ok(attestation)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build::{build_package, BuildOptions};
+    use crate::test_support;
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_build_attestation_verifies_and_names_output_hash() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = test_support::source_tarball(&[("attested-1.0.0/README", "hello")]);
+        let url = test_support::serve(source, "attested-1.0.0.tar.gz");
+        let recipe_path = test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: attested
+version: 1.0.0
+description: Test package
+source: {}
+arch: [x86_64]
+build: |
+  mkdir -p $PAX_BUILD_ROOT/usr/share/attested
+  cp README $PAX_BUILD_ROOT/usr/share/attested/
+"#, url));
+
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let private_key = temp_dir.path().join("private.key");
+        let public_key = temp_dir.path().join("public.key");
+        fs::write(&private_key, hex::encode(signing_key.to_bytes())).unwrap();
+        fs::write(&public_key, hex::encode(signing_key.verifying_key().to_bytes())).unwrap();
+
+        let package_path = temp_dir.path().join("attested.pax");
+        let options = BuildOptions {
+            output: Some(package_path.to_str().unwrap().to_string()),
+            attest: true,
+            sign_key: Some(private_key.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        build_package(recipe_path.to_str().unwrap(), &options).unwrap();
+
+        let attestation = verify_attestation(&package_path, &public_key).unwrap();
+        let package_hash = crate::package::PaxPackage::open(&package_path).unwrap().calculate_hash().unwrap();
+        assert_eq!(attestation.output_sha256, package_hash);
+        assert_eq!(attestation.package, "attested.pax");
+        assert_eq!(attestation.arch, "x86_64");
+        assert_eq!(attestation.source.url, url);
+        let recipe = BuildRecipe::from_file(recipe_path.to_str().unwrap()).unwrap();
+        assert_eq!(attestation.recipe_sha256, recipe_hash(&recipe).unwrap());
+        assert!(attestation.builder.starts_with("paxbuild "));
+
+        // A package that differs from the attested one is rejected
+        fs::write(&package_path, b"tampered").unwrap();
+        let err = verify_attestation(&package_path, &public_key).unwrap_err();
+        assert!(err.to_string().contains("does not match package"), "{}", err);
+    }
+}
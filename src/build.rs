@@ -1,9 +1,266 @@
 use anyhow::{Result, Context};
-use crate::recipe::BuildRecipe;
-use crate::builder::PackageBuilder;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use crate::attestation::Attestation;
+use crate::recipe::{BuildRecipe, Source, RECIPE_EXTENSIONS};
+use crate::builder::{BuildReport, PackageBuilder, PrefixMap, DEFAULT_COMPRESSION_LEVEL};
+use crate::index::PackageIndex;
+use crate::integrity::HashAlgorithm;
+use crate::source::{GitLocation, SourceManager};
+use crate::warnings::Warnings;
+
+/// Options for a build invocation
+#[derive(Debug, Clone, Default)]
+pub struct BuildOptions {
+    /// Output path for the generated .pax package (a directory for multiple architectures)
+    pub output: Option<String>,
+    /// Target architectures (all recipe architectures if empty)
+    pub architectures: Vec<String>,
+    /// Verbose output
+    pub verbose: bool,
+    /// Write a JSON build report to this path
+    pub report: Option<String>,
+    /// Refuse to build unless the recipe carries a valid signature from this public key
+    pub recipe_key: Option<String>,
+    /// Number of leading path components to strip from packaged files
+    pub package_strip: usize,
+    /// Move the file list to a separate member when a package has more files than this
+    pub files_list_threshold: Option<usize>,
+    /// Recipe features to enable
+    pub features: Vec<String>,
+    /// Number of recipes to build in parallel
+    pub jobs: usize,
+    /// Refuse all network access; sources must be local (see `paxbuild vendor`)
+    pub offline: bool,
+    /// Extra metadata fields stored in the package, overriding the recipe's `extra`
+    pub extra_metadata: BTreeMap<String, String>,
+    /// Directory caching pristine extracted source trees (no caching if not set)
+    pub cache_dir: Option<String>,
+    /// Treat warnings as errors
+    pub strict: bool,
+    /// Parallel jobs for build scripts, exported as `PAX_JOBS` (defaults to the number of CPUs)
+    pub build_jobs: Option<usize>,
+    /// Repository index that every runtime dependency must be satisfiable from
+    pub deps_index: Option<String>,
+    /// Repository index whose packages' files the built packages must not overwrite
+    pub conflict_index: Option<String>,
+    /// Package files installed under one prefix under another (`/usr=/usr/local`)
+    pub prefix_maps: Vec<PrefixMap>,
+    /// Write the source hash computed for a recipe without `hash` back into the recipe file
+    pub write_hash: bool,
+    /// Algorithm for source hashes computed for recipes without `hash`
+    pub hash_algorithm: HashAlgorithm,
+    /// Kill build scripts that run longer than this many seconds
+    pub timeout: Option<u64>,
+    /// Directory to write `build-<arch>.log` files to
+    pub log_dir: Option<String>,
+    /// Don't run the recipes' `check` scripts
+    pub skip_check: bool,
+    /// zstd level packages are compressed at (defaults to 19)
+    pub compression_level: Option<i32>,
+    /// Normalize archive mtimes and owners so identical trees give identical packages
+    pub reproducible: bool,
+    /// Repository root to place packages under according to `repo_layout`
+    pub output_root: Option<String>,
+    /// Directory structure used below `output_root`
+    pub repo_layout: RepoLayout,
+    /// Verify every package right after it is written
+    pub verify: bool,
+    /// Write a signed provenance attestation next to every package (needs `sign_key`)
+    pub attest: bool,
+    /// Private key used to sign attestations and embedded signatures
+    pub sign_key: Option<String>,
+    /// Embed a signature made with `sign_key` in every package
+    pub embed_signature: bool,
+    /// Write a `.paxmeta.json` metadata sidecar next to every package
+    pub metadata_sidecar: bool,
+    /// Write a `.chunks.json` chunk manifest next to every package for delta updates
+    pub chunk_manifest: bool,
+    /// Build from this local source tree instead of the recipe's source
+    pub from_directory: Option<String>,
+    /// Run build scripts with only PATH, HOME, the recipe's `env` and `PAX_*` variables
+    pub clean_env: bool,
+    /// Print the resolved build script for each architecture instead of building
+    pub print_build_script: bool,
+}
+
+/// Directory structure of a package repository
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RepoLayout {
+    /// Every package directly in the repository root
+    #[default]
+    Flat,
+    /// `pool/<first letter>/<name>/<file>.pax`
+    Pool,
+}
+
+impl RepoLayout {
+    /// Parse a `--repo-layout` value
+    pub fn parse(value: &str) -> Result<Self> {
+        match value {
+            "flat" => Ok(RepoLayout::Flat),
+            "pool" => Ok(RepoLayout::Pool),
+            _ => anyhow::bail!("Unknown repository layout '{}'. Valid layouts are: flat, pool", value),
+        }
+    }
+
+    /// Where a package file belongs below the repository root
+    pub fn package_path(&self, root: &Path, name: &str, filename: &str) -> PathBuf {
+        match self {
+            RepoLayout::Flat => root.join(filename),
+            RepoLayout::Pool => {
+                let first_letter: String = name.chars().take(1).flat_map(char::to_lowercase).collect();
+                root.join("pool").join(first_letter).join(name).join(filename)
+            }
+        }
+    }
+}
+
+/// Parse a `KEY=VALUE` extra metadata assignment
+pub fn parse_meta_assignment(assignment: &str) -> Result<(String, String)> {
+    let (key, value) = assignment.split_once('=')
+        .ok_or_else(|| anyhow::anyhow!("Invalid metadata assignment '{}': expected KEY=VALUE", assignment))?;
+    crate::package::validate_extra_key(key)?;
+    Ok((key.to_string(), value.to_string()))
+}
 
 /// Build a package from a recipe
-pub fn build_package(recipe_path: &str, output_path: Option<&str>, architectures: &[String], verbose: bool) -> Result<()> {
+pub fn build_package(recipe_path: &str, options: &BuildOptions) -> Result<()> {
+    build_packages(&[recipe_path.to_string()], options)
+}
+
+/// Build packages from several recipes (or directories of recipes), sharing source downloads
+///
+/// Every recipe is attempted; the call fails if any of them failed.
+pub fn build_packages(recipe_paths: &[String], options: &BuildOptions) -> Result<()> {
+    let recipes = expand_recipe_paths(recipe_paths)?;
+    if recipes.is_empty() {
+        anyhow::bail!("No recipes found in: {:?}", recipe_paths);
+    }
+
+    // With several recipes the output is always a directory
+    if recipes.len() > 1 {
+        if let Some(output) = &options.output {
+            std::fs::create_dir_all(output)
+                .with_context(|| format!("Failed to create output directory: {}", output))?;
+        }
+    }
+
+    let source_mgr = Arc::new(SourceManager::new()?
+        .with_offline(options.offline)
+        .with_hash_algorithm(options.hash_algorithm)
+        .with_cache_dir(options.cache_dir.as_ref().map(PathBuf::from)));
+    let mut results = run_parallel(&recipes, options.jobs, |recipe_path| {
+        build_recipe(recipe_path, options, &source_mgr)
+    });
+
+    if recipes.len() == 1 {
+        let report = results.remove(0)?;
+        if let Some(report_path) = &options.report {
+            report.write_json(Path::new(report_path))?;
+            println!("Build report written to: {}", report_path);
+        }
+        return Ok(());
+    }
+
+    // Summarize multi-recipe builds
+    let failed = results.iter().filter(|result| result.is_err()).count();
+    println!();
+    println!("Build summary: {} succeeded, {} failed", recipes.len() - failed, failed);
+    for (recipe_path, result) in recipes.iter().zip(&results) {
+        match result {
+            Ok(report) => println!("  ok      {} ({} {})", recipe_path, report.name, report.version),
+            Err(e) => println!("  FAILED  {}: {:#}", recipe_path, e),
+        }
+    }
+
+    if let Some(report_path) = &options.report {
+        let reports: Vec<&BuildReport> = results.iter().filter_map(|result| result.as_ref().ok()).collect();
+        let json = serde_json::to_string_pretty(&reports)
+            .with_context(|| "Failed to serialize build report")?;
+        std::fs::write(report_path, json)
+            .with_context(|| format!("Failed to write build report: {}", report_path))?;
+        println!("Build report written to: {}", report_path);
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{} of {} recipes failed to build", failed, recipes.len());
+    }
+
+    Ok(())
+}
+
+/// Build a single recipe and return its report
+pub fn build_recipe_report(recipe_path: &str, options: &BuildOptions) -> Result<BuildReport> {
+    let source_mgr = Arc::new(SourceManager::new()?
+        .with_offline(options.offline)
+        .with_hash_algorithm(options.hash_algorithm)
+        .with_cache_dir(options.cache_dir.as_ref().map(PathBuf::from)));
+    build_recipe(recipe_path, options, &source_mgr)
+}
+
+/// Expand directories into the recipe files they contain
+fn expand_recipe_paths(recipe_paths: &[String]) -> Result<Vec<String>> {
+    let mut recipes = Vec::new();
+
+    for recipe_path in recipe_paths {
+        let path = Path::new(recipe_path);
+        if !path.is_dir() {
+            recipes.push(recipe_path.clone());
+            continue;
+        }
+
+        let mut found: Vec<String> = std::fs::read_dir(path)
+            .with_context(|| format!("Failed to read recipe directory: {}", recipe_path))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| RECIPE_EXTENSIONS.contains(&ext))
+            })
+            .map(|path| path.to_string_lossy().to_string())
+            .collect();
+        found.sort();
+        recipes.extend(found);
+    }
+
+    Ok(recipes)
+}
+
+/// Run `f` over `items` on up to `jobs` threads, returning results in input order
+fn run_parallel<T: Sync, R: Send>(items: &[T], jobs: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+
+    std::thread::scope(|scope| {
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::SeqCst);
+                if i >= items.len() {
+                    break;
+                }
+                let result = f(&items[i]);
+                if let Ok(mut results) = results.lock() {
+                    results[i] = Some(result);
+                }
+            });
+        }
+    });
+
+    results.into_inner()
+        .map(|results| results.into_iter().flatten().collect())
+        .unwrap_or_default()
+}
+
+/// Build a single recipe, copying packages to the requested output
+fn build_recipe(recipe_path: &str, options: &BuildOptions, source_mgr: &Arc<SourceManager>) -> Result<BuildReport> {
+    let output_path = options.output.as_deref();
+    let architectures = &options.architectures;
+    let verbose = options.verbose;
+
     println!("PAXBuild - Building package from recipe");
     println!("Recipe: {}", recipe_path);
     
@@ -11,17 +268,72 @@ pub fn build_package(recipe_path: &str, output_path: Option<&str>, architectures
         println!("Verbose mode enabled");
     }
     
+    let is_local = !["git+", "http://", "https://"].iter().any(|prefix| recipe_path.starts_with(prefix));
+    if options.write_hash && !is_local {
+        anyhow::bail!("--write-hash needs a local recipe file: {}", recipe_path);
+    }
+
+    // Recipes inside git repositories are read from a shallow checkout
+    let checkout_recipe_path;
+    let recipe_path = if recipe_path.starts_with("git+") {
+        let location = GitLocation::parse(recipe_path)?;
+        let path = location.path.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("Git recipe location needs a path after '#': {}", recipe_path))?;
+        checkout_recipe_path = source_mgr.checkout_git(&location)?.join(path).to_string_lossy().to_string();
+        checkout_recipe_path.as_str()
+    } else {
+        recipe_path
+    };
+
     // Load recipe
-    let recipe = if recipe_path.starts_with("http://") || recipe_path.starts_with("https://") {
+    let is_url = recipe_path.starts_with("http://") || recipe_path.starts_with("https://");
+    if is_url && options.offline {
+        anyhow::bail!("Refusing to download recipe in offline mode: {}", recipe_path);
+    }
+    let mut recipe = if is_url {
         BuildRecipe::from_url(recipe_path)?
     } else {
         BuildRecipe::from_file(recipe_path)?
     };
 
+    // Verify recipe signature before running anything from it
+    if let Some(key) = &options.recipe_key {
+        let signature_path = format!("{}.asc", recipe_path);
+        let signature = if is_url {
+            download_text(&signature_path)
+        } else {
+            std::fs::read_to_string(&signature_path)
+                .with_context(|| format!("Recipe signature not found: {}", signature_path))
+        }?;
+
+        crate::crypto::verify_recipe(&recipe, &signature, Path::new(key))
+            .with_context(|| format!("Refusing to build recipe: {}", recipe_path))?;
+        println!("Recipe signature verified");
+    }
+
+    let sign_key = match (&options.sign_key, options.attest) {
+        (Some(key), true) => Some(Path::new(key)),
+        (None, true) => anyhow::bail!("Attestations need a signing key (--sign-key)"),
+        (_, false) => None,
+    };
+    let embed_key = match (&options.sign_key, options.embed_signature) {
+        (Some(key), true) => Some(PathBuf::from(key)),
+        (None, true) => anyhow::bail!("Embedded signatures need a signing key (--sign-key)"),
+        (_, false) => None,
+    };
+
+    // Hashed before local sources are resolved, so it covers the same bytes a recipe signature does
+    let recipe_sha256 = crate::attestation::recipe_hash(&recipe)?;
+
+    if !is_url {
+        let recipe_dir = Path::new(recipe_path).parent().unwrap_or(Path::new("."));
+        recipe.resolve_local_source(recipe_dir);
+    }
+
     if verbose {
         println!("Loaded recipe:");
         println!("  Name: {}", recipe.name);
-        println!("  Version: {}", recipe.version);
+        println!("  Version: {}", recipe.full_version());
         println!("  Description: {}", recipe.description);
         println!("  Source: {}", recipe.source);
         if let Some(hash) = &recipe.hash {
@@ -30,18 +342,33 @@ pub fn build_package(recipe_path: &str, output_path: Option<&str>, architectures
         println!("  Dependencies: {:?}", recipe.dependencies);
         println!("  Provides: {:?}", recipe.provides);
         println!("  Architectures: {:?}", recipe.arch);
+        if !recipe.features.is_empty() {
+            println!("  Features: {:?}", recipe.features.keys().collect::<Vec<_>>());
+        }
+        if recipe.no_default_excludes {
+            println!("  Default excludes: disabled");
+        } else {
+            println!("  Default excludes: {:?}", recipe.default_excludes());
+        }
+        if !recipe.exclude.is_empty() {
+            println!("  Excludes: {:?}", recipe.exclude);
+        }
     }
 
-    // Determine target architectures
+    // Determine target architectures, building each requested one once
     let target_architectures = if architectures.is_empty() {
         recipe.arch.clone()
     } else {
+        let mut targets: Vec<String> = Vec::new();
         for arch in architectures {
             if !recipe.arch.contains(arch) {
                 anyhow::bail!("Architecture '{}' is not supported by this recipe. Supported architectures: {:?}", arch, recipe.arch);
             }
+            if !targets.contains(arch) {
+                targets.push(arch.clone());
+            }
         }
-        architectures.to_vec()
+        targets
     };
 
     if verbose {
@@ -52,43 +379,785 @@ pub fn build_package(recipe_path: &str, output_path: Option<&str>, architectures
         }
     }
 
+    let deps_index = options.deps_index.as_deref()
+        .map(|path| PackageIndex::load(Path::new(path)).map(Arc::new))
+        .transpose()?;
+    let conflict_index = options.conflict_index.as_deref()
+        .map(|path| PackageIndex::load(Path::new(path)).map(Arc::new))
+        .transpose()?;
+
     // Build package
-    let builder = PackageBuilder::new()?;
-    let package_paths = builder.build_for_architectures(&recipe, &target_architectures)?;
+    let builder = PackageBuilder::new()?
+        .with_source_manager(Arc::clone(source_mgr))
+        .with_package_strip(options.package_strip)
+        .with_files_list_threshold(options.files_list_threshold)
+        .with_features(options.features.clone())
+        .with_extra_metadata(options.extra_metadata.clone())
+        .with_strict(options.strict)
+        .with_build_jobs(options.build_jobs)
+        .with_deps_index(deps_index)
+        .with_conflict_index(conflict_index)
+        .with_prefix_maps(options.prefix_maps.clone())
+        .with_skip_check(options.skip_check)
+        .with_verbose(options.verbose)
+        .with_timeout(options.timeout)
+        .with_log_dir(options.log_dir.as_ref().map(PathBuf::from))
+        .with_compression_level(options.compression_level.unwrap_or(DEFAULT_COMPRESSION_LEVEL))
+        .with_reproducible(options.reproducible)
+        .with_verify(options.verify)
+        .with_embed_signature(embed_key)
+        .with_source_dir(options.from_directory.as_ref().map(PathBuf::from))
+        .with_clean_env(options.clean_env);
+
+    if options.print_build_script {
+        for (arch, description, script) in builder.resolved_build_scripts(&recipe, &target_architectures)? {
+            println!("==> Build script for {} ({}):", arch, description);
+            println!("{}", script.trim_end());
+        }
+        return Ok(BuildReport {
+            name: recipe.name.clone(),
+            version: recipe.full_version(),
+            packages: Vec::new(),
+            duration_secs: 0.0,
+            warnings: Vec::new(),
+        });
+    }
+    let mut report = builder.build_with_report(&recipe, &target_architectures)?;
 
     // Handle output for multiple architectures
-    if let Some(output) = output_path {
-        if target_architectures.len() == 1 {
+    if let Some(root) = &options.output_root {
+        for package in report.packages.iter_mut() {
+            let filename = format!("{}-{}.pax", recipe.package_id(), package.arch);
+            let dest_path = options.repo_layout.package_path(Path::new(root), &recipe.name, &filename);
+            if let Some(parent) = dest_path.parent() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create output directory: {}", parent.display()))?;
+            }
+
+            std::fs::copy(&package.path, &dest_path)
+                .with_context(|| format!("Failed to copy package to: {}", dest_path.display()))?;
+            println!("Package for {} saved to: {}", package.arch, dest_path.display());
+            package.path = dest_path;
+        }
+    } else if let Some(output) = output_path {
+        if target_architectures.len() == 1 && !Path::new(output).is_dir() {
             // Single architecture - copy to specified output
-            let package_path = &package_paths[0];
+            let package_path = &report.packages[0].path;
             std::fs::copy(package_path, output)
                 .with_context(|| format!("Failed to copy package to: {}", output))?;
             println!("Package saved to: {}", output);
+            report.packages[0].path = std::path::PathBuf::from(output);
         } else {
-            // Multiple architectures - output should be a directory
+            // Multiple architectures (or an existing directory) - output is a directory
             let output_dir = std::path::Path::new(output);
             if !output_dir.exists() {
                 std::fs::create_dir_all(output_dir)
                     .with_context(|| format!("Failed to create output directory: {}", output))?;
             }
 
-            for (i, package_path) in package_paths.iter().enumerate() {
-                let arch = &target_architectures[i];
+            for package in report.packages.iter_mut() {
+                let arch = &package.arch;
                 let filename = format!("{}-{}.pax", recipe.package_id(), arch);
                 let dest_path = output_dir.join(filename);
 
-                std::fs::copy(package_path, &dest_path)
+                std::fs::copy(&package.path, &dest_path)
                     .with_context(|| format!("Failed to copy package to: {}", dest_path.display()))?;
                 println!("Package for {} saved to: {}", arch, dest_path.display());
+                package.path = dest_path;
             }
         }
     } else {
         // No output specified - packages are in temp directory with proper names
-        for (i, package_path) in package_paths.iter().enumerate() {
-            let arch = &target_architectures[i];
-            println!("Package for {} built at: {}", arch, package_path.display());
+        for package in &report.packages {
+            println!("Package for {} built at: {}", package.arch, package.path.display());
         }
     }
-    
-    Ok(())
+
+    if let Some(key) = sign_key {
+        for package in &report.packages {
+            let attestation = Attestation::new(recipe_path, &recipe, &recipe_sha256, package)?;
+            let path = crate::attestation::write_attestation(&attestation, &package.path, key)?;
+            println!("Attestation for {} saved to: {}", package.arch, path.display());
+        }
+    }
+
+    if options.metadata_sidecar {
+        for package in &report.packages {
+            let path = crate::index::write_sidecar(&package.path)?;
+            println!("Metadata sidecar for {} saved to: {}", package.arch, path.display());
+        }
+    }
+
+    if options.chunk_manifest {
+        for package in &report.packages {
+            let path = crate::delta::write_chunk_manifest(&package.path)?;
+            println!("Chunk manifest for {} saved to: {}", package.arch, path.display());
+        }
+    }
+
+    if options.write_hash {
+        let computed = match &recipe.source {
+            Source::Url(url) if recipe.hash.is_none() => source_mgr.computed_hash(url),
+            _ => None,
+        };
+        match computed {
+            Some(hash) => {
+                // Warned before writing, so --strict leaves a signed recipe untouched
+                if Path::new(&format!("{}.asc", recipe_path)).exists() {
+                    let warnings = Warnings::new(options.strict);
+                    warnings.warn(format!("{}.asc no longer matches the recipe once the hash is written; sign it again", recipe_path))?;
+                    report.warnings.extend(warnings.messages());
+                }
+                BuildRecipe::write_hash_to_file(Path::new(recipe_path), &hash)?;
+                println!("Source hash written to: {}", recipe_path);
+            }
+            None => println!("No source hash to write: the recipe has one already, or its source was not downloaded"),
+        }
+    }
+
+    Ok(report)
+}
+
+/// Download a small text file such as a detached signature
+fn download_text(url: &str) -> Result<String> {
+    let response = reqwest::blocking::get(url)
+        .with_context(|| format!("Failed to download: {}", url))?;
+
+    if !response.status().is_success() {
+        anyhow::bail!("HTTP error {}: {}", response.status(), url);
+    }
+
+    response.text()
+        .with_context(|| format!("Failed to read response from: {}", url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_build_writes_json_report() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = test_support::source_tarball(&[("hello-1.0.0/README", "hello")]);
+        let url = test_support::serve(source, "hello-1.0.0.tar.gz");
+        let recipe_path = test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: hello
+version: 1.0.0
+description: Test package
+source: {}
+arch: [x86_64]
+build: |
+  mkdir -p $PAX_BUILD_ROOT/usr/share/hello
+  cp README $PAX_BUILD_ROOT/usr/share/hello/
+"#, url));
+
+        let output = temp_dir.path().join("hello.pax");
+        let report_path = temp_dir.path().join("report.json");
+        let options = BuildOptions {
+            output: Some(output.to_str().unwrap().to_string()),
+            report: Some(report_path.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        build_package(recipe_path.to_str().unwrap(), &options).unwrap();
+
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+        assert_eq!(report["name"], "hello");
+        assert_eq!(report["version"], "1.0.0");
+        assert!(report["duration_secs"].is_number());
+
+        let packages = report["packages"].as_array().unwrap();
+        assert_eq!(packages.len(), 1);
+        assert_eq!(packages[0]["arch"], "x86_64");
+        assert_eq!(packages[0]["path"], output.to_str().unwrap());
+        assert_eq!(packages[0]["size"], std::fs::metadata(&output).unwrap().len());
+        assert_eq!(
+            packages[0]["sha256"],
+            crate::SourceManager::calculate_hash(&output).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_build_refuses_unsigned_or_tampered_recipe() {
+        use ed25519_dalek::SigningKey;
+
+        let temp_dir = TempDir::new().unwrap();
+        let signing_key = SigningKey::generate(&mut rand::rngs::OsRng);
+        let private_key = temp_dir.path().join("private.key");
+        let public_key = temp_dir.path().join("public.key");
+        std::fs::write(&private_key, hex::encode(signing_key.to_bytes())).unwrap();
+        std::fs::write(&public_key, hex::encode(signing_key.verifying_key().to_bytes())).unwrap();
+
+        let recipe_path = test_support::write_recipe(temp_dir.path(), r#"
+name: hello
+version: 1.0.0
+description: Test package
+source: http://127.0.0.1:9/hello-1.0.0.tar.gz
+"#);
+        let options = BuildOptions {
+            recipe_key: Some(public_key.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+
+        // Unsigned
+        let err = build_package(recipe_path.to_str().unwrap(), &options).unwrap_err();
+        assert!(format!("{:#}", err).contains("Recipe signature not found"));
+
+        // Signed, then tampered
+        crate::sign_recipe::sign_recipe_cmd(recipe_path.to_str().unwrap(), &crate::crypto::PrivateKeySource::File(private_key.clone()), None, None).unwrap();
+        let tampered = std::fs::read_to_string(&recipe_path).unwrap().replace("1.0.0", "6.6.6");
+        std::fs::write(&recipe_path, tampered).unwrap();
+
+        let err = build_package(recipe_path.to_str().unwrap(), &options).unwrap_err();
+        assert!(format!("{:#}", err).contains("Signature verification FAILED"));
+    }
+
+    #[test]
+    fn test_build_multiple_recipes() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = test_support::source_tarball(&[("shared-1.0.0/README", "shared")]);
+        let (url, downloads) = test_support::serve_counting(source, "shared-1.0.0.tar.gz");
+
+        let recipe_dir = temp_dir.path().join("recipes");
+        std::fs::create_dir_all(&recipe_dir).unwrap();
+        for name in ["alpha", "beta"] {
+            std::fs::write(recipe_dir.join(format!("{}.paxmeta", name)), format!(r#"
+name: {}
+version: 1.0.0
+description: Test package
+source: {}
+arch: [x86_64]
+build: cp README $PAX_BUILD_ROOT/
+"#, name, url)).unwrap();
+        }
+
+        let output_dir = temp_dir.path().join("out");
+        let options = BuildOptions {
+            output: Some(output_dir.to_str().unwrap().to_string()),
+            jobs: 2,
+            ..Default::default()
+        };
+        build_packages(&[recipe_dir.to_str().unwrap().to_string()], &options).unwrap();
+
+        assert!(output_dir.join("alpha-1.0.0-x86_64.pax").exists());
+        assert!(output_dir.join("beta-1.0.0-x86_64.pax").exists());
+        assert_eq!(downloads.load(Ordering::SeqCst), 1);
+
+        // A failing recipe fails the invocation without stopping the others
+        let broken = temp_dir.path().join("broken.paxmeta");
+        std::fs::write(&broken, "name: broken\n").unwrap();
+        std::fs::remove_dir_all(&output_dir).unwrap();
+
+        let recipes = vec![broken.to_str().unwrap().to_string(), recipe_dir.to_str().unwrap().to_string()];
+        let err = build_packages(&recipes, &options).unwrap_err();
+        assert!(err.to_string().contains("1 of 3 recipes failed"));
+        assert!(output_dir.join("alpha-1.0.0-x86_64.pax").exists());
+        assert!(output_dir.join("beta-1.0.0-x86_64.pax").exists());
+    }
+
+    #[test]
+    fn test_build_recipe_from_git_at_tag() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = test_support::source_tarball(&[("hello-1.0.0/README", "hello")]);
+        let url = test_support::serve(source, "hello-1.0.0.tar.gz");
+
+        let repo = temp_dir.path().join("recipes.git");
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com", "-C"])
+                .arg(&repo)
+                .args(args)
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {:?} failed", args);
+        };
+        let recipe = |version: &str| format!(r#"
+name: hello
+version: {}
+description: Test package
+source: {}
+arch: [x86_64]
+build: cp README $PAX_BUILD_ROOT/
+"#, version, url);
+
+        std::fs::create_dir_all(repo.join("pkgs/hello")).unwrap();
+        git(&["init", "-q"]);
+        std::fs::write(repo.join("pkgs/hello/hello.paxmeta"), recipe("1.0.0")).unwrap();
+        git(&["add", "."]);
+        git(&["commit", "-q", "-m", "hello 1.0.0"]);
+        git(&["tag", "v1.0.0"]);
+        std::fs::write(repo.join("pkgs/hello/hello.paxmeta"), recipe("2.0.0")).unwrap();
+        git(&["commit", "-q", "-am", "hello 2.0.0"]);
+
+        let location = format!("git+file://{}#pkgs/hello/hello.paxmeta@v1.0.0", repo.display());
+        let report_path = temp_dir.path().join("report.json");
+        let options = BuildOptions {
+            output: Some(temp_dir.path().join("out").to_str().unwrap().to_string()),
+            report: Some(report_path.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        build_package(&location, &options).unwrap();
+
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+        assert_eq!(report["version"], "1.0.0");
+
+        assert_eq!(
+            GitLocation::parse("git+https://git@host/repo.git#dir/foo.paxmeta@abc123").unwrap(),
+            GitLocation {
+                url: "https://git@host/repo.git".to_string(),
+                path: Some("dir/foo.paxmeta".to_string()),
+                reference: Some("abc123".to_string()),
+            }
+        );
+        assert!(GitLocation::parse("git+https://host/repo.git#../escape.paxmeta").is_err());
+        assert!(GitLocation::parse("git+--upload-pack=touch pwned#foo.paxmeta").is_err());
+        assert!(GitLocation::parse("git+https://host/repo.git#foo.paxmeta@--upload-pack=touch pwned").is_err());
+    }
+
+    #[test]
+    fn test_build_with_per_arch_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        let x86_source = test_support::source_tarball(&[("hello-1.0.0/README", "built for x86_64")]);
+        let arm_source = test_support::source_tarball(&[("hello-1.0.0/README", "built for aarch64")]);
+        let arm_hash = {
+            use sha2::{Digest, Sha256};
+            hex::encode(Sha256::digest(&arm_source))
+        };
+        let x86_url = test_support::serve(x86_source, "hello-1.0.0-x86_64.tar.gz");
+        let arm_url = test_support::serve(arm_source, "hello-1.0.0-aarch64.tar.gz");
+        let recipe_path = test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: hello
+version: 1.0.0
+description: Test package
+source:
+  x86_64: {}
+  aarch64:
+    url: {}
+    hash: sha256:{}
+arch: [x86_64, aarch64]
+build: cp README $PAX_BUILD_ROOT/
+"#, x86_url, arm_url, arm_hash));
+
+        let output_dir = temp_dir.path().join("out");
+        let options = BuildOptions {
+            output: Some(output_dir.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        build_package(recipe_path.to_str().unwrap(), &options).unwrap();
+
+        for arch in ["x86_64", "aarch64"] {
+            let extract_dir = temp_dir.path().join(format!("extract-{}", arch));
+            crate::PaxPackage::open(output_dir.join(format!("hello-1.0.0-{}.pax", arch))).unwrap()
+                .extract_to(&extract_dir).unwrap();
+            assert_eq!(
+                std::fs::read_to_string(extract_dir.join("README")).unwrap(),
+                format!("built for {}", arch)
+            );
+        }
+
+        let recipe = BuildRecipe::from_file(&recipe_path).unwrap();
+        assert!(recipe.source_for_arch("riscv64").is_err());
+    }
+
+    #[test]
+    fn test_build_with_source_list() {
+        use sha2::{Digest, Sha256};
+
+        let temp_dir = TempDir::new().unwrap();
+        let main_source = test_support::source_tarball(&[("hello-1.0.0/README", "main")]);
+        let extra_source = test_support::source_tarball(&[("extras/data.txt", "extra")]);
+        let icon = b"icon bytes".to_vec();
+        let icon_hash = hex::encode(Sha256::digest(&icon));
+        let main_url = test_support::serve(main_source, "hello-1.0.0.tar.gz");
+        let extra_url = test_support::serve(extra_source, "hello-extras.tar.gz");
+        let icon_url = test_support::serve(icon, "hello.png");
+
+        let build = |name: &str, sources: &str| {
+            let recipe_path = test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: {}
+version: 1.0.0
+description: Test package
+source:
+{}
+arch: [x86_64]
+build: mkdir -p $PAX_BUILD_ROOT/usr/share && cp -r . $PAX_BUILD_ROOT/usr/share/hello
+"#, name, sources));
+            let output = temp_dir.path().join(format!("{}.pax", name));
+            let options = BuildOptions {
+                output: Some(output.to_str().unwrap().to_string()),
+                ..Default::default()
+            };
+            build_package(recipe_path.to_str().unwrap(), &options).map(|()| {
+                let extract_dir = temp_dir.path().join(format!("extract-{}", name));
+                crate::PaxPackage::open(&output).unwrap().extract_to(&extract_dir).unwrap();
+                extract_dir.join("usr/share/hello")
+            })
+        };
+
+        let one = build("one", &format!("  - {}", main_url)).unwrap();
+        assert_eq!(std::fs::read_to_string(one.join("README")).unwrap(), "main");
+        assert!(!one.join("hello.png").exists());
+
+        let three = build("three", &format!(
+            "  - {}\n  - {}\n  - url: {}\n    hash: sha256:{}",
+            main_url, extra_url, icon_url, icon_hash,
+        )).unwrap();
+        assert_eq!(std::fs::read_to_string(three.join("README")).unwrap(), "main");
+        assert_eq!(std::fs::read_to_string(three.join("extras/data.txt")).unwrap(), "extra");
+        assert_eq!(std::fs::read(three.join("hello.png")).unwrap(), b"icon bytes");
+
+        // Every source is checked against its own hash
+        let error = build("mismatch", &format!("  - {}\n  - url: {}\n    hash: sha256:{}", main_url, icon_url, "0".repeat(64))).unwrap_err();
+        assert!(format!("{:#}", error).contains("Hash mismatch"), "{:#}", error);
+
+        let recipe = BuildRecipe::from_yaml(&format!("name: x\nversion: '1'\ndescription: x\nsource: [{}, {}]\nhash: sha256:{}\n", main_url, icon_url, icon_hash)).unwrap();
+        assert!(recipe.validate().is_err());
+        assert_eq!(recipe.source_for_arch("x86_64").unwrap().0, main_url);
+    }
+
+    #[test]
+    fn test_build_applies_patches_in_order() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = test_support::source_tarball(&[("hello-1.0.0/src/greeting", "hello\n")]);
+        let url = test_support::serve(source, "hello-1.0.0.tar.gz");
+        std::fs::write(temp_dir.path().join("01-shout.patch"), "\
+--- a/src/greeting
++++ b/src/greeting
+@@ -1 +1 @@
+-hello
++HELLO
+").unwrap();
+        std::fs::write(temp_dir.path().join("02-punctuate.patch"), "\
+--- a/src/greeting
++++ b/src/greeting
+@@ -1 +1 @@
+-HELLO
++HELLO!
+").unwrap();
+
+        let build = |patches: &str| {
+            let recipe_path = test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: hello
+version: 1.0.0
+description: Test package
+source: {}
+patches:
+{}
+arch: [x86_64]
+build: cp src/greeting $PAX_BUILD_ROOT/
+"#, url, patches));
+            let output = temp_dir.path().join("hello.pax");
+            let options = BuildOptions {
+                output: Some(output.to_str().unwrap().to_string()),
+                ..Default::default()
+            };
+            build_package(recipe_path.to_str().unwrap(), &options).map(|()| {
+                let extract_dir = temp_dir.path().join("extract");
+                crate::PaxPackage::open(&output).unwrap().extract_to(&extract_dir).unwrap();
+                std::fs::read_to_string(extract_dir.join("greeting")).unwrap()
+            })
+        };
+
+        assert_eq!(build("  - 01-shout.patch\n  - url: 02-punctuate.patch\n    strip: 1").unwrap(), "HELLO!\n");
+
+        // Out of order, the second patch does not apply
+        let error = build("  - 02-punctuate.patch\n  - 01-shout.patch").unwrap_err();
+        let message = format!("{:#}", error);
+        assert!(message.contains("Patch 02-punctuate.patch failed to apply"), "{}", message);
+        assert!(message.contains("FAILED"), "{}", message);
+    }
+
+    #[test]
+    fn test_strict_turns_http_source_warning_into_error() {
+        use sha2::{Digest, Sha256};
+
+        let temp_dir = TempDir::new().unwrap();
+        let source = test_support::source_tarball(&[("hello-1.0.0/README", "hello")]);
+        let hash = hex::encode(Sha256::digest(&source));
+        let url = test_support::serve(source, "hello-1.0.0.tar.gz");
+        let recipe_path = test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: hello
+version: 1.0.0
+description: Test package
+source: {}
+hash: sha256:{}
+arch: [x86_64]
+build: cp README $PAX_BUILD_ROOT/
+"#, url, hash));
+
+        let report_path = temp_dir.path().join("report.json");
+        let options = BuildOptions {
+            output: Some(temp_dir.path().join("hello.pax").to_str().unwrap().to_string()),
+            report: Some(report_path.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        build_package(recipe_path.to_str().unwrap(), &options).unwrap();
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+        assert_eq!(report["warnings"].as_array().unwrap().len(), 1);
+        assert!(report["warnings"][0].as_str().unwrap().contains("insecure HTTP"));
+
+        let options = BuildOptions { strict: true, ..options };
+        let err = build_package(recipe_path.to_str().unwrap(), &options).unwrap_err();
+        assert!(format!("{:#}", err).contains("insecure HTTP"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_write_hash_fills_in_missing_source_hash() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = test_support::source_tarball(&[("hashme-1.0.0/README", "hello")]);
+        let expected = format!("sha256:{}", hex::encode(<sha2::Sha256 as sha2::Digest>::digest(&source)));
+        let url = test_support::serve(source, "hashme-1.0.0.tar.gz");
+        let original = format!(r#"# Maintained by hand
+version: 1.0.0
+name: hashme
+source: {}
+description: Test package  # kept as written
+arch: [x86_64]
+build: cp README $PAX_BUILD_ROOT/
+"#, url);
+        let recipe_path = test_support::write_recipe(temp_dir.path(), &original);
+
+        let options = BuildOptions {
+            output: Some(temp_dir.path().join("hashme.pax").to_str().unwrap().to_string()),
+            write_hash: true,
+            ..Default::default()
+        };
+        build_package(recipe_path.to_str().unwrap(), &options).unwrap();
+
+        // Only the hash line is added, right after the source
+        let rewritten = std::fs::read_to_string(&recipe_path).unwrap();
+        let source_line = format!("source: {}\n", url);
+        assert_eq!(rewritten, original.replace(&source_line, &format!("{}hash: {}\n", source_line, expected)));
+
+        // The rebuild verifies the source against the written hash
+        let report = build_recipe_report(recipe_path.to_str().unwrap(), &BuildOptions { write_hash: false, ..options.clone() }).unwrap();
+        assert!(report.warnings.iter().all(|warning| !warning.contains("No hash")), "{:?}", report.warnings);
+
+        // With --hash-algo blake3 the written hash is a BLAKE3 one, and verifies the same way
+        let source = test_support::source_tarball(&[("hashme-1.0.0/README", "hello")]);
+        let expected = format!("blake3:{}", blake3::hash(&source).to_hex());
+        std::fs::write(&recipe_path, &original).unwrap();
+        let options = BuildOptions { hash_algorithm: HashAlgorithm::Blake3, ..options };
+        build_package(recipe_path.to_str().unwrap(), &options).unwrap();
+        let rewritten = std::fs::read_to_string(&recipe_path).unwrap();
+        assert_eq!(rewritten, original.replace(&source_line, &format!("{}hash: {}\n", source_line, expected)));
+        build_recipe_report(recipe_path.to_str().unwrap(), &BuildOptions { write_hash: false, ..options.clone() }).unwrap();
+
+        // A detached signature goes stale once the hash is written
+        std::fs::write(&recipe_path, &original).unwrap();
+        std::fs::write(format!("{}.asc", recipe_path.display()), "signature").unwrap();
+        let report = build_recipe_report(recipe_path.to_str().unwrap(), &options).unwrap();
+        assert!(report.warnings.iter().any(|warning| warning.contains(".asc no longer matches")), "{:?}", report.warnings);
+    }
+
+    #[test]
+    fn test_conflict_index_reports_overwritten_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path().join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        let mut other = test_support::metadata("othertool", "2.0.0");
+        other.files = vec!["usr/bin/tool".to_string(), "usr/share/othertool/README".to_string()];
+        test_support::write_package(&repo, &other, &[("usr/bin/tool", "other"), ("usr/share/othertool/README", "docs")]);
+        let (index, _) = crate::index::build_index(&repo, None, None).unwrap();
+        let index_path = temp_dir.path().join("index.json");
+        index.write(&index_path).unwrap();
+
+        let source = test_support::source_tarball(&[("tool-1.0.0/tool", "#!/bin/sh\n")]);
+        let url = test_support::serve(source, "tool-1.0.0.tar.gz");
+        let build = |replaces: &str| {
+            let recipe_path = test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: tool
+version: 1.0.0
+description: Test package
+source: {}
+arch: [x86_64]
+replaces: [{}]
+build: mkdir -p $PAX_BUILD_ROOT/usr/bin && cp tool $PAX_BUILD_ROOT/usr/bin/tool
+"#, url, replaces));
+            let options = BuildOptions {
+                output: Some(temp_dir.path().join("tool.pax").to_str().unwrap().to_string()),
+                conflict_index: Some(index_path.to_str().unwrap().to_string()),
+                ..Default::default()
+            };
+            build_package(recipe_path.to_str().unwrap(), &options)
+        };
+
+        let error = build("").unwrap_err();
+        let message = format!("{:#}", error);
+        assert!(message.contains("overwrite 1 file(s)"), "{}", message);
+        assert!(message.contains("usr/bin/tool (from othertool 2.0.0)"), "{}", message);
+
+        // Files of a package it replaces are its to take over
+        build("othertool<3.0").unwrap();
+    }
+
+    #[test]
+    fn test_deps_index_warns_about_unsatisfied_dependency() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = test_support::source_tarball(&[("app-1.0.0/README", "app")]);
+        let url = test_support::serve(source, "app-1.0.0.tar.gz");
+        let recipe_path = test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: app
+version: 1.0.0
+description: Test package
+source: {}
+arch: [x86_64]
+runtime_dependencies:
+  - libc>=2.31
+  - libssl.so.3
+  - libfoo>=1.0
+build: cp README $PAX_BUILD_ROOT/
+"#, url));
+
+        let index_path = temp_dir.path().join("index.yaml");
+        std::fs::write(&index_path, r#"
+packages:
+  - filename: glibc-2.35-x86_64.pax
+    name: glibc
+    version: "2.35"
+    description: C library
+    arch: [x86_64]
+    provides: [glibc, libc=2.35]
+    size: 1
+    sha256: "00"
+    mtime: 0
+  - filename: openssl-3.0.0-x86_64.pax
+    name: openssl
+    version: 3.0.0
+    description: TLS library
+    arch: [x86_64]
+    provides: [openssl, libssl.so.3]
+    size: 1
+    sha256: "00"
+    mtime: 0
+"#).unwrap();
+
+        let report_path = temp_dir.path().join("report.json");
+        let options = BuildOptions {
+            output: Some(temp_dir.path().join("app.pax").to_str().unwrap().to_string()),
+            report: Some(report_path.to_str().unwrap().to_string()),
+            deps_index: Some(index_path.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        build_package(recipe_path.to_str().unwrap(), &options).unwrap();
+
+        let report: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&report_path).unwrap()).unwrap();
+        let unsatisfied: Vec<&str> = report["warnings"].as_array().unwrap().iter()
+            .filter_map(|warning| warning.as_str())
+            .filter(|warning| warning.contains("not satisfied"))
+            .collect();
+        assert_eq!(unsatisfied.len(), 1, "{:?}", unsatisfied);
+        assert!(unsatisfied[0].contains("libfoo>=1.0"));
+    }
+
+    #[test]
+    fn test_pool_layout_places_package_by_name() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = test_support::source_tarball(&[("Zlib-1.3/README", "zlib")]);
+        let url = test_support::serve(source, "Zlib-1.3.tar.gz");
+        let recipe_path = test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: Zlib
+version: "1.3"
+description: Test package
+source: {}
+arch: [x86_64, aarch64]
+build: cp README $PAX_BUILD_ROOT/
+"#, url));
+
+        let root = temp_dir.path().join("repo");
+        let options = BuildOptions {
+            output_root: Some(root.to_str().unwrap().to_string()),
+            repo_layout: RepoLayout::parse("pool").unwrap(),
+            verify: true,
+            ..Default::default()
+        };
+        build_package(recipe_path.to_str().unwrap(), &options).unwrap();
+
+        for arch in ["x86_64", "aarch64"] {
+            let expected = root.join(format!("pool/z/Zlib/Zlib-1.3-{}.pax", arch));
+            assert!(expected.is_file(), "missing {}", expected.display());
+        }
+        assert_eq!(RepoLayout::Flat.package_path(&root, "Zlib", "Zlib-1.3-x86_64.pax"), root.join("Zlib-1.3-x86_64.pax"));
+        assert!(RepoLayout::parse("tree").is_err());
+    }
+
+    #[test]
+    fn test_metadata_sidecar_written_and_preferred_by_index() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = test_support::source_tarball(&[("side-1.0/README", "side")]);
+        let url = test_support::serve(source, "side-1.0.tar.gz");
+        let recipe_path = test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: side
+version: "1.0"
+description: Test package
+source: {}
+arch: [x86_64]
+runtime_dependencies: [libc]
+build: cp README $PAX_BUILD_ROOT/
+"#, url));
+
+        let repo = temp_dir.path().join("repo");
+        let options = BuildOptions {
+            output_root: Some(repo.to_str().unwrap().to_string()),
+            metadata_sidecar: true,
+            ..Default::default()
+        };
+        build_package(recipe_path.to_str().unwrap(), &options).unwrap();
+
+        let package = crate::package::PaxPackage::open(repo.join("side-1.0-x86_64.pax")).unwrap();
+        let sidecar_path = repo.join("side-1.0-x86_64.paxmeta.json");
+        let sidecar = crate::index::read_sidecar(&sidecar_path).unwrap();
+        assert_eq!(sidecar.metadata.runtime_dependencies, vec!["libc"]);
+        assert_eq!(sidecar.sha256, package.calculate_hash().unwrap());
+        assert_eq!(sidecar.size, package.size().unwrap());
+
+        // The index takes metadata from the sidecar, even once the package is gone
+        let mut edited = sidecar.clone();
+        edited.metadata.description = "From the sidecar".to_string();
+        std::fs::write(&sidecar_path, serde_json::to_string(&edited).unwrap()).unwrap();
+        let (index, _) = crate::index::build_index(&repo, None, None).unwrap();
+        assert_eq!(index.packages[0].description, "From the sidecar");
+        assert_eq!(index.packages[0].sha256, sidecar.sha256);
+
+        std::fs::remove_file(package.path()).unwrap();
+        let (index, _) = crate::index::build_index(&repo, None, None).unwrap();
+        assert_eq!(index.packages.len(), 1);
+        assert_eq!(index.packages[0].filename, "side-1.0-x86_64.pax");
+    }
+
+    #[test]
+    fn test_from_directory_builds_local_tree_without_touching_it() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("src");
+        std::fs::create_dir_all(&source_dir).unwrap();
+        std::fs::write(source_dir.join("README"), "local").unwrap();
+        let recipe_path = test_support::write_recipe(temp_dir.path(), r#"
+name: local
+version: 1.0.0
+description: Test package
+source: https://example.invalid/local-1.0.0.tar.gz
+arch: [x86_64]
+build: |
+  echo built > artifact
+  cp README artifact $PAX_BUILD_ROOT/
+"#);
+
+        let options = BuildOptions {
+            output: Some(temp_dir.path().to_str().unwrap().to_string()),
+            from_directory: Some(source_dir.to_str().unwrap().to_string()),
+            offline: true,
+            ..Default::default()
+        };
+        let report = build_recipe_report(recipe_path.to_str().unwrap(), &options).unwrap();
+
+        let files = crate::package::PaxPackage::open(&report.packages[0].path).unwrap().list_files().unwrap();
+        assert!(files.iter().any(|file| file.ends_with("README")));
+        assert!(files.iter().any(|file| file.ends_with("artifact")));
+        assert!(!source_dir.join("artifact").exists());
+    }
 }
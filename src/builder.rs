@@ -1,15 +1,186 @@
 use anyhow::{Result, Context};
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::sync::Arc;
+use std::time::Instant;
+use serde::{Deserialize, Serialize};
 use tempfile::TempDir;
-use crate::recipe::BuildRecipe;
+use sha2::{Digest, Sha256};
+use crate::depspec::{Constraint, Provide};
+use crate::exec;
+use crate::index::PackageIndex;
+use crate::ownership::{self, ArchiveOwner};
+use crate::package::{PackageMetadata, FILE_HASHES_LIST_NAME, FILES_LIST_NAME, METADATA_DIR, METADATA_PATH};
+use crate::recipe::{BuildRecipe, Source};
 use crate::source::SourceManager;
+use crate::warnings::Warnings;
+
+/// Compression level packages are written at
+pub const DEFAULT_COMPRESSION_LEVEL: i32 = 19;
+
+/// Levels zstd accepts
+pub const ZSTD_LEVELS: std::ops::RangeInclusive<i32> = 1..=22;
+
+/// Parse a zstd compression level, rejecting anything outside 1-22
+pub fn parse_compression_level(value: &str) -> Result<i32> {
+    value.parse::<i32>().ok()
+        .filter(|level| ZSTD_LEVELS.contains(level))
+        .ok_or_else(|| anyhow::anyhow!(
+            "Invalid compression level '{}': zstd levels are {} to {}", value, ZSTD_LEVELS.start(), ZSTD_LEVELS.end()
+        ))
+}
+
+/// Compression algorithm used for .pax packages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Zstd,
+    Gzip,
+}
+
+impl Compression {
+    /// Algorithms packages are read with
+    pub const READABLE: &'static [Compression] = &[Compression::Zstd, Compression::Gzip];
+
+    /// Algorithms `paxbuild build` writes packages with
+    pub const WRITABLE: &'static [Compression] = &[Compression::Zstd];
+
+    /// Algorithm name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::Zstd => "zstd",
+            Compression::Gzip => "gzip",
+        }
+    }
+
+    /// Whether compression is done in-process rather than by an external program
+    pub fn is_native(&self) -> bool {
+        match self {
+            Compression::Zstd | Compression::Gzip => true,
+        }
+    }
+
+    /// Magic bytes that start data compressed with this algorithm
+    pub fn magic(&self) -> &'static [u8] {
+        match self {
+            Compression::Zstd => &[0x28, 0xb5, 0x2f, 0xfd],
+            Compression::Gzip => &[0x1f, 0x8b],
+        }
+    }
+
+    /// Identify the algorithm from the first bytes of compressed data
+    pub fn detect(data: &[u8]) -> Option<Compression> {
+        Self::READABLE.iter().copied().find(|compression| data.starts_with(compression.magic()))
+    }
+
+    /// Compress data at the given level
+    pub fn compress(&self, data: &[u8], level: i32) -> Result<Vec<u8>> {
+        match self {
+            Compression::Zstd => zstd::encode_all(data, level)
+                .with_context(|| "Failed to compress with zstd"),
+            Compression::Gzip => {
+                let level = flate2::Compression::new(level.clamp(0, 9) as u32);
+                let mut encoder = flate2::write::GzEncoder::new(Vec::new(), level);
+                std::io::Write::write_all(&mut encoder, data)
+                    .and_then(|_| encoder.finish())
+                    .with_context(|| "Failed to compress with gzip")
+            }
+        }
+    }
+
+    /// Decompress data produced by this algorithm
+    pub fn decompress(&self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Compression::Zstd => zstd::decode_all(data)
+                .with_context(|| "Failed to decompress with zstd"),
+            Compression::Gzip => {
+                let mut decompressed = Vec::new();
+                std::io::Read::read_to_end(&mut flate2::read::GzDecoder::new(data), &mut decompressed)
+                    .with_context(|| "Failed to decompress with gzip")?;
+                Ok(decompressed)
+            }
+        }
+    }
+}
+
+/// Result of building a package for a single architecture
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArchBuildReport {
+    /// Target architecture
+    pub arch: String,
+    /// Path to the generated .pax package
+    pub path: PathBuf,
+    /// SHA256 hash of the package file
+    pub sha256: String,
+    /// Package file size in bytes
+    pub size: u64,
+    /// Time spent building and packaging this architecture
+    pub duration_secs: f64,
+}
+
+/// Machine-readable summary of a build
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BuildReport {
+    /// Package name
+    pub name: String,
+    /// Package version
+    pub version: String,
+    /// Per-architecture results, in build order
+    pub packages: Vec<ArchBuildReport>,
+    /// Total build time including source download
+    pub duration_secs: f64,
+    /// Warnings emitted during the build
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+impl BuildReport {
+    /// Get the package paths in build order
+    pub fn package_paths(&self) -> Vec<PathBuf> {
+        self.packages.iter().map(|p| p.path.clone()).collect()
+    }
+
+    /// Serialize the report to pretty-printed JSON
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self)
+            .with_context(|| "Failed to serialize build report")
+    }
+
+    /// Write the report as JSON to a file
+    pub fn write_json(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_json()?)
+            .with_context(|| format!("Failed to write build report: {}", path.display()))
+    }
+}
+
+/// Host environment variables build scripts keep under `with_clean_env`
+pub const CLEAN_ENV_VARS: &[&str] = &["PATH", "HOME"];
 
 /// Package builder that creates .pax packages from recipes
 pub struct PackageBuilder {
     temp_dir: TempDir,
-    source_mgr: SourceManager,
+    source_mgr: Arc<SourceManager>,
+    package_strip: usize,
+    files_list_threshold: Option<usize>,
+    features: Vec<String>,
+    extra_metadata: BTreeMap<String, String>,
+    warnings: Warnings,
+    build_jobs: usize,
+    deps_index: Option<Arc<PackageIndex>>,
+    conflict_index: Option<Arc<PackageIndex>>,
+    verify_packages: bool,
+    source_dir: Option<PathBuf>,
+    clean_env: bool,
+    embed_signature_key: Option<PathBuf>,
+    compression_level: i32,
+    prefix_maps: Vec<PrefixMap>,
+    skip_check: bool,
+    log_dir: Option<PathBuf>,
+    verbose: bool,
+    timeout: Option<u64>,
+    reproducible: bool,
 }
 
 impl PackageBuilder {
@@ -18,14 +189,161 @@ impl PackageBuilder {
         let temp_dir = TempDir::new()
             .with_context(|| "Failed to create temporary directory")?;
 
-        let source_mgr = SourceManager::new()?;
+        let source_mgr = Arc::new(SourceManager::new()?);
 
         Ok(PackageBuilder {
             temp_dir,
             source_mgr,
+            package_strip: 0,
+            files_list_threshold: None,
+            features: Vec::new(),
+            extra_metadata: BTreeMap::new(),
+            warnings: Warnings::default(),
+            build_jobs: default_build_jobs(),
+            deps_index: None,
+            conflict_index: None,
+            verify_packages: false,
+            source_dir: None,
+            clean_env: false,
+            embed_signature_key: None,
+            compression_level: DEFAULT_COMPRESSION_LEVEL,
+            prefix_maps: Vec::new(),
+            skip_check: false,
+            log_dir: None,
+            verbose: false,
+            timeout: None,
+            reproducible: false,
         })
     }
 
+    /// Build from a local source tree instead of downloading the recipe's source
+    ///
+    /// The tree is copied first, so build scripts never write into it.
+    pub fn with_source_dir(mut self, source_dir: Option<PathBuf>) -> Self {
+        self.source_dir = source_dir;
+        self
+    }
+
+    /// Run build scripts with only `CLEAN_ENV_VARS` from the host environment,
+    /// plus the recipe's `env` and the `PAX_*` variables
+    pub fn with_clean_env(mut self, clean_env: bool) -> Self {
+        self.clean_env = clean_env;
+        self
+    }
+
+    /// Share a source manager (and its download cache) with other builders
+    pub fn with_source_manager(mut self, source_mgr: Arc<SourceManager>) -> Self {
+        self.source_mgr = source_mgr;
+        self
+    }
+
+    /// Strip the first `count` path components from every packaged file
+    pub fn with_package_strip(mut self, count: usize) -> Self {
+        self.package_strip = count;
+        self
+    }
+
+    /// Store the file list in a separate `files.list` member when a package has more than `threshold` files
+    pub fn with_files_list_threshold(mut self, threshold: Option<usize>) -> Self {
+        self.files_list_threshold = threshold;
+        self
+    }
+
+    /// Enable recipe features, adding their dependencies and exporting `PAX_FEATURE_<NAME>=1`
+    pub fn with_features(mut self, features: Vec<String>) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Parallel jobs exported to build scripts as `PAX_JOBS` (defaults to the number of CPUs)
+    ///
+    /// Zero would give `make -j0`, so it falls back to the default too.
+    pub fn with_build_jobs(mut self, jobs: Option<usize>) -> Self {
+        self.build_jobs = jobs.filter(|jobs| *jobs > 0).unwrap_or_else(default_build_jobs);
+        self
+    }
+
+    /// Warn about runtime dependencies that no package in `index` satisfies
+    pub fn with_deps_index(mut self, index: Option<Arc<PackageIndex>>) -> Self {
+        self.deps_index = index;
+        self
+    }
+
+    /// Fail the build if a package ships a file that some package in `index` already ships
+    pub fn with_conflict_index(mut self, index: Option<Arc<PackageIndex>>) -> Self {
+        self.conflict_index = index;
+        self
+    }
+
+    /// zstd level packages are compressed at; higher levels trade build time for smaller packages
+    pub fn with_compression_level(mut self, level: i32) -> Self {
+        self.compression_level = level;
+        self
+    }
+
+    /// Package files installed under one prefix under another instead (`/usr` to `/usr/local`)
+    pub fn with_prefix_maps(mut self, prefix_maps: Vec<PrefixMap>) -> Self {
+        self.prefix_maps = prefix_maps;
+        self
+    }
+
+    /// Don't run the recipe's `check` script
+    pub fn with_skip_check(mut self, skip_check: bool) -> Self {
+        self.skip_check = skip_check;
+        self
+    }
+
+    /// Write archives that only depend on the packaged files' contents and modes
+    ///
+    /// Every entry's mtime is set to `SOURCE_DATE_EPOCH` (or 0), and its
+    /// owner to uid/gid 0 without user and group names (except for `file_owners`).
+    pub fn with_reproducible(mut self, reproducible: bool) -> Self {
+        self.reproducible = reproducible;
+        self
+    }
+
+    /// Write each architecture's build and check output to `build-<arch>.log` in this directory
+    pub fn with_log_dir(mut self, log_dir: Option<PathBuf>) -> Self {
+        self.log_dir = log_dir;
+        self
+    }
+
+    /// Echo the standard output of build scripts as they run (their stderr is always shown)
+    pub fn with_verbose(mut self, verbose: bool) -> Self {
+        self.verbose = verbose;
+        self
+    }
+
+    /// Kill a build script, and everything it started, once it has run this many seconds
+    pub fn with_timeout(mut self, seconds: Option<u64>) -> Self {
+        self.timeout = seconds;
+        self
+    }
+
+    /// Re-open each package once written and fail the build if it does not verify
+    pub fn with_verify(mut self, verify: bool) -> Self {
+        self.verify_packages = verify;
+        self
+    }
+
+    /// Sign every package with this private key, embedding the signature in the archive
+    pub fn with_embed_signature(mut self, key: Option<PathBuf>) -> Self {
+        self.embed_signature_key = key;
+        self
+    }
+
+    /// Fail the build on the first warning instead of printing it
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.warnings = Warnings::new(strict);
+        self
+    }
+
+    /// Add extra metadata key/value pairs, overriding the recipe's `extra` entries
+    pub fn with_extra_metadata(mut self, extra: BTreeMap<String, String>) -> Self {
+        self.extra_metadata = extra;
+        self
+    }
+
 
     /// Build a package from a recipe
     pub fn build(&self, recipe: &BuildRecipe) -> Result<PathBuf> {
@@ -37,170 +355,549 @@ impl PackageBuilder {
 
     /// Build a package for specific architectures
     pub fn build_for_architectures(&self, recipe: &BuildRecipe, architectures: &[String]) -> Result<Vec<PathBuf>> {
+        Ok(self.build_with_report(recipe, architectures)?.package_paths())
+    }
+
+    /// Build a package for specific architectures and return a report describing the result
+    pub fn build_with_report(&self, recipe: &BuildRecipe, architectures: &[String]) -> Result<BuildReport> {
+        let started = Instant::now();
         println!("Building package: {} {} for architectures: {:?}",
-                 recipe.name, recipe.version, architectures);
+                 recipe.name, recipe.full_version(), architectures);
 
         // Validate recipe
         recipe.validate()?;
+        recipe.validate_features(&self.features)?;
+        for key in self.extra_metadata.keys() {
+            crate::package::validate_extra_key(key)?;
+        }
 
         if architectures.is_empty() {
             anyhow::bail!("No architectures specified for build");
         }
 
-        // Download and extract source once (shared across architectures)
-        let source_dir = self.source_mgr.download_and_extract(
-            &recipe.source,
-            recipe.hash.as_deref(),
-        )?;
+        for warning in recipe.warnings() {
+            self.warnings.warn(warning)?;
+        }
+
+        let shared_source_dir = self.shared_source_dir(recipe)?;
 
         // Build for each architecture
-        let mut package_paths = Vec::new();
+        let mut packages = Vec::new();
         for target_arch in architectures {
             println!("Building for architecture: {}", target_arch);
+            let arch_started = Instant::now();
+
+            let source_dir = self.source_dir_for_arch(recipe, shared_source_dir.as_deref(), target_arch)?;
 
             // Run build script for specific architecture
-            self.run_build_script_for_arch(recipe, &source_dir, target_arch)?;
+            // Output lines are labelled with their architecture when there are several
+            let prefixed = architectures.len() > 1;
+            self.run_build_script_for_arch(recipe, &source_dir, target_arch, prefixed)?;
+            self.run_check_script_for_arch(recipe, &source_dir, target_arch, prefixed)?;
 
             // Create package for specific architecture
             let package_path = self.create_package_for_arch(recipe, target_arch)?;
             println!("Package created: {}", package_path.display());
 
+            for entry in crate::verify::writable_entries(&crate::package::PaxPackage::open(&package_path)?)? {
+                self.warnings.warn(format!("Packaged path is writable by group or others: {}", entry))?;
+            }
+
+            if let Some(index) = &self.conflict_index {
+                let mut package = crate::package::PaxPackage::open(&package_path)?;
+                let conflicts = index.file_conflicts(package.load_metadata()?);
+                if !conflicts.is_empty() {
+                    let lines: Vec<String> = conflicts.iter()
+                        .map(|(file, entry)| format!("  {} (from {} {})", file, entry.name, entry.version))
+                        .collect();
+                    anyhow::bail!(
+                        "Package for {} would overwrite {} file(s) from other packages in the index:\n{}",
+                        target_arch, conflicts.len(), lines.join("\n")
+                    );
+                }
+            }
+
+            if let Some(key) = &self.embed_signature_key {
+                crate::crypto::embed_package_signature(&package_path, key, self.compression_level)?;
+                println!("Signature embedded");
+            }
+
+            if self.verify_packages {
+                crate::verify::verify_built_package(&package_path)
+                    .with_context(|| format!("Post-build verification failed for {}", package_path.display()))?;
+                println!("Package verified");
+            }
+
+            let size = fs::metadata(&package_path)
+                .with_context(|| format!("Failed to get metadata for: {}", package_path.display()))?
+                .len();
+            let sha256 = SourceManager::calculate_hash(&package_path)?;
+
+            packages.push(ArchBuildReport {
+                arch: target_arch.clone(),
+                path: package_path,
+                sha256,
+                size,
+                duration_secs: arch_started.elapsed().as_secs_f64(),
+            });
+        }
 
-            package_paths.push(package_path);
+        // Catch misspelled or unpublished dependencies before the package ships
+        if let Some(index) = &self.deps_index {
+            let runtime_dependencies = recipe.runtime_dependencies_with_features(&self.features);
+            for dependency in index.unsatisfied(&runtime_dependencies) {
+                let replacements: Vec<String> = Constraint::parse(dependency)
+                    .map(|constraint| {
+                        let version = constraint.requirement.as_ref().map(|(_, version)| version.as_str());
+                        index.replacements_for(&constraint.name, version).iter()
+                            .map(|entry| format!("{} {}", entry.name, entry.version))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                if replacements.is_empty() {
+                    self.warnings.warn(format!("Runtime dependency {} is not satisfied by any package in the index", dependency))?;
+                } else {
+                    self.warnings.warn(format!(
+                        "Runtime dependency {} is not satisfied by any package in the index; it is replaced by {}",
+                        dependency, replacements.join(", ")
+                    ))?;
+                }
+            }
         }
 
         println!("All architecture-specific packages built in temp directory");
-        Ok(package_paths)
+        Ok(BuildReport {
+            name: recipe.name.clone(),
+            version: recipe.full_version(),
+            packages,
+            duration_secs: started.elapsed().as_secs_f64(),
+            warnings: self.warnings.messages(),
+        })
+    }
+
+    /// Download and extract a source shared by every architecture, or copy the
+    /// `with_source_dir` tree, and apply the recipe's patches; `None` for
+    /// per-architecture sources
+    fn shared_source_dir(&self, recipe: &BuildRecipe) -> Result<Option<PathBuf>> {
+        let source_dir = match &recipe.source {
+            _ if self.source_dir.is_some() => Some(self.copy_source_dir()?),
+            Source::Url(url) => Some(self.source_mgr.download_and_extract_with(
+                url,
+                recipe.hash.as_deref(),
+                recipe.source_filename.as_deref(),
+                &recipe.download_plan(),
+            )?),
+            Source::List(sources) => Some(self.source_mgr.download_and_extract_sources(sources, &recipe.download_plan())?),
+            Source::PerArch(_) => None,
+        };
+        if let Some(source_dir) = &source_dir {
+            self.source_mgr.apply_patches(&recipe.patches, source_dir)?;
+        }
+        Ok(source_dir)
+    }
+
+    /// Source tree to build `arch` in, fetching and patching its own source if there is no shared one
+    fn source_dir_for_arch(&self, recipe: &BuildRecipe, shared_source_dir: Option<&Path>, arch: &str) -> Result<PathBuf> {
+        match shared_source_dir {
+            Some(source_dir) => Ok(source_dir.to_path_buf()),
+            None => {
+                let (url, hash) = recipe.source_for_arch(arch)?;
+                let source_dir = self.source_mgr.download_and_extract_with(url, hash, None, &recipe.download_plan())?;
+                self.source_mgr.apply_patches(&recipe.patches, &source_dir)?;
+                Ok(source_dir)
+            }
+        }
+    }
+
+    /// Build script for each architecture with the variables paxbuild sets substituted in
+    ///
+    /// Sources are fetched (build-system detection looks at them) but nothing is built.
+    /// Returns `(arch, description, script)` for every architecture.
+    pub fn resolved_build_scripts(&self, recipe: &BuildRecipe, architectures: &[String]) -> Result<Vec<(String, String, String)>> {
+        recipe.validate()?;
+        recipe.validate_features(&self.features)?;
+
+        let shared_source_dir = self.shared_source_dir(recipe)?;
+        let mut scripts = Vec::new();
+        for arch in architectures {
+            let source_dir = self.source_dir_for_arch(recipe, shared_source_dir.as_deref(), arch)?;
+            let description = match (&recipe.build, recipe.build_system) {
+                _ if recipe.phases.is_some() => "recipe build phases".to_string(),
+                (Some(_), _) => "recipe build script".to_string(),
+                (None, Some(system)) => format!("default {} build script", system.name()),
+                (None, None) => format!("default {} build script (detected)", recipe.effective_build_system(&source_dir).name()),
+            };
+            let phases = recipe.build_phases(&source_dir);
+            let script = match &recipe.phases {
+                Some(_) => phases.iter().map(|(phase, script)| format!("# {}\n{}", phase, script.trim_end())).collect::<Vec<_>>().join("\n"),
+                None => phases.into_iter().map(|(_, script)| script).collect(),
+            };
+            let script = substitute_env(&script, &self.build_env(recipe, &source_dir, arch));
+            scripts.push((arch.clone(), description, script));
+        }
+        Ok(scripts)
+    }
+
+    /// Directory the build script for `arch` installs into (`$PAX_BUILD_ROOT`)
+    ///
+    /// Every architecture gets its own, so files installed for one never end up
+    /// in another's package.
+    fn install_dir(&self, arch: &str) -> PathBuf {
+        self.temp_dir.path().join("install").join(arch)
+    }
+
+    /// Out-of-tree build directory for `arch` (`$PAX_BUILD_DIR`)
+    fn build_dir(&self, arch: &str) -> PathBuf {
+        self.temp_dir.path().join("build").join(arch)
+    }
+
+    /// Variables set for the build script on top of the inherited (or clean) environment
+    fn build_env(&self, recipe: &BuildRecipe, source_dir: &Path, arch: &str) -> Vec<(String, String)> {
+        let path = |path: PathBuf| path.to_string_lossy().to_string();
+        let mut env: Vec<(String, String)> = recipe.env.iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        env.extend([
+            ("PAX_BUILD_ROOT".to_string(), path(self.install_dir(arch))),
+            ("PAX_PACKAGE_NAME".to_string(), recipe.name.clone()),
+            ("PAX_PACKAGE_VERSION".to_string(), recipe.version.clone()),
+            ("PAX_ARCH".to_string(), arch.to_string()),
+            ("PAX_TARGET_ARCH".to_string(), arch.to_string()),
+            ("PAX_SOURCE_DIR".to_string(), path(source_dir.to_path_buf())),
+            ("PAX_BUILD_DIR".to_string(), path(self.build_dir(arch))),
+            ("PAX_JOBS".to_string(), self.build_jobs.to_string()),
+        ]);
+        env.extend(self.features.iter().map(|feature| (BuildRecipe::feature_env_var(feature), "1".to_string())));
+        env
+    }
+
+    /// Copy the `with_source_dir` tree into a fresh directory to build in
+    fn copy_source_dir(&self) -> Result<PathBuf> {
+        let Some(source_dir) = &self.source_dir else {
+            anyhow::bail!("No source directory set");
+        };
+        if !source_dir.is_dir() {
+            anyhow::bail!("Source directory does not exist: {}", source_dir.display());
+        }
+
+        let build_source = self.temp_dir.path().join("local-source");
+        if build_source.exists() {
+            fs::remove_dir_all(&build_source)
+                .with_context(|| "Failed to clean local source directory")?;
+        }
+        fs::create_dir_all(&build_source)
+            .with_context(|| "Failed to create local source directory")?;
+        self.copy_directory(source_dir, &build_source)?;
+        println!("Using source directory: {}", source_dir.display());
+        Ok(build_source)
     }
 
     /// Run the build script for a specific architecture
-    fn run_build_script_for_arch(&self, recipe: &BuildRecipe, source_dir: &Path, arch: &str) -> Result<()> {
+    fn run_build_script_for_arch(&self, recipe: &BuildRecipe, source_dir: &Path, arch: &str, prefixed: bool) -> Result<()> {
         println!("Running build script for architecture: {}...", arch);
 
-        let build_dir = self.temp_dir.path().join("build");
-        let install_dir = self.temp_dir.path().join("install");
+        let build_dir = self.build_dir(arch);
+        let install_dir = self.install_dir(arch);
 
+        if install_dir.exists() {
+            fs::remove_dir_all(&install_dir)
+                .with_context(|| "Failed to clean install directory")?;
+        }
         fs::create_dir_all(&build_dir)
             .with_context(|| "Failed to create build directory")?;
         fs::create_dir_all(&install_dir)
             .with_context(|| "Failed to create install directory")?;
 
-        let build_script = recipe.get_build_script();
+        if recipe.build.is_none() && recipe.phases.is_none() {
+            println!("Using the default {} build script", recipe.effective_build_system(source_dir).name());
+        }
+        if let Some(log_path) = self.log_path(arch) {
+            if let Some(log_dir) = log_path.parent() {
+                fs::create_dir_all(log_dir)
+                    .with_context(|| format!("Failed to create log directory: {}", log_dir.display()))?;
+            }
+            fs::write(&log_path, "")
+                .with_context(|| format!("Failed to create build log: {}", log_path.display()))?;
+        }
 
-        // Set up environment variables with target architecture
-        let mut cmd = Command::new("bash");
-        cmd.arg("-c")
-            .arg(&build_script)
-            .current_dir(source_dir)
-            .env("PAX_BUILD_ROOT", &install_dir)
-            .env("PAX_PACKAGE_NAME", &recipe.name)
-            .env("PAX_PACKAGE_VERSION", &recipe.version)
-            .env("PAX_ARCH", arch)
-            .env("PAX_TARGET_ARCH", arch)
-            .env("PAX_SOURCE_DIR", source_dir)
-            .env("PAX_BUILD_DIR", &build_dir);
+        for (phase, script) in recipe.build_phases(source_dir) {
+            if recipe.phases.is_some() {
+                println!("==> {} phase ({})", phase, arch);
+            }
+
+            let output = self.run_script(recipe, phase, &script, source_dir, arch, prefixed)?;
+            if !output.status.success() {
+                let stderr = String::from_utf8_lossy(&output.stderr);
+                if recipe.phases.is_some() {
+                    anyhow::bail!("The {} phase failed for architecture {}:\n{}", phase, arch, stderr.trim_end());
+                }
+                anyhow::bail!("Build script failed for architecture {}:\n{}", arch, stderr.trim_end());
+            }
+        }
+
+        println!("Build completed successfully for architecture: {}", arch);
+        Ok(())
+    }
 
-        let output = cmd.output()
-            .with_context(|| format!("Failed to run build command for architecture {}", arch))?;
+    /// Run the recipe's `check` script after a successful build, unless checks are skipped
+    fn run_check_script_for_arch(&self, recipe: &BuildRecipe, source_dir: &Path, arch: &str, prefixed: bool) -> Result<()> {
+        let Some(check_script) = &recipe.check else {
+            return Ok(());
+        };
+        if self.skip_check {
+            println!("Skipping check script for architecture: {}", arch);
+            return Ok(());
+        }
+        println!("Running check script for architecture: {}...", arch);
 
+        let output = self.run_script(recipe, "check", check_script, source_dir, arch, prefixed)?;
         if !output.status.success() {
-            println!("Build output for {}:", arch);
-            println!("{}", String::from_utf8_lossy(&output.stdout));
-            println!("Build errors for {}:", arch);
-            println!("{}", String::from_utf8_lossy(&output.stderr));
-            anyhow::bail!("Build script failed for architecture {}", arch);
+            anyhow::bail!(
+                "Check script failed for architecture {}:\n{}",
+                arch, String::from_utf8_lossy(&output.stderr).trim_end()
+            );
         }
 
-        println!("Build completed successfully for architecture: {}", arch);
+        println!("Checks passed for architecture: {}", arch);
         Ok(())
     }
 
+    /// Run one script of the build, streaming its output and logging it
+    ///
+    /// stderr is echoed line by line as the script writes it, and stdout too
+    /// when verbose. If the script fails, the log path is printed, or without
+    /// a log the stdout that was not echoed.
+    fn run_script(&self, recipe: &BuildRecipe, phase: &str, script: &str, source_dir: &Path, arch: &str, prefixed: bool) -> Result<std::process::Output> {
+        let prefix = if prefixed { format!("[{}] ", arch) } else { String::new() };
+        let mut cmd = self.script_command(recipe, script, source_dir, arch);
+        let timeout = self.timeout.map(std::time::Duration::from_secs);
+        let result = exec::run_streaming(&mut cmd, timeout, |stream, line| match stream {
+            exec::OutputStream::Stdout if self.verbose => println!("{}{}", prefix, line),
+            exec::OutputStream::Stdout => {}
+            exec::OutputStream::Stderr => eprintln!("{}{}", prefix, line),
+        });
+        if let (Err(e), Some(seconds)) = (&result, self.timeout) {
+            if e.kind() == std::io::ErrorKind::TimedOut {
+                anyhow::bail!("Build timed out after {} seconds (the {} script for architecture {} was killed)", seconds, phase, arch);
+            }
+        }
+        let output = result.with_context(|| format!("Failed to run {} command for architecture {}", phase, arch))?;
+        let log_path = self.append_log(arch, phase, script, &cmd, &output)?;
+
+        if !output.status.success() {
+            match log_path {
+                Some(log_path) => println!("Build log for {}: {}", arch, log_path.display()),
+                None if !self.verbose => {
+                    println!("Output of the {} script for {}:", phase, arch);
+                    println!("{}", String::from_utf8_lossy(&output.stdout));
+                }
+                None => {}
+            }
+        }
+        Ok(output)
+    }
+
+    /// Build log of `arch`, if logging is enabled
+    fn log_path(&self, arch: &str) -> Option<PathBuf> {
+        self.log_dir.as_ref().map(|log_dir| log_dir.join(format!("build-{}.log", arch)))
+    }
+
+    /// Append a script's command, environment and output to the build log of `arch`
+    fn append_log(&self, arch: &str, phase: &str, script: &str, cmd: &Command, output: &std::process::Output) -> Result<Option<PathBuf>> {
+        let Some(log_path) = self.log_path(arch) else {
+            return Ok(None);
+        };
+
+        let mut entry = format!("==> {} ({})\n", phase, arch);
+        entry.push_str(&format!("Command: {} -c <script>\n", cmd.get_program().to_string_lossy()));
+        entry.push_str(&format!("Directory: {}\n", cmd.get_current_dir().unwrap_or(Path::new(".")).display()));
+        entry.push_str(&format!(
+            "Environment ({}):\n",
+            if self.clean_env { "host environment cleared" } else { "added to the host environment" }
+        ));
+        for (name, value) in cmd.get_envs() {
+            if let Some(value) = value {
+                entry.push_str(&format!("  {}={}\n", name.to_string_lossy(), value.to_string_lossy()));
+            }
+        }
+        entry.push_str(&format!("--- script ---\n{}\n", script.trim_end()));
+        entry.push_str(&format!("--- stdout ---\n{}", String::from_utf8_lossy(&output.stdout)));
+        entry.push_str(&format!("--- stderr ---\n{}", String::from_utf8_lossy(&output.stderr)));
+        entry.push_str(&format!("--- {} ---\n\n", output.status));
+
+        let mut log = fs::OpenOptions::new().create(true).append(true).open(&log_path)
+            .with_context(|| format!("Failed to open build log: {}", log_path.display()))?;
+        std::io::Write::write_all(&mut log, entry.as_bytes())
+            .with_context(|| format!("Failed to write build log: {}", log_path.display()))?;
+        Ok(Some(log_path))
+    }
+
+    /// A bash command running `script` in `source_dir` with the build environment for `arch`
+    fn script_command(&self, recipe: &BuildRecipe, script: &str, source_dir: &Path, arch: &str) -> Command {
+        let mut cmd = Command::new("bash");
+        if self.clean_env {
+            cmd.env_clear();
+            for name in CLEAN_ENV_VARS {
+                if let Some(value) = std::env::var_os(name) {
+                    cmd.env(name, value);
+                }
+            }
+        }
+        cmd.arg("-c")
+            .arg(script)
+            .current_dir(source_dir)
+            .envs(self.build_env(recipe, source_dir, arch));
+        cmd
+    }
+
     /// Create the .pax package for a specific architecture
     fn create_package_for_arch(&self, recipe: &BuildRecipe, arch: &str) -> Result<PathBuf> {
         println!("Creating package for architecture: {}...", arch);
 
+        // Start from an empty directory so nothing carries over from another architecture
         let package_dir = self.temp_dir.path().join("package");
+        if package_dir.exists() {
+            fs::remove_dir_all(&package_dir)
+                .with_context(|| "Failed to clean package directory")?;
+        }
         fs::create_dir_all(&package_dir)
             .with_context(|| "Failed to create package directory")?;
 
         // Copy installed files to package directory
-        let install_dir = self.temp_dir.path().join("install");
+        let install_dir = self.install_dir(arch);
         if install_dir.exists() {
-            self.copy_directory(&install_dir, &package_dir)?;
+            let excludes = Excludes::for_recipe(recipe)?;
+            let renames = Renames::for_recipe(recipe)?;
+            if self.package_strip == 0 && renames.is_empty() && self.prefix_maps.is_empty() {
+                self.copy_directory_excluding(&install_dir, &install_dir, &package_dir, &excludes)?;
+            } else {
+                for (src, dst) in self.packaged_file_paths(&install_dir, &excludes, &renames)? {
+                    self.copy_directory(&install_dir.join(src), &package_dir.join(dst))?;
+                }
+            }
+        }
+
+        if package_dir.join(METADATA_DIR).exists() {
+            anyhow::bail!("Installed files may not use the reserved '{}' directory", METADATA_DIR);
         }
+        if let Some(path) = self.relocated_keys(&recipe.file_owners).keys().find(|path| package_dir.join(path).symlink_metadata().is_err()) {
+            anyhow::bail!("An owner is set on '{}', which is not in the package", path);
+        }
+        fs::create_dir_all(package_dir.join(METADATA_DIR))
+            .with_context(|| "Failed to create metadata directory")?;
 
         // Create package metadata file (not .paxmeta, but actual package metadata)
-        let metadata_path = package_dir.join("metadata.yaml");
-        let metadata_content = self.create_package_metadata_for_arch(recipe, arch)?;
+        let mut metadata = self.create_package_metadata_for_arch(recipe, arch)?;
+
+        // Keep huge file lists out of metadata.yaml so it stays fast to parse
+        if self.files_list_threshold.is_some_and(|threshold| metadata.files.len() > threshold) {
+            metadata.files.sort();
+            let mut files_list = metadata.files.join("\n");
+            files_list.push('\n');
+            fs::write(package_dir.join(FILES_LIST_NAME), files_list)
+                .with_context(|| "Failed to write file list")?;
+            metadata.files = Vec::new();
+            metadata.files_list = Some(FILES_LIST_NAME.to_string());
+
+            let file_hashes: String = metadata.file_hashes.iter()
+                .map(|(path, hash)| format!("{}  {}\n", hash, path))
+                .collect();
+            fs::write(package_dir.join(FILE_HASHES_LIST_NAME), file_hashes)
+                .with_context(|| "Failed to write file hashes")?;
+            metadata.file_hashes.clear();
+            metadata.file_hashes_list = Some(FILE_HASHES_LIST_NAME.to_string());
+        }
+
+        let metadata_path = package_dir.join(METADATA_PATH);
+        let metadata_content = metadata.to_canonical_yaml()?;
         fs::write(&metadata_path, metadata_content)
             .with_context(|| "Failed to write metadata file")?;
 
         // Create the .pax package (zstd-compressed tarball) with architecture in filename
         let package_filename = recipe.package_filename_for_arch(arch);
         let package_path = self.temp_dir.path().join(package_filename);
-        self.create_tarball(&package_dir, &package_path)?;
+        let mtime = if self.reproducible { Some(source_date_epoch()?) } else { None };
+        create_tarball(&package_dir, &package_path, &metadata.archive_owners(), self.compression_level, mtime)?;
 
         Ok(package_path)
     }
     
     /// Create package metadata for the installed package for a specific architecture
-    fn create_package_metadata_for_arch(&self, recipe: &BuildRecipe, arch: &str) -> Result<String> {
-        use serde_yaml;
-
-        #[derive(serde::Serialize)]
-        struct PackageMetadata {
-            name: String,
-            version: String,
-            description: String,
-            arch: Vec<String>,
-            dependencies: Vec<String>,
-            runtime_dependencies: Vec<String>,
-            provides: Vec<String>,
-            conflicts: Vec<String>,
-            install_script: Option<String>,
-            uninstall_script: Option<String>,
-            files: Vec<String>,
-        }
-
-        // List all files in the package
-        let install_dir = self.temp_dir.path().join("install");
-        let files = if install_dir.exists() {
-            self.list_files_recursive(&install_dir)?
+    fn create_package_metadata_for_arch(&self, recipe: &BuildRecipe, arch: &str) -> Result<PackageMetadata> {
+        // List all files in the package, with the SHA256 of each
+        let install_dir = self.install_dir(arch);
+        let paths = if install_dir.exists() {
+            self.packaged_file_paths(&install_dir, &Excludes::for_recipe(recipe)?, &Renames::for_recipe(recipe)?)?
         } else {
             Vec::new()
         };
+        let mut file_hashes = BTreeMap::new();
+        for (src, dst) in &paths {
+            let mut file = fs::File::open(install_dir.join(src))
+                .with_context(|| format!("Failed to open installed file: {}", src.display()))?;
+            let mut hasher = Sha256::new();
+            std::io::copy(&mut file, &mut hasher)
+                .with_context(|| format!("Failed to hash installed file: {}", src.display()))?;
+            file_hashes.insert(dst.to_string_lossy().to_string(), hex::encode(hasher.finalize()));
+        }
+        let files: Vec<String> = paths.into_iter()
+            .map(|(_, dst)| dst.to_string_lossy().to_string())
+            .collect();
+
+        let capabilities = self.relocated_keys(&recipe.capabilities);
+        if let Some(path) = capabilities.keys().find(|path| !files.contains(*path)) {
+            anyhow::bail!("Capabilities are set on '{}', which is not a file in the package", path);
+        }
 
         let metadata = PackageMetadata {
             name: recipe.name.clone(),
             version: recipe.version.clone(),
+            epoch: recipe.epoch,
             description: recipe.description.clone(),
             arch: vec![arch.to_string()], // Only include the target architecture
-            dependencies: recipe.dependencies.clone(),
-            runtime_dependencies: recipe.runtime_dependencies.clone(),
+            dependencies: recipe.dependencies_with_features(&self.features),
+            runtime_dependencies: recipe.runtime_dependencies_with_features(&self.features),
             provides: if recipe.provides.is_empty() {
-                vec![recipe.name.clone()]
+                vec![Provide::new(&recipe.name)]
             } else {
-                recipe.provides.clone()
+                recipe.provides.iter()
+                    .map(|provide| Provide::parse(provide))
+                    .collect::<Result<_>>()?
             },
             conflicts: recipe.conflicts.clone(),
+            replaces: recipe.replaces.clone(),
             install_script: recipe.install.clone(),
             uninstall_script: recipe.uninstall.clone(),
             files,
+            files_list: None,
+            extra: recipe.extra.iter()
+                .chain(&self.extra_metadata)
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect(),
+            installed_size: None,
+            file_hashes,
+            file_hashes_list: None,
+            capabilities,
+            users: recipe.users.clone(),
+            groups: recipe.groups.clone(),
+            file_owners: self.relocated_keys(&recipe.file_owners),
+            prefix_map: self.prefix_maps.iter().map(|prefix_map| prefix_map.to_string()).collect(),
         };
 
-        serde_yaml::to_string(&metadata)
-            .with_context(|| "Failed to serialize package metadata")
+        Ok(metadata)
     }
     
-    /// List files recursively from a directory
-    fn list_files_recursive(&self, dir: &Path) -> Result<Vec<String>> {
+    /// List files recursively from a directory, skipping excluded paths
+    fn list_files_recursive(&self, dir: &Path, excludes: &Excludes) -> Result<Vec<String>> {
         let mut files = Vec::new();
         
         if !dir.exists() {
             return Ok(files);
         }
         
-        for entry in walkdir::WalkDir::new(dir) {
+        let walker = walkdir::WalkDir::new(dir).into_iter()
+            .filter_entry(|entry| !entry.path().strip_prefix(dir).is_ok_and(|relative| excludes.matches(relative)));
+        for entry in walker {
             let entry = entry.with_context(|| "Failed to read directory entry")?;
             if entry.file_type().is_file() {
                 let relative_path = entry.path()
@@ -215,20 +912,86 @@ impl PackageBuilder {
         Ok(files)
     }
 
+    /// Map installed files to their paths inside the package, applying `package_strip`, renames and prefix maps
+    ///
+    /// A renamed file is packaged at its rename destination instead of its
+    /// stripped path; prefix maps then apply to either. Returns `(installed path, packaged path)` pairs relative
+    /// to `install_dir`.
+    fn packaged_file_paths(&self, install_dir: &Path, excludes: &Excludes, renames: &Renames) -> Result<Vec<(PathBuf, PathBuf)>> {
+        let mut seen = std::collections::HashMap::new();
+        let mut paths = Vec::new();
+
+        for file in self.list_files_recursive(install_dir, excludes)? {
+            let src = PathBuf::from(&file);
+            if let Some(dst) = renames.destination(&src) {
+                if let Some(previous) = seen.insert(dst.to_path_buf(), file.clone()) {
+                    anyhow::bail!("Renaming maps both '{}' and '{}' to '{}'", previous, file, dst.display());
+                }
+                paths.push((src, dst.to_path_buf()));
+                continue;
+            }
+
+            let dst: PathBuf = src.components().skip(self.package_strip).collect();
+
+            if dst.as_os_str().is_empty() {
+                anyhow::bail!(
+                    "Cannot strip {} path components from '{}': nothing would remain",
+                    self.package_strip, file
+                );
+            }
+            if let Some(previous) = seen.insert(dst.clone(), file.clone()) {
+                if self.package_strip == 0 {
+                    anyhow::bail!("Renaming maps both '{}' and '{}' to '{}'", previous, file, dst.display());
+                }
+                anyhow::bail!(
+                    "Stripping {} path components maps both '{}' and '{}' to '{}'",
+                    self.package_strip, previous, file, dst.display()
+                );
+            }
+
+            paths.push((src, dst));
+        }
+
+        if !self.prefix_maps.is_empty() {
+            let mut relocated = std::collections::HashMap::new();
+            for (src, dst) in &mut paths {
+                *dst = relocate(&self.prefix_maps, dst);
+                if let Some(previous) = relocated.insert(dst.clone(), src.clone()) {
+                    anyhow::bail!(
+                        "Prefix maps map both '{}' and '{}' to '{}'",
+                        previous.display(), src.display(), dst.display()
+                    );
+                }
+            }
+        }
+
+        Ok(paths)
+    }
+
+    /// Re-key a recipe's per-path table (capabilities, owners) by relocated path
+    fn relocated_keys(&self, table: &BTreeMap<String, String>) -> BTreeMap<String, String> {
+        table.iter()
+            .map(|(path, value)| (relocate(&self.prefix_maps, Path::new(path)).to_string_lossy().to_string(), value.clone()))
+            .collect()
+    }
+
     /// Copy directory recursively
     fn copy_directory(&self, src: &Path, dst: &Path) -> Result<()> {
+        self.copy_directory_excluding(src, src, dst, &Excludes::default())
+    }
+
+    /// Copy directory recursively, skipping paths under `root` that match `excludes`
+    ///
+    /// Mode bits are kept and symlinks inside the tree are recreated as
+    /// symlinks rather than followed, so executables stay executable and
+    /// library chains like `libfoo.so -> libfoo.so.1` survive.
+    fn copy_directory_excluding(&self, root: &Path, src: &Path, dst: &Path, excludes: &Excludes) -> Result<()> {
         if !src.exists() {
             return Ok(());
         }
         
         if src.is_file() {
-            if let Some(parent) = dst.parent() {
-                fs::create_dir_all(parent)
-                    .with_context(|| "Failed to create destination directory")?;
-            }
-            fs::copy(src, dst)
-                .with_context(|| "Failed to copy file")?;
-            return Ok(());
+            return copy_file(src, dst);
         }
         
         for entry in fs::read_dir(src)
@@ -236,97 +999,1307 @@ impl PackageBuilder {
             let entry = entry.with_context(|| "Failed to read directory entry")?;
             let src_path = entry.path();
             let dst_path = dst.join(entry.file_name());
+            if src_path.strip_prefix(root).is_ok_and(|relative| excludes.matches(relative)) {
+                continue;
+            }
+            let file_type = entry.file_type()
+                .with_context(|| format!("Failed to read file type of: {}", src_path.display()))?;
             
-            if src_path.is_dir() {
+            if file_type.is_symlink() {
+                copy_symlink(&src_path, &dst_path)?;
+            } else if file_type.is_dir() {
                 fs::create_dir_all(&dst_path)
                     .with_context(|| "Failed to create destination directory")?;
-                self.copy_directory(&src_path, &dst_path)?;
+                copy_xattrs(&src_path, &dst_path)?;
+                self.copy_directory_excluding(root, &src_path, &dst_path, excludes)?;
+                // Set last, so a read-only directory can still be filled
+                let mode = entry.metadata()
+                    .with_context(|| format!("Failed to read metadata of: {}", src_path.display()))?
+                    .permissions().mode();
+                fs::set_permissions(&dst_path, fs::Permissions::from_mode(mode))
+                    .with_context(|| format!("Failed to set permissions on: {}", dst_path.display()))?;
             } else {
-                if let Some(parent) = dst_path.parent() {
-                    fs::create_dir_all(parent)
-                        .with_context(|| "Failed to create destination directory")?;
-                }
-                fs::copy(&src_path, &dst_path)
-                    .with_context(|| "Failed to copy file")?;
+                copy_file(&src_path, &dst_path)?;
             }
         }
         
         Ok(())
     }
 
-    /// Create a zstd-compressed tarball
-    fn create_tarball(&self, src_dir: &Path, dst_path: &Path) -> Result<()> {
-        let output = Command::new("tar")
-            .arg("-cf")
-            .arg("-")
-            .arg("-C")
-            .arg(src_dir)
-            .arg(".")
-            .output()
-            .with_context(|| "Failed to create tar archive")?;
-        
-        if !output.status.success() {
-            anyhow::bail!("Failed to create tar archive");
-        }
-        
-        // Compress with zstd
-        let mut zstd_cmd = Command::new("zstd");
-        zstd_cmd.arg("-c")
-            .arg("-19") // High compression level
-            .stdin(std::process::Stdio::piped())
-            .stdout(std::process::Stdio::piped());
-        
-        let mut zstd_process = zstd_cmd.spawn()
-            .with_context(|| "Failed to start zstd process")?;
-        
-        if let Some(stdin) = zstd_process.stdin.take() {
-            std::io::Write::write_all(&mut std::io::BufWriter::new(stdin), &output.stdout)
-                .with_context(|| "Failed to write to zstd stdin")?;
-        }
-        
-        let zstd_output = zstd_process.wait_with_output()
-            .with_context(|| "Failed to wait for zstd process")?;
-        
-        if !zstd_output.status.success() {
-            anyhow::bail!("Failed to compress with zstd");
-        }
-        
-        fs::write(dst_path, zstd_output.stdout)
-            .with_context(|| format!("Failed to write compressed package: {}", dst_path.display()))?;
-        
-        Ok(())
-    }
-
     /// Get the temporary directory path
     pub fn temp_dir(&self) -> &Path {
         self.temp_dir.path()
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
+/// Copy a regular file with its mode bits and extended attributes
+fn copy_file(src: &Path, dst: &Path) -> Result<()> {
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| "Failed to create destination directory")?;
+    }
+    fs::copy(src, dst)
+        .with_context(|| "Failed to copy file")?;
+    let mode = fs::metadata(src)
+        .with_context(|| format!("Failed to read metadata of: {}", src.display()))?
+        .permissions().mode();
+    fs::set_permissions(dst, fs::Permissions::from_mode(mode))
+        .with_context(|| format!("Failed to set permissions on: {}", dst.display()))?;
+    copy_xattrs(src, dst)
+}
 
-    #[test]
-    fn test_copy_directory() {
-        let builder = PackageBuilder::new().unwrap();
-        
-        // Create test source directory
-        let src_dir = builder.temp_dir().join("src");
-        fs::create_dir_all(&src_dir).unwrap();
-        fs::write(src_dir.join("file1.txt"), "content1").unwrap();
-        
-        let sub_dir = src_dir.join("subdir");
-        fs::create_dir_all(&sub_dir).unwrap();
-        fs::write(sub_dir.join("file2.txt"), "content2").unwrap();
-        
-        // Copy to destination
-        let dst_dir = builder.temp_dir().join("dst");
-        builder.copy_directory(&src_dir, &dst_dir).unwrap();
-        
-        // Verify copy
-        assert!(dst_dir.join("file1.txt").exists());
-        assert!(dst_dir.join("subdir").join("file2.txt").exists());
+/// Recreate the symlink `src` at `dst` with the same target, replacing whatever is there
+fn copy_symlink(src: &Path, dst: &Path) -> Result<()> {
+    let target = fs::read_link(src)
+        .with_context(|| format!("Failed to read symlink: {}", src.display()))?;
+    if fs::symlink_metadata(dst).is_ok() {
+        fs::remove_file(dst)
+            .with_context(|| format!("Failed to replace: {}", dst.display()))?;
+    }
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| "Failed to create destination directory")?;
+    }
+    std::os::unix::fs::symlink(&target, dst)
+        .with_context(|| format!("Failed to create symlink: {}", dst.display()))
+}
+
+/// Copy the extended attributes (including ACLs) of `src` to `dst`; `fs::copy` drops them
+fn copy_xattrs(src: &Path, dst: &Path) -> Result<()> {
+    if !xattr::SUPPORTED_PLATFORM {
+        return Ok(());
+    }
+
+    let names = xattr::list(src)
+        .with_context(|| format!("Failed to list extended attributes of: {}", src.display()))?;
+    for name in names {
+        if let Some(value) = xattr::get(src, &name)
+            .with_context(|| format!("Failed to read extended attribute {:?} of: {}", name, src.display()))? {
+            xattr::set(dst, &name, &value)
+                .with_context(|| format!("Failed to set extended attribute {:?} on: {}", name, dst.display()))?;
+        }
+    }
+    Ok(())
+}
+
+/// Create a zstd-compressed tarball, recording `owners` in the headers of those members
+///
+/// Members are named relative to `src_dir` (`usr/`, `usr/bin/tool`), sorted
+/// by path and owned by root unless `owners` says otherwise. Mode bits,
+/// modification times, extended attributes, symlinks and hard links are kept.
+///
+/// With an `mtime`, the archive is reproducible: every entry gets that mtime, and
+/// no user or group names unless `owners` names them.
+pub(crate) fn create_tarball(src_dir: &Path, dst_path: &Path, owners: &BTreeMap<String, ArchiveOwner>, level: i32, mtime: Option<u64>) -> Result<()> {
+    if !ZSTD_LEVELS.contains(&level) {
+        anyhow::bail!("Invalid compression level {}: zstd levels are {} to {}", level, ZSTD_LEVELS.start(), ZSTD_LEVELS.end());
+    }
+    let mut tar_data = write_tar(src_dir, mtime)?;
+
+    // Duplicate paths confuse extractors, which silently keep either copy
+    if let Some(path) = find_duplicate_entry(&tar_data)? {
+        anyhow::bail!("Duplicate entry in package archive: {}", path);
+    }
+
+    // Owners may not exist on the build host, so their names are written into the headers afterwards
+    ownership::set_archive_owners(&mut tar_data, owners)?;
+    
+    let compressed = Compression::Zstd.compress(&tar_data, level)?;
+    
+    fs::write(dst_path, compressed)
+        .with_context(|| format!("Failed to write compressed package: {}", dst_path.display()))?;
+    
+    Ok(())
+}
+
+/// Timestamp for reproducible archives: `SOURCE_DATE_EPOCH`, or 0 when unset
+fn source_date_epoch() -> Result<u64> {
+    match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(value) if !value.is_empty() => value.trim().parse()
+            .with_context(|| format!("Invalid SOURCE_DATE_EPOCH '{}': expected seconds since the epoch", value)),
+        _ => Ok(0),
+    }
+}
+
+/// Archive a directory tree as an uncompressed tar stream, entries sorted by path
+fn write_tar(src_dir: &Path, mtime: Option<u64>) -> Result<Vec<u8>> {
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut hard_links: HashMap<(u64, u64), PathBuf> = HashMap::new();
+
+    for entry in walkdir::WalkDir::new(src_dir).min_depth(1).sort_by_file_name() {
+        let entry = entry.with_context(|| format!("Failed to walk directory: {}", src_dir.display()))?;
+        let name = entry.path().strip_prefix(src_dir).unwrap_or(entry.path()).to_path_buf();
+        let metadata = entry.path().symlink_metadata()
+            .with_context(|| format!("Failed to read metadata of: {}", entry.path().display()))?;
+        let file_type = metadata.file_type();
+
+        let mut header = tar::Header::new_ustar();
+        header.set_mode(metadata.mode() & 0o7777);
+        header.set_mtime(mtime.unwrap_or(metadata.mtime().max(0) as u64));
+        header.set_uid(0);
+        header.set_gid(0);
+        let owner_name = if mtime.is_some() { "" } else { "root" };
+        header.set_username(owner_name)
+            .and_then(|()| header.set_groupname(owner_name))
+            .with_context(|| "Failed to set archive owner")?;
+        header.set_size(0);
+
+        // Extended attributes and ACLs (SELinux contexts, capabilities) go into PAX headers
+        let xattrs = read_xattrs(entry.path())?;
+        if !xattrs.is_empty() {
+            builder.append_pax_extensions(xattrs.iter().map(|(key, value)| (key.as_str(), value.as_slice())))
+                .with_context(|| format!("Failed to write extended attributes of: {}", entry.path().display()))?;
+        }
+
+        let written = if file_type.is_dir() {
+            header.set_entry_type(tar::EntryType::Directory);
+            // Directory members end in '/', as tar writes them
+            let mut name = name.into_os_string();
+            name.push("/");
+            builder.append_data(&mut header, name, std::io::empty())
+        } else if file_type.is_symlink() {
+            let target = fs::read_link(entry.path())
+                .with_context(|| format!("Failed to read symlink: {}", entry.path().display()))?;
+            header.set_entry_type(tar::EntryType::Symlink);
+            builder.append_link(&mut header, &name, target)
+        } else if file_type.is_file() {
+            // Further names of a multiply linked file become hard link members
+            let inode = (metadata.dev(), metadata.ino());
+            match hard_links.get(&inode).filter(|_| metadata.nlink() > 1) {
+                Some(first) => {
+                    header.set_entry_type(tar::EntryType::Link);
+                    builder.append_link(&mut header, &name, first)
+                }
+                None => {
+                    if metadata.nlink() > 1 {
+                        hard_links.insert(inode, name.clone());
+                    }
+                    header.set_entry_type(tar::EntryType::Regular);
+                    header.set_size(metadata.len());
+                    let file = fs::File::open(entry.path())
+                        .with_context(|| format!("Failed to open: {}", entry.path().display()))?;
+                    builder.append_data(&mut header, &name, file)
+                }
+            }
+        } else {
+            anyhow::bail!("Cannot package special file: {}", entry.path().display());
+        };
+        written.with_context(|| format!("Failed to add to archive: {}", entry.path().display()))?;
+    }
+
+    builder.into_inner()
+        .with_context(|| "Failed to finish tar archive")
+}
+
+/// Extended attributes of a file (not following symlinks) as `SCHILY.xattr.*` PAX records
+fn read_xattrs(path: &Path) -> Result<Vec<(String, Vec<u8>)>> {
+    if !xattr::SUPPORTED_PLATFORM {
+        return Ok(Vec::new());
+    }
+
+    let mut records = Vec::new();
+    let names = xattr::list(path)
+        .with_context(|| format!("Failed to list extended attributes of: {}", path.display()))?;
+    for name in names {
+        if let Some(value) = xattr::get(path, &name)
+            .with_context(|| format!("Failed to read extended attribute {:?} of: {}", name, path.display()))? {
+            records.push((format!("SCHILY.xattr.{}", name.to_string_lossy()), value));
+        }
+    }
+    records.sort();
+    Ok(records)
+}
+
+/// Compiled exclude patterns of a recipe
+#[derive(Debug, Default)]
+struct Excludes(Vec<glob::Pattern>);
+
+impl Excludes {
+    fn for_recipe(recipe: &BuildRecipe) -> Result<Self> {
+        recipe.exclude_patterns().into_iter()
+            .map(|pattern| glob::Pattern::new(pattern)
+                .with_context(|| format!("Invalid exclude pattern: '{}'", pattern)))
+            .collect::<Result<_>>()
+            .map(Excludes)
+    }
+
+    /// Whether a path relative to the install root, or any of its components, matches a pattern
+    fn matches(&self, relative: &Path) -> bool {
+        self.0.iter().any(|pattern| {
+            pattern.matches_path(relative)
+                || relative.components().any(|component| pattern.matches(&component.as_os_str().to_string_lossy()))
+        })
+    }
+}
+
+/// Compiled rename patterns of a recipe, each with its packaged destination
+#[derive(Debug, Default)]
+struct Renames(Vec<(glob::Pattern, PathBuf)>);
+
+impl Renames {
+    fn for_recipe(recipe: &BuildRecipe) -> Result<Self> {
+        recipe.rename.iter()
+            .map(|(pattern, destination)| glob::Pattern::new(pattern)
+                .map(|pattern| (pattern, PathBuf::from(destination)))
+                .with_context(|| format!("Invalid rename pattern: '{}'", pattern)))
+            .collect::<Result<_>>()
+            .map(Renames)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Packaged path of an installed file (relative to the install root), if a pattern matches it
+    fn destination(&self, relative: &Path) -> Option<&Path> {
+        self.0.iter()
+            .find(|(pattern, _)| pattern.matches_path(relative))
+            .map(|(_, destination)| destination.as_path())
+    }
+}
+
+/// A build-time relocation of packaged paths, written `/usr=/usr/local`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrefixMap {
+    /// Prefix as installed, relative to the install root
+    pub from: PathBuf,
+    /// Prefix to package it under, relative to the package root
+    pub to: PathBuf,
+}
+
+impl PrefixMap {
+    /// Parse `FROM=TO`; both sides are absolute or relative to the root, without `..`
+    pub fn parse(value: &str) -> Result<Self> {
+        let (from, to) = value.split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("Invalid prefix map '{}': expected FROM=TO", value))?;
+        let prefix = |side: &str| -> Result<PathBuf> {
+            let path: PathBuf = Path::new(side).components()
+                .filter(|component| !matches!(component, std::path::Component::RootDir | std::path::Component::CurDir))
+                .collect();
+            if path.as_os_str().is_empty() || path.components().any(|component| !matches!(component, std::path::Component::Normal(_))) {
+                anyhow::bail!("Invalid prefix map '{}': '{}' must name a directory below the root", value, side);
+            }
+            Ok(path)
+        };
+        Ok(PrefixMap { from: prefix(from)?, to: prefix(to)? })
+    }
+}
+
+impl std::fmt::Display for PrefixMap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "/{}=/{}", self.from.display(), self.to.display())
+    }
+}
+
+/// Apply the most specific prefix map whose prefix covers `path` (whole components only)
+fn relocate(prefix_maps: &[PrefixMap], path: &Path) -> PathBuf {
+    prefix_maps.iter()
+        .filter(|prefix_map| path.starts_with(&prefix_map.from))
+        .max_by_key(|prefix_map| prefix_map.from.components().count())
+        .and_then(|prefix_map| path.strip_prefix(&prefix_map.from).ok().map(|rest| prefix_map.to.join(rest)))
+        .unwrap_or_else(|| path.to_path_buf())
+}
+
+/// Replace `$NAME` and `${NAME}` references to the given variables; others are left as written
+fn substitute_env(script: &str, env: &[(String, String)]) -> String {
+    let lookup = |name: &str| env.iter().rev().find(|(key, _)| key == name).map(|(_, value)| value.as_str());
+    let is_name_char = |c: char| c.is_ascii_alphanumeric() || c == '_';
+
+    let mut resolved = String::with_capacity(script.len());
+    let mut rest = script;
+    while let Some(dollar) = rest.find('$') {
+        resolved.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, reference_len) = if let Some(braced) = after.strip_prefix('{') {
+            match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            }
+        } else {
+            let end = after.find(|c: char| !is_name_char(c)).unwrap_or(after.len());
+            (&after[..end], end)
+        };
+
+        match lookup(name).filter(|_| !name.is_empty()) {
+            Some(value) => {
+                resolved.push_str(value);
+                rest = &after[reference_len..];
+            }
+            None => {
+                resolved.push('$');
+                rest = after;
+            }
+        }
+    }
+    resolved.push_str(rest);
+    resolved
+}
+
+/// Number of CPUs available to the build, like `nproc`
+fn default_build_jobs() -> usize {
+    std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+}
+
+/// Find the first path that occurs more than once in an uncompressed tar archive
+fn find_duplicate_entry(tar_data: &[u8]) -> Result<Option<String>> {
+    let mut archive = tar::Archive::new(tar_data);
+    let mut seen = std::collections::HashSet::new();
+
+    for entry in archive.entries().with_context(|| "Failed to read tar archive")? {
+        let entry = entry.with_context(|| "Failed to read tar entry")?;
+        let path = entry.path().with_context(|| "Invalid path in tar entry")?;
+        let path = path.to_string_lossy();
+        let path = path.trim_start_matches("./").trim_end_matches('/');
+        if path.is_empty() || path == "." {
+            continue;
+        }
+        if !seen.insert(path.to_string()) {
+            return Ok(Some(path.to_string()));
+        }
+    }
+
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_copy_directory() {
+        let builder = PackageBuilder::new().unwrap();
+        
+        // Create test source directory
+        let src_dir = builder.temp_dir().join("src");
+        fs::create_dir_all(&src_dir).unwrap();
+        fs::write(src_dir.join("file1.txt"), "content1").unwrap();
+        
+        let sub_dir = src_dir.join("subdir");
+        fs::create_dir_all(&sub_dir).unwrap();
+        fs::write(sub_dir.join("file2.txt"), "content2").unwrap();
+        
+        // Copy to destination
+        let dst_dir = builder.temp_dir().join("dst");
+        builder.copy_directory(&src_dir, &dst_dir).unwrap();
+        
+        // Verify copy
+        assert!(dst_dir.join("file1.txt").exists());
+        assert!(dst_dir.join("subdir").join("file2.txt").exists());
+    }
+
+    #[test]
+    fn test_copy_directory_keeps_modes_and_symlinks() {
+        let builder = PackageBuilder::new().unwrap();
+        let src_dir = builder.temp_dir().join("src");
+        fs::create_dir_all(src_dir.join("usr/bin")).unwrap();
+        fs::create_dir_all(src_dir.join("usr/lib")).unwrap();
+        fs::write(src_dir.join("usr/bin/tool"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(src_dir.join("usr/bin/tool"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::write(src_dir.join("usr/lib/libfoo.so.1"), "elf").unwrap();
+        std::os::unix::fs::symlink("libfoo.so.1", src_dir.join("usr/lib/libfoo.so")).unwrap();
+        std::os::unix::fs::symlink("missing", src_dir.join("usr/lib/dangling")).unwrap();
+
+        let dst_dir = builder.temp_dir().join("dst");
+        builder.copy_directory(&src_dir, &dst_dir).unwrap();
+
+        let tool = fs::metadata(dst_dir.join("usr/bin/tool")).unwrap();
+        assert_eq!(tool.permissions().mode() & 0o7777, 0o755);
+        let link = dst_dir.join("usr/lib/libfoo.so");
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_link(&link).unwrap(), Path::new("libfoo.so.1"));
+        assert_eq!(fs::read_link(dst_dir.join("usr/lib/dangling")).unwrap(), Path::new("missing"));
+    }
+
+    fn test_recipe() -> BuildRecipe {
+        BuildRecipe::from_yaml(r#"
+name: strip-test
+version: 1.0.0
+description: Test package
+source: https://example.com/strip-test-1.0.0.tar.gz
+"#).unwrap()
+    }
+
+    #[test]
+    fn test_package_strip() {
+        let builder = PackageBuilder::new().unwrap().with_package_strip(1);
+
+        let install_dir = builder.temp_dir().join("install/x86_64");
+        fs::create_dir_all(install_dir.join("usr/bin")).unwrap();
+        fs::create_dir_all(install_dir.join("usr/share/doc")).unwrap();
+        fs::write(install_dir.join("usr/bin/tool"), "binary").unwrap();
+        fs::write(install_dir.join("usr/share/doc/README"), "docs").unwrap();
+
+        let package_path = builder.create_package_for_arch(&test_recipe(), "x86_64").unwrap();
+
+        let extract_dir = builder.temp_dir().join("extracted");
+        let mut package = crate::package::PaxPackage::open(&package_path).unwrap();
+        package.extract_to(&extract_dir).unwrap();
+        assert!(extract_dir.join("bin/tool").exists());
+        assert!(extract_dir.join("share/doc/README").exists());
+        assert!(!extract_dir.join("usr").exists());
+
+        let mut files = package.load_metadata().unwrap().files.clone();
+        files.sort();
+        assert_eq!(files, vec!["bin/tool", "share/doc/README"]);
+    }
+
+    #[test]
+    fn test_package_strip_rejects_collisions_and_empty_paths() {
+        let builder = PackageBuilder::new().unwrap().with_package_strip(1);
+        let install_dir = builder.temp_dir().join("install/x86_64");
+        fs::create_dir_all(install_dir.join("a")).unwrap();
+        fs::create_dir_all(install_dir.join("b")).unwrap();
+        fs::write(install_dir.join("a/config"), "a").unwrap();
+        fs::write(install_dir.join("b/config"), "b").unwrap();
+
+        let err = builder.packaged_file_paths(&install_dir, &Excludes::default(), &Renames::default()).unwrap_err();
+        assert!(err.to_string().contains("maps both"));
+
+        let builder = PackageBuilder::new().unwrap().with_package_strip(1);
+        let install_dir = builder.temp_dir().join("install/x86_64");
+        fs::create_dir_all(&install_dir).unwrap();
+        fs::write(install_dir.join("top-level-file"), "x").unwrap();
+
+        let err = builder.packaged_file_paths(&install_dir, &Excludes::default(), &Renames::default()).unwrap_err();
+        assert!(err.to_string().contains("nothing would remain"));
+    }
+
+    #[test]
+    fn test_prefix_map_relocates_packaged_paths() {
+        let builder = PackageBuilder::new().unwrap()
+            .with_prefix_maps(vec![PrefixMap::parse("/usr=/opt/app").unwrap()]);
+
+        let install_dir = builder.temp_dir().join("install/x86_64");
+        fs::create_dir_all(install_dir.join("usr/bin")).unwrap();
+        fs::create_dir_all(install_dir.join("usr/share/doc")).unwrap();
+        fs::create_dir_all(install_dir.join("usrlocal")).unwrap();
+        fs::write(install_dir.join("usr/bin/tool"), "binary").unwrap();
+        fs::write(install_dir.join("usr/share/doc/README"), "docs").unwrap();
+        fs::write(install_dir.join("usrlocal/keep"), "not under /usr").unwrap();
+
+        let package_path = builder.create_package_for_arch(&test_recipe(), "x86_64").unwrap();
+
+        let extract_dir = builder.temp_dir().join("extracted");
+        let mut package = crate::package::PaxPackage::open(&package_path).unwrap();
+        package.extract_to(&extract_dir).unwrap();
+        assert!(extract_dir.join("opt/app/bin/tool").exists());
+        assert!(extract_dir.join("opt/app/share/doc/README").exists());
+        assert!(extract_dir.join("usrlocal/keep").exists());
+        assert!(!extract_dir.join("usr").exists());
+
+        let metadata = package.load_metadata().unwrap();
+        let mut files = metadata.files.clone();
+        files.sort();
+        assert_eq!(files, vec!["opt/app/bin/tool", "opt/app/share/doc/README", "usrlocal/keep"]);
+        assert_eq!(metadata.prefix_map, vec!["/usr=/opt/app"]);
+
+        assert!(PrefixMap::parse("/usr").is_err());
+        assert!(PrefixMap::parse("/usr=/").is_err());
+        assert!(PrefixMap::parse("/usr=/opt/../etc").is_err());
+    }
+
+    #[test]
+    fn test_large_file_list_stored_separately() {
+        let builder = PackageBuilder::new().unwrap().with_files_list_threshold(Some(100));
+
+        let data_dir = builder.temp_dir().join("install/x86_64/usr/share/data");
+        fs::create_dir_all(&data_dir).unwrap();
+        for i in 0..2000 {
+            fs::write(data_dir.join(format!("file-{:04}.dat", i)), "").unwrap();
+        }
+
+        let package_path = builder.create_package_for_arch(&test_recipe(), "x86_64").unwrap();
+
+        let extract_dir = builder.temp_dir().join("extracted");
+        let mut package = crate::package::PaxPackage::open(&package_path).unwrap();
+        package.extract_to(&extract_dir).unwrap();
+        assert!(fs::metadata(extract_dir.join(METADATA_PATH)).unwrap().len() < 1024);
+        assert!(extract_dir.join(FILES_LIST_NAME).exists());
+
+        let metadata = package.load_metadata().unwrap();
+        assert_eq!(metadata.files.len(), 2000);
+        assert!(metadata.files.contains(&"usr/share/data/file-0042.dat".to_string()));
+        assert_eq!(metadata.file_hashes.len(), 2000);
+        assert!(package.file_hash_mismatches().unwrap().is_empty());
+
+        crate::info::show_info(package_path.to_str().unwrap(), false, false).unwrap();
+    }
+
+    #[test]
+    fn test_metadata_bytes_are_stable() {
+        let recipe = BuildRecipe::from_yaml(r#"
+name: stable
+version: 1.0.0
+description: Test package
+source: https://example.com/stable-1.0.0.tar.gz
+dependencies: [zlib, make]
+provides: [stable-bin, stable]
+"#).unwrap();
+
+        let build = |names: &[&str]| {
+            let builder = PackageBuilder::new().unwrap();
+            let bin_dir = builder.temp_dir().join("install/x86_64/usr/bin");
+            fs::create_dir_all(&bin_dir).unwrap();
+            for name in names {
+                fs::write(bin_dir.join(name), name).unwrap();
+            }
+
+            let package_path = builder.create_package_for_arch(&recipe, "x86_64").unwrap();
+            let extract_dir = builder.temp_dir().join("extracted");
+            crate::package::PaxPackage::open(&package_path).unwrap().extract_to(&extract_dir).unwrap();
+            fs::read(extract_dir.join(METADATA_PATH)).unwrap()
+        };
+
+        let first = build(&["b", "c", "a"]);
+        let second = build(&["c", "a", "b"]);
+        assert_eq!(first, second);
+
+        let metadata: PackageMetadata = serde_yaml::from_slice(&first).unwrap();
+        assert_eq!(metadata.files, vec!["usr/bin/a", "usr/bin/b", "usr/bin/c"]);
+        assert_eq!(metadata.dependencies, vec!["make", "zlib"]);
+    }
+
+    #[test]
+    fn test_versioned_provides_round_trip() {
+        let recipe = BuildRecipe::from_yaml(r#"
+name: libfoo
+version: 1.2.0
+description: Test package
+source: https://example.com/libfoo-1.2.0.tar.gz
+provides:
+  - libfoo
+  - foo-api=1.2
+"#).unwrap();
+        recipe.validate().unwrap();
+
+        let builder = PackageBuilder::new().unwrap();
+        fs::create_dir_all(builder.temp_dir().join("install/x86_64")).unwrap();
+        let package_path = builder.create_package_for_arch(&recipe, "x86_64").unwrap();
+
+        let mut package = crate::package::PaxPackage::open(&package_path).unwrap();
+        let metadata = package.load_metadata().unwrap();
+        assert_eq!(metadata.provides, vec![
+            Provide { name: "foo-api".to_string(), version: Some("1.2".to_string()) },
+            Provide::new("libfoo"),
+        ]);
+    }
+
+    #[test]
+    fn test_enabled_feature_adds_dependency_and_env_var() {
+        let recipe = BuildRecipe::from_yaml(r#"
+name: feature-test
+version: 1.0.0
+description: Test package
+source: https://example.com/feature-test-1.0.0.tar.gz
+runtime_dependencies:
+  - libc.so.6
+features:
+  ssl:
+    runtime_dependencies:
+      - libssl.so.3
+  gui:
+    runtime_dependencies:
+      - libgtk-4.so.1
+build: |
+  echo "ssl=$PAX_FEATURE_SSL gui=$PAX_FEATURE_GUI" > $PAX_BUILD_ROOT/features
+"#).unwrap();
+
+        let builder = PackageBuilder::new().unwrap().with_features(vec!["ssl".to_string()]);
+        let source_dir = builder.temp_dir().join("source");
+        fs::create_dir_all(&source_dir).unwrap();
+
+        builder.run_build_script_for_arch(&recipe, &source_dir, "x86_64", false).unwrap();
+        let features = fs::read_to_string(builder.temp_dir().join("install/x86_64/features")).unwrap();
+        assert_eq!(features.trim(), "ssl=1 gui=");
+
+        let metadata = builder.create_package_metadata_for_arch(&recipe, "x86_64").unwrap();
+        assert_eq!(metadata.runtime_dependencies, vec!["libc.so.6", "libssl.so.3"]);
+
+        let builder = PackageBuilder::new().unwrap().with_features(vec!["vulkan".to_string()]);
+        assert!(builder.build_for_architectures(&recipe, &["x86_64".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_build_phases_run_in_order_and_report_failures() {
+        let source_dir = TempDir::new().unwrap();
+        let mut recipe = BuildRecipe::from_yaml(r#"
+name: phased
+version: 1.0.0
+description: Test package
+source: https://example.com/phased-1.0.0.tar.gz
+arch: [x86_64]
+phases:
+  prepare: echo "prepare $PAX_ARCH" >> phases.log
+  build: echo "build $PAX_PACKAGE_NAME" >> phases.log
+  install: |
+    echo install >> phases.log
+    mkdir -p $PAX_BUILD_ROOT/usr/share/phased
+    cp phases.log $PAX_BUILD_ROOT/usr/share/phased/
+"#).unwrap();
+        let arches = ["x86_64".to_string()];
+
+        let builder = PackageBuilder::new().unwrap().with_source_dir(Some(source_dir.path().to_path_buf()));
+        let package_path = builder.build_for_architectures(&recipe, &arches).unwrap().remove(0);
+        let extract_dir = builder.temp_dir().join("extracted");
+        crate::package::PaxPackage::open(&package_path).unwrap().extract_to(&extract_dir).unwrap();
+        assert_eq!(
+            fs::read_to_string(extract_dir.join("usr/share/phased/phases.log")).unwrap(),
+            "prepare x86_64\nbuild phased\ninstall\n"
+        );
+
+        // A failing phase is named, and later phases don't run
+        recipe.phases.as_mut().unwrap().build = Some("echo 'cc: fatal error' >&2; exit 3".to_string());
+        let builder = PackageBuilder::new().unwrap().with_source_dir(Some(source_dir.path().to_path_buf()));
+        let error = builder.build_for_architectures(&recipe, &arches).unwrap_err();
+        assert!(error.to_string().contains("The build phase failed"), "{}", error);
+        assert!(error.to_string().contains("cc: fatal error"), "{}", error);
+        assert!(!builder.temp_dir().join("install/x86_64/usr/share/phased").exists());
+
+        recipe.build = Some("make".to_string());
+        assert!(recipe.validate().unwrap_err().to_string().contains("not both"));
+    }
+
+    #[test]
+    fn test_build_script_timeout_kills_process_group() {
+        let source_dir = TempDir::new().unwrap();
+        let recipe = BuildRecipe::from_yaml(r#"
+name: hangs
+version: 1.0.0
+description: Test package
+source: https://example.com/hangs-1.0.0.tar.gz
+arch: [x86_64]
+build: |
+  (sleep 2; touch $PAX_BUILD_DIR/survived) &
+  sleep 5
+"#).unwrap();
+
+        let builder = PackageBuilder::new().unwrap()
+            .with_source_dir(Some(source_dir.path().to_path_buf()))
+            .with_timeout(Some(1));
+        let started = Instant::now();
+        let error = builder.build_for_architectures(&recipe, &["x86_64".to_string()]).unwrap_err();
+        assert!(error.to_string().contains("timed out after 1 seconds"), "{}", error);
+        assert!(started.elapsed() < std::time::Duration::from_secs(4));
+
+        // The background job the script started was killed with it
+        std::thread::sleep(std::time::Duration::from_secs(2));
+        assert!(!builder.temp_dir().join("build/x86_64/survived").exists());
+    }
+
+    #[test]
+    fn test_build_log_written_per_architecture() {
+        let source_dir = TempDir::new().unwrap();
+        let log_dir = TempDir::new().unwrap();
+        let recipe = BuildRecipe::from_yaml(r#"
+name: logged
+version: 1.0.0
+description: Test package
+source: https://example.com/logged-1.0.0.tar.gz
+arch: [x86_64, aarch64]
+build: |
+  echo "compiling for $PAX_ARCH"
+  echo "warning: unused variable" >&2
+  test "$PAX_ARCH" = x86_64
+"#).unwrap();
+
+        let builder = PackageBuilder::new().unwrap()
+            .with_source_dir(Some(source_dir.path().to_path_buf()))
+            .with_log_dir(Some(log_dir.path().to_path_buf()));
+        let error = builder.build_for_architectures(&recipe, &recipe.arch).unwrap_err();
+        assert!(error.to_string().contains("aarch64"), "{}", error);
+
+        // Successful and failed builds are both logged
+        let x86_64 = fs::read_to_string(log_dir.path().join("build-x86_64.log")).unwrap();
+        assert!(x86_64.contains("compiling for x86_64"), "{}", x86_64);
+        assert!(x86_64.contains("exit status: 0"), "{}", x86_64);
+        let aarch64 = fs::read_to_string(log_dir.path().join("build-aarch64.log")).unwrap();
+        for expected in ["Command: bash -c", "PAX_ARCH=aarch64", "test \"$PAX_ARCH\" = x86_64", "compiling for aarch64", "warning: unused variable", "exit status: 1"] {
+            assert!(aarch64.contains(expected), "missing {:?} in:\n{}", expected, aarch64);
+        }
+    }
+
+    #[test]
+    fn test_each_architecture_packages_only_its_own_install_dir() {
+        let source_dir = TempDir::new().unwrap();
+        let recipe = BuildRecipe::from_yaml(r#"
+name: perarch
+version: 1.0.0
+description: Test package
+source: https://example.com/perarch-1.0.0.tar.gz
+arch: [x86_64, aarch64]
+build: touch $PAX_BUILD_ROOT/only-$PAX_ARCH
+"#).unwrap();
+
+        let builder = PackageBuilder::new().unwrap().with_source_dir(Some(source_dir.path().to_path_buf()));
+        let package_paths = builder.build_for_architectures(&recipe, &recipe.arch).unwrap();
+        assert_eq!(package_paths.len(), 2);
+        for (arch, package_path) in recipe.arch.iter().zip(&package_paths) {
+            let mut package = crate::package::PaxPackage::open(package_path).unwrap();
+            assert_eq!(package.load_metadata().unwrap().files, vec![format!("only-{}", arch)], "{}", arch);
+        }
+    }
+
+    #[test]
+    fn test_check_script_runs_before_packaging_unless_skipped() {
+        let source_dir = TempDir::new().unwrap();
+        fs::write(source_dir.path().join("expected"), "42").unwrap();
+        let mut recipe = BuildRecipe::from_yaml(r#"
+name: checked
+version: 1.0.0
+description: Test package
+source: https://example.com/checked-1.0.0.tar.gz
+arch: [x86_64]
+build: mkdir -p $PAX_BUILD_ROOT/usr/share && echo 42 > $PAX_BUILD_ROOT/usr/share/answer
+check: test "$(cat $PAX_BUILD_ROOT/usr/share/answer)" = "$(cat expected)" && touch $PAX_BUILD_DIR/checked
+"#).unwrap();
+        let arches = ["x86_64".to_string()];
+
+        let builder = PackageBuilder::new().unwrap().with_source_dir(Some(source_dir.path().to_path_buf()));
+        builder.build_for_architectures(&recipe, &arches).unwrap();
+        assert!(builder.temp_dir().join("build/x86_64/checked").exists());
+
+        recipe.check = Some("echo 'test_answer failed' >&2; exit 1".to_string());
+        let builder = PackageBuilder::new().unwrap().with_source_dir(Some(source_dir.path().to_path_buf()));
+        let error = builder.build_for_architectures(&recipe, &arches).unwrap_err();
+        assert!(error.to_string().contains("test_answer failed"), "{}", error);
+
+        let builder = PackageBuilder::new().unwrap()
+            .with_source_dir(Some(source_dir.path().to_path_buf()))
+            .with_skip_check(true);
+        assert_eq!(builder.build_for_architectures(&recipe, &arches).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_resolved_build_script_substitutes_variables_and_build_system() {
+        let source_dir = TempDir::new().unwrap();
+        fs::write(source_dir.path().join("meson.build"), "").unwrap();
+        let mut recipe = BuildRecipe::from_yaml(r#"
+name: printed
+version: 1.0.0
+description: Test package
+source: https://example.com/printed-1.0.0.tar.gz
+arch: [x86_64, aarch64]
+"#).unwrap();
+
+        let builder = PackageBuilder::new().unwrap().with_source_dir(Some(source_dir.path().to_path_buf()));
+        let build_dir = builder.temp_dir().join("build/aarch64");
+        let scripts = builder.resolved_build_scripts(&recipe, &recipe.arch).unwrap();
+        assert_eq!(scripts.len(), 2);
+        let (arch, description, script) = &scripts[1];
+        assert_eq!(arch, "aarch64");
+        assert_eq!(description, "default meson build script (detected)");
+        assert!(script.starts_with(&format!("meson setup \"{}\"", build_dir.display())), "{}", script);
+        assert!(!script.contains("$PAX_"), "{}", script);
+
+        recipe.env.insert("CFLAGS".to_string(), "-O2".to_string());
+        recipe.build = Some("make CFLAGS=\"${CFLAGS}\" NAME=$PAX_PACKAGE_NAME-$PAX_ARCH HOME=$HOME $".to_string());
+        let (_, description, script) = builder.resolved_build_scripts(&recipe, &["x86_64".to_string()]).unwrap().remove(0);
+        assert_eq!(description, "recipe build script");
+        assert_eq!(script, "make CFLAGS=\"-O2\" NAME=printed-x86_64 HOME=$HOME $");
+    }
+
+    #[test]
+    fn test_clean_env_drops_host_variables() {
+        std::env::set_var("PAXBUILD_TEST_HOST_LEAK", "leaked");
+        let recipe = BuildRecipe::from_yaml(r#"
+name: env-test
+version: 1.0.0
+description: Test package
+source: https://example.com/env-test-1.0.0.tar.gz
+env:
+  CFLAGS: -O2
+build: |
+  echo "host=$PAXBUILD_TEST_HOST_LEAK cflags=$CFLAGS arch=$PAX_ARCH path=${PATH:+set}" > $PAX_BUILD_ROOT/env
+"#).unwrap();
+
+        for (clean_env, expected) in [(false, "host=leaked cflags=-O2 arch=x86_64 path=set"), (true, "host= cflags=-O2 arch=x86_64 path=set")] {
+            let builder = PackageBuilder::new().unwrap().with_clean_env(clean_env);
+            let source_dir = builder.temp_dir().join("source");
+            fs::create_dir_all(&source_dir).unwrap();
+
+            builder.run_build_script_for_arch(&recipe, &source_dir, "x86_64", false).unwrap();
+            let env = fs::read_to_string(builder.temp_dir().join("install/x86_64/env")).unwrap();
+            assert_eq!(env.trim(), expected);
+        }
+    }
+
+    #[test]
+    fn test_duplicate_tar_entries_detected() {
+        let tar_data = crate::test_support::tar_archive(&[
+            ("./usr/bin/hello", b"one", 0o644),
+            ("./usr/lib/libhello.so", b"lib", 0o644),
+            ("usr/bin/hello", b"two", 0o644),
+        ]);
+        assert_eq!(find_duplicate_entry(&tar_data).unwrap().as_deref(), Some("usr/bin/hello"));
+
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir_all(temp_dir.path().join("pkg/usr/bin")).unwrap();
+        fs::write(temp_dir.path().join("pkg/usr/bin/hello"), "one").unwrap();
+        create_tarball(&temp_dir.path().join("pkg"), &temp_dir.path().join("out.pax"), &BTreeMap::new(), DEFAULT_COMPRESSION_LEVEL, None).unwrap();
+    }
+
+    #[test]
+    fn test_install_tree_with_symlink_loops_is_packaged_once() {
+        // Symlinks back up the tree and a second name for a file, which a
+        // symlink-following walk would archive twice
+        let temp_dir = TempDir::new().unwrap();
+        let pkg = temp_dir.path().join("pkg");
+        fs::create_dir_all(pkg.join("usr/lib/hello")).unwrap();
+        fs::create_dir_all(pkg.join("usr/bin")).unwrap();
+        fs::write(pkg.join("usr/lib/hello/libhello.so"), "lib").unwrap();
+        std::os::unix::fs::symlink("..", pkg.join("usr/lib/hello/parent")).unwrap();
+        std::os::unix::fs::symlink("lib", pkg.join("usr/lib64")).unwrap();
+        fs::write(pkg.join("usr/bin/hello"), "hello").unwrap();
+        fs::hard_link(pkg.join("usr/bin/hello"), pkg.join("usr/bin/hello-alias")).unwrap();
+
+        let package_path = temp_dir.path().join("out.pax");
+        create_tarball(&pkg, &package_path, &BTreeMap::new(), DEFAULT_COMPRESSION_LEVEL, None).unwrap();
+
+        let tar_data = crate::package::decompress_package(&package_path).unwrap();
+        assert_eq!(find_duplicate_entry(&tar_data).unwrap(), None);
+        let paths: Vec<String> = tar::Archive::new(tar_data.as_slice()).entries().unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().to_string_lossy().trim_end_matches('/').to_string())
+            .collect();
+        assert_eq!(paths, [
+            "usr", "usr/bin", "usr/bin/hello", "usr/bin/hello-alias", "usr/lib",
+            "usr/lib/hello", "usr/lib/hello/libhello.so", "usr/lib/hello/parent", "usr/lib64",
+        ]);
+    }
+
+    #[test]
+    fn test_compression_level_is_configurable_and_validated() {
+        let temp_dir = TempDir::new().unwrap();
+        let pkg = temp_dir.path().join("pkg");
+        fs::create_dir_all(&pkg).unwrap();
+        let text: String = (0..20_000).map(|i| format!("line {} of a fairly repetitive file\n", i % 997)).collect();
+        fs::write(pkg.join("data.txt"), text).unwrap();
+
+        let fast = temp_dir.path().join("fast.pax");
+        let small = temp_dir.path().join("small.pax");
+        create_tarball(&pkg, &fast, &BTreeMap::new(), 1, None).unwrap();
+        create_tarball(&pkg, &small, &BTreeMap::new(), DEFAULT_COMPRESSION_LEVEL, None).unwrap();
+        assert!(fs::metadata(&fast).unwrap().len() > fs::metadata(&small).unwrap().len());
+        assert_eq!(crate::package::decompress_package(&fast).unwrap(), crate::package::decompress_package(&small).unwrap());
+
+        assert_eq!(parse_compression_level("3").unwrap(), 3);
+        for invalid in ["0", "23", "fast"] {
+            assert!(parse_compression_level(invalid).is_err(), "{} accepted", invalid);
+        }
+        assert!(create_tarball(&pkg, &fast, &BTreeMap::new(), 23, None).is_err());
+    }
+
+    #[test]
+    fn test_embedded_signature_keeps_compression_level() {
+        let source_dir = TempDir::new().unwrap();
+        let text: String = (0..20_000).map(|i| format!("line {} of a fairly repetitive file\n", i % 997)).collect();
+        fs::write(source_dir.path().join("data.txt"), text).unwrap();
+        let private_key = source_dir.path().join("private.key");
+        fs::write(&private_key, hex::encode([7; 32])).unwrap();
+        let recipe = BuildRecipe::from_yaml(r#"
+name: fastsigned
+version: 1.0.0
+description: Test package
+source: https://example.com/fastsigned-1.0.0.tar.gz
+build: |
+  mkdir -p $PAX_BUILD_ROOT/usr/share/fastsigned
+  cp data.txt $PAX_BUILD_ROOT/usr/share/fastsigned/
+"#).unwrap();
+
+        let builder = PackageBuilder::new().unwrap()
+            .with_source_dir(Some(source_dir.path().to_path_buf()))
+            .with_compression_level(1)
+            .with_embed_signature(Some(private_key));
+        let package_path = builder.build_for_architectures(&recipe, &["x86_64".to_string()]).unwrap().remove(0);
+        assert!(crate::package::PaxPackage::open(&package_path).unwrap().verify_embedded_signature().is_ok());
+
+        // The signed archive is the level 1 encoding, not a level 19 one
+        let tar_data = crate::package::decompress_package(&package_path).unwrap();
+        let package = fs::read(&package_path).unwrap();
+        assert_eq!(package, Compression::Zstd.compress(&tar_data, 1).unwrap());
+        assert_ne!(package, Compression::Zstd.compress(&tar_data, DEFAULT_COMPRESSION_LEVEL).unwrap());
+    }
+
+    #[test]
+    fn test_reproducible_builds_are_identical() {
+        let source_dir = TempDir::new().unwrap();
+        fs::write(source_dir.path().join("README"), "same contents").unwrap();
+        let recipe = BuildRecipe::from_yaml(r#"
+name: repro
+version: 1.0.0
+description: Test package
+source: https://example.com/repro-1.0.0.tar.gz
+build: |
+  mkdir -p $PAX_BUILD_ROOT/usr/share/repro
+  cp README $PAX_BUILD_ROOT/usr/share/repro/
+  touch -d @$((RANDOM + 1)) $PAX_BUILD_ROOT/usr/share/repro/README
+"#).unwrap();
+
+        let build = |reproducible: bool| {
+            let builder = PackageBuilder::new().unwrap()
+                .with_source_dir(Some(source_dir.path().to_path_buf()))
+                .with_reproducible(reproducible);
+            let package_path = builder.build_for_architectures(&recipe, &["x86_64".to_string()]).unwrap();
+            let package = fs::read(&package_path[0]).unwrap();
+            (hex::encode(Sha256::digest(&package)), crate::repro::package_entries(&package_path[0]).unwrap())
+        };
+
+        let (first, entries) = build(true);
+        let (second, _) = build(true);
+        assert_eq!(first, second);
+        assert!(entries.values().all(|entry| entry.mtime == 0), "{:?}", entries);
+
+        // Without the flag the random mtime leaks into the package
+        let (_, entries) = build(false);
+        assert_ne!(entries["usr/share/repro/README"].mtime, 0);
+    }
+
+    #[test]
+    fn test_native_tarball_keeps_modes_and_links() {
+        use std::os::unix::fs::MetadataExt;
+
+        let temp_dir = TempDir::new().unwrap();
+        let pkg = temp_dir.path().join("pkg");
+        fs::create_dir_all(pkg.join("usr/bin")).unwrap();
+        fs::create_dir_all(pkg.join("usr/lib")).unwrap();
+        fs::write(pkg.join("usr/bin/tool"), "#!/bin/sh\n").unwrap();
+        fs::set_permissions(pkg.join("usr/bin/tool"), fs::Permissions::from_mode(0o755)).unwrap();
+        fs::hard_link(pkg.join("usr/bin/tool"), pkg.join("usr/bin/tool-alias")).unwrap();
+        fs::write(pkg.join("usr/lib/libfoo.so.1"), "elf").unwrap();
+        std::os::unix::fs::symlink("libfoo.so.1", pkg.join("usr/lib/libfoo.so")).unwrap();
+
+        let package_path = temp_dir.path().join("native.pax");
+        create_tarball(&pkg, &package_path, &BTreeMap::new(), DEFAULT_COMPRESSION_LEVEL, None).unwrap();
+        let package = crate::package::PaxPackage::open(&package_path).unwrap();
+        let entries: Vec<(String, tar::EntryType)> = package.entries().unwrap()
+            .map(|entry| entry.unwrap())
+            .map(|entry| (entry.path.to_string_lossy().to_string(), entry.entry_type))
+            .collect();
+        assert_eq!(entries, vec![
+            ("usr/".to_string(), tar::EntryType::Directory),
+            ("usr/bin/".to_string(), tar::EntryType::Directory),
+            ("usr/bin/tool".to_string(), tar::EntryType::Regular),
+            ("usr/bin/tool-alias".to_string(), tar::EntryType::Link),
+            ("usr/lib/".to_string(), tar::EntryType::Directory),
+            ("usr/lib/libfoo.so".to_string(), tar::EntryType::Symlink),
+            ("usr/lib/libfoo.so.1".to_string(), tar::EntryType::Regular),
+        ]);
+
+        let out = temp_dir.path().join("out");
+        package.extract_to(&out).unwrap();
+        let tool = fs::metadata(out.join("usr/bin/tool")).unwrap();
+        assert_eq!(tool.mode() & 0o7777, 0o755);
+        assert_eq!(tool.ino(), fs::metadata(out.join("usr/bin/tool-alias")).unwrap().ino());
+        assert_eq!(fs::read_link(out.join("usr/lib/libfoo.so")).unwrap(), Path::new("libfoo.so.1"));
+
+        // Packages written by the tar program (with `./` member names) still extract
+        let output = Command::new("tar").arg("--xattrs").arg("-cf").arg("-").arg("-C").arg(&pkg).arg(".").output().unwrap();
+        let legacy_path = temp_dir.path().join("legacy.pax");
+        fs::write(&legacy_path, zstd::encode_all(output.stdout.as_slice(), 3).unwrap()).unwrap();
+        let legacy_out = temp_dir.path().join("legacy-out");
+        crate::package::PaxPackage::open(&legacy_path).unwrap().extract_to(&legacy_out).unwrap();
+        assert_eq!(fs::read_to_string(legacy_out.join("usr/bin/tool-alias")).unwrap(), "#!/bin/sh\n");
+        assert_eq!(fs::read_link(legacy_out.join("usr/lib/libfoo.so")).unwrap(), Path::new("libfoo.so.1"));
+    }
+
+    #[test]
+    fn test_payload_metadata_yaml_does_not_collide() {
+        let builder = PackageBuilder::new().unwrap();
+        let install_dir = builder.temp_dir().join("install/x86_64");
+        fs::create_dir_all(install_dir.join("usr/bin")).unwrap();
+        fs::write(install_dir.join("metadata.yaml"), "payload").unwrap();
+        fs::write(install_dir.join("usr/bin/hello"), "hello").unwrap();
+
+        let package_path = builder.create_package_for_arch(&test_recipe(), "x86_64").unwrap();
+
+        let mut package = crate::package::PaxPackage::open(&package_path).unwrap();
+        let metadata = package.load_metadata().unwrap();
+        assert_eq!(metadata.name, test_recipe().name);
+        assert!(metadata.files.contains(&"metadata.yaml".to_string()));
+
+        let files = package.list_files().unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().any(|file| file.ends_with("metadata.yaml")));
+        assert!(!files.iter().any(|file| file.components().any(|c| c.as_os_str() == METADATA_DIR)));
+
+        let extract_dir = builder.temp_dir().join("extracted");
+        package.extract_to(&extract_dir).unwrap();
+        assert_eq!(fs::read_to_string(extract_dir.join("metadata.yaml")).unwrap(), "payload");
+    }
+
+    #[test]
+    fn test_reserved_metadata_dir_rejected() {
+        let builder = PackageBuilder::new().unwrap();
+        let install_dir = builder.temp_dir().join("install/x86_64");
+        fs::create_dir_all(install_dir.join(METADATA_DIR)).unwrap();
+        fs::write(install_dir.join(METADATA_DIR).join("metadata.yaml"), "spoofed").unwrap();
+
+        let err = builder.create_package_for_arch(&test_recipe(), "x86_64").unwrap_err();
+        assert!(err.to_string().contains("reserved"));
+    }
+
+    #[test]
+    fn test_build_jobs_exported_as_pax_jobs() {
+        assert!(BuildRecipe::default_build_script().contains("make -j$PAX_JOBS"));
+
+        let recipe = BuildRecipe::from_yaml(r#"
+name: jobs-test
+version: 1.0.0
+description: Test package
+source: https://example.com/jobs-test-1.0.0.tar.gz
+build: echo $PAX_JOBS > $PAX_BUILD_ROOT/jobs
+"#).unwrap();
+
+        for (jobs, expected) in [(Some(3), 3), (None, default_build_jobs()), (Some(0), default_build_jobs())] {
+            let builder = PackageBuilder::new().unwrap().with_build_jobs(jobs);
+            let source_dir = builder.temp_dir().join("source");
+            fs::create_dir_all(&source_dir).unwrap();
+
+            builder.run_build_script_for_arch(&recipe, &source_dir, "x86_64", false).unwrap();
+            let exported = fs::read_to_string(builder.temp_dir().join("install/x86_64/jobs")).unwrap();
+            assert_eq!(exported.trim(), expected.to_string());
+        }
+    }
+
+    #[test]
+    fn test_default_excludes() {
+        let builder = PackageBuilder::new().unwrap();
+        let install_dir = builder.temp_dir().join("install/x86_64");
+        fs::create_dir_all(install_dir.join("usr/lib/python/.git")).unwrap();
+        fs::write(install_dir.join("usr/lib/python/.git/config"), "[core]").unwrap();
+        fs::write(install_dir.join("usr/lib/python/mod.py"), "pass").unwrap();
+        fs::write(install_dir.join("usr/lib/python/mod.pyc"), "bytecode").unwrap();
+        fs::write(install_dir.join("usr/lib/libfoo.la"), "libtool").unwrap();
+        fs::write(install_dir.join("usr/lib/libfoo.a"), "archive").unwrap();
+
+        let recipe = test_recipe();
+        let package_path = builder.create_package_for_arch(&recipe, "x86_64").unwrap();
+        let package_files = |package_path: &Path| -> Vec<String> {
+            let mut files: Vec<String> = crate::package::PaxPackage::open(package_path).unwrap()
+                .entries().unwrap()
+                .map(|entry| entry.unwrap())
+                .filter(|entry| entry.is_file() && !entry.path.starts_with(METADATA_DIR))
+                .map(|entry| entry.path.to_string_lossy().to_string())
+                .collect();
+            files.sort();
+            files
+        };
+        assert_eq!(package_files(&package_path), vec!["usr/lib/libfoo.a", "usr/lib/python/mod.py"]);
+
+        let mut extended = recipe.clone();
+        extended.exclude = vec!["*.a".to_string()];
+        let metadata = builder.create_package_metadata_for_arch(&extended, "x86_64").unwrap();
+        assert_eq!(metadata.files, vec!["usr/lib/python/mod.py"]);
+
+        let mut opted_out = recipe;
+        opted_out.no_default_excludes = true;
+        let package_path = builder.create_package_for_arch(&opted_out, "x86_64").unwrap();
+        let files = package_files(&package_path);
+        assert!(files.contains(&"usr/lib/python/.git/config".to_string()));
+        assert!(files.contains(&"usr/lib/libfoo.la".to_string()));
+    }
+
+    #[test]
+    fn test_rename_packages_file_under_new_path() {
+        let builder = PackageBuilder::new().unwrap();
+        let install_dir = builder.temp_dir().join("install/x86_64");
+        fs::create_dir_all(install_dir.join("etc/tool")).unwrap();
+        fs::write(install_dir.join("etc/tool/config.sample"), "key = value").unwrap();
+        fs::write(install_dir.join("etc/tool/other.conf"), "other").unwrap();
+
+        let mut recipe = test_recipe();
+        recipe.rename.insert("etc/tool/*.sample".to_string(), "etc/tool/config".to_string());
+        recipe.validate().unwrap();
+
+        let package_path = builder.create_package_for_arch(&recipe, "x86_64").unwrap();
+        let mut package = crate::package::PaxPackage::open(&package_path).unwrap();
+        let mut files: Vec<String> = package.entries().unwrap()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.is_file() && !entry.path.starts_with(METADATA_DIR))
+            .map(|entry| entry.path.to_string_lossy().to_string())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["etc/tool/config", "etc/tool/other.conf"]);
+        let mut listed = package.load_metadata().unwrap().files.clone();
+        listed.sort();
+        assert_eq!(listed, files);
+
+        // A rename onto a path that is already installed is a collision
+        let mut colliding = test_recipe();
+        colliding.rename.insert("etc/tool/config.sample".to_string(), "etc/tool/other.conf".to_string());
+        let err = builder.create_package_for_arch(&colliding, "x86_64").unwrap_err();
+        assert!(err.to_string().contains("maps both"), "{}", err);
+
+        let mut escaping = test_recipe();
+        escaping.rename.insert("etc/tool/config.sample".to_string(), "../config".to_string());
+        assert!(escaping.validate().is_err());
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_capabilities_recorded_and_applied_on_extract() {
+        let recipe = BuildRecipe::from_yaml(r#"
+name: pinger
+version: 1.0.0
+description: Test package
+source: https://example.com/pinger-1.0.0.tar.gz
+capabilities:
+  usr/bin/pinger: cap_net_raw=+ep
+"#).unwrap();
+        recipe.validate().unwrap();
+
+        let builder = PackageBuilder::new().unwrap();
+        let install_dir = builder.temp_dir().join("install/x86_64");
+        fs::create_dir_all(install_dir.join("usr/bin")).unwrap();
+        fs::write(install_dir.join("usr/bin/pinger"), "binary").unwrap();
+        let package_path = builder.create_package_for_arch(&recipe, "x86_64").unwrap();
+
+        let mut package = crate::package::PaxPackage::open(&package_path).unwrap();
+        let metadata = package.load_metadata().unwrap();
+        assert_eq!(metadata.capabilities["usr/bin/pinger"], "cap_net_raw=+ep");
+
+        let mut missing = recipe.clone();
+        missing.capabilities.insert("usr/bin/absent".to_string(), "cap_chown=ep".to_string());
+        assert!(builder.create_package_for_arch(&missing, "x86_64").is_err());
+
+        // Setting file capabilities needs setcap and CAP_SETFCAP
+        let probe = builder.temp_dir().join("probe");
+        fs::write(&probe, "").unwrap();
+        if !Command::new("setcap").arg("cap_net_raw=ep").arg(&probe).status().is_ok_and(|status| status.success()) {
+            return;
+        }
+
+        let root = builder.temp_dir().join("root");
+        crate::extract::extract_package(package_path.to_str().unwrap(), root.to_str(), true, false).unwrap();
+        let output = Command::new("getcap").arg(root.join("usr/bin/pinger")).output().unwrap();
+        assert!(String::from_utf8_lossy(&output.stdout).contains("cap_net_raw=ep"));
+    }
+
+    #[test]
+    fn test_replaces_round_trips_into_index() {
+        let recipe = BuildRecipe::from_yaml(r#"
+name: newtool
+version: 2.0.0
+description: Test package
+source: https://example.com/newtool-2.0.0.tar.gz
+replaces:
+  - oldtool<2.0
+"#).unwrap();
+        recipe.validate().unwrap();
+
+        let builder = PackageBuilder::new().unwrap();
+        let install_dir = builder.temp_dir().join("install/x86_64");
+        fs::create_dir_all(install_dir.join("usr/bin")).unwrap();
+        fs::write(install_dir.join("usr/bin/newtool"), "#!/bin/sh\n").unwrap();
+        let package_path = builder.create_package_for_arch(&recipe, "x86_64").unwrap();
+
+        let mut package = crate::package::PaxPackage::open(&package_path).unwrap();
+        assert_eq!(package.load_metadata().unwrap().replaces, vec!["oldtool<2.0"]);
+
+        let repo = builder.temp_dir().join("repo");
+        fs::create_dir_all(&repo).unwrap();
+        fs::copy(&package_path, repo.join(package_path.file_name().unwrap())).unwrap();
+        let (index, _) = crate::index::build_index(&repo, None, None).unwrap();
+        assert_eq!(index.packages[0].replaces, vec!["oldtool<2.0"]);
+        assert_eq!(index.replacements_for("oldtool", Some("1.5")).len(), 1);
+        assert!(index.replacements_for("oldtool", Some("2.0")).is_empty());
+
+        let mut old = index.packages[0].clone();
+        old.name = "oldtool".to_string();
+        old.version = "1.5".to_string();
+        old.replaces.clear();
+        assert!(index.packages[0].conflicts_with(&old));
+        assert!(old.conflicts_with(&index.packages[0]));
+
+        let mut invalid = recipe.clone();
+        invalid.replaces = vec!["oldtool >>".to_string()];
+        assert!(invalid.validate().is_err());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_file_owners_recorded_by_name_and_kept_on_extract() {
+        use std::os::unix::fs::MetadataExt;
+
+        let recipe = BuildRecipe::from_yaml(r#"
+name: daemon
+version: 1.0.0
+description: Test package
+source: https://example.com/daemon-1.0.0.tar.gz
+users:
+  - name: paxtestsvc
+    uid: 4242
+groups:
+  - name: paxtestlog
+    gid: 4343
+file_owners:
+  var/lib/daemon: paxtestsvc
+  var/log/daemon.log: paxtestsvc:paxtestlog
+"#).unwrap();
+        recipe.validate().unwrap();
+
+        let builder = PackageBuilder::new().unwrap();
+        let install_dir = builder.temp_dir().join("install/x86_64");
+        fs::create_dir_all(install_dir.join("var/lib/daemon")).unwrap();
+        fs::create_dir_all(install_dir.join("var/log")).unwrap();
+        fs::write(install_dir.join("var/log/daemon.log"), "").unwrap();
+        fs::write(install_dir.join("var/lib/daemon/state"), "").unwrap();
+        let package_path = builder.create_package_for_arch(&recipe, "x86_64").unwrap();
+
+        let mut package = crate::package::PaxPackage::open(&package_path).unwrap();
+        let metadata = package.load_metadata().unwrap();
+        assert_eq!(metadata.users[0].name, "paxtestsvc");
+        assert_eq!(metadata.file_owners["var/log/daemon.log"], "paxtestsvc:paxtestlog");
+
+        let owners: BTreeMap<String, (Option<String>, Option<String>)> = package.entries().unwrap()
+            .map(|entry| entry.unwrap())
+            .map(|entry| (entry.path.to_string_lossy().trim_end_matches('/').to_string(), (entry.owner, entry.group)))
+            .collect();
+        let owned = |user: &str, group: &str| (Some(user.to_string()), Some(group.to_string()));
+        assert_eq!(owners["var/lib/daemon"], owned("paxtestsvc", "paxtestsvc"));
+        assert_eq!(owners["var/log/daemon.log"], owned("paxtestsvc", "paxtestlog"));
+        assert_ne!(owners["var/lib/daemon/state"].0.as_deref(), Some("paxtestsvc"));
+
+        let mut undeclared = recipe.clone();
+        undeclared.file_owners.insert("var/lib/daemon/state".to_string(), "nobody".to_string());
+        assert!(undeclared.validate().is_err());
+
+        // Only root can chown on extract; the names are unknown here, so tar falls back to the declared IDs
+        let root = builder.temp_dir().join("root");
+        crate::extract::extract_package(package_path.to_str().unwrap(), root.to_str(), false, false).unwrap();
+        if Command::new("id").arg("-u").output().is_ok_and(|output| output.stdout.trim_ascii() == b"0") {
+            let log = fs::metadata(root.join("var/log/daemon.log")).unwrap();
+            assert_eq!((log.uid(), log.gid()), (4242, 4343));
+        }
+
+        // Regenerating metadata repacks the payload and keeps the owners
+        crate::remeta::remeta(&package_path, false, DEFAULT_COMPRESSION_LEVEL).unwrap();
+        let package = crate::package::PaxPackage::open(&package_path).unwrap();
+        let entry = package.entries().unwrap()
+            .map(|entry| entry.unwrap())
+            .find(|entry| entry.path == Path::new("var/log/daemon.log"))
+            .unwrap();
+        assert_eq!((entry.owner.as_deref(), entry.group.as_deref()), (Some("paxtestsvc"), Some("paxtestlog")));
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn test_xattrs_survive_package_and_extract() {
+        let builder = PackageBuilder::new().unwrap();
+        let install_dir = builder.temp_dir().join("install/x86_64");
+        fs::create_dir_all(install_dir.join("usr/bin")).unwrap();
+        let binary = install_dir.join("usr/bin/hello");
+        fs::write(&binary, "hello").unwrap();
+        // Not every filesystem supports user extended attributes
+        if xattr::set(&binary, "user.paxbuild.test", b"kept").is_err() {
+            return;
+        }
+
+        let package_path = builder.create_package_for_arch(&test_recipe(), "x86_64").unwrap();
+        let extract_dir = builder.temp_dir().join("extract");
+        crate::package::PaxPackage::open(&package_path).unwrap().extract_to(&extract_dir).unwrap();
+
+        let value = xattr::get(extract_dir.join("usr/bin/hello"), "user.paxbuild.test").unwrap();
+        assert_eq!(value.as_deref(), Some(&b"kept"[..]));
     }
 }
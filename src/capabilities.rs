@@ -0,0 +1,103 @@
+use anyhow::{Result, Context};
+use std::collections::BTreeMap;
+use std::path::{Component, Path};
+use std::process::Command;
+use crate::exec;
+
+/// Check a capability string in `setcap` text form, such as `cap_net_bind_service=+ep`
+///
+/// Each whitespace-separated clause is a comma-separated list of capability
+/// names (or `all`) followed by one or more operator (`=`, `+`, `-`) and
+/// flag (`e`, `i`, `p`) groups.
+pub fn validate_capability(caps: &str) -> Result<()> {
+    let invalid = |reason: &str| anyhow::anyhow!("Invalid capability '{}': {}", caps, reason);
+
+    let mut clauses = caps.split_whitespace().peekable();
+    if clauses.peek().is_none() {
+        return Err(invalid("empty"));
+    }
+
+    for clause in clauses {
+        let op_start = clause.find(['=', '+', '-'])
+            .ok_or_else(|| invalid("missing '=', '+' or '-'"))?;
+        let (names, actions) = clause.split_at(op_start);
+
+        for name in names.split(',') {
+            let known = name == "all"
+                || name.strip_prefix("cap_").is_some_and(|rest| {
+                    !rest.is_empty() && rest.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+                });
+            if !known {
+                return Err(invalid(&format!("'{}' is not a capability name", name)));
+            }
+        }
+
+        // `=` may stand alone (clear all flags); `+` and `-` need at least one flag
+        let mut chars = actions.chars().peekable();
+        while let Some(op) = chars.next() {
+            if !matches!(op, '=' | '+' | '-') {
+                return Err(invalid(&format!("unexpected '{}'", op)));
+            }
+            let mut flags = 0;
+            while let Some(flag) = chars.next_if(|c| !matches!(c, '=' | '+' | '-')) {
+                if !matches!(flag, 'e' | 'i' | 'p') {
+                    return Err(invalid(&format!("unknown flag '{}'", flag)));
+                }
+                flags += 1;
+            }
+            if flags == 0 && op != '=' {
+                return Err(invalid(&format!("'{}' needs at least one of e, i, p", op)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Check a capability path: relative to the package root and without `..`
+pub fn validate_capability_path(path: &str) -> Result<()> {
+    let valid = !path.is_empty()
+        && Path::new(path).components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+    if !valid {
+        anyhow::bail!("Invalid capability path '{}': it must be relative to the package root", path);
+    }
+    Ok(())
+}
+
+/// Set file capabilities below `root` with `setcap`
+pub fn apply_capabilities(root: &Path, capabilities: &BTreeMap<String, String>) -> Result<()> {
+    for (path, caps) in capabilities {
+        validate_capability_path(path)?;
+        validate_capability(caps)?;
+
+        let target = root.join(path);
+        let output = exec::run(Command::new("setcap").arg(caps).arg(&target))
+            .with_context(|| "Failed to run setcap")?;
+        if !output.status.success() {
+            anyhow::bail!(
+                "Failed to set capabilities '{}' on {}: {}",
+                caps, target.display(), String::from_utf8_lossy(&output.stderr).trim()
+            );
+        }
+        println!("Set capabilities {} on {}", caps, path);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_capability() {
+        for caps in ["cap_net_bind_service=+ep", "cap_net_raw,cap_net_admin+ep", "cap_sys_nice=ep cap_chown+i", "all=", "cap_setuid=p-e"] {
+            validate_capability(caps).unwrap_or_else(|e| panic!("{}: {}", caps, e));
+        }
+        for caps in ["", "cap_net_bind_service", "net_bind_service=+ep", "cap_net_raw+x", "cap_net_raw+", "CAP_NET_RAW=ep"] {
+            assert!(validate_capability(caps).is_err(), "{} accepted", caps);
+        }
+        assert!(validate_capability_path("usr/bin/ping").is_ok());
+        assert!(validate_capability_path("/usr/bin/ping").is_err());
+        assert!(validate_capability_path("usr/../../etc/passwd").is_err());
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::Result;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::Path;
+use crate::depspec::Constraint;
+use crate::index::{self, PackageIndex};
+
+/// A runtime dependency of a repository package that no package in the repository meets
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnsatisfiedDependency {
+    /// File name of the package declaring the dependency
+    pub package: String,
+    /// The dependency as written in the metadata
+    pub dependency: String,
+    /// Why it cannot be met
+    pub reason: String,
+}
+
+impl fmt::Display for UnsatisfiedDependency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} ({})", self.package, self.dependency, self.reason)
+    }
+}
+
+/// Every name a package in the index answers to (its own name and its provides),
+/// with the version it offers under that name
+fn provides_map(index: &PackageIndex) -> BTreeMap<&str, Vec<Option<&str>>> {
+    let mut map: BTreeMap<&str, Vec<Option<&str>>> = BTreeMap::new();
+    for entry in &index.packages {
+        map.entry(&entry.name).or_default().push(Some(&entry.version));
+        // A package usually provides its own name unversioned, which adds nothing
+        for provide in entry.provides.iter().filter(|provide| provide.name != entry.name || provide.version.is_some()) {
+            map.entry(&provide.name).or_default().push(provide.version.as_deref());
+        }
+    }
+    map
+}
+
+/// Find the runtime dependencies of indexed packages that the index itself cannot satisfy
+pub fn unsatisfied_dependencies(index: &PackageIndex) -> Vec<UnsatisfiedDependency> {
+    let provided = provides_map(index);
+
+    let mut unsatisfied = Vec::new();
+    for entry in &index.packages {
+        for dependency in &entry.runtime_dependencies {
+            let reason = match Constraint::parse(dependency) {
+                Err(e) => Some(format!("{:#}", e)),
+                Ok(constraint) => match provided.get(constraint.name.as_str()) {
+                    None => Some("not provided by any package".to_string()),
+                    Some(versions) if versions.iter().any(|version| constraint.satisfied_by(&constraint.name, *version)) => None,
+                    Some(versions) => {
+                        let mut available: Vec<&str> = versions.iter().map(|version| version.unwrap_or("unversioned")).collect();
+                        available.sort();
+                        available.dedup();
+                        Some(format!("available: {}", available.join(", ")))
+                    }
+                },
+            };
+            if let Some(reason) = reason {
+                unsatisfied.push(UnsatisfiedDependency {
+                    package: entry.filename.clone(),
+                    dependency: dependency.clone(),
+                    reason,
+                });
+            }
+        }
+    }
+    unsatisfied
+}
+
+/// Check that every runtime dependency in a package directory is met within it (CLI entry point)
+pub fn closure_check_cmd(dir: &str) -> Result<()> {
+    println!("PAXBuild - Checking dependency closure");
+    println!("Directory: {}", dir);
+
+    let (index, _) = index::build_index(Path::new(dir), None, None)?;
+    let unsatisfied = unsatisfied_dependencies(&index);
+    if unsatisfied.is_empty() {
+        println!("All runtime dependencies of {} package(s) are satisfied", index.packages.len());
+        return Ok(());
+    }
+
+    println!("Unsatisfied runtime dependencies:");
+    for dependency in &unsatisfied {
+        println!("  {}", dependency);
+    }
+    anyhow::bail!("{} unsatisfied runtime dependency(ies) in {}", unsatisfied.len(), dir)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::depspec::Provide;
+    use crate::test_support;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_closure_check_reports_unsatisfiable_dependency() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut libfoo = test_support::metadata("libfoo", "1.2.0");
+        libfoo.provides.push(Provide::parse("libfoo.so.1").unwrap());
+        test_support::write_package(temp_dir.path(), &libfoo, &[]);
+
+        let mut app = test_support::metadata("app", "1.0.0");
+        app.runtime_dependencies = vec!["libfoo>=1.0".to_string(), "libfoo.so.1".to_string(), "libfoo>=2.0".to_string()];
+        test_support::write_package(temp_dir.path(), &app, &[]);
+
+        let mut tool = test_support::metadata("tool", "1.0.0");
+        tool.runtime_dependencies = vec!["app".to_string(), "libbar".to_string()];
+        test_support::write_package(temp_dir.path(), &tool, &[]);
+
+        let (index, _) = index::build_index(temp_dir.path(), None, None).unwrap();
+        let unsatisfied = unsatisfied_dependencies(&index);
+        assert_eq!(unsatisfied, vec![
+            UnsatisfiedDependency {
+                package: "app-1.0.0-x86_64.pax".to_string(),
+                dependency: "libfoo>=2.0".to_string(),
+                reason: "available: 1.2.0".to_string(),
+            },
+            UnsatisfiedDependency {
+                package: "tool-1.0.0-x86_64.pax".to_string(),
+                dependency: "libbar".to_string(),
+                reason: "not provided by any package".to_string(),
+            },
+        ]);
+
+        let error = closure_check_cmd(temp_dir.path().to_str().unwrap()).unwrap_err();
+        assert!(error.to_string().contains("2 unsatisfied"), "{}", error);
+    }
+}
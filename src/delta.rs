@@ -0,0 +1,359 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use crate::builder::{Compression, DEFAULT_COMPRESSION_LEVEL, ZSTD_LEVELS};
+use crate::package::decompress_package;
+
+/// Name of the chunking scheme, recorded in chunk manifests and deltas
+pub const CHUNKER: &str = "gear-8k";
+
+/// Extension of the chunk manifest written next to a package (`<stem>.chunks.json`)
+pub const CHUNK_MANIFEST_EXTENSION: &str = ".chunks.json";
+
+/// Chunks average about 8 KiB: a boundary falls where the top 13 bits of the rolling hash are zero
+const BOUNDARY_MASK: u64 = !0 << (64 - 13);
+const MIN_CHUNK_SIZE: usize = 2 * 1024;
+const MAX_CHUNK_SIZE: usize = 64 * 1024;
+
+/// First bytes of a delta file; the rest is zstd-compressed
+const DELTA_MAGIC: &[u8] = b"PAXDELTA1\n";
+
+/// Per-byte values of the gear rolling hash (splitmix64 from a fixed seed, so every build agrees)
+fn gear_table() -> [u64; 256] {
+    let mut table = [0; 256];
+    let mut state: u64 = 0x5041_5844_454c_5441;
+    for value in table.iter_mut() {
+        state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        *value = z ^ (z >> 31);
+    }
+    table
+}
+
+/// Split data into content-defined chunks
+///
+/// Boundaries depend only on the 64 bytes before them, so an insertion or
+/// removal changes the chunks around it and leaves the rest alone.
+pub fn chunk_ranges(data: &[u8]) -> Vec<Range<usize>> {
+    let gear = gear_table();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+    let mut hash: u64 = 0;
+    for (i, byte) in data.iter().enumerate() {
+        hash = (hash << 1).wrapping_add(gear[*byte as usize]);
+        let size = i + 1 - start;
+        if (size >= MIN_CHUNK_SIZE && hash & BOUNDARY_MASK == 0) || size >= MAX_CHUNK_SIZE {
+            chunks.push(start..i + 1);
+            start = i + 1;
+            hash = 0;
+        }
+    }
+    if start < data.len() {
+        chunks.push(start..data.len());
+    }
+    chunks
+}
+
+/// One chunk of a package payload
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Chunk {
+    pub offset: u64,
+    pub size: u64,
+    pub sha256: String,
+}
+
+/// The chunks of a package's uncompressed payload, so clients can tell which they already have
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChunkManifest {
+    pub chunker: String,
+    /// SHA256 of the package file
+    pub sha256: String,
+    pub compression: String,
+    /// Size of the uncompressed payload (the tar stream)
+    pub payload_size: u64,
+    pub chunks: Vec<Chunk>,
+}
+
+/// Chunk manifest path for a package: `hello-1.0.0-x86_64.pax` becomes `hello-1.0.0-x86_64.chunks.json`
+pub fn chunk_manifest_path(package_path: &Path) -> PathBuf {
+    let filename = package_path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = filename.strip_suffix(".pax").unwrap_or(&filename);
+    package_path.with_file_name(format!("{}{}", stem, CHUNK_MANIFEST_EXTENSION))
+}
+
+/// Read a package's compression and uncompressed payload
+fn read_payload(package_path: &Path) -> Result<(Vec<u8>, Compression, Vec<u8>)> {
+    let compressed = fs::read(package_path)
+        .with_context(|| format!("Failed to read package: {}", package_path.display()))?;
+    let compression = Compression::detect(&compressed)
+        .ok_or_else(|| anyhow::anyhow!("Unrecognized package compression: {}", package_path.display()))?;
+    let payload = decompress_package(package_path)?;
+    Ok((compressed, compression, payload))
+}
+
+/// Chunk a package's payload
+pub fn chunk_manifest(package_path: &Path) -> Result<ChunkManifest> {
+    let (compressed, compression, payload) = read_payload(package_path)?;
+    let chunks = chunk_ranges(&payload).into_iter()
+        .map(|range| Chunk {
+            offset: range.start as u64,
+            size: range.len() as u64,
+            sha256: hex::encode(Sha256::digest(&payload[range])),
+        })
+        .collect();
+    Ok(ChunkManifest {
+        chunker: CHUNKER.to_string(),
+        sha256: hex::encode(Sha256::digest(&compressed)),
+        compression: compression.name().to_string(),
+        payload_size: payload.len() as u64,
+        chunks,
+    })
+}
+
+/// Write the chunk manifest of a finished package, returning its path
+pub fn write_chunk_manifest(package_path: &Path) -> Result<PathBuf> {
+    let json = serde_json::to_string_pretty(&chunk_manifest(package_path)?)
+        .with_context(|| "Failed to serialize chunk manifest")?;
+    let path = chunk_manifest_path(package_path);
+    fs::write(&path, json)
+        .with_context(|| format!("Failed to write chunk manifest: {}", path.display()))?;
+    Ok(path)
+}
+
+/// What a delta was made from, and how to turn the rebuilt payload back into the package
+#[derive(Debug, Serialize, Deserialize)]
+struct DeltaHeader {
+    chunker: String,
+    old_sha256: String,
+    new_sha256: String,
+    compression: String,
+    level: i32,
+    payload_size: u64,
+}
+
+/// One step of rebuilding the new payload
+#[derive(Debug, PartialEq)]
+enum DeltaOp<'a> {
+    /// Bytes taken from the old payload
+    Copy { offset: u64, size: u64 },
+    /// Bytes only the new payload has
+    Insert(&'a [u8]),
+}
+
+/// The compression level that reproduces `compressed` from `payload`, trying the default first
+fn find_level(compression: Compression, payload: &[u8], compressed: &[u8]) -> Result<i32> {
+    let levels: Vec<i32> = match compression {
+        Compression::Zstd => ZSTD_LEVELS.collect(),
+        Compression::Gzip => (0..=9).collect(),
+    };
+    let candidates = std::iter::once(DEFAULT_COMPRESSION_LEVEL)
+        .filter(|level| levels.contains(level))
+        .chain(levels.iter().copied().filter(|level| *level != DEFAULT_COMPRESSION_LEVEL));
+    for level in candidates {
+        if compression.compress(payload, level)? == compressed {
+            return Ok(level);
+        }
+    }
+    anyhow::bail!("The new package cannot be reproduced from its payload at any {} level", compression.name())
+}
+
+/// Make a delta that rebuilds `new_path` from `old_path`
+///
+/// Both payloads are chunked; chunks of the new payload found in the old one
+/// are copied from it, and everything else is carried in the delta.
+pub fn create_delta(old_path: &Path, new_path: &Path) -> Result<Vec<u8>> {
+    let (old_compressed, _, old_payload) = read_payload(old_path)?;
+    let (new_compressed, compression, new_payload) = read_payload(new_path)?;
+    let level = find_level(compression, &new_payload, &new_compressed)?;
+
+    let old_chunks: HashMap<[u8; 32], Range<usize>> = chunk_ranges(&old_payload).into_iter()
+        .map(|range| (Sha256::digest(&old_payload[range.clone()]).into(), range))
+        .collect();
+
+    let mut ops: Vec<DeltaOp> = Vec::new();
+    for range in chunk_ranges(&new_payload) {
+        let digest: [u8; 32] = Sha256::digest(&new_payload[range.clone()]).into();
+        let op = match old_chunks.get(&digest) {
+            Some(old) => DeltaOp::Copy { offset: old.start as u64, size: old.len() as u64 },
+            None => DeltaOp::Insert(&new_payload[range]),
+        };
+        // Runs of neighbouring chunks become one step
+        match (ops.last_mut(), op) {
+            (Some(DeltaOp::Copy { offset, size }), DeltaOp::Copy { offset: next, size: next_size }) if *offset + *size == next => {
+                *size += next_size;
+            }
+            (Some(DeltaOp::Insert(data)), DeltaOp::Insert(next)) if data.as_ptr_range().end == next.as_ptr() => {
+                let start = data.as_ptr() as usize - new_payload.as_ptr() as usize;
+                *data = &new_payload[start..start + data.len() + next.len()];
+            }
+            (_, op) => ops.push(op),
+        }
+    }
+
+    let header = DeltaHeader {
+        chunker: CHUNKER.to_string(),
+        old_sha256: hex::encode(Sha256::digest(&old_compressed)),
+        new_sha256: hex::encode(Sha256::digest(&new_compressed)),
+        compression: compression.name().to_string(),
+        level,
+        payload_size: new_payload.len() as u64,
+    };
+    let mut body = serde_json::to_vec(&header)
+        .with_context(|| "Failed to serialize delta header")?;
+    body.push(b'\n');
+    for op in &ops {
+        match op {
+            DeltaOp::Copy { offset, size } => {
+                body.push(b'C');
+                body.extend_from_slice(&offset.to_le_bytes());
+                body.extend_from_slice(&size.to_le_bytes());
+            }
+            DeltaOp::Insert(data) => {
+                body.push(b'I');
+                body.extend_from_slice(&(data.len() as u64).to_le_bytes());
+                body.extend_from_slice(data);
+            }
+        }
+    }
+
+    let mut delta = DELTA_MAGIC.to_vec();
+    delta.extend(Compression::Zstd.compress(&body, DEFAULT_COMPRESSION_LEVEL)?);
+    Ok(delta)
+}
+
+/// Rebuild the new package from the old one and a delta, checking it byte for byte
+pub fn apply_delta(old_path: &Path, delta: &[u8]) -> Result<Vec<u8>> {
+    let body = delta.strip_prefix(DELTA_MAGIC)
+        .ok_or_else(|| anyhow::anyhow!("Not a paxbuild delta"))?;
+    let body = Compression::Zstd.decompress(body)?;
+    let newline = body.iter().position(|byte| *byte == b'\n')
+        .ok_or_else(|| anyhow::anyhow!("Truncated delta header"))?;
+    let header: DeltaHeader = serde_json::from_slice(&body[..newline])
+        .with_context(|| "Failed to parse delta header")?;
+    if header.chunker != CHUNKER {
+        anyhow::bail!("Unsupported delta chunker: {}", header.chunker);
+    }
+
+    let (old_compressed, _, old_payload) = read_payload(old_path)?;
+    let old_sha256 = hex::encode(Sha256::digest(&old_compressed));
+    if old_sha256 != header.old_sha256 {
+        anyhow::bail!("Delta was made against a different package (expected SHA256 {}, got {})", header.old_sha256, old_sha256);
+    }
+
+    let mut payload = Vec::with_capacity(header.payload_size as usize);
+    let mut ops = &body[newline + 1..];
+    let read_u64 = |ops: &mut &[u8]| -> Result<u64> {
+        let (bytes, rest) = ops.split_first_chunk::<8>()
+            .ok_or_else(|| anyhow::anyhow!("Truncated delta"))?;
+        *ops = rest;
+        Ok(u64::from_le_bytes(*bytes))
+    };
+    while let Some((kind, rest)) = ops.split_first() {
+        ops = rest;
+        match kind {
+            b'C' => {
+                let offset = read_u64(&mut ops)? as usize;
+                let size = read_u64(&mut ops)? as usize;
+                let chunk = offset.checked_add(size)
+                    .and_then(|end| old_payload.get(offset..end))
+                    .ok_or_else(|| anyhow::anyhow!("Delta copies past the end of the old payload"))?;
+                payload.extend_from_slice(chunk);
+            }
+            b'I' => {
+                let size = read_u64(&mut ops)? as usize;
+                let data = ops.get(..size)
+                    .ok_or_else(|| anyhow::anyhow!("Truncated delta"))?;
+                payload.extend_from_slice(data);
+                ops = &ops[size..];
+            }
+            other => anyhow::bail!("Invalid delta operation: {:#04x}", other),
+        }
+    }
+    if payload.len() as u64 != header.payload_size {
+        anyhow::bail!("Rebuilt payload is {} bytes, expected {}", payload.len(), header.payload_size);
+    }
+
+    let compression = Compression::READABLE.iter().copied()
+        .find(|compression| compression.name() == header.compression)
+        .ok_or_else(|| anyhow::anyhow!("Unsupported delta compression: {}", header.compression))?;
+    let package = compression.compress(&payload, header.level)?;
+    let sha256 = hex::encode(Sha256::digest(&package));
+    if sha256 != header.new_sha256 {
+        anyhow::bail!("Rebuilt package does not match (expected SHA256 {}, got {})", header.new_sha256, sha256);
+    }
+    Ok(package)
+}
+
+/// Write a delta between two packages (CLI entry point)
+pub fn delta_cmd(old_path: &str, new_path: &str, output: &str) -> Result<()> {
+    println!("PAXBuild - Creating package delta");
+    println!("From: {}", old_path);
+    println!("To: {}", new_path);
+
+    let delta = create_delta(Path::new(old_path), Path::new(new_path))?;
+    fs::write(output, &delta)
+        .with_context(|| format!("Failed to write delta: {}", output))?;
+    let new_size = fs::metadata(new_path).map(|metadata| metadata.len()).unwrap_or(0);
+    println!("Delta saved to: {} ({} bytes, package is {} bytes)", output, delta.len(), new_size);
+    Ok(())
+}
+
+/// Rebuild a package from an old package and a delta (CLI entry point)
+pub fn apply_delta_cmd(old_path: &str, delta_path: &str, output: &str) -> Result<()> {
+    println!("PAXBuild - Applying package delta");
+    println!("From: {}", old_path);
+    println!("Delta: {}", delta_path);
+
+    let delta = fs::read(delta_path)
+        .with_context(|| format!("Failed to read delta: {}", delta_path))?;
+    let package = apply_delta(Path::new(old_path), &delta)?;
+    fs::write(output, package)
+        .with_context(|| format!("Failed to write package: {}", output))?;
+    println!("Package rebuilt and verified: {}", output);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_delta_rebuilds_new_package_byte_for_byte() {
+        let temp_dir = TempDir::new().unwrap();
+        let old_dir = temp_dir.path().join("old");
+        let new_dir = temp_dir.path().join("new");
+        fs::create_dir_all(&old_dir).unwrap();
+        fs::create_dir_all(&new_dir).unwrap();
+
+        // Incompressible data, so the delta only stays small by reusing chunks
+        let mut state: u64 = 1;
+        let library: Vec<u8> = (0..256 * 1024).map(|_| {
+            state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (state >> 56) as u8
+        }).collect();
+        let mut patched = library.clone();
+        patched.splice(100_000..100_000, b"a small fix".iter().copied());
+
+        let old = test_support::write_package_bytes(&old_dir, &test_support::metadata("lib", "1.0.0"), &[("usr/lib/libfoo.so", &library)]);
+        let new = test_support::write_package_bytes(&new_dir, &test_support::metadata("lib", "1.0.1"), &[("usr/lib/libfoo.so", &patched)]);
+
+        let manifest = chunk_manifest(&new).unwrap();
+        assert_eq!(manifest.chunks.iter().map(|chunk| chunk.size).sum::<u64>(), manifest.payload_size);
+
+        let delta = create_delta(&old, &new).unwrap();
+        assert!(delta.len() < fs::metadata(&new).unwrap().len() as usize / 4, "delta is {} bytes", delta.len());
+        assert_eq!(apply_delta(&old, &delta).unwrap(), fs::read(&new).unwrap());
+
+        // The delta only applies to the package it was made from
+        let error = apply_delta(&new, &delta).unwrap_err();
+        assert!(error.to_string().contains("different package"), "{}", error);
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::Result;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// Comparison operator in a dependency constraint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ge,
+    Le,
+    Gt,
+    Lt,
+}
+
+impl Op {
+    /// Operator as written in a constraint
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Op::Eq => "=",
+            Op::Ge => ">=",
+            Op::Le => "<=",
+            Op::Gt => ">",
+            Op::Lt => "<",
+        }
+    }
+}
+
+/// A parsed dependency specification such as `libc>=2.31` or `libssl.so.1.1`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constraint {
+    /// Package (or provided capability) name
+    pub name: String,
+    /// Version requirement, if any
+    pub requirement: Option<(Op, String)>,
+}
+
+impl Constraint {
+    /// Parse a dependency specification
+    pub fn parse(spec: &str) -> Result<Self> {
+        let spec = spec.trim();
+
+        let op_start = spec.find(['<', '>', '=']).unwrap_or(spec.len());
+        let (name, rest) = spec.split_at(op_start);
+
+        if name.is_empty() {
+            anyhow::bail!("Malformed dependency '{}': missing package name", spec);
+        }
+        if !name.chars().all(is_name_char) {
+            anyhow::bail!("Malformed dependency '{}': invalid character in package name", spec);
+        }
+
+        if rest.is_empty() {
+            return Ok(Constraint { name: name.to_string(), requirement: None });
+        }
+
+        // Longest operators first so ">=" isn't read as ">"
+        let (op, version) = [Op::Ge, Op::Le, Op::Eq, Op::Gt, Op::Lt]
+            .iter()
+            .find_map(|op| rest.strip_prefix(op.as_str()).map(|version| (*op, version)))
+            .ok_or_else(|| anyhow::anyhow!("Malformed dependency '{}': unknown operator", spec))?;
+
+        if version.is_empty() {
+            anyhow::bail!("Malformed dependency '{}': missing version after '{}'", spec, op.as_str());
+        }
+        if !version.chars().all(is_version_char) {
+            anyhow::bail!("Malformed dependency '{}': invalid version '{}'", spec, version);
+        }
+
+        Ok(Constraint {
+            name: name.to_string(),
+            requirement: Some((op, version.to_string())),
+        })
+    }
+
+    /// Whether a package or provide called `name`, at `version`, meets this constraint
+    ///
+    /// A versioned requirement is never met by something without a version.
+    pub fn satisfied_by(&self, name: &str, version: Option<&str>) -> bool {
+        if self.name != name {
+            return false;
+        }
+        match (&self.requirement, version) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some((op, required)), Some(version)) => {
+                let ordering = crate::version::compare(version, required);
+                match op {
+                    Op::Eq => ordering == Ordering::Equal,
+                    Op::Ge => ordering != Ordering::Less,
+                    Op::Le => ordering != Ordering::Greater,
+                    Op::Gt => ordering == Ordering::Greater,
+                    Op::Lt => ordering == Ordering::Less,
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Constraint {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Constraint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.requirement {
+            Some((op, version)) => write!(f, "{}{}{}", self.name, op.as_str(), version),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// A provided capability, optionally versioned (`foo` or `foo=1.2`)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Provide {
+    /// Provided name
+    pub name: String,
+    /// Version the capability is provided at, if any
+    pub version: Option<String>,
+}
+
+impl Provide {
+    /// An unversioned provide
+    pub fn new(name: &str) -> Self {
+        Provide { name: name.to_string(), version: None }
+    }
+
+    /// Parse a provide specification; only `=` may be used to attach a version
+    pub fn parse(spec: &str) -> Result<Self> {
+        let constraint = Constraint::parse(spec)?;
+        match constraint.requirement {
+            None => Ok(Provide { name: constraint.name, version: None }),
+            Some((Op::Eq, version)) => Ok(Provide { name: constraint.name, version: Some(version) }),
+            Some((op, _)) => anyhow::bail!(
+                "Malformed provide '{}': only '=' may be used to give a version, not '{}'",
+                spec.trim(), op.as_str()
+            ),
+        }
+    }
+}
+
+impl FromStr for Provide {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::parse(s)
+    }
+}
+
+impl fmt::Display for Provide {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.version {
+            Some(version) => write!(f, "{}={}", self.name, version),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// Serialized form of a provide: a bare name, or a `{name, version}` mapping
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ProvideRepr {
+    Spec(String),
+    Versioned { name: String, version: Option<String> },
+}
+
+impl Serialize for Provide {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match &self.version {
+            None => ProvideRepr::Spec(self.name.clone()),
+            Some(version) => ProvideRepr::Versioned { name: self.name.clone(), version: Some(version.clone()) },
+        }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Provide {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        match ProvideRepr::deserialize(deserializer)? {
+            ProvideRepr::Spec(spec) => Provide::parse(&spec).map_err(serde::de::Error::custom),
+            ProvideRepr::Versioned { name, version } => Ok(Provide { name, version }),
+        }
+    }
+}
+
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | '+')
+}
+
+fn is_version_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '.' | '-' | '_' | '+' | '~' | ':')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_constraints() {
+        assert_eq!(
+            Constraint::parse("libc>=2.31").unwrap(),
+            Constraint { name: "libc".to_string(), requirement: Some((Op::Ge, "2.31".to_string())) }
+        );
+        assert_eq!(Constraint::parse("foo<1.0").unwrap().requirement, Some((Op::Lt, "1.0".to_string())));
+        assert_eq!(Constraint::parse("libssl.so.1.1").unwrap().requirement, None);
+        assert_eq!(Constraint::parse("libc>=2.31").unwrap().to_string(), "libc>=2.31");
+    }
+
+    #[test]
+    fn test_parse_malformed_constraints() {
+        assert!(Constraint::parse("libc>>2").is_err());
+        assert!(Constraint::parse(">=1.0").is_err());
+        assert!(Constraint::parse("libc>=").is_err());
+        assert!(Constraint::parse("lib c").is_err());
+    }
+
+    #[test]
+    fn test_satisfied_by_each_operator() {
+        let cases = [
+            ("libc>=2.31", [false, true, true]),
+            ("libc<=2.31", [true, true, false]),
+            ("libc=2.31", [false, true, false]),
+            ("libc>2.31", [false, false, true]),
+            ("libc<2.31", [true, false, false]),
+        ];
+        for (spec, expected) in cases {
+            let constraint = Constraint::parse(spec).unwrap();
+            for (version, satisfied) in ["2.30", "2.31", "2.31.1"].into_iter().zip(expected) {
+                assert_eq!(constraint.satisfied_by("libc", Some(version)), satisfied, "{} by {}", spec, version);
+            }
+            assert!(!constraint.satisfied_by("libc", None), "{} by an unversioned provide", spec);
+            assert!(!constraint.satisfied_by("glibc", Some("2.31")));
+        }
+
+        // A bare name accepts any version of that name, or none
+        let bare = Constraint::parse("zlib").unwrap();
+        assert!(bare.satisfied_by("zlib", Some("1.3")));
+        assert!(bare.satisfied_by("zlib", None));
+        assert!(!bare.satisfied_by("zlib-ng", Some("1.3")));
+    }
+
+    #[test]
+    fn test_parse_provides() {
+        assert_eq!(Provide::parse("foo").unwrap(), Provide::new("foo"));
+        assert_eq!(
+            Provide::parse("foo=1.2").unwrap(),
+            Provide { name: "foo".to_string(), version: Some("1.2".to_string()) }
+        );
+        assert!(Provide::parse("foo>=1.2").is_err());
+    }
+
+    #[test]
+    fn test_provide_yaml_round_trip() {
+        let provides = vec![Provide::new("foo"), Provide::parse("libfoo=1.2").unwrap()];
+        let yaml = serde_yaml::to_string(&provides).unwrap();
+        assert!(yaml.contains("version: '1.2'") || yaml.contains("version: \"1.2\""), "{}", yaml);
+
+        let parsed: Vec<Provide> = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(parsed, provides);
+
+        // Older metadata stores provides as plain strings
+        let parsed: Vec<Provide> = serde_yaml::from_str("- foo\n- libfoo=1.2\n").unwrap();
+        assert_eq!(parsed, provides);
+    }
+}
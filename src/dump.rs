@@ -0,0 +1,94 @@
+use anyhow::{Result, Context};
+use sha2::{Digest, Sha256};
+use std::fmt::Write;
+use crate::package::{PaxPackage, METADATA_DIR, LEGACY_FILES_LIST_NAME, LEGACY_METADATA_PATH};
+
+/// Deterministic text form of a package, meant to be compared with `diff`
+///
+/// The canonical metadata YAML (without the file list and hashes, which the
+/// entry lines repeat) is followed by one `mode size sha256 path` line per
+/// payload entry, sorted by path. Directories and links have `-` for a hash;
+/// links end in `-> target`.
+pub fn dump_package(package_path: &str) -> Result<String> {
+    let mut package = PaxPackage::open(package_path)?;
+    let mut metadata = package.load_metadata()?.clone();
+    metadata.files = Vec::new();
+    metadata.files_list = None;
+    metadata.file_hashes.clear();
+    metadata.file_hashes_list = None;
+
+    let mut lines = Vec::new();
+    for entry in package.entries()? {
+        let mut entry = entry?;
+        let path = entry.path.to_string_lossy().trim_end_matches('/').to_string();
+        let is_metadata = path.is_empty()
+            || path == "."
+            || path == METADATA_DIR
+            || path.starts_with(&format!("{}/", METADATA_DIR))
+            || path == LEGACY_METADATA_PATH
+            || path == LEGACY_FILES_LIST_NAME;
+        if is_metadata {
+            continue;
+        }
+
+        let hash = if entry.is_file() {
+            let mut hasher = Sha256::new();
+            std::io::copy(&mut entry, &mut hasher)
+                .with_context(|| format!("Failed to read package entry: {}", path))?;
+            hex::encode(hasher.finalize())
+        } else {
+            "-".to_string()
+        };
+        let mut line = format!("{:04o} {} {} {}", entry.mode & 0o7777, entry.size, hash, path);
+        if let Some(target) = &entry.link_target {
+            write!(line, " -> {}", target.display()).unwrap();
+        }
+        lines.push((path, line));
+    }
+    lines.sort();
+
+    let mut dump = metadata.to_canonical_yaml()?;
+    dump.push_str("---\n");
+    for (_, line) in lines {
+        dump.push_str(&line);
+        dump.push('\n');
+    }
+    Ok(dump)
+}
+
+/// Print a package's text dump
+pub fn dump_cmd(package_path: &str) -> Result<()> {
+    print!("{}", dump_package(package_path)?);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_dump_is_sorted_and_stable() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut metadata = test_support::metadata("dumped", "1.0.0");
+        metadata.files = vec!["usr/bin/dumped".to_string(), "usr/share/dumped/a".to_string()];
+        let package_path = test_support::write_package(temp_dir.path(), &metadata, &[
+            ("usr/share/dumped/a", "aaa"),
+            ("usr/bin/dumped", "#!/bin/sh\n"),
+        ]);
+        let package_path = package_path.to_str().unwrap();
+
+        let dump = dump_package(package_path).unwrap();
+        assert_eq!(dump, dump_package(package_path).unwrap());
+        assert!(dump.starts_with("arch:"), "{}", dump);
+        assert!(!dump.contains("metadata.yaml"));
+
+        let (_, entries) = dump.split_once("---\n").unwrap();
+        let entries: Vec<&str> = entries.lines().collect();
+        assert_eq!(entries, vec![
+            format!("0644 10 {} usr/bin/dumped", hex::encode(Sha256::digest(b"#!/bin/sh\n"))),
+            format!("0644 3 {} usr/share/dumped/a", hex::encode(Sha256::digest(b"aaa"))),
+        ]);
+    }
+}
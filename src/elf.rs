@@ -0,0 +1,56 @@
+/// ELF machine types of the architectures packages can target
+const ARCH_MACHINES: &[(&str, u16)] = &[
+    ("x86_64", 62),
+    ("aarch64", 183),
+    ("armv7", 40),
+    ("i686", 3),
+    ("riscv64", 243),
+];
+
+/// Read the machine type (`e_machine`) from the start of an ELF file
+///
+/// Returns `None` for anything that is not ELF, so callers can skip scripts,
+/// data and other non-binary files.
+pub fn machine(header: &[u8]) -> Option<u16> {
+    if header.len() < 20 || !header.starts_with(b"\x7fELF") {
+        return None;
+    }
+    let bytes = [header[18], header[19]];
+    match header[5] {
+        1 => Some(u16::from_le_bytes(bytes)),
+        2 => Some(u16::from_be_bytes(bytes)),
+        _ => None,
+    }
+}
+
+/// ELF machine type expected for binaries built for `arch`
+pub fn arch_machine(arch: &str) -> Option<u16> {
+    ARCH_MACHINES.iter().find(|(name, _)| *name == arch).map(|(_, machine)| *machine)
+}
+
+/// Architecture name of an ELF machine type, for reporting
+pub fn machine_arch(machine: u16) -> Option<&'static str> {
+    ARCH_MACHINES.iter().find(|(_, known)| *known == machine).map(|(name, _)| *name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_machine_from_header() {
+        let mut header = vec![0u8; 64];
+        header[..4].copy_from_slice(b"\x7fELF");
+        header[5] = 1;
+        header[18..20].copy_from_slice(&183u16.to_le_bytes());
+        assert_eq!(machine(&header), Some(183));
+        assert_eq!(machine_arch(183), Some("aarch64"));
+
+        header[5] = 2;
+        header[18..20].copy_from_slice(&62u16.to_be_bytes());
+        assert_eq!(machine(&header), Some(arch_machine("x86_64").unwrap()));
+
+        assert_eq!(machine(b"#!/bin/sh\necho not elf\n"), None);
+        assert_eq!(machine(b"\x7fELF"), None);
+    }
+}
@@ -0,0 +1,240 @@
+//! Execution of external commands, with optional tracing
+
+use std::io::{BufRead, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Output, Stdio};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Environment variable that enables command tracing when set to a non-empty value
+pub const TRACE_ENV_VAR: &str = "PAX_TRACE_COMMANDS";
+
+static TRACE_SINK: Mutex<Option<Box<dyn Write + Send>>> = Mutex::new(None);
+
+/// Log every external command and its result to `sink` (or stop tracing with `None`)
+pub fn set_trace_sink(sink: Option<Box<dyn Write + Send>>) {
+    if let Ok(mut guard) = TRACE_SINK.lock() {
+        *guard = sink;
+    }
+}
+
+/// Trace commands to stderr
+pub fn enable_trace() {
+    set_trace_sink(Some(Box::new(std::io::stderr())));
+}
+
+/// Whether `PAX_TRACE_COMMANDS` asks for tracing
+pub fn trace_requested_by_env() -> bool {
+    std::env::var_os(TRACE_ENV_VAR).is_some_and(|value| !value.is_empty() && value != "0")
+}
+
+fn trace(line: &str) {
+    if let Ok(mut guard) = TRACE_SINK.lock() {
+        if let Some(sink) = guard.as_mut() {
+            let _ = writeln!(sink, "[trace] {}", line);
+        }
+    }
+}
+
+/// Render a command line for the trace log
+fn describe(cmd: &Command) -> String {
+    std::iter::once(cmd.get_program())
+        .chain(cmd.get_args())
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '"') {
+                format!("{:?}", arg)
+            } else {
+                arg.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn trace_result(cmd: &Command, result: &std::io::Result<Output>) {
+    let program = cmd.get_program().to_string_lossy();
+    match result {
+        Ok(output) => trace(&format!("{} exited with {}", program, output.status)),
+        Err(e) => trace(&format!("{} failed to start: {}", program, e)),
+    }
+}
+
+/// Run a command to completion, capturing its output
+pub fn run(cmd: &mut Command) -> std::io::Result<Output> {
+    trace(&describe(cmd));
+    let result = cmd.output();
+    trace_result(cmd, &result);
+    result
+}
+
+/// Run a command to completion with `input` written to its stdin, capturing its output
+pub fn run_with_input(cmd: &mut Command, input: &[u8]) -> std::io::Result<Output> {
+    trace(&describe(cmd));
+    let result = (|| {
+        let mut child = cmd
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        if let Some(stdin) = child.stdin.take() {
+            std::io::BufWriter::new(stdin).write_all(input)?;
+        }
+
+        child.wait_with_output()
+    })();
+    trace_result(cmd, &result);
+    result
+}
+
+/// Which output stream of a command a line came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputStream {
+    Stdout,
+    Stderr,
+}
+
+/// Run a command to completion, handing each line of output to `on_line` as
+/// it is written while still capturing all of it
+///
+/// With a `timeout`, the command runs in its own process group, and the whole
+/// group (the command and everything it started) is killed once the timeout
+/// passes; the result is then an error of kind `TimedOut`.
+pub fn run_streaming(
+    cmd: &mut Command,
+    timeout: Option<Duration>,
+    on_line: impl Fn(OutputStream, &str) + Sync,
+) -> std::io::Result<Output> {
+    trace(&describe(cmd));
+    let result = (|| {
+        if timeout.is_some() {
+            cmd.process_group(0);
+        }
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+        let stdout = child.stdout.take();
+        let stderr = child.stderr.take();
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+
+        // Both pipes are drained at once so neither fills up and blocks the child
+        std::thread::scope(|scope| {
+            let stdout = scope.spawn(|| read_lines(stdout, OutputStream::Stdout, &on_line));
+            let stderr = scope.spawn(|| read_lines(stderr, OutputStream::Stderr, &on_line));
+
+            let status = match deadline {
+                None => child.wait()?,
+                Some(deadline) => loop {
+                    if let Some(status) = child.try_wait()? {
+                        break status;
+                    }
+                    if Instant::now() >= deadline {
+                        kill_process_group(child.id());
+                        child.wait()?;
+                        return Err(std::io::Error::new(std::io::ErrorKind::TimedOut, "command timed out"));
+                    }
+                    std::thread::sleep(Duration::from_millis(50));
+                },
+            };
+
+            let join = |reader: std::thread::ScopedJoinHandle<std::io::Result<Vec<u8>>>| {
+                reader.join().unwrap_or_else(|_| Err(std::io::Error::other("output reader panicked")))
+            };
+            Ok(Output { status, stdout: join(stdout)?, stderr: join(stderr)? })
+        })
+    })();
+    trace_result(cmd, &result);
+    result
+}
+
+/// Kill every process in the group led by `pid`
+fn kill_process_group(pid: u32) {
+    // SAFETY: kill() takes no pointers; a negative pid addresses the process group
+    unsafe {
+        libc::kill(-(pid as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+/// Read a pipe to the end, passing each line to `on_line` and returning everything read
+fn read_lines(pipe: Option<impl std::io::Read>, stream: OutputStream, on_line: &impl Fn(OutputStream, &str)) -> std::io::Result<Vec<u8>> {
+    let mut captured = Vec::new();
+    let Some(pipe) = pipe else {
+        return Ok(captured);
+    };
+    let mut reader = std::io::BufReader::new(pipe);
+    loop {
+        let start = captured.len();
+        if reader.read_until(b'\n', &mut captured)? == 0 {
+            break;
+        }
+        let line = String::from_utf8_lossy(&captured[start..]);
+        on_line(stream, line.trim_end_matches(['\n', '\r']));
+    }
+    Ok(captured)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_run_streaming_passes_lines_and_captures_output() {
+        let lines = Mutex::new(Vec::new());
+        let mut cmd = Command::new("bash");
+        cmd.arg("-c").arg("echo one; echo oops >&2; printf 'two\\r\\nthree'; exit 2");
+        let output = run_streaming(&mut cmd, None, |stream, line| lines.lock().unwrap().push((stream, line.to_string()))).unwrap();
+
+        assert_eq!(output.status.code(), Some(2));
+        assert_eq!(output.stdout, b"one\ntwo\r\nthree");
+        assert_eq!(output.stderr, b"oops\n");
+        let lines = lines.into_inner().unwrap();
+        let stdout: Vec<&str> = lines.iter().filter(|(stream, _)| *stream == OutputStream::Stdout).map(|(_, line)| line.as_str()).collect();
+        assert_eq!(stdout, ["one", "two", "three"]);
+        assert!(lines.contains(&(OutputStream::Stderr, "oops".to_string())));
+    }
+
+    #[test]
+    fn test_trace_logs_package_commands() {
+        let buffer = SharedBuffer::default();
+        set_trace_sink(Some(Box::new(buffer.clone())));
+
+        let tarball = crate::test_support::source_tarball(&[("trace-test-1.0.0/README", "trace")]);
+        let url = crate::test_support::serve(tarball, "trace-test-1.0.0.tar.gz");
+        let recipe = crate::recipe::BuildRecipe::from_yaml(&format!(r#"
+name: trace-test
+version: 1.0.0
+description: Test package
+source: {}
+build: touch $PAX_BUILD_ROOT/traced
+"#, url)).unwrap();
+
+        let builder = crate::builder::PackageBuilder::new().unwrap();
+        let package_path = builder.build_for_architectures(&recipe, &["x86_64".to_string()]).unwrap();
+        crate::package::PaxPackage::open(&package_path[0]).unwrap()
+            .extract_to(&builder.temp_dir().join("extracted")).unwrap();
+
+        set_trace_sink(None);
+
+        let log = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        assert!(log.contains("[trace] tar -xzf"), "{}", log);
+        assert!(log.contains("[trace] bash -c \"touch $PAX_BUILD_ROOT/traced\""), "{}", log);
+        assert!(log.contains("[trace] tar exited with exit status: 0"), "{}", log);
+    }
+}
@@ -1,13 +1,57 @@
 use anyhow::Result;
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+use crate::capabilities;
 use crate::package::PaxPackage;
 
+/// Where one package member would be written by `extract`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExtractionTarget {
+    /// Path of the member inside the package
+    pub entry: PathBuf,
+    /// Destination under the output directory, or `None` if the member would be skipped
+    pub destination: Option<PathBuf>,
+    /// Why the destination differs from the member path
+    pub warning: Option<String>,
+}
+
+/// Work out where each package member would be extracted, without writing anything
+///
+/// Members with an absolute path or a `..` component get no destination:
+/// extraction refuses packages containing them.
+pub fn plan_extraction(package: &PaxPackage, output_dir: &Path) -> Result<Vec<ExtractionTarget>> {
+    let mut targets = Vec::new();
+    for entry in package.entries()? {
+        let entry = entry?.path;
+        let target = if entry.components().any(|component| component == Component::ParentDir) {
+            ExtractionTarget {
+                warning: Some(format!("Member containing '..' would be refused: {}", entry.display())),
+                destination: None,
+                entry,
+            }
+        } else if entry.has_root() {
+            ExtractionTarget {
+                warning: Some(format!("Member with an absolute path would be refused: {}", entry.display())),
+                destination: None,
+                entry,
+            }
+        } else {
+            ExtractionTarget { destination: Some(output_dir.join(&entry)), warning: None, entry }
+        };
+        targets.push(target);
+    }
+    Ok(targets)
+}
+
 /// Extract contents of a .pax package
-pub fn extract_package(package_path: &str, output_path: Option<&str>) -> Result<()> {
+///
+/// With `apply_capabilities`, the file capabilities recorded in the metadata
+/// are set on the extracted files, as an install into that root would. With
+/// `list_only`, the destination of every member is printed and nothing is written.
+pub fn extract_package(package_path: &str, output_path: Option<&str>, apply_capabilities: bool, list_only: bool) -> Result<()> {
     println!("PAXBuild - Extracting package");
     println!("Package: {}", package_path);
     
-    let package = PaxPackage::open(package_path)?;
+    let mut package = PaxPackage::open(package_path)?;
     
     // Determine output directory
     let output_dir = if let Some(output) = output_path {
@@ -19,11 +63,39 @@ pub fn extract_package(package_path: &str, output_path: Option<&str>) -> Result<
             .trim_end_matches(".pax");
         Path::new(package_name).to_path_buf()
     };
+
+    if list_only {
+        println!("Would extract to: {}", output_dir.display());
+        let targets = plan_extraction(&package, &output_dir)?;
+        for target in &targets {
+            if let Some(warning) = &target.warning {
+                println!("Warning: {}", warning);
+            }
+            if let Some(destination) = &target.destination {
+                println!("  {} -> {}", target.entry.display(), destination.display());
+            }
+        }
+        let written = targets.iter().filter(|target| target.destination.is_some()).count();
+        println!("{} entries would be extracted", written);
+        return Ok(());
+    }
     
     println!("Extracting to: {}", output_dir.display());
     
     // Extract package
     package.extract_to(&output_dir)?;
+
+    // Legacy archives may have no metadata; their files are still worth extracting
+    match package.try_load_metadata()? {
+        Some(metadata) if apply_capabilities => {
+            capabilities::apply_capabilities(&output_dir, &metadata.capabilities)?;
+        }
+        Some(_) => {}
+        None if apply_capabilities => {
+            println!("Warning: package has no metadata; no capabilities were applied");
+        }
+        None => println!("Warning: package has no metadata"),
+    }
     
     println!("Package extracted successfully");
     
@@ -33,3 +105,56 @@ pub fn extract_package(package_path: &str, output_path: Option<&str>) -> Result<
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_list_only_plans_paths_without_writing() {
+        let temp_dir = TempDir::new().unwrap();
+        let metadata = test_support::metadata("listed", "1.0.0");
+        let package_path = test_support::write_package(temp_dir.path(), &metadata, &[
+            ("usr/bin/listed", "#!/bin/sh\n"),
+            ("usr/share/doc/listed/README", "docs"),
+        ]);
+
+        // Append members extraction refuses, bypassing the tar crate's own path checks
+        let mut tarball = zstd::decode_all(std::fs::File::open(&package_path).unwrap()).unwrap();
+        tarball.truncate(tarball.len() - 1024);
+        for name in ["/etc/listed.conf", "../escape"] {
+            let mut header = tar::Header::new_gnu();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_size(0);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tarball.extend_from_slice(header.as_bytes());
+        }
+        tarball.extend_from_slice(&[0; 1024]);
+        std::fs::write(&package_path, zstd::encode_all(tarball.as_slice(), 3).unwrap()).unwrap();
+
+        let output_dir = temp_dir.path().join("out");
+        let package = PaxPackage::open(&package_path).unwrap();
+        let targets = plan_extraction(&package, &output_dir).unwrap();
+
+        let destinations: Vec<_> = targets.iter()
+            .map(|target| (target.entry.to_str().unwrap(), target.destination.clone()))
+            .collect();
+        assert_eq!(destinations, vec![
+            (crate::package::METADATA_PATH, Some(output_dir.join(crate::package::METADATA_PATH))),
+            ("usr/bin/listed", Some(output_dir.join("usr/bin/listed"))),
+            ("usr/share/doc/listed/README", Some(output_dir.join("usr/share/doc/listed/README"))),
+            ("/etc/listed.conf", None),
+            ("../escape", None),
+        ]);
+        assert!(targets[3].warning.as_ref().unwrap().contains("absolute path"));
+        assert!(targets[4].warning.as_ref().unwrap().contains("'..'"));
+
+        extract_package(package_path.to_str().unwrap(), output_dir.to_str(), false, true).unwrap();
+        assert!(!output_dir.exists());
+        assert!(extract_package(package_path.to_str().unwrap(), output_dir.to_str(), false, false).is_err());
+        assert!(!temp_dir.path().join("escape").exists());
+    }
+}
@@ -0,0 +1,61 @@
+use anyhow::Result;
+use crate::builder::Compression;
+use crate::source::ARCHIVE_FORMATS;
+
+/// Describe the supported source archive formats and package compression algorithms
+pub fn formats_report() -> String {
+    let mut report = String::from("Source archive formats:\n");
+    for format in ARCHIVE_FORMATS {
+        let handler = match format.tool {
+            Some(tool) => format!("shells out to `{}`", tool),
+            None => "native".to_string(),
+        };
+        report.push_str(&format!("  {:<10} {:<20} {}\n", format.name, format.extensions.join(", "), handler));
+    }
+
+    report.push_str("\nPackage compression algorithms:\n");
+    for compression in Compression::READABLE {
+        let handler = if compression.is_native() { "native" } else { "external" };
+        let usage = if Compression::WRITABLE.contains(compression) { "read, build output" } else { "read only" };
+        report.push_str(&format!("  {:<10} {:<20} {}\n", compression.name(), usage, handler));
+    }
+
+    report
+}
+
+/// List supported archive formats and compression algorithms
+pub fn show_formats() -> Result<()> {
+    println!("PAXBuild - Supported formats");
+    println!();
+    print!("{}", formats_report());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formats_report_lists_known_formats() {
+        let report = formats_report();
+        for name in ["tar.gz", ".tgz", "tar.xz", "tar.bz2", "zip", "zstd"] {
+            assert!(report.contains(name), "missing {} in:\n{}", name, report);
+        }
+        assert!(report.contains("shells out to `unzip`"));
+        assert!(report.lines().any(|line| line.contains("zstd") && line.contains("build output")));
+        assert!(report.lines().any(|line| line.contains("gzip") && line.contains("read only")));
+    }
+
+    #[test]
+    fn test_builder_writes_a_listed_output_compression() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let src = temp_dir.path().join("src");
+        std::fs::create_dir_all(&src).unwrap();
+        std::fs::write(src.join("file"), "data").unwrap();
+        let package = temp_dir.path().join("out.pax");
+        crate::builder::create_tarball(&src, &package, &std::collections::BTreeMap::new(), 3, None).unwrap();
+
+        let compression = crate::package::package_compression(&package).unwrap();
+        assert!(Compression::WRITABLE.contains(&compression));
+    }
+}
@@ -0,0 +1,407 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use crate::depspec::{Constraint, Provide};
+use crate::package::{PackageMetadata, PaxPackage};
+
+/// Default index file name, written into the indexed directory
+pub const INDEX_FILENAME: &str = "index.json";
+
+/// Extension of the metadata sidecar written next to a package (`<stem>.paxmeta.json`)
+pub const SIDECAR_EXTENSION: &str = ".paxmeta.json";
+
+/// A package's metadata, size and hash, kept next to it so indexers need not fetch the package
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetadataSidecar {
+    /// Package file size in bytes
+    pub size: u64,
+    /// SHA256 of the package file
+    pub sha256: String,
+    pub metadata: PackageMetadata,
+}
+
+/// Sidecar path for a package: `hello-1.0.0-x86_64.pax` becomes `hello-1.0.0-x86_64.paxmeta.json`
+pub fn sidecar_path(package_path: &Path) -> PathBuf {
+    let filename = package_path.file_name().unwrap_or_default().to_string_lossy();
+    let stem = filename.strip_suffix(".pax").unwrap_or(&filename);
+    package_path.with_file_name(format!("{}{}", stem, SIDECAR_EXTENSION))
+}
+
+/// Write the metadata sidecar of a finished package, returning its path
+pub fn write_sidecar(package_path: &Path) -> Result<PathBuf> {
+    let mut package = PaxPackage::open(package_path)?;
+    let sidecar = MetadataSidecar {
+        size: package.size()?,
+        sha256: package.calculate_hash()?,
+        metadata: package.load_metadata()?.clone(),
+    };
+    let json = serde_json::to_string_pretty(&sidecar)
+        .with_context(|| "Failed to serialize metadata sidecar")?;
+
+    let path = sidecar_path(package_path);
+    fs::write(&path, json)
+        .with_context(|| format!("Failed to write metadata sidecar: {}", path.display()))?;
+    Ok(path)
+}
+
+/// Read a metadata sidecar
+pub fn read_sidecar(path: &Path) -> Result<MetadataSidecar> {
+    let contents = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read metadata sidecar: {}", path.display()))?;
+    serde_json::from_str(&contents)
+        .with_context(|| format!("Failed to parse metadata sidecar: {}", path.display()))
+}
+
+/// One package in a repository index
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexEntry {
+    /// Package file name, relative to the indexed directory
+    pub filename: String,
+    pub name: String,
+    pub version: String,
+    pub description: String,
+    pub arch: Vec<String>,
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    #[serde(default)]
+    pub runtime_dependencies: Vec<String>,
+    #[serde(default)]
+    pub provides: Vec<Provide>,
+    #[serde(default)]
+    pub conflicts: Vec<String>,
+    /// Packages this one supersedes, as dependency constraints
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replaces: Vec<String>,
+    /// Payload files, for finding packages that would overwrite each other
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+    /// Package file size in bytes
+    pub size: u64,
+    /// SHA256 of the package file
+    pub sha256: String,
+    /// Package file modification time (seconds since the Unix epoch)
+    pub mtime: u64,
+}
+
+impl IndexEntry {
+    /// Whether this package replaces a package called `name` at `version`
+    pub fn replaces(&self, name: &str, version: Option<&str>) -> bool {
+        self.replaces.iter()
+            .filter_map(|replaced| Constraint::parse(replaced).ok())
+            .any(|constraint| constraint.satisfied_by(name, version))
+    }
+
+    /// Whether the two packages cannot be installed together: either one lists
+    /// the other in `conflicts`, or replaces it
+    pub fn conflicts_with(&self, other: &IndexEntry) -> bool {
+        let listed = |entry: &IndexEntry, other: &IndexEntry| entry.conflicts.iter()
+            .filter_map(|conflict| Constraint::parse(conflict).ok())
+            .any(|constraint| constraint.satisfied_by(&other.name, Some(&other.version)));
+        listed(self, other) || listed(other, self)
+            || self.replaces(&other.name, Some(&other.version))
+            || other.replaces(&self.name, Some(&self.version))
+    }
+}
+
+/// Repository index of every .pax package in a directory
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PackageIndex {
+    pub packages: Vec<IndexEntry>,
+}
+
+impl PackageIndex {
+    /// Load an index from a JSON file, or a YAML file with a `.yaml`/`.yml` extension
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read index: {}", path.display()))?;
+        let is_yaml = path.extension().is_some_and(|ext| ext == "yaml" || ext == "yml");
+        if is_yaml {
+            serde_yaml::from_str(&contents)
+                .with_context(|| format!("Failed to parse index: {}", path.display()))
+        } else {
+            serde_json::from_str(&contents)
+                .with_context(|| format!("Failed to parse index: {}", path.display()))
+        }
+    }
+
+    /// Whether some package in the index meets a dependency, by its name or one of its provides
+    pub fn satisfies(&self, constraint: &Constraint) -> bool {
+        self.packages.iter().any(|entry| {
+            constraint.satisfied_by(&entry.name, Some(&entry.version))
+                || entry.provides.iter().any(|provide| constraint.satisfied_by(&provide.name, provide.version.as_deref()))
+        })
+    }
+
+    /// Packages in the index that replace a package called `name` (at `version`, if known)
+    pub fn replacements_for(&self, name: &str, version: Option<&str>) -> Vec<&IndexEntry> {
+        self.packages.iter()
+            .filter(|entry| entry.replaces(name, version))
+            .collect()
+    }
+
+    /// The dependencies no package in the index satisfies; malformed ones count as unsatisfied
+    pub fn unsatisfied<'a>(&self, dependencies: &'a [String]) -> Vec<&'a str> {
+        dependencies.iter()
+            .filter(|dependency| !Constraint::parse(dependency).is_ok_and(|constraint| self.satisfies(&constraint)))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// Files of a package that packages in the index already ship, with the package shipping each
+    ///
+    /// Other versions of the same package, packages it replaces and packages
+    /// for other architectures can never be installed alongside it, so they
+    /// are not considered.
+    pub fn file_conflicts<'a>(&'a self, metadata: &'a PackageMetadata) -> Vec<(&'a str, &'a IndexEntry)> {
+        let replaced: Vec<Constraint> = metadata.replaces.iter()
+            .filter_map(|replaced| Constraint::parse(replaced).ok())
+            .collect();
+        let candidates: Vec<&IndexEntry> = self.packages.iter()
+            .filter(|entry| entry.name != metadata.name)
+            .filter(|entry| !replaced.iter().any(|constraint| constraint.satisfied_by(&entry.name, Some(&entry.version))))
+            .filter(|entry| entry.arch.is_empty() || metadata.arch.is_empty()
+                || entry.arch.iter().any(|arch| metadata.arch.contains(arch)))
+            .collect();
+
+        let mut conflicts = Vec::new();
+        for file in &metadata.files {
+            for entry in candidates.iter().filter(|entry| entry.files.contains(file)) {
+                conflicts.push((file.as_str(), *entry));
+            }
+        }
+        conflicts
+    }
+
+    /// Write the index as pretty-printed JSON
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .with_context(|| "Failed to serialize index")?;
+        fs::write(path, json)
+            .with_context(|| format!("Failed to write index: {}", path.display()))
+    }
+}
+
+/// Which packages an incremental index update may skip re-reading
+#[derive(Debug, Clone, PartialEq)]
+pub enum Since {
+    /// Re-read packages modified after this Unix timestamp
+    Time(u64),
+    /// Re-read packages missing from this earlier index
+    PreviousIndex(PathBuf),
+}
+
+impl Since {
+    /// Parse a `--since` value: a Unix timestamp or the path of an earlier index
+    pub fn parse(value: &str) -> Result<Self> {
+        if let Ok(timestamp) = value.parse::<u64>() {
+            return Ok(Since::Time(timestamp));
+        }
+        let path = PathBuf::from(value);
+        if !path.is_file() {
+            anyhow::bail!("Invalid --since '{}': expected a Unix timestamp or an existing index file", value);
+        }
+        Ok(Since::PreviousIndex(path))
+    }
+}
+
+/// Index the packages in `dir`, reusing entries from `previous` where allowed
+///
+/// An entry is reused when its package is still present with the size and
+/// modification time it was indexed with and, if `cutoff` is set, has not been
+/// modified after it; a package rewritten under the same name is re-read.
+/// Returns the index and the file names of the packages that were read.
+pub fn build_index(dir: &Path, previous: Option<&PackageIndex>, cutoff: Option<u64>) -> Result<(PackageIndex, Vec<String>)> {
+    let previous: HashMap<&str, &IndexEntry> = previous
+        .map(|index| index.packages.iter().map(|entry| (entry.filename.as_str(), entry)).collect())
+        .unwrap_or_default();
+
+    // A sidecar stands in for its package, which need not be present
+    let mut filenames: Vec<String> = fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_file())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .filter_map(|name| match name.strip_suffix(SIDECAR_EXTENSION) {
+            Some(stem) => Some(format!("{}.pax", stem)),
+            None => name.ends_with(".pax").then_some(name),
+        })
+        .collect();
+    filenames.sort();
+    filenames.dedup();
+
+    let mut index = PackageIndex::default();
+    let mut read = Vec::new();
+    for filename in filenames {
+        let path = dir.join(&filename);
+        let sidecar = sidecar_path(&path);
+        let mtime = modified_secs(if path.is_file() { &path } else { &sidecar })?;
+        let size = if path.is_file() { Some(package_size(&path)?) } else { None };
+
+        let reusable = previous.get(filename.as_str())
+            .filter(|entry| entry.mtime == mtime && size.is_none_or(|size| size == entry.size))
+            .filter(|_| cutoff.is_none_or(|cutoff| mtime <= cutoff));
+        let entry = match reusable {
+            Some(entry) => (*entry).clone(),
+            None => {
+                read.push(filename.clone());
+                match read_sidecar_entry(&path, &filename, mtime)? {
+                    Some(entry) => entry,
+                    None => read_entry(&path, &filename, mtime)?,
+                }
+            }
+        };
+        index.packages.push(entry);
+    }
+
+    Ok((index, read))
+}
+
+/// Read a package's metadata and hash into an index entry
+pub(crate) fn read_entry(path: &Path, filename: &str, mtime: u64) -> Result<IndexEntry> {
+    let mut package = PaxPackage::open(path)?;
+    let size = package.size()?;
+    let sha256 = package.calculate_hash()?;
+    let metadata = package.load_metadata()
+        .with_context(|| format!("Failed to read metadata of {}", filename))?;
+
+    Ok(entry_from_metadata(filename, metadata, size, sha256, mtime))
+}
+
+/// Build an index entry from a package's sidecar, if it has one
+///
+/// A sidecar whose recorded size differs from the package next to it is
+/// stale and ignored, so the package is read instead.
+fn read_sidecar_entry(path: &Path, filename: &str, mtime: u64) -> Result<Option<IndexEntry>> {
+    let sidecar_path = sidecar_path(path);
+    if !sidecar_path.is_file() {
+        return Ok(None);
+    }
+    let sidecar = read_sidecar(&sidecar_path)?;
+    if path.is_file() && fs::metadata(path).map(|metadata| metadata.len()).ok() != Some(sidecar.size) {
+        return Ok(None);
+    }
+    Ok(Some(entry_from_metadata(filename, &sidecar.metadata, sidecar.size, sidecar.sha256, mtime)))
+}
+
+/// Index entry for a package with the given metadata, size and hash
+fn entry_from_metadata(filename: &str, metadata: &PackageMetadata, size: u64, sha256: String, mtime: u64) -> IndexEntry {
+    IndexEntry {
+        filename: filename.to_string(),
+        name: metadata.name.clone(),
+        version: metadata.full_version(),
+        description: metadata.description.clone(),
+        arch: metadata.arch.clone(),
+        dependencies: metadata.dependencies.clone(),
+        runtime_dependencies: metadata.runtime_dependencies.clone(),
+        provides: metadata.provides.clone(),
+        conflicts: metadata.conflicts.clone(),
+        replaces: metadata.replaces.clone(),
+        files: metadata.files.clone(),
+        size,
+        sha256,
+        mtime,
+    }
+}
+
+/// Size of a package file in bytes
+fn package_size(path: &Path) -> Result<u64> {
+    fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .with_context(|| format!("Failed to get size of: {}", path.display()))
+}
+
+/// Modification time of a file in whole seconds since the Unix epoch
+pub(crate) fn modified_secs(path: &Path) -> Result<u64> {
+    let modified = fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .with_context(|| format!("Failed to get modification time of: {}", path.display()))?;
+    Ok(modified.duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0))
+}
+
+/// Generate a repository index (CLI entry point)
+pub fn index_cmd(dir: &str, output: Option<&str>, since: Option<&str>) -> Result<()> {
+    println!("PAXBuild - Indexing packages");
+    println!("Directory: {}", dir);
+
+    let dir = Path::new(dir);
+    let output = output.map(PathBuf::from).unwrap_or_else(|| dir.join(INDEX_FILENAME));
+
+    // With a timestamp, the index being replaced supplies the unchanged entries
+    let (previous, cutoff) = match since.map(Since::parse).transpose()? {
+        None => (None, None),
+        Some(Since::PreviousIndex(path)) => (Some(PackageIndex::load(&path)?), None),
+        Some(Since::Time(timestamp)) => {
+            let previous = if output.is_file() { Some(PackageIndex::load(&output)?) } else { None };
+            (previous, Some(timestamp))
+        }
+    };
+
+    let (index, read) = build_index(dir, previous.as_ref(), cutoff)?;
+    index.write(&output)?;
+
+    println!("Read {} package(s), reused {}", read.len(), index.packages.len() - read.len());
+    println!("Index written to: {}", output.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_incremental_index_reads_only_new_packages() {
+        let temp_dir = TempDir::new().unwrap();
+        test_support::write_package(temp_dir.path(), &test_support::metadata("alpha", "1.0.0"), &[("usr/bin/alpha", "a")]);
+
+        let (first, read) = build_index(temp_dir.path(), None, None).unwrap();
+        assert_eq!(read, vec!["alpha-1.0.0-x86_64.pax"]);
+        let previous_path = temp_dir.path().join("previous.json");
+        first.write(&previous_path).unwrap();
+
+        test_support::write_package(temp_dir.path(), &test_support::metadata("beta", "2.0.0"), &[("usr/bin/beta", "b")]);
+
+        let previous = match Since::parse(previous_path.to_str().unwrap()).unwrap() {
+            Since::PreviousIndex(path) => PackageIndex::load(&path).unwrap(),
+            since => panic!("unexpected {:?}", since),
+        };
+        let (index, read) = build_index(temp_dir.path(), Some(&previous), None).unwrap();
+        assert_eq!(read, vec!["beta-2.0.0-x86_64.pax"]);
+        assert_eq!(index.packages.len(), 2);
+        assert_eq!(index.packages[0], first.packages[0]);
+        assert_eq!(index.packages[1].name, "beta");
+
+        // Everything is newer than the epoch, so a zero cutoff re-reads all packages
+        let (_, read) = build_index(temp_dir.path(), Some(&index), Some(0)).unwrap();
+        assert_eq!(read.len(), 2);
+    }
+
+    #[test]
+    fn test_incremental_index_rereads_rewritten_packages() {
+        let temp_dir = TempDir::new().unwrap();
+        let metadata = test_support::metadata("alpha", "1.0.0");
+        let path = test_support::write_package(temp_dir.path(), &metadata, &[("usr/bin/alpha", "a")]);
+        let (first, _) = build_index(temp_dir.path(), None, None).unwrap();
+
+        // Rebuilt in place with the old modification time kept
+        let mtime = fs::metadata(&path).unwrap().modified().unwrap();
+        test_support::write_package(temp_dir.path(), &metadata, &[("usr/bin/alpha", "rebuilt")]);
+        fs::File::options().write(true).open(&path).unwrap().set_modified(mtime).unwrap();
+
+        let (index, read) = build_index(temp_dir.path(), Some(&first), None).unwrap();
+        assert_eq!(read, vec!["alpha-1.0.0-x86_64.pax"]);
+        assert_ne!(index.packages[0].sha256, first.packages[0].sha256);
+        assert_eq!(index.packages[0].size, fs::metadata(&path).unwrap().len());
+
+        // The same bytes with a new modification time are re-read as well
+        fs::File::options().write(true).open(&path).unwrap()
+            .set_modified(mtime + std::time::Duration::from_secs(60)).unwrap();
+        let (_, read) = build_index(temp_dir.path(), Some(&index), None).unwrap();
+        assert_eq!(read, vec!["alpha-1.0.0-x86_64.pax"]);
+    }
+}
@@ -1,8 +1,94 @@
-use anyhow::Result;
-use crate::package::PaxPackage;
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use crate::package::{PackageMetadata, PaxPackage};
+
+/// What `info --json` prints: the package metadata, plus fields computed from the package file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageInfo {
+    /// Every metadata field, at the top level of the object
+    #[serde(flatten)]
+    pub metadata: PackageMetadata,
+    /// Size of the package file in bytes
+    pub size: u64,
+    /// Hex SHA256 of the package file
+    pub sha256: String,
+    /// File name of the package
+    pub filename: Option<String>,
+    /// Name, version and architecture parsed from `filename`, if it follows the naming scheme
+    pub parsed_filename: Option<FilenameInfo>,
+}
+
+/// Package name, version and architecture as encoded in a package file name
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilenameInfo {
+    pub name: String,
+    pub version: String,
+    pub arch: String,
+}
+
+impl PackageInfo {
+    /// Collect the information about an opened package, or `None` if it has no metadata
+    fn collect(package: &mut PaxPackage) -> Result<Option<Self>> {
+        let size = package.size()?;
+        let sha256 = package.calculate_hash()?;
+        let filename = package.filename().map(|filename| filename.to_string());
+        let parsed_filename = package.parse_package_info()
+            .map(|(name, version, arch)| FilenameInfo { name, version, arch });
+        let Some(metadata) = package.try_load_metadata()? else {
+            return Ok(None);
+        };
+
+        Ok(Some(PackageInfo { metadata: metadata.clone(), size, sha256, filename, parsed_filename }))
+    }
+}
+
+/// Package metadata and computed fields ([`PackageInfo`]) as pretty-printed JSON
+pub fn info_json(package_path: &str) -> Result<String> {
+    let mut package = PaxPackage::open(package_path)?;
+    let info = PackageInfo::collect(&mut package)?
+        .ok_or_else(|| anyhow::anyhow!("{} not found in package", crate::package::METADATA_PATH))?;
+    serde_json::to_string_pretty(&info)
+        .with_context(|| "Failed to serialize package information")
+}
+
+/// Like [`info_json`], but a package without metadata gives `null` instead of an error
+pub fn info_json_best_effort(package_path: &str) -> Result<String> {
+    let mut package = PaxPackage::open(package_path)?;
+    let info = PackageInfo::collect(&mut package)?;
+    serde_json::to_string_pretty(&info)
+        .with_context(|| "Failed to serialize package information")
+}
+
+/// Print a file list, truncated to the first 20 entries
+fn print_file_list(files: &[String]) {
+    println!("  Files: {}", files.len());
+    
+    if files.len() <= 20 {
+        println!("  File List:");
+        for file in files {
+            println!("    {}", file);
+        }
+    } else {
+        println!("  File List (showing first 20):");
+        for file in files.iter().take(20) {
+            println!("    {}", file);
+        }
+        println!("    ... and {} more files", files.len() - 20);
+    }
+}
 
 /// Show information about a .pax package
-pub fn show_info(package_path: &str) -> Result<()> {
+///
+/// With `best_effort`, a package without metadata (such as a legacy archive
+/// of bare files) is described from its file name and contents, and its
+/// metadata reported as unavailable, instead of failing.
+pub fn show_info(package_path: &str, json: bool, best_effort: bool) -> Result<()> {
+    if json {
+        let json = if best_effort { info_json_best_effort(package_path)? } else { info_json(package_path)? };
+        println!("{}", json);
+        return Ok(());
+    }
+
     println!("PAXBuild - Package Information");
     println!("Package: {}", package_path);
     println!();
@@ -16,12 +102,47 @@ pub fn show_info(package_path: &str) -> Result<()> {
     let package_info = package.parse_package_info();
 
     // Load metadata (mutable borrow)
-    let metadata = package.load_metadata()?;
+    let metadata = if best_effort {
+        match package.try_load_metadata()? {
+            Some(metadata) => metadata,
+            None => {
+                println!("Package Information:");
+                println!("  Metadata: unavailable (no {} in package)", crate::package::METADATA_PATH);
+                if let Some(filename) = filename {
+                    println!("  Filename: {}", filename);
+                }
+                if let Some((name, version, arch)) = package_info {
+                    println!("  Parsed from filename:");
+                    println!("    Name: {}", name);
+                    println!("    Version: {}", version);
+                    println!("    Architecture: {}", arch);
+                }
+                println!();
+
+                // Without a recorded file list, list what the archive holds
+                let mut files = Vec::new();
+                for entry in package.entries()? {
+                    let entry = entry?;
+                    if !entry.is_dir() {
+                        files.push(entry.path.to_string_lossy().to_string());
+                    }
+                }
+
+                println!("Package File Information:");
+                println!("  Size: {} bytes", size);
+                println!("  Hash: {}", hash);
+                print_file_list(&files);
+                return Ok(());
+            }
+        }
+    } else {
+        package.load_metadata()?
+    };
 
     // Display package information
     println!("Package Information:");
     println!("  Name: {}", metadata.name);
-    println!("  Version: {}", metadata.version);
+    println!("  Version: {}", metadata.full_version());
     println!("  Description: {}", metadata.description);
     println!("  Architectures: {:?}", metadata.arch);
 
@@ -46,12 +167,17 @@ pub fn show_info(package_path: &str) -> Result<()> {
     }
     
     if !metadata.provides.is_empty() {
-        println!("  Provides: {:?}", metadata.provides);
+        let provides: Vec<String> = metadata.provides.iter().map(|p| p.to_string()).collect();
+        println!("  Provides: {}", provides.join(", "));
     }
     
     if !metadata.conflicts.is_empty() {
         println!("  Conflicts: {:?}", metadata.conflicts);
     }
+
+    if !metadata.replaces.is_empty() {
+        println!("  Replaces: {:?}", metadata.replaces);
+    }
     
     if let Some(install) = &metadata.install_script {
         println!("  Install Script: {}", install);
@@ -60,6 +186,13 @@ pub fn show_info(package_path: &str) -> Result<()> {
     if let Some(uninstall) = &metadata.uninstall_script {
         println!("  Uninstall Script: {}", uninstall);
     }
+
+    if !metadata.extra.is_empty() {
+        println!("  Extra Metadata:");
+        for (key, value) in &metadata.extra {
+            println!("    {}: {}", key, value);
+        }
+    }
     
     println!();
     
@@ -70,20 +203,95 @@ pub fn show_info(package_path: &str) -> Result<()> {
     println!("  Hash: {}", hash);
     
     // List files from metadata
-    println!("  Files: {}", metadata.files.len());
-    
-    if metadata.files.len() <= 20 {
-        println!("  File List:");
-        for file in &metadata.files {
-            println!("    {}", file);
-        }
-    } else {
-        println!("  File List (showing first 20):");
-        for file in metadata.files.iter().take(20) {
-            println!("    {}", file);
-        }
-        println!("    ... and {} more files", metadata.files.len() - 20);
-    }
+    print_file_list(&metadata.files);
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build::{build_package, BuildOptions};
+    use crate::test_support;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_extra_metadata_in_info_json() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = test_support::source_tarball(&[("hello-1.0.0/README", "hello")]);
+        let url = test_support::serve(source, "hello-1.0.0.tar.gz");
+        let recipe_path = test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: hello
+version: 1.0.0
+description: Test package
+source: {}
+arch: [x86_64]
+extra:
+  git_branch: main
+  ticket: OLD-1
+build: cp README $PAX_BUILD_ROOT/
+"#, url));
+
+        let output = temp_dir.path().join("hello.pax");
+        let options = BuildOptions {
+            output: Some(output.to_str().unwrap().to_string()),
+            extra_metadata: [crate::build::parse_meta_assignment("ticket=BUILD-42").unwrap()].into(),
+            ..Default::default()
+        };
+        build_package(recipe_path.to_str().unwrap(), &options).unwrap();
+
+        let info: serde_json::Value = serde_json::from_str(&info_json(output.to_str().unwrap()).unwrap()).unwrap();
+        assert_eq!(info["name"], "hello");
+        assert_eq!(info["extra"]["ticket"], "BUILD-42");
+        assert_eq!(info["extra"]["git_branch"], "main");
+    }
+
+    #[test]
+    fn test_info_json_parses_back_with_computed_fields() {
+        let temp_dir = TempDir::new().unwrap();
+        let metadata = test_support::metadata("hello", "1.0.0");
+        let package_path = test_support::write_package(temp_dir.path(), &metadata, &[("usr/bin/hello", "echo hello")]);
+        let package_path = package_path.to_str().unwrap();
+
+        let json = info_json(package_path).unwrap();
+        assert!(json.trim_start().starts_with('{') && json.trim_end().ends_with('}'), "{}", json);
+        let info: PackageInfo = serde_json::from_str(&json).unwrap();
+        assert_eq!(info.metadata.name, "hello");
+        assert_eq!(info.metadata.full_version(), "1.0.0");
+        assert_eq!(info.size, std::fs::metadata(package_path).unwrap().len());
+        assert_eq!(info.sha256, PaxPackage::open(package_path).unwrap().calculate_hash().unwrap());
+        let arch = metadata.arch[0].clone();
+        assert_eq!(info.filename, Some(format!("hello-1.0.0-{}.pax", arch)));
+        assert_eq!(info.parsed_filename, Some(FilenameInfo { name: "hello".to_string(), version: "1.0.0".to_string(), arch }));
+    }
+
+    #[test]
+    fn test_metadata_less_package_extracts_and_reports_missing_metadata() {
+        let temp_dir = TempDir::new().unwrap();
+        let package_path = test_support::write_package_members(temp_dir.path(), "bare-1.0.0-x86_64.pax", None, &[
+            ("usr/bin/bare", b"#!/bin/sh\n", 0o644),
+            ("usr/share/bare/README", b"bare", 0o644),
+        ]);
+        let package_path = package_path.to_str().unwrap();
+
+        let output_dir = temp_dir.path().join("out");
+        crate::extract::extract_package(package_path, output_dir.to_str(), false, false).unwrap();
+        assert_eq!(std::fs::read_to_string(output_dir.join("usr/share/bare/README")).unwrap(), "bare");
+
+        assert!(PaxPackage::open(package_path).unwrap().try_load_metadata().unwrap().is_none());
+        let error = show_info(package_path, false, false).unwrap_err();
+        assert!(error.to_string().contains("not found in package"), "{}", error);
+        show_info(package_path, false, true).unwrap();
+        assert_eq!(info_json_best_effort(package_path).unwrap(), "null");
+    }
+
+    #[test]
+    fn test_extra_metadata_rejects_reserved_keys() {
+        assert!(crate::build::parse_meta_assignment("version=2.0").is_err());
+        assert!(crate::build::parse_meta_assignment("no-equals-sign").is_err());
+        assert_eq!(
+            crate::build::parse_meta_assignment("build.url=https://ci/1?a=b").unwrap(),
+            ("build.url".to_string(), "https://ci/1?a=b".to_string())
+        );
+    }
+}
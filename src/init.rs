@@ -0,0 +1,120 @@
+use anyhow::{Result, Context};
+use std::fs;
+use std::path::Path;
+use crate::recipe::{BuildRecipe, BuildSystem};
+
+/// Architectures a new recipe targets: the ones this machine can build for
+fn host_architectures() -> Vec<String> {
+    let archs: Vec<String> = BuildRecipe::get_compatible_architectures().into_iter()
+        .filter(|arch| BuildRecipe::is_architecture_supported(arch))
+        .collect();
+    if archs.is_empty() {
+        vec!["x86_64".to_string()]
+    } else {
+        archs
+    }
+}
+
+/// A commented recipe for `name`, with every optional field explained in place
+///
+/// The version and source are placeholders to be filled in; the build script
+/// is the default autotools one.
+pub fn recipe_template(name: &str) -> String {
+    let build: String = BuildSystem::Autotools.script()
+        .split(" && ")
+        .map(|command| format!("  {}\n", command))
+        .collect();
+
+    format!(r#"# Recipe for {name}, generated by `paxbuild init`
+# Build it with: paxbuild build <this file>
+
+name: {name}
+version: 0.1.0  # TODO: upstream version
+# epoch: 1  # Bump when upstream resets its versioning
+description: TODO describe {name}
+
+# Source archive: a URL, a path relative to this recipe, a list of sources,
+# a map of architecture to source, or git+<url>#<ref>
+source: https://example.com/{name}-0.1.0.tar.gz  # TODO: real source URL
+# hash: sha256:...  # `paxbuild build --write-hash` fills this in
+# mirrors:  # Alternate URLs for the same file
+#   - https://mirror.example.org/{name}-0.1.0.tar.gz
+
+arch: [{arch}]
+
+# Runs in the extracted source with PAX_BUILD_ROOT, PAX_BUILD_DIR, PAX_JOBS
+# and PAX_ARCH set; install everything into $PAX_BUILD_ROOT. Without a build
+# script, one is picked from the source tree (cmake, meson, cargo or autotools)
+build: |
+{build}
+# check: make check  # Test suite, run between building and packaging
+
+# Build-time and runtime dependencies (name, optionally with a version constraint)
+dependencies: []
+runtime_dependencies: []
+
+# provides:  # Defaults to the package name
+#   - {name}
+# conflicts: []
+# replaces: []
+
+# patches:  # Applied in order as `patch -p1`
+#   - fix-build.patch
+
+# exclude:  # Installed paths left out of the package (glob patterns)
+#   - "*.a"
+
+# env:  # Extra environment variables for the build script
+#   CFLAGS: -O2
+
+# install: ldconfig  # Run after installing the package
+# uninstall: ldconfig  # Run after removing it
+"#, name = name, arch = host_architectures().join(", "), build = build.trim_end())
+}
+
+/// Write a new recipe template for `name` to `output` (CLI entry point)
+pub fn init_cmd(name: &str, output: Option<&str>, force: bool) -> Result<()> {
+    println!("PAXBuild - Creating recipe");
+
+    let default_output = format!("{}.paxmeta", name);
+    let output = Path::new(output.unwrap_or(&default_output));
+    if output.exists() && !force {
+        anyhow::bail!("{} already exists (use --force to overwrite it)", output.display());
+    }
+
+    let template = recipe_template(name);
+    BuildRecipe::from_yaml(&template)
+        .and_then(|recipe| recipe.validate())
+        .with_context(|| format!("Cannot create a recipe for package name '{}'", name))?;
+
+    fs::write(output, template)
+        .with_context(|| format!("Failed to write recipe: {}", output.display()))?;
+    println!("Wrote {}", output.display());
+    println!("Fill in the version, description and source, then run: paxbuild build {}", output.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_init_template_parses_and_requires_force() {
+        let recipe = BuildRecipe::from_yaml(&recipe_template("hello")).unwrap();
+        recipe.validate().unwrap();
+        assert_eq!(recipe.name, "hello");
+        assert_eq!(recipe.arch, host_architectures());
+        assert!(recipe.build.as_deref().unwrap().contains("make install DESTDIR=$PAX_BUILD_ROOT"));
+
+        let temp_dir = TempDir::new().unwrap();
+        let output = temp_dir.path().join("hello.paxmeta");
+        let output_str = output.to_str().unwrap();
+        init_cmd("hello", Some(output_str), false).unwrap();
+        assert!(init_cmd("hello", Some(output_str), false).is_err());
+        fs::write(&output, "changed").unwrap();
+        init_cmd("hello", Some(output_str), true).unwrap();
+        assert_eq!(fs::read_to_string(&output).unwrap(), recipe_template("hello"));
+    }
+}
@@ -0,0 +1,211 @@
+use anyhow::{Result, Context};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use std::fmt;
+use std::io::Read;
+use std::path::Path;
+
+/// Hash algorithms an expected digest can name
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha256,
+    Sha384,
+    Sha512,
+    Blake3,
+}
+
+impl HashAlgorithm {
+    const ALL: [HashAlgorithm; 4] = [HashAlgorithm::Sha256, HashAlgorithm::Sha384, HashAlgorithm::Sha512, HashAlgorithm::Blake3];
+
+    /// Name as used in SRI strings and `<name>:<hex>` hashes
+    pub fn name(&self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha256 => "sha256",
+            HashAlgorithm::Sha384 => "sha384",
+            HashAlgorithm::Sha512 => "sha512",
+            HashAlgorithm::Blake3 => "blake3",
+        }
+    }
+
+    /// Parse an algorithm name (a `--hash-algo` value)
+    pub fn parse(value: &str) -> Result<Self> {
+        Self::ALL.iter().copied()
+            .find(|algorithm| algorithm.name() == value)
+            .ok_or_else(|| anyhow::anyhow!(
+                "Unknown hash algorithm '{}'. Valid algorithms are: {}",
+                value,
+                Self::ALL.iter().map(|algorithm| algorithm.name()).collect::<Vec<_>>().join(", "),
+            ))
+    }
+
+    /// Whether Subresource Integrity strings can use this algorithm
+    fn in_sri(&self) -> bool {
+        !matches!(self, HashAlgorithm::Blake3)
+    }
+
+    /// Length of a digest in bytes
+    pub fn digest_len(&self) -> usize {
+        match self {
+            HashAlgorithm::Sha256 | HashAlgorithm::Blake3 => 32,
+            HashAlgorithm::Sha384 => 48,
+            HashAlgorithm::Sha512 => 64,
+        }
+    }
+
+    /// Hex digest of a file's contents
+    pub fn hash_file(&self, path: &Path) -> Result<String> {
+        let mut file = std::fs::File::open(path)
+            .with_context(|| format!("Failed to open file: {}", path.display()))?;
+        let digest = self.digest_reader(&mut file)
+            .with_context(|| format!("Failed to read file for hashing: {}", path.display()))?;
+        Ok(hex::encode(digest))
+    }
+
+    /// Digest everything `reader` yields
+    pub fn digest_reader(&self, reader: &mut impl Read) -> std::io::Result<Vec<u8>> {
+        fn digest<D: Digest + std::io::Write>(reader: &mut impl Read) -> std::io::Result<Vec<u8>> {
+            let mut hasher = D::new();
+            std::io::copy(reader, &mut hasher)?;
+            Ok(hasher.finalize().to_vec())
+        }
+        match self {
+            HashAlgorithm::Sha256 => digest::<Sha256>(reader),
+            HashAlgorithm::Sha384 => digest::<Sha384>(reader),
+            HashAlgorithm::Sha512 => digest::<Sha512>(reader),
+            HashAlgorithm::Blake3 => {
+                let mut hasher = blake3::Hasher::new();
+                std::io::copy(reader, &mut hasher)?;
+                Ok(hasher.finalize().as_bytes().to_vec())
+            }
+        }
+    }
+}
+
+/// A digest something is expected to have
+///
+/// Written either as hex, bare for SHA256 or prefixed with the algorithm
+/// (`sha256:`, `blake3:`, ...), or as a Subresource Integrity string:
+/// `sha256-`, `sha384-` or `sha512-` followed by the base64 digest. It
+/// displays in the notation it was written in, with the prefix only for
+/// algorithms other than SHA256.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedDigest {
+    pub algorithm: HashAlgorithm,
+    pub digest: Vec<u8>,
+    sri: bool,
+}
+
+impl ExpectedDigest {
+    /// Parse a hex or SRI digest
+    pub fn parse(expected: &str) -> Result<Self> {
+        let expected = expected.trim();
+        for algorithm in HashAlgorithm::ALL.into_iter().filter(HashAlgorithm::in_sri) {
+            if let Some(encoded) = expected.strip_prefix(algorithm.name()).and_then(|rest| rest.strip_prefix('-')) {
+                let digest = BASE64.decode(encoded)
+                    .with_context(|| format!("Invalid base64 in integrity string: {}", expected))?;
+                if digest.len() != algorithm.digest_len() {
+                    anyhow::bail!(
+                        "Integrity string {} has a {}-byte digest; {} digests are {} bytes",
+                        expected, digest.len(), algorithm.name(), algorithm.digest_len()
+                    );
+                }
+                return Ok(ExpectedDigest { algorithm, digest, sri: true });
+            }
+        }
+
+        let (algorithm, encoded) = HashAlgorithm::ALL.into_iter()
+            .find_map(|algorithm| {
+                let encoded = expected.strip_prefix(algorithm.name())?.strip_prefix(':')?;
+                Some((algorithm, encoded))
+            })
+            .unwrap_or((HashAlgorithm::Sha256, expected));
+        let digest = hex::decode(encoded)
+            .ok()
+            .filter(|digest| digest.len() == algorithm.digest_len())
+            .ok_or_else(|| anyhow::anyhow!(
+                "Invalid hash: {} (expected hex SHA256, <algorithm>:<hex> such as blake3:<hex>, or an SRI string such as sha256-<base64>)", expected
+            ))?;
+        Ok(ExpectedDigest { algorithm, digest, sri: false })
+    }
+
+    /// Digest `reader` with this digest's algorithm
+    pub fn calculate(&self, reader: &mut impl Read) -> std::io::Result<Vec<u8>> {
+        self.algorithm.digest_reader(reader)
+    }
+
+    /// Whether a calculated digest is the expected one
+    pub fn matches(&self, digest: &[u8]) -> bool {
+        self.digest == digest
+    }
+
+    /// Write a digest of this algorithm in the notation this one was written in
+    pub fn format(&self, digest: &[u8]) -> String {
+        if self.sri {
+            format!("{}-{}", self.algorithm.name(), BASE64.encode(digest))
+        } else if self.algorithm == HashAlgorithm::Sha256 {
+            hex::encode(digest)
+        } else {
+            format!("{}:{}", self.algorithm.name(), hex::encode(digest))
+        }
+    }
+}
+
+impl fmt::Display for ExpectedDigest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.format(&self.digest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sri_and_hex_digests() {
+        let data = b"hello sri";
+        let sha256 = Sha256::digest(data);
+        let sha512 = Sha512::digest(data);
+
+        let hex_digest = ExpectedDigest::parse(&format!("sha256:{}", hex::encode(sha256))).unwrap();
+        let sri_digest = ExpectedDigest::parse(&format!("sha256-{}", BASE64.encode(sha256))).unwrap();
+        assert_eq!(hex_digest.digest, sri_digest.digest);
+        assert_eq!(hex_digest.to_string(), hex::encode(sha256));
+        assert_eq!(sri_digest.to_string(), format!("sha256-{}", BASE64.encode(sha256)));
+
+        let sha512_digest = ExpectedDigest::parse(&format!("sha512-{}", BASE64.encode(sha512))).unwrap();
+        assert_eq!(sha512_digest.algorithm, HashAlgorithm::Sha512);
+        assert!(sha512_digest.matches(&sha512_digest.calculate(&mut &data[..]).unwrap()));
+        assert!(!sha512_digest.matches(&sha512_digest.calculate(&mut &b"other"[..]).unwrap()));
+
+        // A sha256 digest passed off as sha384, bad base64 and short hex are all rejected
+        assert!(ExpectedDigest::parse(&format!("sha384-{}", BASE64.encode(sha256))).is_err());
+        assert!(ExpectedDigest::parse("sha256-not*base64").is_err());
+        assert!(ExpectedDigest::parse("abc123").is_err());
+    }
+
+    #[test]
+    fn test_blake3_digests() {
+        // BLAKE3 of "abc", from the reference test vectors
+        let known = "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85";
+        assert_eq!(hex::encode(HashAlgorithm::Blake3.digest_reader(&mut &b"abc"[..]).unwrap()), known);
+
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let file = temp_dir.path().join("abc");
+        std::fs::write(&file, "abc").unwrap();
+        assert_eq!(HashAlgorithm::Blake3.hash_file(&file).unwrap(), known);
+
+        let expected = ExpectedDigest::parse(&format!("blake3:{}", known)).unwrap();
+        assert_eq!(expected.algorithm, HashAlgorithm::Blake3);
+        assert_eq!(expected.to_string(), format!("blake3:{}", known));
+        assert!(expected.matches(&expected.calculate(&mut &b"abc"[..]).unwrap()));
+        assert!(!expected.matches(&expected.calculate(&mut &b"abd"[..]).unwrap()));
+
+        // Bare hex stays SHA256, and there is no BLAKE3 SRI form
+        assert_eq!(ExpectedDigest::parse(known).unwrap().algorithm, HashAlgorithm::Sha256);
+        assert!(ExpectedDigest::parse("blake3-ZDezrDhGUTP/tjt1JzqNtUjFWEZdedsD/TWcbNW9nYU=").is_err());
+        assert_eq!(HashAlgorithm::parse("blake3").unwrap(), HashAlgorithm::Blake3);
+        assert!(HashAlgorithm::parse("md5").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+use anyhow::{Result, Context};
+use ed25519_dalek::VerifyingKey;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Kind of key stored in a key file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    Private,
+    Public,
+}
+
+impl FromStr for KeyType {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "private" => Ok(KeyType::Private),
+            "public" => Ok(KeyType::Public),
+            _ => anyhow::bail!("Invalid key type: {}. Valid key types are: private, public", s),
+        }
+    }
+}
+
+impl fmt::Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyType::Private => write!(f, "private"),
+            KeyType::Public => write!(f, "public"),
+        }
+    }
+}
+
+/// Read a hex-encoded 32-byte key file
+pub fn read_key_bytes(path: &Path) -> Result<[u8; 32]> {
+    let key_hex = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read key file: {}", path.display()))?;
+
+    let key_bytes = hex::decode(key_hex.trim())
+        .with_context(|| format!("Failed to decode key hex in: {}", path.display()))?;
+
+    key_bytes.as_slice().try_into()
+        .map_err(|_| anyhow::anyhow!("Invalid key length: expected 32 bytes, got {}", key_bytes.len()))
+}
+
+/// Detect whether a key file holds a private or a public key
+///
+/// Any 32 bytes form a valid Ed25519 private key, but only encodings of a
+/// curve point are valid public keys. Keys that are valid as both are
+/// resolved from the filename (`private`/`secret` vs `public`/`pub`).
+pub fn detect_key_type(path: &Path, key_bytes: &[u8; 32]) -> Result<KeyType> {
+    if VerifyingKey::from_bytes(key_bytes).is_err() {
+        return Ok(KeyType::Private);
+    }
+
+    key_type_from_filename(path).ok_or_else(|| anyhow::anyhow!(
+        "Cannot determine key type of {}: it is valid as both a private and a public key. Specify --type private|public",
+        path.display()
+    ))
+}
+
+/// Guess key type from naming conventions like `private.key` or `signing.pub`
+fn key_type_from_filename(path: &Path) -> Option<KeyType> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+
+    if name.contains("priv") || name.contains("secret") {
+        Some(KeyType::Private)
+    } else if name.contains("pub") {
+        Some(KeyType::Public)
+    } else {
+        None
+    }
+}
+
+/// Import a key into a new location, detecting its type when not specified
+pub fn import_key(source: &Path, dest: &Path, key_type: Option<KeyType>) -> Result<KeyType> {
+    let key_bytes = read_key_bytes(source)?;
+
+    let key_type = match key_type {
+        Some(KeyType::Public) => {
+            VerifyingKey::from_bytes(&key_bytes)
+                .with_context(|| format!("Not a valid Ed25519 public key: {}", source.display()))?;
+            KeyType::Public
+        }
+        Some(KeyType::Private) => KeyType::Private,
+        None => detect_key_type(source, &key_bytes)?,
+    };
+
+    fs::write(dest, hex::encode(key_bytes))
+        .with_context(|| format!("Failed to write key: {}", dest.display()))?;
+
+    Ok(key_type)
+}
+
+/// Import a key (CLI entry point)
+pub fn import_key_cmd(source: &str, dest: &str, key_type: Option<&str>) -> Result<()> {
+    println!("PAXBuild - Importing key");
+    println!("Source: {}", source);
+
+    let key_type = key_type.map(KeyType::from_str).transpose()?;
+    let detected = key_type.is_none();
+    let key_type = import_key(Path::new(source), Path::new(dest), key_type)?;
+
+    if detected {
+        println!("Detected key type: {}", key_type);
+    }
+    println!("Imported {} key to: {}", key_type, dest);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+    use rand::RngCore;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_import_private_key_without_type() {
+        let temp_dir = TempDir::new().unwrap();
+
+        // Pick a seed that is not a curve point so it can only be a private key
+        let mut seed = [0u8; 32];
+        loop {
+            OsRng.fill_bytes(&mut seed);
+            if VerifyingKey::from_bytes(&seed).is_err() {
+                break;
+            }
+        }
+
+        let source = temp_dir.path().join("backup.key");
+        let dest = temp_dir.path().join("imported.key");
+        fs::write(&source, hex::encode(seed)).unwrap();
+
+        assert_eq!(import_key(&source, &dest, None).unwrap(), KeyType::Private);
+        assert_eq!(read_key_bytes(&dest).unwrap(), seed);
+    }
+
+    #[test]
+    fn test_import_public_key_without_type() {
+        let temp_dir = TempDir::new().unwrap();
+        let public_key = SigningKey::generate(&mut OsRng).verifying_key().to_bytes();
+
+        let source = temp_dir.path().join("signing.pub");
+        let dest = temp_dir.path().join("imported.key");
+        fs::write(&source, hex::encode(public_key)).unwrap();
+
+        assert_eq!(import_key(&source, &dest, None).unwrap(), KeyType::Public);
+        assert_eq!(read_key_bytes(&dest).unwrap(), public_key);
+    }
+
+    #[test]
+    fn test_import_ambiguous_key_requires_type() {
+        let temp_dir = TempDir::new().unwrap();
+        let public_key = SigningKey::generate(&mut OsRng).verifying_key().to_bytes();
+
+        let source = temp_dir.path().join("backup.key");
+        let dest = temp_dir.path().join("imported.key");
+        fs::write(&source, hex::encode(public_key)).unwrap();
+
+        assert!(import_key(&source, &dest, None).is_err());
+        assert!(!dest.exists());
+        assert_eq!(import_key(&source, &dest, Some(KeyType::Public)).unwrap(), KeyType::Public);
+    }
+}
@@ -1,13 +1,45 @@
 pub mod recipe;
+pub mod analyze;
+pub mod attestation;
 pub mod builder;
+pub mod capabilities;
+pub mod closure;
 pub mod package;
 pub mod crypto;
+pub mod depspec;
+pub mod delta;
+pub mod dump;
+pub mod elf;
+pub mod exec;
+pub mod keys;
+pub mod lint;
 pub mod source;
 pub mod build;
 pub mod verify;
 pub mod extract;
+pub mod formats;
+pub mod info;
+pub mod init;
+pub mod integrity;
+pub mod prune;
+pub mod remeta;
+pub mod repro;
+pub mod index;
+pub mod manifest;
+pub mod merge;
+pub mod ownership;
+pub mod sign;
+pub mod sign_recipe;
+pub mod timestamp;
+pub mod vendor;
+pub mod version;
+pub mod warnings;
+pub mod watch;
+
+#[cfg(test)]
+mod test_support;
 
 pub use recipe::BuildRecipe;
-pub use builder::PackageBuilder;
+pub use builder::{BuildReport, PackageBuilder};
 pub use package::PaxPackage;
 pub use source::SourceManager;
@@ -0,0 +1,202 @@
+use anyhow::Result;
+use serde::Serialize;
+use crate::recipe::{BuildRecipe, Source};
+
+/// Variables paxbuild sets for build and check scripts (besides `PAX_FEATURE_<NAME>`)
+const BUILD_VARIABLES: &[&str] = &[
+    "PAX_BUILD_ROOT", "PAX_PACKAGE_NAME", "PAX_PACKAGE_VERSION", "PAX_ARCH",
+    "PAX_TARGET_ARCH", "PAX_SOURCE_DIR", "PAX_BUILD_DIR", "PAX_JOBS",
+];
+
+/// URL schemes sources can be fetched with (`git+<scheme>` sources aside)
+const SOURCE_SCHEMES: &[&str] = &["http", "https", "file", "git"];
+
+/// How serious a lint finding is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    /// Worth fixing, but the recipe still builds
+    Warning,
+    /// The recipe cannot be built as it is
+    Error,
+}
+
+/// One problem found in a recipe
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl Diagnostic {
+    fn warning(message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Warning, message: message.into() }
+    }
+
+    fn error(message: impl Into<String>) -> Self {
+        Diagnostic { severity: Severity::Error, message: message.into() }
+    }
+}
+
+/// Check a recipe without building it
+///
+/// A failed `validate` is the (only) error, since it stops at the first
+/// problem; everything else is a warning.
+pub fn lint_recipe(recipe: &BuildRecipe) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    if let Err(e) = recipe.validate() {
+        diagnostics.push(Diagnostic::error(format!("{:#}", e)));
+    }
+
+    diagnostics.extend(recipe.warnings().into_iter().map(Diagnostic::warning));
+
+    // (An empty description already fails validation)
+    let description = recipe.description.trim();
+    if !recipe.description.is_empty() && (description.is_empty() || description.starts_with("TODO")) {
+        diagnostics.push(Diagnostic::warning(format!("Description is a placeholder: '{}'", recipe.description)));
+    }
+
+    let urls: Vec<&str> = match &recipe.source {
+        Source::Url(url) => vec![url.as_str()],
+        Source::List(sources) => sources.iter().map(|source| source.url.as_str()).collect(),
+        Source::PerArch(sources) => sources.values().map(|source| source.url.as_str()).collect(),
+    };
+    for url in urls.into_iter().chain(recipe.mirrors.iter().map(String::as_str)) {
+        if let Some((scheme, _)) = url.split_once("://") {
+            if !SOURCE_SCHEMES.contains(&scheme) && !scheme.starts_with("git+") {
+                diagnostics.push(Diagnostic::warning(format!(
+                    "Source uses the unsupported URL scheme '{}' (use http, https, git or file): {}", scheme, url
+                )));
+            }
+        }
+    }
+
+    diagnostics.extend(script_diagnostics(recipe));
+    diagnostics
+}
+
+/// Misused build variables in the recipe's own build, phase and check scripts
+fn script_diagnostics(recipe: &BuildRecipe) -> Vec<Diagnostic> {
+    let mut scripts: Vec<(&str, &str)> = Vec::new();
+    if let Some(build) = &recipe.build {
+        scripts.push(("build", build));
+    }
+    if let Some(phases) = &recipe.phases {
+        scripts.extend(phases.scripts());
+    }
+    if let Some(check) = &recipe.check {
+        scripts.push(("check", check));
+    }
+
+    let mut diagnostics = Vec::new();
+    for (name, script) in &scripts {
+        for variable in pax_variables(script) {
+            if !BUILD_VARIABLES.contains(&variable) && !variable.starts_with("PAX_FEATURE_") {
+                diagnostics.push(Diagnostic::warning(format!("The {} script uses ${}, which paxbuild does not set", name, variable)));
+            }
+        }
+    }
+
+    // A build that never installs into $PAX_BUILD_ROOT packages nothing
+    let installs = scripts.iter()
+        .filter(|(name, _)| *name != "check")
+        .any(|(_, script)| pax_variables(script).contains(&"PAX_BUILD_ROOT"));
+    if !scripts.is_empty() && !installs {
+        diagnostics.push(Diagnostic::warning(
+            "The build script never refers to $PAX_BUILD_ROOT, so nothing it installs ends up in the package"
+        ));
+    }
+    diagnostics
+}
+
+/// Names of the `$PAX_*` and `${PAX_*}` variables a script refers to
+fn pax_variables(script: &str) -> Vec<&str> {
+    script.match_indices('$')
+        .filter_map(|(start, _)| {
+            let rest = script[start + 1..].strip_prefix('{').unwrap_or(&script[start + 1..]);
+            let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+            Some(&rest[..end]).filter(|name| name.starts_with("PAX_"))
+        })
+        .collect()
+}
+
+/// Lint a recipe file or URL (CLI entry point)
+pub fn lint_cmd(recipe_path: &str) -> Result<()> {
+    println!("PAXBuild - Linting recipe");
+    println!("Recipe: {}", recipe_path);
+
+    let recipe = if recipe_path.starts_with("http://") || recipe_path.starts_with("https://") {
+        BuildRecipe::from_url(recipe_path)?
+    } else {
+        BuildRecipe::from_file(recipe_path)?
+    };
+
+    let diagnostics = lint_recipe(&recipe);
+    for diagnostic in &diagnostics {
+        match diagnostic.severity {
+            Severity::Error => println!("error: {}", diagnostic.message),
+            Severity::Warning => println!("warning: {}", diagnostic.message),
+        }
+    }
+
+    let errors = diagnostics.iter().filter(|diagnostic| diagnostic.severity == Severity::Error).count();
+    let warnings = diagnostics.len() - errors;
+    if errors > 0 {
+        anyhow::bail!("Recipe has {} error(s) and {} warning(s)", errors, warnings);
+    }
+    if warnings == 0 {
+        println!("No problems found");
+    } else {
+        println!("{} warning(s)", warnings);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lint_clean_recipe() {
+        let recipe = BuildRecipe::from_yaml(r#"
+name: clean
+version: 1.0.0
+description: Test package
+source: https://example.com/clean-1.0.0.tar.gz
+hash: sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855
+build: make -j$PAX_JOBS && make install DESTDIR=${PAX_BUILD_ROOT}
+check: make check
+"#).unwrap();
+        assert_eq!(lint_recipe(&recipe), Vec::new());
+    }
+
+    #[test]
+    fn test_lint_reports_every_problem() {
+        let recipe = BuildRecipe::from_yaml(r#"
+name: messy
+version: 1.0.0
+description: TODO describe messy
+source: ftp://example.com/messy-1.0.0.tar.gz
+arch: [x86_64, sparc]
+build: make && make install DESTDIR=$PAX_BUILDROOT
+"#).unwrap();
+
+        let diagnostics = lint_recipe(&recipe);
+        let errors: Vec<&str> = diagnostics.iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].contains("Invalid architecture: sparc"), "{}", errors[0]);
+
+        let warnings: Vec<&str> = diagnostics.iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Warning)
+            .map(|diagnostic| diagnostic.message.as_str())
+            .collect();
+        for expected in ["No hash given", "placeholder", "scheme 'ftp'", "$PAX_BUILDROOT", "never refers to $PAX_BUILD_ROOT"] {
+            assert!(warnings.iter().any(|warning| warning.contains(expected)), "missing {:?} in {:?}", expected, warnings);
+        }
+        assert_eq!(warnings.len(), 5, "{:?}", warnings);
+    }
+}
@@ -1,21 +1,33 @@
-use clap::{Parser, Subcommand};
-use paxbuild::{build, verify, extract};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use paxbuild::SourceManager;
+use paxbuild::crypto::PrivateKeySource;
+use paxbuild::{analyze, build, closure, delta, dump, verify, exec, extract, formats, index, info, init, keys, lint, manifest, merge, prune, remeta, repro, sign, sign_recipe, vendor, watch};
 
 #[derive(Parser)]
 #[command(name = "paxbuild")]
 #[command(about = "PAX package builder - builds .pax packages from .paxmeta recipes")]
 #[command(version)]
 struct Cli {
+    /// Log every external command run, with its arguments and exit status (or set PAX_TRACE_COMMANDS=1)
+    #[arg(long, global = true)]
+    trace_commands: bool,
+    /// Treat warnings (insecure HTTP source, missing hash, ...) as errors
+    #[arg(long, global = true, visible_alias = "werror")]
+    strict: bool,
     #[command(subcommand)]
     command: Commands,
 }
 
+// Parsed once per run, so the size of the build variant does not matter
+#[allow(clippy::large_enum_variant)]
 #[derive(Subcommand)]
 enum Commands {
-    /// Build a .pax package from a .paxmeta recipe
+    /// Build .pax packages from .paxmeta recipes
     Build {
-        /// Path to .paxmeta recipe file or URL
-        recipe: String,
+        /// Paths to .paxmeta recipe files, directories of recipes, URLs, or git+<repo>#<path>[@<ref>] locations
+        #[arg(required = true)]
+        recipes: Vec<String>,
         /// Output path for the generated .pax package
         #[arg(short, long)]
         output: Option<String>,
@@ -25,14 +37,264 @@ enum Commands {
         /// Verbose output
         #[arg(short, long)]
         verbose: bool,
+        /// Write a JSON build report to this file
+        #[arg(long)]
+        report: Option<String>,
+        /// Refuse to build unless the recipe has a valid signature (<recipe>.asc)
+        #[arg(long, requires = "key")]
+        verify_recipe: bool,
+        /// Public key used to verify the recipe signature
+        #[arg(short, long)]
+        key: Option<String>,
+        /// Strip this many leading path components from packaged files
+        #[arg(long, value_name = "N", default_value_t = 0)]
+        package_strip: usize,
+        /// Store the file list in a separate files.list member when a package has more than N files
+        #[arg(long, value_name = "N")]
+        files_list_threshold: Option<usize>,
+        /// Enable a recipe feature (can specify multiple)
+        #[arg(long = "with", value_name = "FEATURE")]
+        features: Vec<String>,
+        /// Number of recipes to build in parallel
+        #[arg(short, long, default_value_t = 1)]
+        jobs: usize,
+        /// Parallel jobs for each build script, exported as PAX_JOBS (defaults to the number of CPUs)
+        #[arg(long, value_name = "N", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        build_jobs: Option<usize>,
+        /// Refuse all network access (build vendored recipes only)
+        #[arg(long)]
+        offline: bool,
+        /// Store an extra metadata field in the package (can specify multiple)
+        #[arg(long = "set-meta", value_name = "KEY=VALUE", value_parser = build::parse_meta_assignment)]
+        extra_metadata: Vec<(String, String)>,
+        /// Don't reuse or store extracted source trees in the cache
+        #[arg(long)]
+        no_cache: bool,
+        /// Warn about runtime dependencies no package in this index (JSON or YAML) satisfies
+        #[arg(long, value_name = "INDEX")]
+        deps_index: Option<String>,
+        /// Fail if a package would overwrite files shipped by other packages in this index (JSON or YAML)
+        #[arg(long, value_name = "INDEX")]
+        conflict_index: Option<String>,
+        /// Package files installed under FROM under TO instead, e.g. /usr=/usr/local (can specify multiple)
+        #[arg(long = "prefix-map", value_name = "FROM=TO", value_parser = paxbuild::builder::PrefixMap::parse)]
+        prefix_maps: Vec<paxbuild::builder::PrefixMap>,
+        /// Write the computed source hash into a recipe that has none (local recipe files only)
+        #[arg(long)]
+        write_hash: bool,
+        /// Algorithm for computed source hashes: sha256 (default) or blake3
+        #[arg(long, value_name = "ALGO", value_parser = paxbuild::integrity::HashAlgorithm::parse)]
+        hash_algo: Option<paxbuild::integrity::HashAlgorithm>,
+        /// Kill a build script (and every process it started) after SECONDS and fail the build
+        #[arg(long, value_name = "SECONDS")]
+        timeout: Option<u64>,
+        /// Write each architecture's build command, environment and output to DIR/build-<arch>.log
+        #[arg(long, value_name = "DIR")]
+        log_dir: Option<String>,
+        /// Don't run the recipe's check script between building and packaging
+        #[arg(long)]
+        skip_check: bool,
+        /// zstd compression level, 1-22 (default 19); higher levels take longer for smaller packages
+        #[arg(long, value_name = "LEVEL", value_parser = paxbuild::builder::parse_compression_level)]
+        compression_level: Option<i32>,
+        /// Set every archive entry's mtime to SOURCE_DATE_EPOCH (or 0) and drop owner names, for bit-for-bit reproducible packages
+        #[arg(long)]
+        reproducible: bool,
+        /// Repository root to place packages under, following --repo-layout
+        #[arg(long, value_name = "DIR", conflicts_with = "output")]
+        output_root: Option<String>,
+        /// Repository layout below --output-root: flat, or pool (pool/<first letter>/<name>/)
+        #[arg(long, value_name = "LAYOUT", requires = "output_root", value_parser = build::RepoLayout::parse)]
+        repo_layout: Option<build::RepoLayout>,
+        /// Verify each package (metadata, file list, file hashes) right after it is written
+        #[arg(long = "verify")]
+        verify_after_build: bool,
+        /// Write a signed provenance attestation (<package>.attestation.json) next to each package
+        #[arg(long, requires = "sign_key")]
+        attest: bool,
+        /// Private key to sign attestations (writes <attestation>.asc) and embedded signatures with
+        #[arg(long, value_name = "KEY")]
+        sign_key: Option<String>,
+        /// Embed a signature made with --sign-key in every package
+        #[arg(long, requires = "sign_key")]
+        embed_signature: bool,
+        /// Write a <package>.paxmeta.json metadata sidecar next to each package for fast indexing
+        #[arg(long)]
+        metadata_sidecar: bool,
+        /// Write a <package>.chunks.json chunk manifest next to each package for delta updates
+        #[arg(long)]
+        chunk_manifest: bool,
+        /// Build from this local source tree instead of downloading the recipe's source
+        #[arg(long, value_name = "DIR")]
+        from_directory: Option<String>,
+        /// Run the build script with only PATH, HOME, the recipe's env and PAX_* variables
+        #[arg(long)]
+        clean_env: bool,
+        /// Print the resolved build script for each architecture and exit without building
+        #[arg(long)]
+        print_build_script: bool,
+    },
+    /// Rebuild a recipe from a local source tree every time the tree or recipe changes
+    Watch {
+        /// Path to .paxmeta recipe file
+        recipe: String,
+        /// Source tree to build from and watch
+        #[arg(long, value_name = "DIR")]
+        source_dir: String,
+        /// Output path for the generated .pax package (defaults to the current directory)
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Target architecture(s) (defaults to all architectures in the recipe)
+        #[arg(short, long)]
+        arch: Vec<String>,
+    },
+    /// Build a recipe twice and report archive entries that differ between the builds
+    ReproCheck {
+        /// Path to .paxmeta recipe file or URL
+        recipe: String,
+        /// Target architecture(s) to check (can specify multiple)
+        #[arg(short, long)]
+        arch: Vec<String>,
     },
     /// Verify a .pax package checksum
     Verify {
         /// Path to .pax package file
         package: String,
-        /// Public key file for verification (optional)
+        /// Public key file; checks the signature in <package>.sig unless --signature-file is given
         #[arg(short, long)]
         key: Option<String>,
+        /// Check that dependency constraints in the metadata are well-formed
+        #[arg(long)]
+        check_deps: bool,
+        /// Syntax-check install and uninstall scripts (runs `<shell> -n`, never executes them)
+        #[arg(long)]
+        check_scripts: bool,
+        /// Shell used by --check-scripts
+        #[arg(long, default_value = "bash")]
+        shell: String,
+        /// Published .sha256 file the package hash must match
+        #[arg(long)]
+        checksum_file: Option<String>,
+        /// Published SHA256 the package hash must match (hex, optionally prefixed with sha256:)
+        #[arg(long, value_name = "HASH")]
+        expected_hash: Option<String>,
+        /// Detached signature over the package, verified with --key
+        #[arg(long, requires = "key")]
+        signature_file: Option<String>,
+        /// Check that ELF executables and libraries match the metadata architecture
+        #[arg(long)]
+        check_binary_arch: bool,
+        /// Fail if any packaged file or directory is writable by group or others
+        #[arg(long)]
+        check_permissions: bool,
+        /// Validate the RFC 3161 timestamp saved next to --signature-file (<signature>.tsr)
+        #[arg(long, requires_all = ["signature_file", "tsa_ca"])]
+        check_timestamp: bool,
+        /// CA certificate (PEM) the timestamp authority must chain to
+        #[arg(long, value_name = "PEM", requires = "check_timestamp")]
+        tsa_ca: Option<String>,
+    },
+    /// Show information about a .pax package
+    Info {
+        /// Path to .pax package file
+        package: String,
+        /// Print the package metadata, size, hash and file name fields as one JSON object
+        #[arg(long)]
+        json: bool,
+        /// Describe packages without metadata (e.g. legacy archives) instead of failing
+        #[arg(long)]
+        best_effort: bool,
+    },
+    /// Print a sorted, diff-friendly text dump of a package (metadata, then mode size sha256 path per entry)
+    Dump {
+        /// Path to .pax package file
+        package: String,
+    },
+    /// Combine single-architecture packages of one name and version into a multi-arch metadata view
+    MergeMeta {
+        /// Packages to merge (.pax files)
+        #[arg(required = true)]
+        packages: Vec<String>,
+        /// Write the merged metadata (with an index entry per package) as JSON
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Check that every package's runtime dependencies are met by packages in the same directory
+    ClosureCheck {
+        /// Directory containing .pax packages
+        dir: String,
+    },
+    /// Generate a repository index of the .pax packages in a directory
+    Index {
+        /// Directory containing .pax packages
+        dir: String,
+        /// Index file to write (defaults to <dir>/index.json)
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Only re-read packages modified after a Unix timestamp, or missing from an earlier index file
+        #[arg(long, value_name = "TIMESTAMP|INDEX")]
+        since: Option<String>,
+    },
+    /// Remove old package versions from a directory
+    Prune {
+        /// Directory containing .pax packages
+        dir: String,
+        /// Number of newest versions to keep per package and architecture
+        #[arg(long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        keep: usize,
+        /// Move pruned packages to this directory instead of deleting them
+        #[arg(long, value_name = "DIR")]
+        move_to: Option<String>,
+        /// Only report what would be pruned
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Show a package's compression ratio and where its bytes go
+    Analyze {
+        /// Path to .pax package file
+        package: String,
+    },
+    /// Write a manifest of every file, mode, size and hash in a package
+    Manifest {
+        /// Path to .pax package file
+        package: String,
+        /// Output path for the manifest (defaults to <package>.manifest.json)
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Private key to sign the manifest with (writes <manifest>.asc)
+        #[arg(long, value_name = "KEY")]
+        sign_key: Option<String>,
+    },
+    /// Recompute the file list, file hashes and installed size of a package and repack it
+    Remeta {
+        /// Path to .pax package file
+        package: String,
+        /// Remove the package's embedded signature, which the new metadata would invalidate
+        #[arg(long)]
+        drop_signature: bool,
+        /// zstd compression level for the repacked package, 1-22 (default 19)
+        #[arg(long, value_name = "LEVEL", value_parser = paxbuild::builder::parse_compression_level)]
+        compression_level: Option<i32>,
+    },
+    /// Write a binary delta that rebuilds a new package from an old one
+    Delta {
+        /// Path to the old .pax package
+        old: String,
+        /// Path to the new .pax package
+        new: String,
+        /// Output path for the delta
+        #[arg(short, long)]
+        output: String,
+    },
+    /// Rebuild a package from an old package and a delta
+    ApplyDelta {
+        /// Path to the old .pax package
+        old: String,
+        /// Path to the delta
+        delta: String,
+        /// Output path for the rebuilt package
+        #[arg(short, long)]
+        output: String,
     },
     /// Extract contents of a .pax package
     Extract {
@@ -41,24 +303,306 @@ enum Commands {
         /// Output directory for extracted contents
         #[arg(short, long)]
         output: Option<String>,
+        /// Set the package's recorded file capabilities on the extracted files (needs setcap and privileges)
+        #[arg(long)]
+        apply_capabilities: bool,
+        /// Print each entry and where it would be written, without extracting anything
+        #[arg(long, conflicts_with = "apply_capabilities")]
+        list_only: bool,
+    },
+    /// Sign a .pax package with a detached Ed25519 signature
+    Sign {
+        /// Path to .pax package file
+        package: String,
+        /// Private key file for signing
+        #[arg(short, long, required_unless_present = "keyring_entry")]
+        key: Option<String>,
+        /// Read the private key from this OS keyring entry (<service>/<user>) instead of a file
+        #[arg(long, value_name = "SERVICE/USER", conflicts_with = "key")]
+        keyring_entry: Option<String>,
+        /// Output path for the signature (defaults to <package>.sig)
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Store the signature and public key inside the package instead of a .sig file
+        #[arg(long, conflicts_with = "output")]
+        embed: bool,
+        /// zstd compression level for the package rewritten by --embed, 1-22 (default 19)
+        #[arg(long, value_name = "LEVEL", value_parser = paxbuild::builder::parse_compression_level, requires = "embed")]
+        compression_level: Option<i32>,
+        /// Timestamp the signature with this RFC 3161 authority (writes <signature>.tsr)
+        #[arg(long, value_name = "URL", conflicts_with = "embed")]
+        tsa: Option<String>,
+    },
+    /// Sign a .paxmeta recipe with a detached armored signature
+    SignRecipe {
+        /// Path to .paxmeta recipe file
+        recipe: String,
+        /// Private key file for signing
+        #[arg(short, long, required_unless_present = "keyring_entry")]
+        key: Option<String>,
+        /// Read the private key from this OS keyring entry (<service>/<user>) instead of a file
+        #[arg(long, value_name = "SERVICE/USER", conflicts_with = "key")]
+        keyring_entry: Option<String>,
+        /// Output path for the signature (defaults to <recipe>.asc)
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Timestamp the signature with this RFC 3161 authority (writes <signature>.tsr)
+        #[arg(long, value_name = "URL")]
+        tsa: Option<String>,
+    },
+    /// Check a recipe for errors and likely mistakes without building it
+    Lint {
+        /// Path to .paxmeta recipe file or URL
+        recipe: String,
+    },
+    /// Write a commented recipe template to start a new package from
+    Init {
+        /// Package name
+        name: String,
+        /// Output path for the recipe (defaults to <name>.paxmeta)
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Overwrite the output file if it exists
+        #[arg(long)]
+        force: bool,
+    },
+    /// Download a recipe's sources into a directory for offline builds
+    Vendor {
+        /// Path to .paxmeta recipe file or URL
+        recipe: String,
+        /// Directory to store the sources and the rewritten recipe
+        #[arg(short, long)]
+        output: String,
+        /// Algorithm for the hashes pinned on sources without one: sha256 (default) or blake3
+        #[arg(long, value_name = "ALGO", value_parser = paxbuild::integrity::HashAlgorithm::parse)]
+        hash_algo: Option<paxbuild::integrity::HashAlgorithm>,
+    },
+    /// List supported source archive formats and package compression algorithms
+    Formats,
+    /// Manage signing keys
+    Keys {
+        #[command(subcommand)]
+        command: KeysCommands,
+    },
+    /// Print a shell completion script to stdout
+    Completions {
+        /// Shell to generate completions for (bash, zsh, fish, powershell, elvish)
+        shell: Shell,
     },
 }
 
+#[derive(Subcommand)]
+enum KeysCommands {
+    /// Import a key from another location
+    Import {
+        /// Key file to import
+        #[arg(long)]
+        source: String,
+        /// Destination key file
+        #[arg(long)]
+        dest: String,
+        /// Key type: private or public (auto-detected if not specified)
+        #[arg(short = 't', long = "type")]
+        key_type: Option<String>,
+    },
+}
+
+/// Write the completion script for `shell` to `out`
+fn write_completions(shell: Shell, out: &mut dyn std::io::Write) {
+    clap_complete::generate(shell, &mut Cli::command(), "paxbuild", out);
+}
 
 fn main() -> anyhow::Result<()> {
     let cli = Cli::parse();
 
+    if cli.trace_commands || exec::trace_requested_by_env() {
+        exec::enable_trace();
+    }
+
     match cli.command {
-        Commands::Build { recipe, output, arch, verbose } => {
-            build::build_package(&recipe, output.as_deref(), &arch, verbose)?;
+        Commands::Build {
+            recipes, output, arch, verbose, report, verify_recipe, key, package_strip, files_list_threshold, features, jobs,
+            build_jobs, offline, extra_metadata, no_cache, deps_index, conflict_index, prefix_maps, write_hash, hash_algo, timeout, log_dir, skip_check, compression_level, reproducible,
+            output_root, repo_layout, verify_after_build, attest, sign_key, embed_signature, metadata_sidecar, chunk_manifest, from_directory, clean_env, print_build_script,
+        } => {
+            let options = build::BuildOptions {
+                output,
+                architectures: arch,
+                verbose,
+                report,
+                recipe_key: if verify_recipe { key } else { None },
+                package_strip,
+                files_list_threshold,
+                features,
+                jobs,
+                offline,
+                extra_metadata: extra_metadata.into_iter().collect(),
+                strict: cli.strict,
+                build_jobs,
+                deps_index,
+                conflict_index,
+                prefix_maps,
+                write_hash,
+                hash_algorithm: hash_algo.unwrap_or_default(),
+                timeout,
+                log_dir,
+                skip_check,
+                compression_level,
+                reproducible,
+                output_root,
+                repo_layout: repo_layout.unwrap_or_default(),
+                verify: verify_after_build,
+                attest,
+                sign_key,
+                embed_signature,
+                metadata_sidecar,
+                chunk_manifest,
+                from_directory,
+                clean_env,
+                print_build_script,
+                cache_dir: if no_cache {
+                    None
+                } else {
+                    SourceManager::default_cache_dir().map(|dir| dir.to_string_lossy().to_string())
+                },
+            };
+            build::build_packages(&recipes, &options)?;
+        }
+        Commands::Watch { recipe, source_dir, output, arch } => {
+            let options = build::BuildOptions {
+                output,
+                architectures: arch,
+                strict: cli.strict,
+                ..Default::default()
+            };
+            watch::watch_cmd(&recipe, &source_dir, &options)?;
+        }
+        Commands::ReproCheck { recipe, arch } => {
+            repro::repro_check_cmd(&recipe, &arch)?;
+        }
+        Commands::Verify { package, key, check_deps, check_scripts, shell, checksum_file, expected_hash, signature_file, check_binary_arch, check_permissions, check_timestamp, tsa_ca } => {
+            let options = verify::VerifyOptions {
+                key,
+                check_deps,
+                check_scripts,
+                shell: Some(shell),
+                checksum_file,
+                expected_hash,
+                signature_file,
+                check_binary_arch,
+                check_permissions,
+                check_timestamp,
+                tsa_ca,
+            };
+            verify::verify_package(&package, &options)?;
+        }
+        Commands::Info { package, json, best_effort } => {
+            info::show_info(&package, json, best_effort)?;
+        }
+        Commands::Dump { package } => {
+            dump::dump_cmd(&package)?;
+        }
+        Commands::MergeMeta { packages, output } => {
+            merge::merge_meta_cmd(&packages, output.as_deref())?;
         }
-        Commands::Verify { package, key } => {
-            verify::verify_package(&package, key.as_deref())?;
+        Commands::ClosureCheck { dir } => {
+            closure::closure_check_cmd(&dir)?;
         }
-        Commands::Extract { package, output } => {
-            extract::extract_package(&package, output.as_deref())?;
+        Commands::Index { dir, output, since } => {
+            index::index_cmd(&dir, output.as_deref(), since.as_deref())?;
+        }
+        Commands::Prune { dir, keep, move_to, dry_run } => {
+            let options = prune::PruneOptions { keep, move_to, dry_run };
+            prune::prune_cmd(&dir, &options)?;
+        }
+        Commands::Analyze { package } => {
+            analyze::analyze_cmd(&package)?;
+        }
+        Commands::Manifest { package, output, sign_key } => {
+            manifest::manifest_cmd(&package, output.as_deref(), sign_key.as_deref())?;
+        }
+        Commands::Remeta { package, drop_signature, compression_level } => {
+            remeta::remeta_cmd(&package, drop_signature, compression_level)?;
+        }
+        Commands::Delta { old, new, output } => {
+            delta::delta_cmd(&old, &new, &output)?;
+        }
+        Commands::ApplyDelta { old, delta, output } => {
+            delta::apply_delta_cmd(&old, &delta, &output)?;
+        }
+        Commands::Extract { package, output, apply_capabilities, list_only } => {
+            extract::extract_package(&package, output.as_deref(), apply_capabilities, list_only)?;
+        }
+        Commands::Sign { package, key, keyring_entry, output, embed, compression_level, tsa } => {
+            let key = match keyring_entry {
+                Some(entry) => PrivateKeySource::Keyring(entry),
+                None => PrivateKeySource::File(key.unwrap_or_default().into()),
+            };
+            sign::sign_package_cmd(&package, &key, output.as_deref(), embed, compression_level, tsa.as_deref())?;
+        }
+        Commands::SignRecipe { recipe, key, keyring_entry, output, tsa } => {
+            let key = match keyring_entry {
+                Some(entry) => PrivateKeySource::Keyring(entry),
+                None => PrivateKeySource::File(key.unwrap_or_default().into()),
+            };
+            sign_recipe::sign_recipe_cmd(&recipe, &key, output.as_deref(), tsa.as_deref())?;
+        }
+        Commands::Lint { recipe } => {
+            lint::lint_cmd(&recipe)?;
+        }
+        Commands::Init { name, output, force } => {
+            init::init_cmd(&name, output.as_deref(), force)?;
+        }
+        Commands::Vendor { recipe, output, hash_algo } => {
+            vendor::vendor_cmd(&recipe, &output, hash_algo.unwrap_or_default())?;
+        }
+        Commands::Formats => {
+            formats::show_formats()?;
+        }
+        Commands::Keys { command } => match command {
+            KeysCommands::Import { source, dest, key_type } => {
+                keys::import_key_cmd(&source, &dest, key_type.as_deref())?;
+            }
+        },
+        Commands::Completions { shell } => {
+            write_completions(shell, &mut std::io::stdout());
         }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bash_completions() {
+        let mut out = Vec::new();
+        write_completions(Shell::Bash, &mut out);
+        let script = String::from_utf8(out).unwrap();
+
+        for command in ["build", "verify", "info", "extract", "keys", "completions"] {
+            assert!(script.contains(command), "missing {} in completions", command);
+        }
+    }
+
+    #[test]
+    fn test_prune_requires_keeping_a_version() {
+        assert!(Cli::try_parse_from(["paxbuild", "prune", "repo", "--keep", "0"]).is_err());
+        assert!(Cli::try_parse_from(["paxbuild", "prune", "repo", "--keep", "1"]).is_ok());
+    }
+
+    #[test]
+    fn test_sign_takes_a_key_file_or_keyring_entry() {
+        assert!(Cli::try_parse_from(["paxbuild", "sign", "package.pax"]).is_err());
+        assert!(Cli::try_parse_from(["paxbuild", "sign", "package.pax", "--key", "private.key", "--keyring-entry", "paxbuild/release"]).is_err());
+        assert!(Cli::try_parse_from(["paxbuild", "sign", "package.pax", "--keyring-entry", "paxbuild/release"]).is_ok());
+    }
+
+    #[test]
+    fn test_build_jobs_must_be_positive() {
+        assert!(Cli::try_parse_from(["paxbuild", "build", "recipe.paxmeta", "--build-jobs", "0"]).is_err());
+        assert!(Cli::try_parse_from(["paxbuild", "build", "recipe.paxmeta", "--build-jobs", "4"]).is_ok());
+    }
+}
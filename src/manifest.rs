@@ -0,0 +1,147 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use sha2::{Sha256, Digest};
+use std::fs;
+use std::path::Path;
+use crate::crypto;
+use crate::package::PaxPackage;
+
+/// One archive member listed in a package manifest
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub path: String,
+    /// Permission bits
+    pub mode: u32,
+    /// Size of the contents in bytes
+    pub size: u64,
+    /// SHA256 of the contents (regular files only)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// Target of a symlink or hard link
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub link_target: Option<String>,
+}
+
+/// Standalone description of every file in a package, for auditing without the package itself
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Package file name
+    pub package: String,
+    /// SHA256 of the package file
+    pub sha256: String,
+    /// Every non-directory member, in archive order
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Serialize the manifest as pretty-printed JSON
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self)
+            .with_context(|| "Failed to serialize manifest")
+    }
+}
+
+/// Build the manifest of a package from its archive entries
+pub fn package_manifest(package_path: &Path) -> Result<Manifest> {
+    let package = PaxPackage::open(package_path)?;
+
+    let mut entries = Vec::new();
+    for entry in package.entries()? {
+        let mut entry = entry?;
+        if entry.is_dir() {
+            continue;
+        }
+
+        let sha256 = if entry.is_file() {
+            let mut hasher = Sha256::new();
+            std::io::copy(&mut entry, &mut hasher)
+                .with_context(|| format!("Failed to hash package entry: {}", entry.path.display()))?;
+            Some(hex::encode(hasher.finalize()))
+        } else {
+            None
+        };
+
+        entries.push(ManifestEntry {
+            path: entry.path.to_string_lossy().to_string(),
+            mode: entry.mode,
+            size: entry.size,
+            sha256,
+            link_target: entry.link_target.as_ref().map(|target| target.to_string_lossy().to_string()),
+        });
+    }
+
+    Ok(Manifest {
+        package: package.filename().unwrap_or_default().to_string(),
+        sha256: package.calculate_hash()?,
+        entries,
+    })
+}
+
+/// Write a package manifest, optionally with an armored signature (CLI entry point)
+pub fn manifest_cmd(package_path: &str, output_path: Option<&str>, sign_key: Option<&str>) -> Result<()> {
+    println!("PAXBuild - Writing package manifest");
+    println!("Package: {}", package_path);
+
+    let manifest = package_manifest(Path::new(package_path))?;
+    let json = manifest.to_json()?;
+
+    let manifest_path = match output_path {
+        Some(output) => output.to_string(),
+        None => format!("{}.manifest.json", package_path),
+    };
+    fs::write(&manifest_path, &json)
+        .with_context(|| format!("Failed to write manifest to: {}", manifest_path))?;
+    println!("Manifest of {} entries saved to: {}", manifest.entries.len(), manifest_path);
+
+    if let Some(key) = sign_key {
+        let signature = crypto::sign_bytes(json.as_bytes(), Path::new(key))?;
+        let signature_path = format!("{}.asc", manifest_path);
+        fs::write(&signature_path, crypto::armor_signature(&signature))
+            .with_context(|| format!("Failed to write signature to: {}", signature_path))?;
+        println!("Signature saved to: {}", signature_path);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_manifest_lists_every_file_with_hash() {
+        let temp_dir = TempDir::new().unwrap();
+        let files = [("usr/bin/tool", "#!/bin/sh\n"), ("usr/share/doc/tool/README", "docs")];
+        let package_path = test_support::write_package(temp_dir.path(), &test_support::metadata("tool", "1.0.0"), &files);
+
+        let manifest = package_manifest(&package_path).unwrap();
+        assert_eq!(manifest.package, "tool-1.0.0-x86_64.pax");
+        assert_eq!(manifest.sha256, PaxPackage::open(&package_path).unwrap().calculate_hash().unwrap());
+        assert_eq!(manifest.entries.len(), files.len() + 1);
+        for (path, contents) in files {
+            let entry = manifest.entries.iter().find(|entry| entry.path == path).unwrap();
+            assert_eq!(entry.size, contents.len() as u64);
+            assert_eq!(entry.mode, 0o644);
+            assert_eq!(entry.sha256.as_deref(), Some(hex::encode(Sha256::digest(contents)).as_str()));
+        }
+
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let private_key = temp_dir.path().join("private.key");
+        let public_key = temp_dir.path().join("public.key");
+        fs::write(&private_key, hex::encode(signing_key.to_bytes())).unwrap();
+        fs::write(&public_key, hex::encode(signing_key.verifying_key().to_bytes())).unwrap();
+
+        let manifest_path = temp_dir.path().join("tool.manifest.json");
+        manifest_cmd(package_path.to_str().unwrap(), manifest_path.to_str(), private_key.to_str()).unwrap();
+        let json = fs::read_to_string(&manifest_path).unwrap();
+        let parsed: Manifest = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, manifest);
+        let armored = fs::read_to_string(temp_dir.path().join("tool.manifest.json.asc")).unwrap();
+        let signature = crypto::dearmor_signature(&armored).unwrap();
+        crypto::verify_bytes(json.as_bytes(), &signature, &public_key).unwrap();
+    }
+}
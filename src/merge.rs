@@ -0,0 +1,126 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use crate::index::{self, IndexEntry};
+
+/// Combined view of single-architecture packages of one name and version
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MergedMetadata {
+    pub name: String,
+    /// Version including any epoch
+    pub version: String,
+    pub description: String,
+    /// Union of the packages' architectures, sorted
+    pub arch: Vec<String>,
+    /// Index entry of every merged package, sorted by architecture
+    pub packages: Vec<IndexEntry>,
+}
+
+impl MergedMetadata {
+    /// Write the merged view as pretty-printed JSON
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)
+            .with_context(|| "Failed to serialize merged metadata")?;
+        fs::write(path, json)
+            .with_context(|| format!("Failed to write merged metadata: {}", path.display()))
+    }
+}
+
+/// Merge packages built separately for different architectures
+///
+/// Every package must have the same name and version, and no architecture may
+/// come from more than one package.
+pub fn merge_metadata(package_paths: &[&Path]) -> Result<MergedMetadata> {
+    let mut entries = Vec::new();
+    for path in package_paths {
+        let filename = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        entries.push(index::read_entry(path, &filename, index::modified_secs(path)?)?);
+    }
+    let Some(first) = entries.first().cloned() else {
+        anyhow::bail!("No packages to merge");
+    };
+
+    let mut arch: Vec<(String, String)> = Vec::new();
+    for entry in &entries {
+        if entry.name != first.name || entry.version != first.version {
+            anyhow::bail!(
+                "Cannot merge {} ({} {}) with {} ({} {}): name and version must match",
+                entry.filename, entry.name, entry.version, first.filename, first.name, first.version
+            );
+        }
+        for entry_arch in &entry.arch {
+            if let Some((_, other)) = arch.iter().find(|(existing, _)| existing == entry_arch) {
+                anyhow::bail!("Architecture {} is provided by both {} and {}", entry_arch, other, entry.filename);
+            }
+            arch.push((entry_arch.clone(), entry.filename.clone()));
+        }
+    }
+    let mut arch: Vec<String> = arch.into_iter().map(|(arch, _)| arch).collect();
+    arch.sort();
+    entries.sort_by(|a, b| a.arch.cmp(&b.arch));
+
+    Ok(MergedMetadata {
+        name: first.name,
+        version: first.version,
+        description: first.description,
+        arch,
+        packages: entries,
+    })
+}
+
+/// Merge single-architecture packages and print the combined metadata (CLI entry point)
+pub fn merge_meta_cmd(packages: &[String], output: Option<&str>) -> Result<()> {
+    println!("PAXBuild - Merging package metadata");
+
+    let paths: Vec<&Path> = packages.iter().map(Path::new).collect();
+    let merged = merge_metadata(&paths)?;
+
+    println!("Name: {}", merged.name);
+    println!("Version: {}", merged.version);
+    println!("Description: {}", merged.description);
+    println!("Architectures: {}", merged.arch.join(", "));
+    for entry in &merged.packages {
+        println!("  {}: {} ({} bytes, sha256 {})", entry.arch.join(", "), entry.filename, entry.size, entry.sha256);
+    }
+
+    if let Some(output) = output {
+        merged.write(Path::new(output))?;
+        println!("Merged metadata written to: {}", output);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_merge_architectures_of_one_version() {
+        let temp_dir = TempDir::new().unwrap();
+        let x86_64 = test_support::write_package(temp_dir.path(), &test_support::metadata("merged", "1.0.0"), &[("usr/bin/merged", "x86")]);
+        let mut metadata = test_support::metadata("merged", "1.0.0");
+        metadata.arch = vec!["aarch64".to_string()];
+        let aarch64 = test_support::write_package(temp_dir.path(), &metadata, &[("usr/bin/merged", "arm")]);
+
+        let merged = merge_metadata(&[&x86_64, &aarch64]).unwrap();
+        assert_eq!(merged.name, "merged");
+        assert_eq!(merged.version, "1.0.0");
+        assert_eq!(merged.arch, vec!["aarch64", "x86_64"]);
+        let filenames: Vec<&str> = merged.packages.iter().map(|entry| entry.filename.as_str()).collect();
+        assert_eq!(filenames, vec!["merged-1.0.0-aarch64.pax", "merged-1.0.0-x86_64.pax"]);
+
+        let output = temp_dir.path().join("merged.json");
+        merge_meta_cmd(&[x86_64.to_str().unwrap().to_string(), aarch64.to_str().unwrap().to_string()], output.to_str()).unwrap();
+        let written: MergedMetadata = serde_json::from_str(&fs::read_to_string(&output).unwrap()).unwrap();
+        assert_eq!(written, merged);
+
+        let newer = test_support::write_package(temp_dir.path(), &test_support::metadata("merged", "1.1.0"), &[]);
+        let err = merge_metadata(&[&aarch64, &newer]).unwrap_err();
+        assert!(err.to_string().contains("name and version must match"), "{}", err);
+        assert!(merge_metadata(&[&x86_64, &x86_64]).is_err());
+    }
+}
@@ -0,0 +1,213 @@
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Component, Path};
+
+/// A system user the package needs, created on install if it does not exist
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct User {
+    pub name: String,
+    /// Fixed user ID (allocated by the installer if not set)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uid: Option<u32>,
+    /// Primary group (defaults to a group named after the user)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// Home directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub home: Option<String>,
+    /// Login shell
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shell: Option<String>,
+}
+
+impl User {
+    /// Name of the user's primary group
+    pub fn primary_group(&self) -> &str {
+        self.group.as_deref().unwrap_or(&self.name)
+    }
+}
+
+/// A system group the package needs, created on install if it does not exist
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Group {
+    pub name: String,
+    /// Fixed group ID (allocated by the installer if not set)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gid: Option<u32>,
+}
+
+/// Owner of one packaged file as written into its tar header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArchiveOwner {
+    pub user: String,
+    pub group: String,
+    /// Declared ID, or 0 so unknown names fall back to root rather than the build user
+    pub uid: u32,
+    pub gid: u32,
+}
+
+/// Check a user or group name: lowercase letters, digits, `_` and `-`, not starting
+/// with a digit or `-`, at most 31 characters (it must fit a tar header)
+pub fn validate_name(kind: &str, name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name.len() <= 31
+        && !name.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || matches!(c, '_' | '-'));
+    if !valid {
+        anyhow::bail!("Invalid {} name '{}'", kind, name);
+    }
+    Ok(())
+}
+
+/// Split a `file_owners` value (`user` or `user:group`)
+pub fn parse_owner(spec: &str) -> (&str, Option<&str>) {
+    match spec.split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (spec, None),
+    }
+}
+
+/// Check declared users and groups, and that every file owner refers to them
+///
+/// Owners may also be `root`, which exists everywhere. A user's primary group
+/// counts as declared even without a `groups` entry.
+pub fn validate_ownership(users: &[User], groups: &[Group], file_owners: &BTreeMap<String, String>) -> Result<()> {
+    let mut user_names = BTreeSet::from(["root"]);
+    for user in users {
+        validate_name("user", &user.name)?;
+        if !user_names.insert(&user.name) {
+            anyhow::bail!("User '{}' is declared more than once", user.name);
+        }
+    }
+
+    let mut group_names = BTreeSet::from(["root"]);
+    for group in groups {
+        validate_name("group", &group.name)?;
+        if !group_names.insert(&group.name) {
+            anyhow::bail!("Group '{}' is declared more than once", group.name);
+        }
+    }
+    for user in users {
+        validate_name("group", user.primary_group())?;
+        group_names.insert(user.primary_group());
+    }
+
+    for (path, spec) in file_owners {
+        let valid_path = !path.is_empty()
+            && Path::new(path).components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+        if !valid_path {
+            anyhow::bail!("Invalid file owner path '{}': it must be relative to the package root", path);
+        }
+        let (user, group) = parse_owner(spec);
+        if !user_names.contains(user) {
+            anyhow::bail!("File '{}' is owned by '{}', which is not a declared user", path, user);
+        }
+        if let Some(group) = group.filter(|group| !group_names.contains(group)) {
+            anyhow::bail!("File '{}' is owned by group '{}', which is not a declared group", path, group);
+        }
+    }
+
+    Ok(())
+}
+
+/// Resolve `file_owners` into the names and IDs written into each file's tar header
+///
+/// A file without an explicit group gets its user's primary group.
+pub fn archive_owners(users: &[User], groups: &[Group], file_owners: &BTreeMap<String, String>) -> BTreeMap<String, ArchiveOwner> {
+    let user = |name: &str| users.iter().find(|user| user.name == name);
+    let gid = |name: &str| groups.iter().find(|group| group.name == name).and_then(|group| group.gid);
+
+    file_owners.iter()
+        .map(|(path, spec)| {
+            let (user_name, group) = parse_owner(spec);
+            let group = group
+                .or_else(|| user(user_name).map(User::primary_group))
+                .unwrap_or(user_name);
+            let path = path.trim_start_matches("./").to_string();
+            (path, ArchiveOwner {
+                user: user_name.to_string(),
+                group: group.to_string(),
+                uid: user(user_name).and_then(|user| user.uid).unwrap_or(0),
+                gid: gid(group).unwrap_or(0),
+            })
+        })
+        .collect()
+}
+
+/// Whether this process runs as root, and so can give files any owner
+pub fn running_as_root() -> bool {
+    // A new file is owned by the effective user
+    tempfile::tempfile()
+        .and_then(|file| file.metadata())
+        .is_ok_and(|metadata| std::os::unix::fs::MetadataExt::uid(&metadata) == 0)
+}
+
+/// ID of a user or group in a host database such as `/etc/passwd` or `/etc/group`
+pub fn host_id(database: &Path, name: &str) -> Option<u32> {
+    let contents = std::fs::read_to_string(database).ok()?;
+    contents.lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.first() == Some(&name))
+        .and_then(|fields| fields.get(2)?.parse().ok())
+}
+
+/// Set the owner names and IDs of archive members in place, leaving everything else untouched
+pub fn set_archive_owners(tar_data: &mut [u8], owners: &BTreeMap<String, ArchiveOwner>) -> Result<()> {
+    if owners.is_empty() {
+        return Ok(());
+    }
+
+    let mut headers = Vec::new();
+    let mut archive = tar::Archive::new(&tar_data[..]);
+    for entry in archive.entries().with_context(|| "Failed to read tar archive")? {
+        let entry = entry.with_context(|| "Failed to read tar entry")?;
+        let path = entry.path().with_context(|| "Invalid path in tar entry")?;
+        let path = path.to_string_lossy();
+        let path = path.trim_start_matches("./").trim_end_matches('/');
+        if let Some(owner) = owners.get(path) {
+            headers.push((entry.raw_header_position() as usize, owner));
+        }
+    }
+
+    for (position, owner) in headers {
+        let block = &mut tar_data[position..position + 512];
+        let mut header = tar::Header::from_byte_slice(block).clone();
+        header.set_username(&owner.user)
+            .with_context(|| format!("Failed to set owner '{}'", owner.user))?;
+        header.set_groupname(&owner.group)
+            .with_context(|| format!("Failed to set group '{}'", owner.group))?;
+        header.set_uid(owner.uid.into());
+        header.set_gid(owner.gid.into());
+        header.set_cksum();
+        block.copy_from_slice(header.as_bytes());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_ownership() {
+        let users = vec![User { name: "svc".to_string(), uid: Some(4242), group: None, home: None, shell: None }];
+        let groups = vec![Group { name: "logs".to_string(), gid: Some(4343) }];
+        let owners = |entries: &[(&str, &str)]| -> BTreeMap<String, String> {
+            entries.iter().map(|(path, owner)| (path.to_string(), owner.to_string())).collect()
+        };
+
+        validate_ownership(&users, &groups, &owners(&[("var/lib/svc", "svc"), ("var/log/svc.log", "svc:logs"), ("etc/svc", "root:svc")])).unwrap();
+        assert!(validate_ownership(&users, &groups, &owners(&[("var/lib/svc", "nobody")])).is_err());
+        assert!(validate_ownership(&users, &groups, &owners(&[("var/lib/svc", "svc:wheel")])).is_err());
+        assert!(validate_ownership(&users, &groups, &owners(&[("/var/lib/svc", "svc")])).is_err());
+        assert!(validate_ownership(&[users[0].clone(), users[0].clone()], &groups, &BTreeMap::new()).is_err());
+        assert!(validate_name("user", "Svc").is_err());
+        assert!(validate_name("user", "a-very-long-user-name-for-a-tar-header").is_err());
+
+        let resolved = archive_owners(&users, &groups, &owners(&[("./var/lib/svc", "svc"), ("var/log/svc.log", "svc:logs")]));
+        assert_eq!(resolved["var/lib/svc"], ArchiveOwner { user: "svc".to_string(), group: "svc".to_string(), uid: 4242, gid: 0 });
+        assert_eq!(resolved["var/log/svc.log"].gid, 4343);
+    }
+}
@@ -1,24 +1,526 @@
 use anyhow::{Result, Context};
 use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::{Path, PathBuf};
-use std::process::Command;
-use tempfile::TempDir;
+use std::io::Read;
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use sha2::Digest;
+use crate::builder::Compression;
+use crate::depspec::Provide;
+use crate::ownership::{self, ArchiveOwner, Group, User};
+
+/// Reserved package directory holding metadata; payload files may not use it
+pub const METADATA_DIR: &str = ".pax";
+
+/// Package member holding the package metadata
+pub const METADATA_PATH: &str = ".pax/metadata.yaml";
+
+/// Metadata location used by packages built before metadata moved under `.pax/`
+pub const LEGACY_METADATA_PATH: &str = "metadata.yaml";
+
+/// Package member holding the newline-delimited file list for large packages
+pub const FILES_LIST_NAME: &str = ".pax/files.list";
+
+/// Package member holding `<sha256>  <path>` lines (as written by `sha256sum`)
+/// for large packages whose file list is stored separately
+pub const FILE_HASHES_LIST_NAME: &str = ".pax/file_hashes.list";
+
+/// File list location used by packages built before metadata moved under `.pax/`
+pub const LEGACY_FILES_LIST_NAME: &str = "files.list";
+
+/// Package member holding an embedded Ed25519 signature (64 raw bytes)
+pub const SIGNATURE_PATH: &str = ".pax/signature.bin";
+
+/// Package member holding the hex public key of the embedded signature's signer
+pub const SIGNER_KEY_PATH: &str = ".pax/signer.pub";
+
+/// Domain separator starting the embedded signature digest, bumped if its layout changes
+const SIGNING_DIGEST_PREFIX: &[u8] = b"PAXSIG2\n";
+
+/// Metadata fields that extra metadata keys may not shadow
+pub const RESERVED_METADATA_KEYS: &[&str] = &[
+    "name", "version", "description", "arch", "dependencies", "runtime_dependencies",
+    "provides", "conflicts", "replaces", "install_script", "uninstall_script", "files", "files_list", "extra",
+    "installed_size", "file_hashes", "capabilities", "epoch", "users", "groups", "file_owners", "prefix_map",
+    "file_hashes_list",
+];
+
+/// Check that an extra metadata key is well-formed and not a reserved field
+pub fn validate_extra_key(key: &str) -> Result<()> {
+    if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.')) {
+        anyhow::bail!("Invalid extra metadata key '{}'. Only alphanumeric, dash, underscore and dot are allowed", key);
+    }
+    if RESERVED_METADATA_KEYS.contains(&key) {
+        anyhow::bail!("Extra metadata key '{}' is reserved", key);
+    }
+    Ok(())
+}
+
+/// Read a package file and decompress it, picking the algorithm from its magic bytes
+///
+/// The `.pax` extension does not say how a package is compressed, so renamed
+/// gzip packages are read as readily as zstd ones.
+pub fn decompress_package(path: &Path) -> Result<Vec<u8>> {
+    let compressed = fs::read(path)
+        .with_context(|| format!("Failed to open package: {}", path.display()))?;
+    let compression = Compression::detect(&compressed).ok_or_else(|| {
+        let magic: Vec<String> = compressed.iter().take(4).map(|byte| format!("{:02x}", byte)).collect();
+        anyhow::anyhow!(
+            "Unrecognized package compression in {} (starts with [{}]). Supported: {}",
+            path.display(),
+            magic.join(" "),
+            Compression::READABLE.iter().map(|compression| compression.name()).collect::<Vec<_>>().join(", "),
+        )
+    })?;
+    compression.decompress(&compressed)
+        .with_context(|| format!("Failed to decompress package: {}", path.display()))
+}
+
+/// Open a package file as a stream of decompressed tar data
+///
+/// Like `decompress_package`, but nothing is held in memory beyond the
+/// decoder's buffers.
+fn package_stream(path: &Path) -> Result<Box<dyn Read>> {
+    let file = fs::File::open(path)
+        .with_context(|| format!("Failed to open package: {}", path.display()))?;
+    let mut reader = std::io::BufReader::new(file);
+    let magic = std::io::BufRead::fill_buf(&mut reader)
+        .with_context(|| format!("Failed to read package: {}", path.display()))?;
+    match Compression::detect(magic) {
+        Some(Compression::Zstd) => Ok(Box::new(zstd::Decoder::with_buffer(reader)
+            .with_context(|| format!("Failed to decompress package: {}", path.display()))?)),
+        Some(Compression::Gzip) => Ok(Box::new(flate2::read::GzDecoder::new(reader))),
+        // Let decompress_package describe the unrecognized magic
+        None => decompress_package(path).map(|data| Box::new(std::io::Cursor::new(data)) as Box<dyn Read>),
+    }
+}
+
+/// Compression algorithm of a package file, from its magic bytes
+pub fn package_compression(path: &Path) -> Result<Compression> {
+    let mut magic = Vec::new();
+    fs::File::open(path)
+        .and_then(|file| file.take(4).read_to_end(&mut magic))
+        .with_context(|| format!("Failed to open package: {}", path.display()))?;
+    Compression::detect(&magic)
+        .ok_or_else(|| anyhow::anyhow!("Unrecognized package compression in {}", path.display()))
+}
+
+/// Member path as written by `tar`, without a leading `./` or `/`
+pub(crate) fn member_path(path: &Path) -> &Path {
+    let path = path.strip_prefix("./").unwrap_or(path);
+    path.strip_prefix("/").unwrap_or(path)
+}
 
 /// Package metadata for installed packages
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PackageMetadata {
     pub name: String,
     pub version: String,
+    /// Version epoch, compared before `version`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epoch: Option<u32>,
     pub description: String,
     pub arch: Vec<String>,
     pub dependencies: Vec<String>,
     pub runtime_dependencies: Vec<String>,
-    pub provides: Vec<String>,
+    pub provides: Vec<Provide>,
     pub conflicts: Vec<String>,
+    /// Packages this one supersedes, as dependency constraints
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replaces: Vec<String>,
     pub install_script: Option<String>,
     pub uninstall_script: Option<String>,
+    #[serde(default)]
     pub files: Vec<String>,
+    /// Package member holding the file list when it is stored outside metadata.yaml
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub files_list: Option<String>,
+    /// Free-form key/value pairs stored verbatim (build ticket, git branch, ...)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, String>,
+    /// Total size in bytes of the payload files once installed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub installed_size: Option<u64>,
+    /// SHA256 of each payload file, keyed by its path in the package
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub file_hashes: BTreeMap<String, String>,
+    /// Package member holding `file_hashes` when it is stored outside metadata.yaml
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub file_hashes_list: Option<String>,
+    /// Linux file capabilities to set on install, keyed by path in the package
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub capabilities: BTreeMap<String, String>,
+    /// System users to create on install if missing
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<User>,
+    /// System groups to create on install if missing
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<Group>,
+    /// Owner (`user` or `user:group`) to chown to on install, keyed by path in the package
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub file_owners: BTreeMap<String, String>,
+    /// Prefix maps (`/usr=/usr/local`) applied to packaged paths at build time
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub prefix_map: Vec<String>,
+}
+
+impl PackageMetadata {
+    /// Version including the epoch prefix, e.g. `2:1.0.0`
+    pub fn full_version(&self) -> String {
+        crate::version::with_epoch(self.epoch, &self.version)
+    }
+
+    /// Owner names and IDs to record in the tar headers of owned files
+    pub fn archive_owners(&self) -> BTreeMap<String, ArchiveOwner> {
+        ownership::archive_owners(&self.users, &self.groups, &self.file_owners)
+    }
+
+    /// Serialize metadata to YAML with a stable byte representation
+    ///
+    /// Mapping keys are sorted, and so are all list fields: `arch`,
+    /// `dependencies`, `runtime_dependencies`, `provides`, `conflicts`, `replaces` and
+    /// `files` carry no meaningful order. Scripts are kept verbatim.
+    pub fn to_canonical_yaml(&self) -> Result<String> {
+        let mut metadata = self.clone();
+        for list in [
+            &mut metadata.arch,
+            &mut metadata.dependencies,
+            &mut metadata.runtime_dependencies,
+            &mut metadata.conflicts,
+            &mut metadata.replaces,
+            &mut metadata.files,
+        ] {
+            list.sort();
+        }
+        metadata.provides.sort();
+
+        let value = serde_yaml::to_value(&metadata)
+            .with_context(|| "Failed to serialize package metadata")?;
+        serde_yaml::to_string(&sort_mapping_keys(value))
+            .with_context(|| "Failed to serialize package metadata")
+    }
+}
+
+/// Recursively sort the keys of every mapping in a YAML value
+fn sort_mapping_keys(value: serde_yaml::Value) -> serde_yaml::Value {
+    match value {
+        serde_yaml::Value::Mapping(mapping) => {
+            let mut entries: Vec<_> = mapping.into_iter()
+                .map(|(key, value)| (key, sort_mapping_keys(value)))
+                .collect();
+            entries.sort_by_cached_key(|(key, _)| serde_yaml::to_string(key).unwrap_or_default());
+            serde_yaml::Value::Mapping(entries.into_iter().collect())
+        }
+        serde_yaml::Value::Sequence(items) => {
+            serde_yaml::Value::Sequence(items.into_iter().map(sort_mapping_keys).collect())
+        }
+        other => other,
+    }
+}
+
+/// One member of a package archive, with a reader over its contents
+///
+/// Contents come straight from the package stream, so they can only be read
+/// until the iterator moves on to the next member.
+pub struct PaxEntry {
+    /// Path inside the package, without a leading `./`
+    pub path: PathBuf,
+    /// Permission bits
+    pub mode: u32,
+    /// Size of the contents in bytes
+    pub size: u64,
+    /// Tar entry type (regular file, directory, symlink, ...)
+    pub entry_type: tar::EntryType,
+    /// Target of a symlink or hard link
+    pub link_target: Option<PathBuf>,
+    /// Owner user name recorded in the archive
+    pub owner: Option<String>,
+    /// Owner group name recorded in the archive
+    pub group: Option<String>,
+    /// Owner user ID recorded in the archive (used when `owner` is unknown on the host)
+    pub uid: u64,
+    /// Owner group ID recorded in the archive
+    pub group_id: u64,
+    /// Extended attributes from `SCHILY.xattr.*` PAX records, sorted by name
+    pub xattrs: Vec<(String, Vec<u8>)>,
+    stream: Rc<RefCell<EntryStream>>,
+    position: u64,
+    end: u64,
+}
+
+impl PaxEntry {
+    /// Whether this entry is a regular file
+    pub fn is_file(&self) -> bool {
+        self.entry_type.is_file()
+    }
+
+    /// Whether this entry is a directory
+    pub fn is_dir(&self) -> bool {
+        self.entry_type.is_dir()
+    }
+
+    /// Whether this entry is a symbolic link
+    pub fn is_symlink(&self) -> bool {
+        self.entry_type.is_symlink()
+    }
+}
+
+impl std::fmt::Debug for PaxEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PaxEntry")
+            .field("path", &self.path)
+            .field("mode", &self.mode)
+            .field("size", &self.size)
+            .field("entry_type", &self.entry_type)
+            .field("link_target", &self.link_target)
+            .field("owner", &self.owner)
+            .field("group", &self.group)
+            .field("uid", &self.uid)
+            .field("group_id", &self.group_id)
+            .field("xattrs", &self.xattrs)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Read for PaxEntry {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut stream = self.stream.borrow_mut();
+        if stream.position != self.position {
+            return Err(std::io::Error::other(format!(
+                "Package entry {} read after moving past it", self.path.display()
+            )));
+        }
+        let wanted = buf.len().min((self.end - self.position) as usize);
+        if wanted == 0 {
+            return Ok(0);
+        }
+        let n = stream.read(&mut buf[..wanted])?;
+        if n == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                format!("Truncated package entry: {}", self.path.display()),
+            ));
+        }
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+/// Decompressed tar data, counting the bytes read so far
+struct EntryStream {
+    reader: Box<dyn Read>,
+    position: u64,
+    /// End of the current member's contents
+    data_end: u64,
+}
+
+impl Read for EntryStream {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.reader.read(buf)?;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+/// Iterator over the members of a package archive, read as a stream
+///
+/// Each step skips whatever is left of the previous member and parses the
+/// next header; contents are read on demand, and only one member's at a time.
+struct PaxEntries {
+    stream: Rc<RefCell<EntryStream>>,
+    done: bool,
+}
+
+impl PaxEntries {
+    fn new(reader: Box<dyn Read>) -> Self {
+        let stream = EntryStream { reader, position: 0, data_end: 0 };
+        PaxEntries { stream: Rc::new(RefCell::new(stream)), done: false }
+    }
+
+    /// Offset just past the last member read, before the end-of-archive blocks
+    fn members_end(&self) -> u64 {
+        self.stream.borrow().data_end.div_ceil(512) * 512
+    }
+
+    fn next_entry(&mut self) -> Result<Option<PaxEntry>> {
+        let mut stream = self.stream.borrow_mut();
+
+        // Member data is padded to the next 512-byte block
+        let next_header = stream.data_end.div_ceil(512) * 512;
+        let skip = next_header - stream.position;
+        let skipped = std::io::copy(&mut (&mut *stream).take(skip), &mut std::io::sink())
+            .with_context(|| "Failed to read package archive")?;
+        if skipped < skip {
+            anyhow::bail!("Truncated package archive");
+        }
+
+        let start = stream.position;
+        let mut archive = tar::Archive::new(&mut *stream);
+        let mut entries = archive.entries()
+            .with_context(|| "Failed to read package archive")?;
+        let Some(entry) = entries.next() else {
+            return Ok(None);
+        };
+        let mut entry = entry.with_context(|| "Failed to read package entry")?;
+
+        let path = entry.path().with_context(|| "Invalid path in package entry")?;
+        let path = path.strip_prefix("./").unwrap_or(&path).to_path_buf();
+        let mut xattrs = Vec::new();
+        if let Some(extensions) = entry.pax_extensions().with_context(|| format!("Failed to read PAX headers of: {}", path.display()))? {
+            for extension in extensions {
+                let extension = extension.with_context(|| format!("Invalid PAX header in: {}", path.display()))?;
+                if let Some(name) = extension.key().ok().and_then(|key| key.strip_prefix("SCHILY.xattr.")) {
+                    xattrs.push((name.to_string(), extension.value_bytes().to_vec()));
+                }
+            }
+        }
+        xattrs.sort();
+        let link_target = entry.link_name()
+            .with_context(|| format!("Invalid link target in package entry: {}", path.display()))?
+            .map(|target| target.into_owned());
+        let size = entry.size();
+        let data_start = start + entry.raw_file_position();
+
+        let name = |name: Option<&str>| name.filter(|name| !name.is_empty()).map(str::to_string);
+        let pax_entry = PaxEntry {
+            mode: entry.header().mode().unwrap_or(0),
+            entry_type: entry.header().entry_type(),
+            owner: name(entry.header().username().ok().flatten()),
+            group: name(entry.header().groupname().ok().flatten()),
+            uid: entry.header().uid().unwrap_or(0),
+            group_id: entry.header().gid().unwrap_or(0),
+            xattrs,
+            path,
+            size,
+            link_target,
+            stream: Rc::clone(&self.stream),
+            position: data_start,
+            end: data_start + size,
+        };
+
+        // The header is read, and the member's contents come next
+        if stream.position != data_start {
+            anyhow::bail!("Unexpected layout of package entry: {}", pax_entry.path.display());
+        }
+        stream.data_end = data_start + size;
+        Ok(Some(pax_entry))
+    }
+}
+
+impl Iterator for PaxEntries {
+    type Item = Result<PaxEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_entry().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.done = true;
+        }
+        result
+    }
+}
+
+/// Check that every member of a package lands inside `dest_dir`
+///
+/// Absolute paths and `..` components are refused, in member names and hard
+/// link targets, as are members whose parent already resolves outside
+/// `dest_dir` through a symlink. Symlinks the archive itself creates are
+/// checked by the tar crate as it unpacks.
+fn check_entry_paths(tar_data: &[u8], dest_dir: &Path) -> Result<()> {
+    let root = dest_dir.canonicalize()
+        .with_context(|| format!("Failed to resolve destination: {}", dest_dir.display()))?;
+    let escapes = |path: &Path| path.components().any(|component| matches!(component, Component::RootDir | Component::Prefix(_) | Component::ParentDir));
+
+    let mut archive = tar::Archive::new(tar_data);
+    for entry in archive.entries().with_context(|| "Failed to read package archive")? {
+        let entry = entry.with_context(|| "Failed to read package entry")?;
+        let path = entry.path().with_context(|| "Invalid path in package entry")?.into_owned();
+        if escapes(&path) {
+            anyhow::bail!("Entry '{}' points outside the destination directory", path.display());
+        }
+        if entry.header().entry_type() == tar::EntryType::Link {
+            if let Some(target) = entry.link_name().with_context(|| "Invalid link target in package entry")? {
+                if escapes(&target) {
+                    anyhow::bail!("Entry '{}' links to '{}', outside the destination directory", path.display(), target.display());
+                }
+            }
+        }
+
+        // The deepest existing ancestor must still resolve below the destination
+        let relative: PathBuf = path.components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect();
+        let resolved = relative.parent()
+            .and_then(|parent| dest_dir.join(parent).ancestors().find_map(|ancestor| ancestor.canonicalize().ok()));
+        if resolved.is_some_and(|resolved| !resolved.starts_with(&root)) {
+            anyhow::bail!("Entry '{}' resolves outside the destination directory", path.display());
+        }
+    }
+    Ok(())
+}
+
+/// Give extracted members the owners recorded in their headers
+///
+/// Changing the owner clears setuid/setgid bits and file capabilities, so
+/// the member's mode and extended attributes are set again afterwards.
+fn restore_owners(tar_data: &[u8], dest_dir: &Path) -> Result<()> {
+    let mut archive = tar::Archive::new(tar_data);
+    for entry in archive.entries().with_context(|| "Failed to read package archive")? {
+        let mut entry = entry.with_context(|| "Failed to read package entry")?;
+        let path = entry.path().with_context(|| "Invalid path in package entry")?.into_owned();
+        if path.components().any(|component| component == Component::ParentDir) {
+            continue;
+        }
+        let relative: PathBuf = path.components()
+            .filter(|component| matches!(component, Component::Normal(_)))
+            .collect();
+        let target = dest_dir.join(&relative);
+        let Ok(current) = fs::symlink_metadata(&target) else {
+            continue;
+        };
+        // The destination itself keeps its owner
+        if relative.as_os_str().is_empty() {
+            continue;
+        }
+
+        let header = entry.header();
+        let uid = header.username().ok().flatten()
+            .and_then(|name| ownership::host_id(Path::new("/etc/passwd"), name))
+            .or_else(|| header.uid().ok().map(|uid| uid as u32))
+            .unwrap_or(0);
+        let gid = header.groupname().ok().flatten()
+            .and_then(|name| ownership::host_id(Path::new("/etc/group"), name))
+            .or_else(|| header.gid().ok().map(|gid| gid as u32))
+            .unwrap_or(0);
+        if (current.uid(), current.gid()) == (uid, gid) {
+            continue;
+        }
+        let mode = header.mode().unwrap_or(0o644);
+
+        std::os::unix::fs::lchown(&target, Some(uid), Some(gid))
+            .with_context(|| format!("Failed to set owner of: {}", target.display()))?;
+        if current.file_type().is_symlink() {
+            continue;
+        }
+        fs::set_permissions(&target, fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to set permissions on: {}", target.display()))?;
+        if let Some(extensions) = entry.pax_extensions().with_context(|| "Failed to read PAX headers")? {
+            for extension in extensions {
+                let extension = extension.with_context(|| "Invalid PAX header")?;
+                if let Some(name) = extension.key().ok().and_then(|key| key.strip_prefix("SCHILY.xattr.")) {
+                    xattr::set(&target, name, extension.value_bytes())
+                        .with_context(|| format!("Failed to set extended attribute {} on: {}", name, target.display()))?;
+                }
+            }
+        }
+    }
+    Ok(())
 }
 
 /// Represents a .pax package
@@ -44,76 +546,319 @@ impl PaxPackage {
 
     /// Load metadata from the package
     pub fn load_metadata(&mut self) -> Result<&PackageMetadata> {
+        self.try_load_metadata()?
+            .ok_or_else(|| anyhow::anyhow!("{} not found in package", METADATA_PATH))
+    }
+
+    /// Load metadata from the package, or `None` if it has no metadata member
+    ///
+    /// Old archives sometimes carry only files; a metadata member that is
+    /// present but unreadable is still an error.
+    pub fn try_load_metadata(&mut self) -> Result<Option<&PackageMetadata>> {
         if self.metadata.is_some() {
-            return Ok(self.metadata.as_ref().unwrap());
+            return Ok(self.metadata.as_ref());
         }
+
+        let members = self.metadata_members()?;
+        let member = |relative: &str| members.get(member_path(Path::new(relative))).map(|contents| {
+            String::from_utf8(contents.clone()).with_context(|| format!("Failed to read {}", relative))
+        });
+
+        // Read metadata.yaml, falling back to the legacy root location
+        let Some(contents) = member(METADATA_PATH).or_else(|| member(LEGACY_METADATA_PATH)) else {
+            return Ok(None);
+        };
         
-        // Extract .paxmeta from the package
-        let temp_dir = TempDir::new()
-            .with_context(|| "Failed to create temporary directory")?;
-        
-        let extract_dir = temp_dir.path().join("extract");
-        fs::create_dir_all(&extract_dir)
-            .with_context(|| "Failed to create extract directory")?;
-        
-        // Decompress and extract
-        self.extract_to(&extract_dir)?;
-        
-        // Find and read metadata.yaml
-        let metadata_path = extract_dir.join("metadata.yaml");
-        if !metadata_path.exists() {
-            anyhow::bail!("metadata.yaml not found in package");
+        let mut metadata = self.parse_package_metadata(&contents?)?;
+
+        // Members that were not collected (hard links, or ones stored after
+        // the payload) are read on their own
+        let read_member = |relative: &str| match member(relative) {
+            Some(contents) => contents.map(Some),
+            None => self.read_member_to_string(relative),
+        };
+
+        // Large packages keep their file list in a separate member
+        if let Some(files_list) = &metadata.files_list {
+            let contents = read_member(files_list)?
+                .ok_or_else(|| anyhow::anyhow!("File list member not found in package: {}", files_list))?;
+            metadata.files = contents.lines()
+                .filter(|line| !line.is_empty())
+                .map(|line| line.to_string())
+                .collect();
         }
-        
-        let contents = fs::read_to_string(&metadata_path)
-            .with_context(|| "Failed to read metadata.yaml file")?;
-        
-        let metadata = self.parse_package_metadata(&contents)?;
+        if let Some(file_hashes_list) = &metadata.file_hashes_list {
+            let contents = read_member(file_hashes_list)?
+                .ok_or_else(|| anyhow::anyhow!("File hashes member not found in package: {}", file_hashes_list))?;
+            for line in contents.lines().filter(|line| !line.is_empty()) {
+                let (hash, path) = line.split_once("  ")
+                    .ok_or_else(|| anyhow::anyhow!("Invalid line in {}: {}", file_hashes_list, line))?;
+                metadata.file_hashes.insert(path.to_string(), hash.to_string());
+            }
+        }
+
         self.metadata = Some(metadata);
         
-        Ok(self.metadata.as_ref().unwrap())
+        Ok(self.metadata.as_ref())
+    }
+
+    /// Iterate over every member of the package archive, including metadata members
+    ///
+    /// The package is decompressed as the iterator advances; read each
+    /// entry's contents before moving on to the next.
+    pub fn entries(&self) -> Result<impl Iterator<Item = Result<PaxEntry>>> {
+        Ok(PaxEntries::new(package_stream(&self.path)?))
+    }
+
+    /// Digest covered by an embedded signature
+    ///
+    /// SHA256 over `PAXSIG2\n` followed by one line per archive member, sorted
+    /// by path (bytewise, without a leading `./` or trailing `/`):
+    ///
+    /// ```text
+    /// <path> NUL <type> NUL <mode, octal> NUL <content>
+    ///     NUL <owner> NUL <uid> NUL <group> NUL <gid>
+    ///     NUL <xattr count> { NUL <xattr name> NUL <hex value> } LF
+    /// ```
+    ///
+    /// `type` is `f` (file), `d` (directory), `l` (symlink), `h` (hard link) or
+    /// `o` (anything else); `content` is the hex SHA256 of a file's data, a
+    /// link's target, or empty. Owner and group names are empty when not
+    /// recorded, and extended attributes (`SCHILY.xattr.*`, file capabilities
+    /// included) are sorted by name, so everything extraction applies as root
+    /// is covered. `.pax/metadata.yaml` is covered like any other
+    /// member; the root directory, `.pax/signature.bin` and `.pax/signer.pub`
+    /// are left out, so the digest is the same before and after signing and
+    /// does not depend on member order or compression.
+    pub fn signing_digest(&self) -> Result<[u8; 32]> {
+        let mut lines = Vec::new();
+        for entry in self.entries()? {
+            let mut entry = entry?;
+            let path = entry.path.to_string_lossy().trim_end_matches('/').to_string();
+            if path.is_empty() || path == "." || path == SIGNATURE_PATH || path == SIGNER_KEY_PATH {
+                continue;
+            }
+
+            let (kind, content) = match entry.entry_type {
+                tar::EntryType::Regular | tar::EntryType::Continuous => {
+                    let mut hasher = sha2::Sha256::new();
+                    std::io::copy(&mut entry, &mut hasher)
+                        .with_context(|| format!("Failed to hash package entry: {}", path))?;
+                    ('f', hex::encode(hasher.finalize()))
+                }
+                tar::EntryType::Directory => ('d', String::new()),
+                tar::EntryType::Symlink | tar::EntryType::Link => {
+                    let kind = if entry.is_symlink() { 'l' } else { 'h' };
+                    let target = entry.link_target.as_deref().unwrap_or(Path::new(""));
+                    (kind, target.to_string_lossy().to_string())
+                }
+                _ => ('o', String::new()),
+            };
+            let mut line = format!(
+                "{}\0{}\0{:o}\0{}\0{}\0{}\0{}\0{}\0{}",
+                path, kind, entry.mode & 0o7777, content,
+                entry.owner.as_deref().unwrap_or(""), entry.uid,
+                entry.group.as_deref().unwrap_or(""), entry.group_id,
+                entry.xattrs.len(),
+            );
+            for (name, value) in &entry.xattrs {
+                line.push_str(&format!("\0{}\0{}", name, hex::encode(value)));
+            }
+            line.push('\n');
+            lines.push((path, line));
+        }
+        lines.sort();
+
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(SIGNING_DIGEST_PREFIX);
+        for (_, line) in lines {
+            hasher.update(line.as_bytes());
+        }
+        Ok(hasher.finalize().into())
+    }
+
+    /// Contents of the embedded signature and signer key members, if the package has them
+    fn embedded_signature(&self) -> Result<Option<(Vec<u8>, String)>> {
+        let mut signature = None;
+        let mut signer = None;
+        for entry in self.entries()? {
+            let mut entry = entry?;
+            let path = entry.path.to_string_lossy().to_string();
+            if path == SIGNATURE_PATH {
+                let mut bytes = Vec::new();
+                entry.read_to_end(&mut bytes)
+                    .with_context(|| format!("Failed to read {}", SIGNATURE_PATH))?;
+                signature = Some(bytes);
+            } else if path == SIGNER_KEY_PATH {
+                let mut key = String::new();
+                entry.read_to_string(&mut key)
+                    .with_context(|| format!("Failed to read {}", SIGNER_KEY_PATH))?;
+                signer = Some(key);
+            }
+        }
+
+        match (signature, signer) {
+            (None, None) => Ok(None),
+            (Some(signature), Some(signer)) => Ok(Some((signature, signer))),
+            (Some(_), None) => anyhow::bail!("Package has {} but no {}", SIGNATURE_PATH, SIGNER_KEY_PATH),
+            (None, Some(_)) => anyhow::bail!("Package has {} but no {}", SIGNER_KEY_PATH, SIGNATURE_PATH),
+        }
+    }
+
+    /// Whether the package carries an embedded signature
+    pub fn has_embedded_signature(&self) -> Result<bool> {
+        Ok(self.embedded_signature()?.is_some())
+    }
+
+    /// Check the embedded signature against [`signing_digest`](Self::signing_digest)
+    ///
+    /// Returns the signer's public key; whether to trust it is up to the caller.
+    pub fn verify_embedded_signature(&self) -> Result<ed25519_dalek::VerifyingKey> {
+        let (signature, signer) = self.embedded_signature()?
+            .ok_or_else(|| anyhow::anyhow!("Package has no embedded signature: {}", self.path.display()))?;
+
+        let signer: [u8; 32] = hex::decode(signer.trim()).ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| anyhow::anyhow!("Malformed signer key in {}", SIGNER_KEY_PATH))?;
+        let signer = ed25519_dalek::VerifyingKey::from_bytes(&signer)
+            .with_context(|| format!("Not a valid Ed25519 public key in {}", SIGNER_KEY_PATH))?;
+        let signature = ed25519_dalek::Signature::from_slice(&signature)
+            .with_context(|| format!("Malformed signature in {}: expected 64 bytes, got {}", SIGNATURE_PATH, signature.len()))?;
+
+        signer.verify_strict(&self.signing_digest()?, &signature)
+            .with_context(|| format!("Embedded signature verification FAILED for {}", self.path.display()))?;
+        Ok(signer)
+    }
+
+    /// Add `.pax/signature.bin` and `.pax/signer.pub` to the archive, keeping its compression
+    ///
+    /// Existing members are left byte-for-byte as they were; the two new
+    /// members are appended after them. The archive is recompressed at
+    /// `level`, since the level it was written with is not recorded.
+    pub fn embed_signature(&mut self, signature: &[u8], signer: &ed25519_dalek::VerifyingKey, level: i32) -> Result<()> {
+        if self.has_embedded_signature()? {
+            anyhow::bail!("Package already has an embedded signature: {}", self.path.display());
+        }
+
+        let compressed = fs::read(&self.path)
+            .with_context(|| format!("Failed to open package: {}", self.path.display()))?;
+        let compression = Compression::detect(&compressed)
+            .ok_or_else(|| anyhow::anyhow!("Unrecognized package compression in {}", self.path.display()))?;
+        let decompressed: Arc<[u8]> = compression.decompress(&compressed)?.into();
+
+        // Drop the end-of-archive blocks so the new members follow the last existing one
+        let mut entries = PaxEntries::new(Box::new(std::io::Cursor::new(Arc::clone(&decompressed))));
+        while entries.next_entry()?.is_some() {}
+        let mut builder = tar::Builder::new(decompressed[..entries.members_end() as usize].to_vec());
+
+        let signer = hex::encode(signer.to_bytes());
+        for (path, contents) in [(SIGNATURE_PATH, signature), (SIGNER_KEY_PATH, signer.as_bytes())] {
+            let mut header = tar::Header::new_ustar();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(0);
+            header.set_cksum();
+            builder.append_data(&mut header, path, contents)
+                .with_context(|| format!("Failed to add {} to package", path))?;
+        }
+        let tar_data = builder.into_inner()
+            .with_context(|| "Failed to finish package archive")?;
+
+        fs::write(&self.path, compression.compress(&tar_data, level)?)
+            .with_context(|| format!("Failed to write package: {}", self.path.display()))?;
+        Ok(())
     }
 
     /// Extract package contents to a directory
+    ///
+    /// Packages with an absolute or `..` member path or hard link target, or
+    /// a member reached through a symlink leading outside `dest_dir`, are
+    /// refused before anything is written. Modes and modification times are
+    /// restored. When running as root, so are extended attributes (every
+    /// namespace, including file capabilities and ACLs) and owners, which
+    /// are looked up by name on this host and fall back to the recorded IDs.
     pub fn extract_to(&self, dest_dir: &Path) -> Result<()> {
         fs::create_dir_all(dest_dir)
             .with_context(|| "Failed to create destination directory")?;
         
-        // Decompress with zstd and extract with tar
-        let zstd_output = Command::new("zstd")
-            .arg("-dc")
-            .arg(&self.path)
-            .output()
-            .with_context(|| "Failed to decompress package")?;
-        
-        if !zstd_output.status.success() {
-            anyhow::bail!("Failed to decompress package");
-        }
-        
-        let mut tar_process = Command::new("tar")
-            .arg("-xf")
-            .arg("-")
-            .arg("-C")
-            .arg(dest_dir)
-            .stdin(std::process::Stdio::piped())
-            .spawn()
-            .with_context(|| "Failed to start tar process")?;
-        
-        if let Some(stdin) = tar_process.stdin.take() {
-            std::io::Write::write_all(&mut std::io::BufWriter::new(stdin), &zstd_output.stdout)
-                .with_context(|| "Failed to write to tar stdin")?;
-        }
+        // Decompress with whichever algorithm the package uses
+        let decompressed = decompress_package(&self.path)?;
+        check_entry_paths(&decompressed, dest_dir)
+            .with_context(|| format!("Refusing to extract package: {}", self.path.display()))?;
+        let as_root = ownership::running_as_root();
         
-        let tar_output = tar_process.wait_with_output()
-            .with_context(|| "Failed to wait for tar process")?;
+        let mut archive = tar::Archive::new(decompressed.as_slice());
+        archive.set_preserve_permissions(true);
+        archive.set_overwrite(true);
+        archive.set_unpack_xattrs(as_root);
+        archive.unpack(dest_dir)
+            .with_context(|| format!("Failed to extract package: {}", self.path.display()))?;
         
-        if !tar_output.status.success() {
-            anyhow::bail!("Failed to extract package");
+        if as_root {
+            restore_owners(&decompressed, dest_dir)?;
         }
-        
         Ok(())
     }
 
+    /// Extract the single member `relative` to the file `dest`
+    ///
+    /// The package is streamed until the member turns up, so nothing else is
+    /// written or held in memory. A hard link is extracted as a copy of its
+    /// target. Fails if the member is missing or is not a regular file.
+    pub fn extract_file(&self, relative: &str, dest: &Path) -> Result<()> {
+        let found = self.with_member(relative, |member| {
+            if let Some(parent) = dest.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+                fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+            }
+            let mut file = fs::File::create(dest)
+                .with_context(|| format!("Failed to create file: {}", dest.display()))?;
+            std::io::copy(member, &mut file)
+                .with_context(|| format!("Failed to extract '{}' to {}", relative, dest.display()))?;
+            Ok(())
+        })?;
+        found.ok_or_else(|| anyhow::anyhow!("'{}' not found in package: {}", relative, self.path.display()))
+    }
+
+    /// Contents of the member `relative` as text, or `None` if the package lacks it
+    fn read_member_to_string(&self, relative: &str) -> Result<Option<String>> {
+        self.with_member(relative, |member| {
+            let mut contents = String::new();
+            member.read_to_string(&mut contents)
+                .with_context(|| format!("Failed to read {}", relative))?;
+            Ok(contents)
+        })
+    }
+
+    /// Stream the package up to the member `relative` and hand its data to `f`
+    ///
+    /// Returns `None` if there is no such member.
+    fn with_member<T>(&self, relative: &str, f: impl FnOnce(&mut dyn Read) -> Result<T>) -> Result<Option<T>> {
+        let wanted = member_path(Path::new(relative));
+        for entry in self.entries()? {
+            let mut entry = entry?;
+            if member_path(&entry.path) != wanted {
+                continue;
+            }
+
+            if entry.entry_type == tar::EntryType::Link {
+                let target = entry.link_target.as_deref()
+                    .ok_or_else(|| anyhow::anyhow!("Hard link without a target: {}", entry.path.display()))?;
+                let target = target.to_str()
+                    .ok_or_else(|| anyhow::anyhow!("Invalid link target in package entry: {}", entry.path.display()))?;
+                if member_path(Path::new(target)) == wanted {
+                    anyhow::bail!("Hard link '{}' points to itself", relative);
+                }
+                return self.with_member(target, f);
+            }
+            if !entry.is_file() {
+                anyhow::bail!("'{}' in package is not a regular file", relative);
+            }
+            return f(&mut entry).map(Some);
+        }
+        Ok(None)
+    }
+
     /// Get package file path
     pub fn path(&self) -> &Path {
         &self.path
@@ -157,48 +902,126 @@ impl PaxPackage {
 
     /// Calculate SHA256 hash of the package
     pub fn calculate_hash(&self) -> Result<String> {
-        use sha2::{Sha256, Digest};
-        use hex;
-        
-        let mut file = fs::File::open(&self.path)
-            .with_context(|| format!("Failed to open package: {}", self.path.display()))?;
-        
-        let mut hasher = Sha256::new();
-        std::io::copy(&mut file, &mut hasher)
-            .with_context(|| "Failed to read package for hashing")?;
-        
-        Ok(hex::encode(hasher.finalize()))
+        crate::integrity::HashAlgorithm::Sha256.hash_file(&self.path)
+            .with_context(|| format!("Failed to hash package: {}", self.path.display()))
     }
 
-    /// List files in the package
+    /// List payload files in the package, excluding metadata members
+    ///
+    /// Paths are relative to the package root, in archive order. Only the
+    /// entry headers are read; nothing is written to disk. Symlinks and hard
+    /// links count as files; directories do not.
     pub fn list_files(&self) -> Result<Vec<PathBuf>> {
-        let temp_dir = TempDir::new()
-            .with_context(|| "Failed to create temporary directory")?;
-        
-        let extract_dir = temp_dir.path().join("extract");
-        self.extract_to(&extract_dir)?;
-        
         let mut files = Vec::new();
-        self.collect_files(&extract_dir, &mut files)?;
-        
+        for entry in self.entries()? {
+            let entry = entry?;
+            let path = member_path(&entry.path).to_path_buf();
+            if !entry.is_dir() && !path.as_os_str().is_empty() {
+                files.push(path);
+            }
+        }
+
+        // Legacy packages keep their metadata members at the root
+        let mut reserved = vec![Path::new(METADATA_DIR)];
+        if !files.iter().any(|file| file.as_path() == Path::new(METADATA_PATH)) {
+            reserved.push(Path::new(LEGACY_METADATA_PATH));
+            reserved.push(Path::new(LEGACY_FILES_LIST_NAME));
+        }
+        files.retain(|file| !reserved.iter().any(|path| file.starts_with(path)));
+
         Ok(files)
     }
 
-    /// Recursively collect files from a directory
-    fn collect_files(&self, dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
-        for entry in fs::read_dir(dir)
-            .with_context(|| format!("Failed to read directory: {}", dir.display()))? {
-            let entry = entry.with_context(|| "Failed to read directory entry")?;
-            let path = entry.path();
-            
-            if path.is_dir() {
-                self.collect_files(&path, files)?;
-            } else {
-                files.push(path);
+    /// Read the regular files under `.pax/` and the legacy root metadata members
+    ///
+    /// Packages are written with `.pax/` first, so once `.pax/metadata.yaml`
+    /// has been read the rest of the payload is not decompressed. Legacy
+    /// packages are read until the end of the archive.
+    fn metadata_members(&self) -> Result<BTreeMap<PathBuf, Vec<u8>>> {
+        let mut members = BTreeMap::new();
+        for entry in self.entries()? {
+            let mut entry = entry?;
+            let path = member_path(&entry.path).to_path_buf();
+            let in_metadata_dir = path.starts_with(METADATA_DIR);
+            if !in_metadata_dir && members.contains_key(Path::new(METADATA_PATH)) {
+                break;
+            }
+
+            let is_metadata_member = in_metadata_dir
+                || path.as_path() == Path::new(LEGACY_METADATA_PATH)
+                || path.as_path() == Path::new(LEGACY_FILES_LIST_NAME);
+            if is_metadata_member && entry.is_file() {
+                let mut contents = Vec::new();
+                entry.read_to_end(&mut contents)
+                    .with_context(|| format!("Failed to read package member: {}", path.display()))?;
+                members.insert(path, contents);
             }
         }
-        
-        Ok(())
+        Ok(members)
+    }
+
+    /// Payload files whose contents do not match the SHA256 recorded in `file_hashes`
+    ///
+    /// Recorded files missing from the archive are reported too. Packages
+    /// without `file_hashes` have nothing to check.
+    pub fn file_hash_mismatches(&mut self) -> Result<Vec<String>> {
+        let mut expected = self.load_metadata()?.file_hashes.clone();
+        if expected.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut mismatches = Vec::new();
+        let mut hashes = std::collections::HashMap::new();
+        for entry in self.entries()? {
+            let mut entry = entry?;
+            let path = entry.path.to_string_lossy().to_string();
+            let Some(expected_hash) = expected.remove(&path) else { continue };
+
+            // A hard link member has no data of its own; it shares its target's
+            let hash = match (entry.entry_type, &entry.link_target) {
+                (tar::EntryType::Link, Some(target)) => hashes.get(target.to_string_lossy().trim_start_matches("./"))
+                    .cloned()
+                    .unwrap_or_default(),
+                _ => {
+                    let mut hasher = sha2::Sha256::new();
+                    std::io::copy(&mut entry, &mut hasher)
+                        .with_context(|| format!("Failed to hash package entry: {}", path))?;
+                    hex::encode(hasher.finalize())
+                }
+            };
+            hashes.insert(path.clone(), hash.clone());
+            if hash != expected_hash {
+                mismatches.push(format!("{}: expected sha256 {}, found {}", path, expected_hash, hash));
+            }
+        }
+        mismatches.extend(expected.into_keys().map(|path| format!("{}: missing from package", path)));
+
+        Ok(mismatches)
+    }
+
+    /// Files under `dir` (where the package was extracted or installed) that no
+    /// longer match the SHA256 recorded in `file_hashes`
+    pub fn extracted_file_mismatches(&mut self, dir: &Path) -> Result<Vec<String>> {
+        let mut mismatches = Vec::new();
+        for (path, expected_hash) in &self.load_metadata()?.file_hashes {
+            let mut file = match fs::File::open(dir.join(path)) {
+                Ok(file) => file,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                    mismatches.push(format!("{}: missing", path));
+                    continue;
+                }
+                Err(e) => return Err(e).with_context(|| format!("Failed to open extracted file: {}", path)),
+            };
+            let mut hasher = sha2::Sha256::new();
+            std::io::copy(&mut file, &mut hasher)
+                .with_context(|| format!("Failed to hash extracted file: {}", path))?;
+            let hash = hex::encode(hasher.finalize());
+            if hash != *expected_hash {
+                mismatches.push(format!("{}: expected sha256 {}, found {}", path, expected_hash, hash));
+            }
+        }
+
+        Ok(mismatches)
     }
 
     /// Verify package integrity
@@ -223,6 +1046,7 @@ impl PaxPackage {
 mod tests {
     use super::*;
     use std::fs;
+    use tempfile::TempDir;
 
     #[test]
     fn test_package_operations() {
@@ -236,4 +1060,390 @@ mod tests {
         assert_eq!(package.path(), test_file);
         assert_eq!(package.filename(), Some("test.pax"));
     }
+
+    #[test]
+    fn test_legacy_root_metadata_fallback() {
+        let temp_dir = TempDir::new().unwrap();
+        let metadata = crate::test_support::metadata("legacy", "1.0.0");
+        let metadata_yaml = serde_yaml::to_string(&metadata).unwrap();
+        let package_path = crate::test_support::write_package_members(temp_dir.path(), "legacy-1.0.0-x86_64.pax", None, &[
+            (LEGACY_METADATA_PATH, metadata_yaml.as_bytes(), 0o644),
+            ("usr/bin/legacy", b"bin", 0o644),
+        ]);
+
+        let mut package = PaxPackage::open(&package_path).unwrap();
+        assert_eq!(package.load_metadata().unwrap().name, "legacy");
+
+        let files = package.list_files().unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].ends_with("usr/bin/legacy"));
+    }
+
+    #[test]
+    fn test_entries_of_built_package() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = crate::test_support::source_tarball(&[("hello-1.0.0/hello.sh", "echo hello\n")]);
+        let url = crate::test_support::serve(source, "hello-1.0.0.tar.gz");
+        let recipe_path = crate::test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: hello
+version: 1.0.0
+description: Test package
+source: {}
+arch: [x86_64]
+build: |
+  mkdir -p $PAX_BUILD_ROOT/usr/bin $PAX_BUILD_ROOT/usr/share/doc/hello
+  cp hello.sh $PAX_BUILD_ROOT/usr/bin/hello
+  echo docs > $PAX_BUILD_ROOT/usr/share/doc/hello/README
+"#, url));
+        let package_path = temp_dir.path().join("hello.pax");
+        let options = crate::build::BuildOptions {
+            output: Some(package_path.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        crate::build::build_package(recipe_path.to_str().unwrap(), &options).unwrap();
+
+        let package = PaxPackage::open(&package_path).unwrap();
+        let mut files = Vec::new();
+        for entry in package.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            if entry.is_dir() {
+                continue;
+            }
+            assert!(entry.is_file() && !entry.is_symlink());
+
+            let mut contents = String::new();
+            entry.read_to_string(&mut contents).unwrap();
+            assert_eq!(contents.len() as u64, entry.size);
+            match entry.path.to_str().unwrap() {
+                "usr/bin/hello" => assert_eq!(contents, "echo hello\n"),
+                "usr/share/doc/hello/README" => assert_eq!(contents, "docs\n"),
+                METADATA_PATH => assert!(contents.contains("name: hello")),
+                other => panic!("unexpected entry {}", other),
+            }
+            files.push(entry.path.to_string_lossy().to_string());
+        }
+
+        files.sort();
+        assert_eq!(files, vec![METADATA_PATH, "usr/bin/hello", "usr/share/doc/hello/README"]);
+
+        // Entries are streamed: one left unread is skipped, and can no longer be read once passed
+        let mut entries = package.entries().unwrap().map(Result::unwrap);
+        let mut passed = entries.find(|entry| entry.is_file()).unwrap();
+        assert!(entries.any(|entry| entry.path.ends_with("README")));
+        assert!(passed.read_to_end(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_list_files_of_built_package() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = crate::test_support::source_tarball(&[("listed-1.0.0/listed.sh", "echo listed\n")]);
+        let url = crate::test_support::serve(source, "listed-1.0.0.tar.gz");
+        let recipe_path = crate::test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: listed
+version: 1.0.0
+description: Test package
+source: {}
+arch: [x86_64]
+build: |
+  mkdir -p $PAX_BUILD_ROOT/usr/bin $PAX_BUILD_ROOT/usr/lib $PAX_BUILD_ROOT/var/empty
+  cp listed.sh $PAX_BUILD_ROOT/usr/bin/listed
+  echo lib > $PAX_BUILD_ROOT/usr/lib/liblisted.so.1
+  ln -s liblisted.so.1 $PAX_BUILD_ROOT/usr/lib/liblisted.so
+"#, url));
+        let package_path = temp_dir.path().join("listed.pax");
+        let options = crate::build::BuildOptions {
+            output: Some(package_path.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        crate::build::build_package(recipe_path.to_str().unwrap(), &options).unwrap();
+
+        let package = PaxPackage::open(&package_path).unwrap();
+        let mut files = package.list_files().unwrap();
+        files.sort();
+        assert_eq!(files, [
+            PathBuf::from("usr/bin/listed"),
+            PathBuf::from("usr/lib/liblisted.so"),
+            PathBuf::from("usr/lib/liblisted.so.1"),
+        ]);
+    }
+
+    #[test]
+    fn test_metadata_and_split_lists_load_before_payload() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut metadata = crate::test_support::metadata("split", "1.0.0");
+        metadata.files_list = Some(FILES_LIST_NAME.to_string());
+        metadata.file_hashes_list = Some(FILE_HASHES_LIST_NAME.to_string());
+        let hash = hex::encode(sha2::Sha256::digest(b"binary"));
+        let package_path = crate::test_support::write_package(temp_dir.path(), &metadata, &[
+            (FILES_LIST_NAME, "usr/bin/split\nusr/share/split/data\n"),
+            (FILE_HASHES_LIST_NAME, &format!("{}  usr/bin/split\n", hash)),
+            ("usr/bin/split", "binary"),
+            ("usr/share/split/data", "data"),
+        ]);
+
+        let mut package = PaxPackage::open(&package_path).unwrap();
+        let loaded = package.load_metadata().unwrap();
+        assert_eq!(loaded.name, "split");
+        assert_eq!(loaded.files, ["usr/bin/split", "usr/share/split/data"]);
+        assert_eq!(loaded.file_hashes.get("usr/bin/split"), Some(&hash));
+        assert_eq!(package.list_files().unwrap(), [PathBuf::from("usr/bin/split"), PathBuf::from("usr/share/split/data")]);
+        assert_eq!(package.list_files().unwrap(), PaxPackage::open(&package_path).unwrap().list_files().unwrap());
+        assert!(package.file_hash_mismatches().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_load_metadata_stops_before_payload() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut seed = 1u32;
+        let payload: Vec<u8> = (0..1 << 20).map(|_| {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) as u8
+        }).collect();
+        let metadata = crate::test_support::metadata("large", "1.0.0");
+        let package_path = crate::test_support::write_package_members(
+            temp_dir.path(), "large-1.0.0-x86_64.pax", Some(&metadata), &[("usr/share/large/data", &payload, 0o644)],
+        );
+
+        // Cut the archive off inside the payload: only a full listing notices
+        let contents = fs::read(&package_path).unwrap();
+        fs::write(&package_path, &contents[..contents.len() / 2]).unwrap();
+
+        let mut package = PaxPackage::open(&package_path).unwrap();
+        assert_eq!(package.load_metadata().unwrap().name, "large");
+        assert!(package.list_files().is_err());
+    }
+
+    #[test]
+    fn test_file_checksums_detect_altered_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let source_dir = temp_dir.path().join("source");
+        fs::create_dir_all(&source_dir).unwrap();
+        let recipe = crate::recipe::BuildRecipe::from_yaml(r#"
+name: hashed
+version: 1.0.0
+description: Test package
+source: https://example.com/hashed-1.0.0.tar.gz
+build: |
+  mkdir -p $PAX_BUILD_ROOT/usr/bin
+  echo payload > $PAX_BUILD_ROOT/usr/bin/tool
+  echo other > $PAX_BUILD_ROOT/usr/bin/helper
+"#).unwrap();
+        let builder = crate::builder::PackageBuilder::new().unwrap().with_source_dir(Some(source_dir));
+        let package_path = builder.build_for_architectures(&recipe, &["x86_64".to_string()]).unwrap().remove(0);
+
+        let mut package = PaxPackage::open(&package_path).unwrap();
+        assert_eq!(package.load_metadata().unwrap().file_hashes["usr/bin/tool"], hex::encode(sha2::Sha256::digest(b"payload\n")));
+        assert!(package.file_hash_mismatches().unwrap().is_empty());
+
+        // An extracted file changed after extraction is reported by path
+        let extract_dir = temp_dir.path().join("extract");
+        package.extract_to(&extract_dir).unwrap();
+        fs::write(extract_dir.join("usr/bin/tool"), "altered\n").unwrap();
+        fs::remove_file(extract_dir.join("usr/bin/helper")).unwrap();
+        let mismatches = package.extracted_file_mismatches(&extract_dir).unwrap();
+        assert_eq!(mismatches.len(), 2, "{:?}", mismatches);
+        assert!(mismatches[0].starts_with("usr/bin/helper: missing"), "{:?}", mismatches);
+        assert!(mismatches[1].starts_with("usr/bin/tool: expected sha256"), "{:?}", mismatches);
+
+        // So is a file altered inside the package
+        let tarball = zstd::decode_all(fs::File::open(&package_path).unwrap()).unwrap();
+        let start = tarball.windows(7).position(|window| window == b"payload").unwrap();
+        let mut tampered = tarball.clone();
+        tampered[start..start + 7].copy_from_slice(b"PAYLOAD");
+        fs::write(&package_path, zstd::encode_all(tampered.as_slice(), 3).unwrap()).unwrap();
+        let mismatches = PaxPackage::open(&package_path).unwrap().file_hash_mismatches().unwrap();
+        assert_eq!(mismatches.len(), 1, "{:?}", mismatches);
+        assert!(mismatches[0].starts_with("usr/bin/tool:"), "{:?}", mismatches);
+    }
+
+    #[test]
+    fn test_embedded_signature() {
+        let temp_dir = TempDir::new().unwrap();
+        let metadata = crate::test_support::metadata("embedded", "1.0.0");
+        let package_path = crate::test_support::write_package(temp_dir.path(), &metadata, &[("usr/bin/embedded", "payload")]);
+        let private_key = temp_dir.path().join("private.key");
+        let public_key = temp_dir.path().join("public.key");
+        let signing_key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+        fs::write(&private_key, hex::encode(signing_key.to_bytes())).unwrap();
+        fs::write(&public_key, hex::encode(signing_key.verifying_key().to_bytes())).unwrap();
+
+        let package = PaxPackage::open(&package_path).unwrap();
+        assert!(!package.has_embedded_signature().unwrap());
+        assert!(package.verify_embedded_signature().is_err());
+        let digest = package.signing_digest().unwrap();
+
+        crate::crypto::embed_package_signature(&package_path, &private_key, crate::builder::DEFAULT_COMPRESSION_LEVEL).unwrap();
+        assert_eq!(package.signing_digest().unwrap(), digest);
+        assert_eq!(package.verify_embedded_signature().unwrap(), signing_key.verifying_key());
+        assert!(crate::crypto::embed_package_signature(&package_path, &private_key, crate::builder::DEFAULT_COMPRESSION_LEVEL).is_err());
+
+        // The signed package still extracts, and verify checks the signer against --key
+        let extract_dir = temp_dir.path().join("extract");
+        package.extract_to(&extract_dir).unwrap();
+        assert_eq!(fs::read_to_string(extract_dir.join("usr/bin/embedded")).unwrap(), "payload");
+        assert!(extract_dir.join(SIGNATURE_PATH).is_file());
+        let options = crate::verify::VerifyOptions {
+            key: Some(public_key.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        crate::verify::verify_package(package_path.to_str().unwrap(), &options).unwrap();
+
+        let other_key = temp_dir.path().join("other.key");
+        fs::write(&other_key, hex::encode(ed25519_dalek::SigningKey::from_bytes(&[8; 32]).verifying_key().to_bytes())).unwrap();
+        let options = crate::verify::VerifyOptions { key: Some(other_key.to_string_lossy().to_string()), ..Default::default() };
+        assert!(crate::verify::verify_package(package_path.to_str().unwrap(), &options).is_err());
+
+        // Changing a payload file breaks the signature
+        let tarball = zstd::decode_all(fs::File::open(&package_path).unwrap()).unwrap();
+        let start = tarball.windows(7).position(|window| window == b"payload").unwrap();
+        let mut tampered = tarball.clone();
+        tampered[start..start + 7].copy_from_slice(b"PAYLOAD");
+        fs::write(&package_path, zstd::encode_all(tampered.as_slice(), 3).unwrap()).unwrap();
+        let error = package.verify_embedded_signature().unwrap_err();
+        assert!(format!("{:#}", error).contains("Embedded signature verification FAILED"), "{:#}", error);
+    }
+
+    /// Sign a one-file package, then rewrite its archive with `tamper` applied
+    /// to the payload file's header (and any PAX records it returns)
+    fn tampered_signed_package(temp_dir: &Path, tamper: impl Fn(&mut tar::Header) -> Vec<(&'static str, &'static [u8])>) -> PaxPackage {
+        let metadata = crate::test_support::metadata("tampered", "1.0.0");
+        let package_path = crate::test_support::write_package(temp_dir, &metadata, &[("usr/bin/tampered", "payload")]);
+        let private_key = temp_dir.join("private.key");
+        fs::write(&private_key, hex::encode([7; 32])).unwrap();
+        crate::crypto::embed_package_signature(&package_path, &private_key, crate::builder::DEFAULT_COMPRESSION_LEVEL).unwrap();
+        PaxPackage::open(&package_path).unwrap().verify_embedded_signature().unwrap();
+
+        let tarball = zstd::decode_all(fs::File::open(&package_path).unwrap()).unwrap();
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut archive = tar::Archive::new(tarball.as_slice());
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let mut header = entry.header().clone();
+            let mut data = Vec::new();
+            entry.read_to_end(&mut data).unwrap();
+            if entry.path().unwrap().ends_with("usr/bin/tampered") {
+                let records = tamper(&mut header);
+                if !records.is_empty() {
+                    builder.append_pax_extensions(records).unwrap();
+                }
+                header.set_cksum();
+            }
+            builder.append(&header, data.as_slice()).unwrap();
+        }
+        fs::write(&package_path, zstd::encode_all(builder.into_inner().unwrap().as_slice(), 3).unwrap()).unwrap();
+        PaxPackage::open(&package_path).unwrap()
+    }
+
+    #[test]
+    fn test_embedded_signature_covers_xattrs() {
+        let temp_dir = TempDir::new().unwrap();
+        let package = tampered_signed_package(temp_dir.path(), |_| vec![
+            ("SCHILY.xattr.security.capability", &b"\x01\x00\x00\x02\x00\x20\x00\x00"[..]),
+        ]);
+        let entry = package.entries().unwrap().map(Result::unwrap).find(|entry| entry.path.ends_with("usr/bin/tampered")).unwrap();
+        assert_eq!(entry.xattrs.len(), 1);
+        let error = package.verify_embedded_signature().unwrap_err();
+        assert!(format!("{:#}", error).contains("Embedded signature verification FAILED"), "{:#}", error);
+    }
+
+    #[test]
+    fn test_embedded_signature_covers_owners() {
+        let temp_dir = TempDir::new().unwrap();
+        let package = tampered_signed_package(temp_dir.path(), |header| {
+            header.set_username("daemon").unwrap();
+            header.set_uid(1);
+            Vec::new()
+        });
+        let error = package.verify_embedded_signature().unwrap_err();
+        assert!(format!("{:#}", error).contains("Embedded signature verification FAILED"), "{:#}", error);
+
+        // Changing only the numeric ID (used when the name is unknown on the host) is caught too
+        let temp_dir = TempDir::new().unwrap();
+        let package = tampered_signed_package(temp_dir.path(), |header| {
+            header.set_gid(42);
+            Vec::new()
+        });
+        assert!(package.verify_embedded_signature().is_err());
+    }
+
+    #[test]
+    fn test_extract_refuses_entries_outside_destination() {
+        let temp_dir = TempDir::new().unwrap();
+
+        // tar::Builder refuses such names, so the header name is written directly
+        let crafted = |name: &str, entry_type: tar::EntryType| {
+            let mut builder = tar::Builder::new(Vec::new());
+            let mut header = tar::Header::new_ustar();
+            header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+            header.set_entry_type(entry_type);
+            header.set_size(4);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append(&header, &b"evil"[..]).unwrap();
+            let package_path = temp_dir.path().join("crafted.pax");
+            fs::write(&package_path, Compression::Zstd.compress(&builder.into_inner().unwrap(), 3).unwrap()).unwrap();
+            PaxPackage::open(&package_path).unwrap()
+        };
+
+        let dest = temp_dir.path().join("dest");
+        for name in ["../escape", "usr/../../escape", "/tmp/paxbuild-absolute-escape"] {
+            let error = crafted(name, tar::EntryType::Regular).extract_to(&dest).unwrap_err();
+            assert!(format!("{:#}", error).contains(name), "{:#}", error);
+        }
+        assert!(!temp_dir.path().join("escape").exists());
+        assert!(!Path::new("/tmp/paxbuild-absolute-escape").exists());
+
+        // A symlink already in the destination cannot be used to write through
+        std::os::unix::fs::symlink(temp_dir.path(), dest.join("outside")).unwrap();
+        let error = crafted("outside/escape", tar::EntryType::Regular).extract_to(&dest).unwrap_err();
+        assert!(format!("{:#}", error).contains("resolves outside"), "{:#}", error);
+        assert!(!temp_dir.path().join("escape").exists());
+
+        crafted("usr/file", tar::EntryType::Regular).extract_to(&dest).unwrap();
+        assert_eq!(fs::read(dest.join("usr/file")).unwrap(), b"evil");
+    }
+
+    #[test]
+    fn test_extract_single_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let metadata = crate::test_support::metadata("multi", "1.0.0");
+        let package_path = crate::test_support::write_package(temp_dir.path(), &metadata, &[
+            ("usr/bin/multi", "binary"),
+            ("usr/share/doc/multi/README", "readme"),
+            ("etc/multi.conf", "config"),
+        ]);
+        let package = PaxPackage::open(&package_path).unwrap();
+
+        let dest_dir = temp_dir.path().join("dest");
+        package.extract_file("./usr/share/doc/multi/README", &dest_dir.join("doc/README")).unwrap();
+        assert_eq!(fs::read_to_string(dest_dir.join("doc/README")).unwrap(), "readme");
+        assert_eq!(fs::read_dir(&dest_dir).unwrap().count(), 1);
+
+        let error = package.extract_file("usr/bin/missing", &dest_dir.join("missing")).unwrap_err();
+        assert!(error.to_string().contains("'usr/bin/missing' not found in package"), "{}", error);
+        assert!(!dest_dir.join("missing").exists());
+        assert!(package.extract_file("usr/bin", &dest_dir.join("bin")).is_err());
+    }
+
+    #[test]
+    fn test_gzip_package_is_detected_by_magic() {
+        let temp_dir = TempDir::new().unwrap();
+        let metadata = crate::test_support::metadata("gzipped", "1.0.0");
+        let package_path = crate::test_support::write_package(temp_dir.path(), &metadata, &[("usr/bin/gzipped", "binary")]);
+
+        // Recompress the zstd package with gzip, keeping the .pax name
+        let tarball = zstd::decode_all(fs::File::open(&package_path).unwrap()).unwrap();
+        fs::write(&package_path, Compression::Gzip.compress(&tarball, 6).unwrap()).unwrap();
+
+        let mut package = PaxPackage::open(&package_path).unwrap();
+        assert_eq!(package.load_metadata().unwrap().name, "gzipped");
+        let extract_dir = temp_dir.path().join("extract");
+        package.extract_to(&extract_dir).unwrap();
+        assert_eq!(fs::read_to_string(extract_dir.join("usr/bin/gzipped")).unwrap(), "binary");
+
+        fs::write(&package_path, b"BZh91AY&SY not a supported package").unwrap();
+        let err = PaxPackage::open(&package_path).unwrap().extract_to(&extract_dir).unwrap_err();
+        assert!(err.to_string().contains("Unrecognized package compression"), "{}", err);
+        assert!(err.to_string().contains("[42 5a 68 39]"), "{}", err);
+    }
 }
@@ -0,0 +1,131 @@
+use anyhow::{Result, Context};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::recipe::BuildRecipe;
+use crate::version;
+
+/// Options for pruning old package versions
+#[derive(Debug, Clone, Default)]
+pub struct PruneOptions {
+    /// Number of newest versions to keep per (name, arch)
+    pub keep: usize,
+    /// Move pruned packages here instead of deleting them
+    pub move_to: Option<String>,
+    /// Only report what would be pruned
+    pub dry_run: bool,
+}
+
+/// Find the packages in `dir` that fall outside the newest `keep` versions of their (name, arch)
+///
+/// Files whose names don't parse as `name-version-arch.pax` are left alone.
+pub fn packages_to_prune(dir: &Path, keep: usize) -> Result<Vec<PathBuf>> {
+    let mut groups: BTreeMap<(String, String), Vec<(String, PathBuf)>> = BTreeMap::new();
+
+    for entry in fs::read_dir(dir)
+        .with_context(|| format!("Failed to read directory: {}", dir.display()))? {
+        let entry = entry.with_context(|| "Failed to read directory entry")?;
+        let filename = entry.file_name().to_string_lossy().to_string();
+        if let Some((name, version, arch)) = BuildRecipe::parse_package_filename(&filename) {
+            groups.entry((name, arch)).or_default().push((version, entry.path()));
+        }
+    }
+
+    let mut pruned = Vec::new();
+    for mut packages in groups.into_values() {
+        // Newest first
+        packages.sort_by(|(a, _), (b, _)| version::compare(b, a));
+        pruned.extend(packages.into_iter().skip(keep).map(|(_, path)| path));
+    }
+
+    pruned.sort();
+    Ok(pruned)
+}
+
+/// Delete (or move) old package versions, returning the pruned packages
+pub fn prune(dir: &Path, options: &PruneOptions) -> Result<Vec<PathBuf>> {
+    if options.keep == 0 {
+        anyhow::bail!("Refusing to prune with keep = 0: every package would be removed");
+    }
+    let pruned = packages_to_prune(dir, options.keep)?;
+    if options.dry_run {
+        return Ok(pruned);
+    }
+
+    if let Some(move_to) = &options.move_to {
+        fs::create_dir_all(move_to)
+            .with_context(|| format!("Failed to create directory: {}", move_to))?;
+    }
+
+    // A sidecar left behind would keep the pruned package in the index
+    let sidecars: Vec<PathBuf> = pruned.iter()
+        .map(|path| crate::index::sidecar_path(path))
+        .filter(|sidecar| sidecar.is_file())
+        .collect();
+    for path in pruned.iter().chain(&sidecars) {
+        match &options.move_to {
+            Some(move_to) => {
+                let dest = Path::new(move_to).join(path.file_name().unwrap_or_default());
+                fs::rename(path, &dest)
+                    .with_context(|| format!("Failed to move {} to {}", path.display(), dest.display()))?;
+            }
+            None => fs::remove_file(path)
+                .with_context(|| format!("Failed to delete: {}", path.display()))?,
+        }
+    }
+
+    Ok(pruned)
+}
+
+/// Prune old package versions (CLI entry point)
+pub fn prune_cmd(dir: &str, options: &PruneOptions) -> Result<()> {
+    println!("PAXBuild - Pruning old package versions");
+    println!("Directory: {}", dir);
+    println!("Keeping {} newest version(s) per package and architecture", options.keep);
+
+    let pruned = prune(Path::new(dir), options)?;
+
+    let action = match (&options.move_to, options.dry_run) {
+        (_, true) => "Would prune",
+        (Some(_), false) => "Moved",
+        (None, false) => "Deleted",
+    };
+    for path in &pruned {
+        println!("  {}: {}", action, path.display());
+    }
+    println!("{} {} package(s)", action, pruned.len());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_prune_keeps_newest_versions() {
+        let temp_dir = TempDir::new().unwrap();
+        for filename in [
+            "hello-1.2.0-x86_64.pax",
+            "hello-1.9.0-x86_64.pax",
+            "hello-1.10.0-x86_64.pax",
+            "hello-1.2.0-aarch64.pax",
+            "notes.txt",
+        ] {
+            fs::write(temp_dir.path().join(filename), "").unwrap();
+        }
+
+        let options = PruneOptions { keep: 2, dry_run: true, ..Default::default() };
+        let pruned = prune(temp_dir.path(), &options).unwrap();
+        assert_eq!(pruned, vec![temp_dir.path().join("hello-1.2.0-x86_64.pax")]);
+        assert!(pruned[0].exists());
+
+        let options = PruneOptions { keep: 2, ..Default::default() };
+        prune(temp_dir.path(), &options).unwrap();
+        assert!(!temp_dir.path().join("hello-1.2.0-x86_64.pax").exists());
+        for filename in ["hello-1.9.0-x86_64.pax", "hello-1.10.0-x86_64.pax", "hello-1.2.0-aarch64.pax", "notes.txt"] {
+            assert!(temp_dir.path().join(filename).exists(), "{} was pruned", filename);
+        }
+    }
+}
@@ -1,23 +1,72 @@
 use serde::{Deserialize, Serialize};
 use serde_yaml;
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::Path;
 use anyhow::{Result, Context};
+use crate::depspec::Constraint;
+use crate::ownership::{Group, User};
+use crate::source::{DownloadPlan, DownloadPolicy};
+
+/// File extensions recognized as recipes when building a directory
+pub const RECIPE_EXTENSIONS: &[&str] = &["paxmeta"];
+
+/// Serialization format of a recipe file; both describe the same [`BuildRecipe`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecipeFormat {
+    Yaml,
+    Toml,
+}
+
+impl RecipeFormat {
+    /// TOML for paths ending in `.toml`, YAML for everything else (`.paxmeta`, `.yaml`)
+    pub fn for_path(path: &str) -> Self {
+        if path.ends_with(".toml") {
+            RecipeFormat::Toml
+        } else {
+            RecipeFormat::Yaml
+        }
+    }
+}
+
+/// Installed paths left out of every package unless `no_default_excludes` is set
+pub const DEFAULT_EXCLUDES: &[&str] = &[".git", ".DS_Store", "*.pyc", "*.la"];
 
 /// Build recipe format (.paxmeta)
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct BuildRecipe {
     /// Package name
     pub name: String,
     /// Package version
     pub version: String,
+    /// Version epoch, bumped when upstream resets its versioning so newer releases still sort higher
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub epoch: Option<u32>,
     /// Package description
     pub description: String,
-    /// Source URL (tarball, git repo, etc.)
-    pub source: String,
-    /// SHA256 checksum (optional, auto-generated if missing)
+    /// Source URL (tarball, `git+` or `.git` repository, etc.) or local path relative to the recipe,
+    /// a list of sources (a main tarball plus supplementary files), or a map of
+    /// architecture to source for per-architecture upstream tarballs
+    pub source: Source,
+    /// Filename to save the source as, overriding the one derived from the URL
+    /// (its extension selects the archive format)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_filename: Option<String>,
+    /// Alternate URLs serving the same source file, tried when `source` fails
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+    /// Extra download attempts per URL after a failure
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub retries: u32,
+    /// Whether retries stay on one URL (`sequential`) or move through the mirrors (`rotate`)
+    #[serde(default, skip_serializing_if = "is_default_policy")]
+    pub download_policy: DownloadPolicy,
+    /// Source checksum (hex SHA256, or `blake3:<hex>`), or the expected commit of a git source (optional; `build --write-hash` fills it in)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub hash: Option<String>,
+    /// Patches applied in order to the extracted source (local paths or URLs)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<Patch>,
     /// Target architectures (defaults to x86_64, aarch64)
     #[serde(default = "default_arch")]
     pub arch: Vec<String>,
@@ -27,15 +76,59 @@ pub struct BuildRecipe {
     /// Runtime dependencies
     #[serde(default)]
     pub runtime_dependencies: Vec<String>,
-    /// What this package provides
+    /// What this package provides (`name` or `name=version`)
     #[serde(default)]
     pub provides: Vec<String>,
     /// Packages this conflicts with
     #[serde(default)]
     pub conflicts: Vec<String>,
+    /// Packages this one supersedes (renamed or merged packages), as dependency constraints
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub replaces: Vec<String>,
+    /// Optional features enabled with `--with <feature>`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub features: BTreeMap<String, Feature>,
+    /// Extra metadata stored verbatim in the package
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub extra: BTreeMap<String, String>,
+    /// Glob patterns of installed paths to leave out of the package, in addition to the defaults
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    /// Package paths matching `DEFAULT_EXCLUDES` too
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_default_excludes: bool,
+    /// Linux file capabilities to set on install, by package path (`setcap` text form)
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub capabilities: BTreeMap<String, String>,
+    /// System users the package needs; the installer creates missing ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub users: Vec<User>,
+    /// System groups the package needs; the installer creates missing ones
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub groups: Vec<Group>,
+    /// Owner (`user` or `user:group`) of installed paths, by package path;
+    /// users and groups must be declared above or be `root`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub file_owners: BTreeMap<String, String>,
+    /// Installed paths (glob patterns) to package under a different path
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub rename: BTreeMap<String, String>,
+    /// Environment variables exported to the build script
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    /// Build system whose default script runs when `build` is not given
+    /// (detected from the source tree if not set)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_system: Option<BuildSystem>,
     /// Build script (runs in extracted source directory)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub build: Option<String>,
+    /// Build split into prepare/build/install scripts, used instead of `build`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub phases: Option<BuildPhases>,
+    /// Test script run after a successful build, before packaging (same directory and variables)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub check: Option<String>,
     /// Post-install script (runs after installation)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub install: Option<String>,
@@ -44,18 +137,270 @@ pub struct BuildRecipe {
     pub uninstall: Option<String>,
 }
 
+/// Where a recipe's source comes from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Source {
+    /// One source for every architecture, verified against the recipe's `hash`
+    Url(String),
+    /// Several sources for every architecture, each with its own optional hash;
+    /// the first is the main source and becomes the source root
+    List(Vec<SourceSpec>),
+    /// A separate source per architecture, each with its own optional hash
+    PerArch(BTreeMap<String, SourceSpec>),
+}
+
+impl Source {
+    /// Mutable access to every source URL
+    fn urls_mut(&mut self) -> Vec<&mut String> {
+        match self {
+            Source::Url(url) => vec![url],
+            Source::List(sources) => sources.iter_mut().map(|source| &mut source.url).collect(),
+            Source::PerArch(sources) => sources.values_mut().map(|source| &mut source.url).collect(),
+        }
+    }
+}
+
+impl From<&str> for Source {
+    fn from(url: &str) -> Self {
+        Source::Url(url.to_string())
+    }
+}
+
+impl std::fmt::Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Url(url) => write!(f, "{}", url),
+            Source::List(sources) => {
+                let sources: Vec<&str> = sources.iter().map(|source| source.url.as_str()).collect();
+                write!(f, "{}", sources.join(", "))
+            }
+            Source::PerArch(sources) => {
+                let sources: Vec<String> = sources.iter()
+                    .map(|(arch, source)| format!("{}: {}", arch, source.url))
+                    .collect();
+                write!(f, "{}", sources.join(", "))
+            }
+        }
+    }
+}
+
+/// One source file: a bare URL, or a `{url, hash}` mapping
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceSpec {
+    pub url: String,
+    pub hash: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SourceSpecRepr {
+    Url(String),
+    WithHash {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hash: Option<String>,
+    },
+}
+
+impl Serialize for SourceSpec {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match &self.hash {
+            None => SourceSpecRepr::Url(self.url.clone()),
+            Some(hash) => SourceSpecRepr::WithHash { url: self.url.clone(), hash: Some(hash.clone()) },
+        }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SourceSpec {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Ok(match SourceSpecRepr::deserialize(deserializer)? {
+            SourceSpecRepr::Url(url) => SourceSpec { url, hash: None },
+            SourceSpecRepr::WithHash { url, hash } => SourceSpec { url, hash },
+        })
+    }
+}
+
+/// A patch for the extracted source: a bare path or URL, or a `{url, hash, strip}` mapping
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Patch {
+    pub url: String,
+    pub hash: Option<String>,
+    /// Leading path components to strip (`patch -pN`), 1 if not set
+    pub strip: Option<u32>,
+}
+
+impl Patch {
+    /// The `-p` level the patch is applied with
+    pub fn strip_level(&self) -> u32 {
+        self.strip.unwrap_or(1)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum PatchRepr {
+    Url(String),
+    WithOptions {
+        url: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        hash: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        strip: Option<u32>,
+    },
+}
+
+impl Serialize for Patch {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        match (&self.hash, self.strip) {
+            (None, None) => PatchRepr::Url(self.url.clone()),
+            (hash, strip) => PatchRepr::WithOptions { url: self.url.clone(), hash: hash.clone(), strip },
+        }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Patch {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        Ok(match PatchRepr::deserialize(deserializer)? {
+            PatchRepr::Url(url) => Patch { url, hash: None, strip: None },
+            PatchRepr::WithOptions { url, hash, strip } => Patch { url, hash, strip },
+        })
+    }
+}
+
+/// Build system a default build script is chosen for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BuildSystem {
+    Autotools,
+    Cmake,
+    Meson,
+    Cargo,
+}
+
+impl BuildSystem {
+    /// Marker files checked in the source root, in detection order
+    const MARKERS: &'static [(&'static str, BuildSystem)] = &[
+        ("CMakeLists.txt", BuildSystem::Cmake),
+        ("meson.build", BuildSystem::Meson),
+        ("Cargo.toml", BuildSystem::Cargo),
+        ("configure", BuildSystem::Autotools),
+    ];
+
+    /// Detect the build system of a source tree from its marker files
+    pub fn detect(source_dir: &Path) -> Option<BuildSystem> {
+        Self::MARKERS.iter()
+            .find(|(marker, _)| source_dir.join(marker).is_file())
+            .map(|(_, system)| *system)
+    }
+
+    /// Name as written in recipes
+    pub fn name(&self) -> &'static str {
+        match self {
+            BuildSystem::Autotools => "autotools",
+            BuildSystem::Cmake => "cmake",
+            BuildSystem::Meson => "meson",
+            BuildSystem::Cargo => "cargo",
+        }
+    }
+
+    /// Default build script, installing under `/usr` into `$PAX_BUILD_ROOT`
+    ///
+    /// Out-of-tree builds go to `$PAX_BUILD_DIR`, which is separate for each architecture.
+    pub fn script(&self) -> String {
+        match self {
+            BuildSystem::Autotools => "./configure --prefix=/usr && make -j$PAX_JOBS && make install DESTDIR=$PAX_BUILD_ROOT",
+            BuildSystem::Cmake => "cmake -S . -B \"$PAX_BUILD_DIR\" -DCMAKE_INSTALL_PREFIX=/usr -DCMAKE_BUILD_TYPE=Release \
+  && cmake --build \"$PAX_BUILD_DIR\" -j $PAX_JOBS \
+  && DESTDIR=$PAX_BUILD_ROOT cmake --install \"$PAX_BUILD_DIR\"",
+            BuildSystem::Meson => "meson setup \"$PAX_BUILD_DIR\" --prefix=/usr --buildtype=release \
+  && meson compile -C \"$PAX_BUILD_DIR\" -j $PAX_JOBS \
+  && DESTDIR=$PAX_BUILD_ROOT meson install -C \"$PAX_BUILD_DIR\"",
+            BuildSystem::Cargo => "cargo install --path . --root \"$PAX_BUILD_ROOT/usr\" --target-dir \"$PAX_BUILD_DIR\" -j $PAX_JOBS \
+  && rm -f \"$PAX_BUILD_ROOT/usr/.crates.toml\" \"$PAX_BUILD_ROOT/usr/.crates2.json\"",
+        }.to_string()
+    }
+}
+
+/// Optional feature that adds dependencies when enabled
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Feature {
+    /// Extra build dependencies
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    /// Extra runtime dependencies
+    #[serde(default)]
+    pub runtime_dependencies: Vec<String>,
+}
+
+/// Build scripts run one after another, each in the source directory; any may be left out
+///
+/// (The top-level `install` field is the package's post-install script, so the
+/// phases live under their own key.)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct BuildPhases {
+    /// Configure or patch the source tree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prepare: Option<String>,
+    /// Compile
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<String>,
+    /// Install into `$PAX_BUILD_ROOT`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub install: Option<String>,
+}
+
+impl BuildPhases {
+    /// The phases given, in the order they run
+    pub fn scripts(&self) -> Vec<(&'static str, &str)> {
+        [("prepare", &self.prepare), ("build", &self.build), ("install", &self.install)].into_iter()
+            .filter_map(|(phase, script)| script.as_deref().map(|script| (phase, script)))
+            .collect()
+    }
+}
+
+fn is_zero(value: &u32) -> bool {
+    *value == 0
+}
+
+fn is_default_policy(policy: &DownloadPolicy) -> bool {
+    *policy == DownloadPolicy::default()
+}
+
 fn default_arch() -> Vec<String> {
     vec!["x86_64".to_string(), "aarch64".to_string()]
 }
 
 impl BuildRecipe {
-    /// Load recipe from a file
+    /// Load recipe from a file, in the format given by its extension
     pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self> {
         let path = path.as_ref();
         let contents = fs::read_to_string(path)
             .with_context(|| format!("Failed to read recipe file: {}", path.display()))?;
         
-        Self::from_yaml(&contents)
+        Self::parse(&contents, RecipeFormat::for_path(&path.to_string_lossy()))
+    }
+
+    /// Parse a recipe in `format`
+    ///
+    /// Recipes not named `.toml` are read as YAML, and as TOML if that fails,
+    /// so a TOML recipe named `.paxmeta` still loads; the YAML error is
+    /// reported if neither parses.
+    pub fn parse(contents: &str, format: RecipeFormat) -> Result<Self> {
+        Self::parse_detecting_format(contents, format).map(|(recipe, _)| recipe)
+    }
+
+    /// Like [`BuildRecipe::parse`], also returning the format the recipe was actually in
+    fn parse_detecting_format(contents: &str, format: RecipeFormat) -> Result<(Self, RecipeFormat)> {
+        match format {
+            RecipeFormat::Toml => Ok((Self::from_toml(contents)?, RecipeFormat::Toml)),
+            RecipeFormat::Yaml => match Self::from_yaml(contents) {
+                Ok(recipe) => Ok((recipe, RecipeFormat::Yaml)),
+                Err(yaml_error) => Self::from_toml(contents)
+                    .map(|recipe| (recipe, RecipeFormat::Toml))
+                    .map_err(|_| yaml_error),
+            },
+        }
     }
 
     /// Load recipe from a URL
@@ -70,12 +415,27 @@ impl BuildRecipe {
         let contents = response.text()
             .with_context(|| format!("Failed to read response from: {}", url))?;
         
-        Self::from_yaml(&contents)
+        Self::parse(&contents, RecipeFormat::for_path(url))
     }
 
     /// Parse recipe from YAML string
+    ///
+    /// Anchors and aliases are expanded and `<<` merge keys applied before the
+    /// recipe fields are read, so shared lists and mappings behave as if
+    /// written out in full.
     pub fn from_yaml(yaml: &str) -> Result<Self> {
-        serde_yaml::from_str(yaml)
+        let mut value: serde_yaml::Value = serde_yaml::from_str(yaml).map_err(|e| {
+            // serde_yaml reports an alias used inside its own anchor as runaway recursion
+            if e.to_string().starts_with("recursion limit exceeded") {
+                anyhow::anyhow!("Recipe contains a recursive YAML alias (an anchor referenced inside its own definition): {}", e)
+            } else {
+                anyhow::Error::new(e)
+            }
+        }).with_context(|| "Failed to parse recipe YAML")?;
+        value.apply_merge()
+            .with_context(|| "Failed to apply YAML merge keys in recipe")?;
+
+        serde_yaml::from_value(value)
             .with_context(|| "Failed to parse recipe YAML")
     }
 
@@ -85,14 +445,99 @@ impl BuildRecipe {
             .with_context(|| "Failed to serialize recipe to YAML")
     }
 
+    /// Parse recipe from TOML string
+    pub fn from_toml(toml: &str) -> Result<Self> {
+        toml::from_str(toml)
+            .with_context(|| "Failed to parse recipe TOML")
+    }
+
+    /// Convert recipe to TOML string
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self)
+            .with_context(|| "Failed to serialize recipe to TOML")
+    }
+
+    /// Serialize the recipe in `format`
+    pub fn serialize(&self, format: RecipeFormat) -> Result<String> {
+        match format {
+            RecipeFormat::Yaml => self.to_yaml(),
+            RecipeFormat::Toml => self.to_toml(),
+        }
+    }
+
+    /// Write `hash` into the recipe file at `path`, which must not have one yet
+    ///
+    /// A `hash:` line is inserted right after the top-level `source:` entry
+    /// (in TOML, a `hash = ` line right before `source = `) so comments and
+    /// the order of other fields stay as written. If the edited file would not
+    /// parse back to this recipe plus the hash, the recipe is rewritten with
+    /// `to_yaml` or `to_toml` instead.
+    pub fn write_hash_to_file(path: &Path, hash: &str) -> Result<()> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read recipe file: {}", path.display()))?;
+        let (mut with_hash, format) = Self::parse_detecting_format(&contents, RecipeFormat::for_path(&path.to_string_lossy()))?;
+        if with_hash.hash.is_some() {
+            anyhow::bail!("Recipe already has a hash: {}", path.display());
+        }
+        with_hash.hash = Some(hash.to_string());
+
+        let lines: Vec<&str> = contents.lines().collect();
+        let insertion = match format {
+            RecipeFormat::Yaml => lines.iter().position(|line| line.starts_with("source:")).map(|source| {
+                // The value may continue on indented lines
+                let end = lines[source + 1..].iter()
+                    .position(|line| !line.starts_with([' ', '\t']))
+                    .map_or(lines.len(), |offset| source + 1 + offset);
+                (end, format!("hash: {}", hash))
+            }),
+            RecipeFormat::Toml => lines.iter()
+                .take_while(|line| !line.trim_start().starts_with('['))
+                .position(|line| line.split_once('=').is_some_and(|(key, _)| key.trim() == "source"))
+                .map(|source| (source, format!("hash = {:?}", hash))),
+        };
+        let edited = insertion.map(|(index, line)| {
+            let mut edited: Vec<String> = lines.iter().map(|line| line.to_string()).collect();
+            edited.insert(index, line);
+            let mut edited = edited.join("\n");
+            edited.push('\n');
+            edited
+        });
+
+        let rewritten = match edited {
+            Some(edited) if Self::parse(&edited, format).is_ok_and(|recipe| recipe == with_hash) => edited,
+            _ => with_hash.serialize(format)?,
+        };
+        fs::write(path, rewritten)
+            .with_context(|| format!("Failed to write recipe file: {}", path.display()))
+    }
+
     /// Get the default build script for autotools packages
     pub fn default_build_script() -> String {
-        "./configure --prefix=/usr && make -j$(nproc) && make install DESTDIR=$PAX_BUILD_ROOT".to_string()
+        BuildSystem::Autotools.script()
+    }
+
+    /// Build system used when no `build` script is given: `build_system` if set,
+    /// else the one detected in `source_dir`, else autotools
+    pub fn effective_build_system(&self, source_dir: &Path) -> BuildSystem {
+        self.build_system
+            .or_else(|| BuildSystem::detect(source_dir))
+            .unwrap_or(BuildSystem::Autotools)
+    }
+
+    /// Get the build script, using the build system's default if none specified
+    pub fn get_build_script(&self, source_dir: &Path) -> String {
+        self.build.clone().unwrap_or_else(|| self.effective_build_system(source_dir).script())
     }
 
-    /// Get the build script, using default if none specified
-    pub fn get_build_script(&self) -> String {
-        self.build.clone().unwrap_or_else(Self::default_build_script)
+    /// Scripts to build with, in order, named by phase: the `phases` given,
+    /// or else the single build script as the `build` phase
+    pub fn build_phases(&self, source_dir: &Path) -> Vec<(&'static str, String)> {
+        match &self.phases {
+            Some(phases) => phases.scripts().into_iter()
+                .map(|(phase, script)| (phase, script.to_string()))
+                .collect(),
+            None => vec![("build", self.get_build_script(source_dir))],
+        }
     }
 
     /// Validate the recipe
@@ -106,8 +551,63 @@ impl BuildRecipe {
         if self.description.is_empty() {
             anyhow::bail!("Package description cannot be empty");
         }
-        if self.source.is_empty() {
-            anyhow::bail!("Package source cannot be empty");
+        if let Some(phases) = &self.phases {
+            if self.build.is_some() {
+                anyhow::bail!("Give either a build script or build phases, not both");
+            }
+            if phases.scripts().is_empty() {
+                anyhow::bail!("Build phases need at least one of prepare, build or install");
+            }
+        }
+        match &self.source {
+            Source::Url(url) if url.is_empty() => anyhow::bail!("Package source cannot be empty"),
+            Source::Url(_) => {}
+            Source::List(sources) => {
+                if sources.is_empty() {
+                    anyhow::bail!("Package source list cannot be empty");
+                }
+                if sources.iter().any(|source| source.url.is_empty()) {
+                    anyhow::bail!("Package source cannot be empty");
+                }
+                if self.hash.is_some() {
+                    anyhow::bail!("With a list of sources, give each source its own hash instead of `hash`");
+                }
+            }
+            Source::PerArch(sources) => {
+                if sources.values().any(|source| source.url.is_empty()) {
+                    anyhow::bail!("Package source cannot be empty");
+                }
+                Self::validate_architectures(&sources.keys().cloned().collect::<Vec<_>>())?;
+                if self.hash.is_some() {
+                    anyhow::bail!("With per-architecture sources, give each source its own hash instead of `hash`");
+                }
+                if let Some(arch) = self.arch.iter().find(|arch| !sources.contains_key(*arch)) {
+                    anyhow::bail!("No source given for architecture {}", arch);
+                }
+            }
+        }
+        if !self.mirrors.is_empty() && !matches!(self.source, Source::Url(_)) {
+            anyhow::bail!("Mirrors can only be given for a single source, not a source list or per-architecture sources");
+        }
+        if self.source_filename.is_some() && !matches!(self.source, Source::Url(_)) {
+            anyhow::bail!("A source filename can only be given for a single source");
+        }
+        if self.mirrors.iter().any(|mirror| mirror.is_empty()) {
+            anyhow::bail!("Source mirror cannot be empty");
+        }
+        if let Source::Url(url) = &self.source {
+            if crate::source::GitLocation::from_source_url(url)?.is_some()
+                && (!self.mirrors.is_empty() || self.source_filename.is_some()) {
+                anyhow::bail!("Mirrors and a source filename cannot be given for a git source");
+            }
+        }
+        if self.patches.iter().any(|patch| patch.url.is_empty()) {
+            anyhow::bail!("Patch path cannot be empty");
+        }
+        if let Some(filename) = &self.source_filename {
+            if filename.is_empty() || filename.contains('/') || filename == "." || filename == ".." {
+                anyhow::bail!("Invalid source filename: '{}'. It must be a plain file name", filename);
+            }
         }
 
         // Validate package name format
@@ -119,16 +619,221 @@ impl BuildRecipe {
         if !self.version.chars().any(|c| c.is_numeric()) {
             anyhow::bail!("Package version must contain at least one number");
         }
+        if self.version.contains(':') {
+            anyhow::bail!("Package version cannot contain ':'. Set the epoch field instead");
+        }
+
+        // Validate dependency specs
+        self.parsed_dependencies()?;
+        let feature_deps = self.features.values().flat_map(|feature| &feature.dependencies);
+        for dependency in feature_deps {
+            Constraint::parse(dependency)
+                .with_context(|| format!("Invalid dependencies entry '{}'", dependency))?;
+        }
+        let feature_runtime_deps = self.features.values().flat_map(|feature| &feature.runtime_dependencies);
+        for dependency in self.runtime_dependencies.iter().chain(feature_runtime_deps) {
+            Constraint::parse(dependency)
+                .with_context(|| format!("Invalid runtime_dependencies entry '{}'", dependency))?;
+        }
+        for conflict in &self.conflicts {
+            Constraint::parse(conflict)
+                .with_context(|| format!("Invalid conflicts entry '{}'", conflict))?;
+        }
+
+        // Validate replaces
+        for replaced in &self.replaces {
+            Constraint::parse(replaced)
+                .with_context(|| format!("Invalid replaces entry '{}'", replaced))?;
+        }
+
+        // Validate provides
+        for provide in &self.provides {
+            crate::depspec::Provide::parse(provide)?;
+        }
 
         // Validate architectures
+        if self.arch.is_empty() {
+            anyhow::bail!("Recipe arch list cannot be empty");
+        }
+        if let Some((i, arch)) = self.arch.iter().enumerate().find(|(i, arch)| self.arch[..*i].contains(arch)) {
+            anyhow::bail!("Architecture '{}' is listed more than once (entry {})", arch, i + 1);
+        }
         Self::validate_architectures(&self.arch)?;
 
+        for key in self.extra.keys() {
+            crate::package::validate_extra_key(key)?;
+        }
+
+        for (path, caps) in &self.capabilities {
+            crate::capabilities::validate_capability_path(path)?;
+            crate::capabilities::validate_capability(caps)?;
+        }
+
+        crate::ownership::validate_ownership(&self.users, &self.groups, &self.file_owners)?;
+
+        for pattern in &self.exclude {
+            glob::Pattern::new(pattern)
+                .with_context(|| format!("Invalid exclude pattern: '{}'", pattern))?;
+        }
+
+        for (pattern, destination) in &self.rename {
+            glob::Pattern::new(pattern)
+                .with_context(|| format!("Invalid rename pattern: '{}'", pattern))?;
+            let valid = !destination.is_empty()
+                && Path::new(destination).components().all(|component| matches!(component, std::path::Component::Normal(_)));
+            if !valid {
+                anyhow::bail!("Invalid rename destination '{}': it must be a relative path without '.' or '..'", destination);
+            }
+        }
+
+        for name in self.env.keys() {
+            let valid = !name.is_empty()
+                && !name.starts_with(|c: char| c.is_ascii_digit())
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                anyhow::bail!("Invalid environment variable name: '{}'", name);
+            }
+            if name.starts_with("PAX_") {
+                anyhow::bail!("Environment variable '{}' is reserved: PAX_* variables are set by paxbuild", name);
+            }
+        }
+
         Ok(())
     }
 
-    /// Get package identifier (name-version)
+    /// Default excludes applied to this recipe (empty with `no_default_excludes`)
+    pub fn default_excludes(&self) -> &'static [&'static str] {
+        if self.no_default_excludes { &[] } else { DEFAULT_EXCLUDES }
+    }
+
+    /// Every exclude pattern applied when packaging: the defaults, then the recipe's `exclude`
+    pub fn exclude_patterns(&self) -> Vec<&str> {
+        self.default_excludes().iter().copied()
+            .chain(self.exclude.iter().map(String::as_str))
+            .collect()
+    }
+
+    /// Resolve relative local `source` and `patches` paths against the recipe's directory
+    pub fn resolve_local_source(&mut self, recipe_dir: &Path) {
+        let patch_urls = self.patches.iter_mut().map(|patch| &mut patch.url);
+        for url in self.source.urls_mut().into_iter().chain(patch_urls) {
+            if let Some(path) = crate::source::SourceManager::local_source_path(url) {
+                if path.is_relative() {
+                    *url = recipe_dir.join(path).to_string_lossy().to_string();
+                }
+            }
+        }
+    }
+
+    /// Problems that don't stop a build but weaken its integrity
+    pub fn warnings(&self) -> Vec<String> {
+        let sources: Vec<(&str, Option<&str>)> = match &self.source {
+            Source::Url(url) => vec![(url, self.hash.as_deref())],
+            Source::List(sources) => sources.iter()
+                .map(|source| (source.url.as_str(), source.hash.as_deref()))
+                .collect(),
+            Source::PerArch(sources) => sources.values()
+                .map(|source| (source.url.as_str(), source.hash.as_deref()))
+                .collect(),
+        };
+
+        let mut warnings = Vec::new();
+        for mirror in self.mirrors.iter().filter(|mirror| mirror.starts_with("http://")) {
+            warnings.push(format!("Source mirror is fetched over insecure HTTP: {}", mirror));
+        }
+        for patch in self.patches.iter().filter(|patch| patch.url.starts_with("http://")) {
+            warnings.push(format!("Patch is fetched over insecure HTTP: {}", patch.url));
+        }
+        for (url, hash) in sources {
+            if url.starts_with("http://") {
+                warnings.push(format!("Source is fetched over insecure HTTP: {}", url));
+            }
+            if hash.is_none() {
+                warnings.push(format!("No hash given for source, so its integrity is not verified: {}", url));
+            }
+        }
+        warnings
+    }
+
+    /// Mirrors and retry behavior for downloading the recipe's source
+    pub fn download_plan(&self) -> DownloadPlan {
+        DownloadPlan {
+            mirrors: self.mirrors.clone(),
+            retries: self.retries,
+            policy: self.download_policy,
+        }
+    }
+
+    /// Source URL and expected hash to build `arch` from (the main one, for a source list)
+    pub fn source_for_arch(&self, arch: &str) -> Result<(&str, Option<&str>)> {
+        match &self.source {
+            Source::Url(url) => Ok((url, self.hash.as_deref())),
+            Source::List(sources) => sources.first()
+                .map(|source| (source.url.as_str(), source.hash.as_deref()))
+                .ok_or_else(|| anyhow::anyhow!("Package source list cannot be empty")),
+            Source::PerArch(sources) => sources.get(arch)
+                .map(|source| (source.url.as_str(), source.hash.as_deref()))
+                .ok_or_else(|| anyhow::anyhow!("No source given for architecture {}", arch)),
+        }
+    }
+
+    /// Check that every requested feature is declared by the recipe
+    pub fn validate_features(&self, features: &[String]) -> Result<()> {
+        for feature in features {
+            if !self.features.contains_key(feature) {
+                let known: Vec<&String> = self.features.keys().collect();
+                anyhow::bail!("Unknown feature: {}. Features declared by this recipe: {:?}", feature, known);
+            }
+        }
+        Ok(())
+    }
+
+    /// Build dependencies including those added by enabled features
+    pub fn dependencies_with_features(&self, features: &[String]) -> Vec<String> {
+        self.merge_feature_deps(&self.dependencies, features, |f| &f.dependencies)
+    }
+
+    /// The recipe's `dependencies`, parsed into name and version requirement
+    pub fn parsed_dependencies(&self) -> Result<Vec<Constraint>> {
+        self.dependencies.iter()
+            .map(|dependency| Constraint::parse(dependency)
+                .with_context(|| format!("Invalid dependencies entry '{}'", dependency)))
+            .collect()
+    }
+
+    /// Runtime dependencies including those added by enabled features
+    pub fn runtime_dependencies_with_features(&self, features: &[String]) -> Vec<String> {
+        self.merge_feature_deps(&self.runtime_dependencies, features, |f| &f.runtime_dependencies)
+    }
+
+    fn merge_feature_deps(&self, base: &[String], features: &[String], deps: fn(&Feature) -> &Vec<String>) -> Vec<String> {
+        let mut merged = base.to_vec();
+        for feature in features.iter().filter_map(|name| self.features.get(name)) {
+            for dep in deps(feature) {
+                if !merged.contains(dep) {
+                    merged.push(dep.clone());
+                }
+            }
+        }
+        merged
+    }
+
+    /// Environment variable exported to the build script for an enabled feature
+    pub fn feature_env_var(feature: &str) -> String {
+        let name: String = feature.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect();
+        format!("PAX_FEATURE_{}", name)
+    }
+
+    /// Version including the epoch prefix, e.g. `2:1.0.0`
+    pub fn full_version(&self) -> String {
+        crate::version::with_epoch(self.epoch, &self.version)
+    }
+
+    /// Get package identifier (name-[epoch:]version)
     pub fn package_id(&self) -> String {
-        format!("{}-{}", self.name, self.version)
+        format!("{}-{}", self.name, self.full_version())
     }
 
     /// Get package filename
@@ -177,6 +882,7 @@ impl BuildRecipe {
         // The version should be the rightmost part that looks like a version (contains dots or numbers)
         let mut version_start = arch_index;
         for (i, part) in parts.iter().enumerate().rev().skip(1) {
+            let part = crate::version::split_epoch(part).1;
             if part.contains('.') || part.chars().all(|c| c.is_numeric()) {
                 version_start = i; // i is the actual index since we're going backwards
                 break;
@@ -258,20 +964,77 @@ build: |
         assert!(recipe.build.is_some());
     }
 
+    #[test]
+    fn test_build_system_detected_from_marker_files() {
+        let recipe = BuildRecipe::from_yaml(r#"
+name: detected
+version: 1.0.0
+description: Test package
+source: https://example.com/detected-1.0.0.tar.gz
+"#).unwrap();
+
+        for (marker, system, command) in [
+            ("CMakeLists.txt", BuildSystem::Cmake, "cmake --install"),
+            ("meson.build", BuildSystem::Meson, "meson install"),
+            ("Cargo.toml", BuildSystem::Cargo, "cargo install --path ."),
+            ("configure", BuildSystem::Autotools, "./configure --prefix=/usr"),
+        ] {
+            let source_dir = tempfile::TempDir::new().unwrap();
+            fs::write(source_dir.path().join(marker), "").unwrap();
+            assert_eq!(BuildSystem::detect(source_dir.path()), Some(system), "{}", marker);
+            assert!(recipe.get_build_script(source_dir.path()).contains(command), "{}", marker);
+        }
+
+        // No marker falls back to autotools; a forced build system wins over detection
+        let empty = tempfile::TempDir::new().unwrap();
+        assert_eq!(recipe.get_build_script(empty.path()), BuildRecipe::default_build_script());
+        let cmake_tree = tempfile::TempDir::new().unwrap();
+        fs::write(cmake_tree.path().join("CMakeLists.txt"), "").unwrap();
+        let forced = BuildRecipe::from_yaml(r#"
+name: forced
+version: 1.0.0
+description: Test package
+source: https://example.com/forced-1.0.0.tar.gz
+build_system: meson
+"#).unwrap();
+        assert!(forced.get_build_script(cmake_tree.path()).starts_with("meson setup"));
+        assert!(BuildRecipe::from_yaml("name: x\nversion: '1'\ndescription: x\nsource: x\nbuild_system: scons\n").is_err());
+    }
+
     #[test]
     fn test_recipe_validation() {
         let mut recipe = BuildRecipe {
             name: "test".to_string(),
             version: "1.0.0".to_string(),
+            epoch: None,
             description: "Test".to_string(),
-            source: "https://example.com/test.tar.gz".to_string(),
+            source: "https://example.com/test.tar.gz".into(),
+            source_filename: None,
+            mirrors: Vec::new(),
+            retries: 0,
+            download_policy: DownloadPolicy::default(),
             hash: None,
+            patches: Vec::new(),
             arch: default_arch(),
             dependencies: vec![],
             runtime_dependencies: vec![],
             provides: vec![],
             conflicts: vec![],
+            replaces: vec![],
+            features: BTreeMap::new(),
+            extra: BTreeMap::new(),
+            exclude: Vec::new(),
+            no_default_excludes: false,
+            capabilities: BTreeMap::new(),
+            users: Vec::new(),
+            groups: Vec::new(),
+            file_owners: BTreeMap::new(),
+            rename: BTreeMap::new(),
+            env: BTreeMap::new(),
+            build_system: None,
             build: None,
+            phases: None,
+            check: None,
             install: None,
             uninstall: None,
         };
@@ -288,20 +1051,69 @@ build: |
         assert!(recipe.validate().is_err());
     }
 
+    #[test]
+    fn test_dependencies_are_parsed_and_validated() {
+        let mut recipe = BuildRecipe::from_yaml(r#"
+name: deps
+version: 1.0.0
+description: Test package
+source: https://example.com/deps-1.0.0.tar.gz
+dependencies: [libc>=2.31, zlib]
+runtime_dependencies: [libc.so.6]
+"#).unwrap();
+        recipe.validate().unwrap();
+        let parsed = recipe.parsed_dependencies().unwrap();
+        assert_eq!(parsed.iter().map(|constraint| constraint.to_string()).collect::<Vec<_>>(), ["libc>=2.31", "zlib"]);
+        assert!(parsed[0].satisfied_by("libc", Some("2.35")));
+
+        for (field, spec) in [("dependencies", "libc>>2"), ("runtime_dependencies", "lib c"), ("conflicts", "old>=")] {
+            let mut broken = recipe.clone();
+            match field {
+                "dependencies" => broken.dependencies.push(spec.to_string()),
+                "runtime_dependencies" => broken.runtime_dependencies.push(spec.to_string()),
+                _ => broken.conflicts.push(spec.to_string()),
+            }
+            let error = format!("{:#}", broken.validate().unwrap_err());
+            assert!(error.contains(&format!("Invalid {} entry '{}'", field, spec)), "{}", error);
+        }
+        recipe.features.insert("ssl".to_string(), Feature { dependencies: vec!["openssl dev".to_string()], ..Default::default() });
+        assert!(recipe.validate().is_err());
+    }
+
     #[test]
     fn test_package_id() {
         let recipe = BuildRecipe {
             name: "test-package".to_string(),
             version: "1.0.0".to_string(),
+            epoch: None,
             description: "Test".to_string(),
-            source: "https://example.com/test.tar.gz".to_string(),
+            source: "https://example.com/test.tar.gz".into(),
+            source_filename: None,
+            mirrors: Vec::new(),
+            retries: 0,
+            download_policy: DownloadPolicy::default(),
             hash: None,
+            patches: Vec::new(),
             arch: default_arch(),
             dependencies: vec![],
             runtime_dependencies: vec![],
             provides: vec![],
             conflicts: vec![],
+            replaces: vec![],
+            features: BTreeMap::new(),
+            extra: BTreeMap::new(),
+            exclude: Vec::new(),
+            no_default_excludes: false,
+            capabilities: BTreeMap::new(),
+            users: Vec::new(),
+            groups: Vec::new(),
+            file_owners: BTreeMap::new(),
+            rename: BTreeMap::new(),
+            env: BTreeMap::new(),
+            build_system: None,
             build: None,
+            phases: None,
+            check: None,
             install: None,
             uninstall: None,
         };
@@ -315,15 +1127,35 @@ build: |
         let recipe = BuildRecipe {
             name: "test-package".to_string(),
             version: "1.0.0".to_string(),
+            epoch: None,
             description: "Test".to_string(),
-            source: "https://example.com/test.tar.gz".to_string(),
+            source: "https://example.com/test.tar.gz".into(),
+            source_filename: None,
+            mirrors: Vec::new(),
+            retries: 0,
+            download_policy: DownloadPolicy::default(),
             hash: None,
+            patches: Vec::new(),
             arch: default_arch(),
             dependencies: vec![],
             runtime_dependencies: vec![],
             provides: vec![],
             conflicts: vec![],
+            replaces: vec![],
+            features: BTreeMap::new(),
+            extra: BTreeMap::new(),
+            exclude: Vec::new(),
+            no_default_excludes: false,
+            capabilities: BTreeMap::new(),
+            users: Vec::new(),
+            groups: Vec::new(),
+            file_owners: BTreeMap::new(),
+            rename: BTreeMap::new(),
+            env: BTreeMap::new(),
+            build_system: None,
             build: None,
+            phases: None,
+            check: None,
             install: None,
             uninstall: None,
         };
@@ -359,6 +1191,34 @@ build: |
         assert_eq!(BuildRecipe::parse_package_filename("hello-world-x86_64.pax"), None); // No version
     }
 
+    #[test]
+    fn test_epoch_in_package_id_and_filename() {
+        let mut recipe = BuildRecipe::from_yaml(r#"
+name: reset
+version: 1.0.0
+epoch: 2
+description: Test package
+source: https://example.com/reset-1.0.0.tar.gz
+"#).unwrap();
+        recipe.validate().unwrap();
+
+        assert_eq!(recipe.full_version(), "2:1.0.0");
+        assert_eq!(recipe.package_id(), "reset-2:1.0.0");
+        let filename = recipe.package_filename_for_arch("x86_64");
+        assert_eq!(filename, "reset-2:1.0.0-x86_64.pax");
+        assert_eq!(
+            BuildRecipe::parse_package_filename(&filename),
+            Some(("reset".to_string(), "2:1.0.0".to_string(), "x86_64".to_string()))
+        );
+        assert_eq!(
+            BuildRecipe::parse_package_filename("reset-1:7-aarch64.pax"),
+            Some(("reset".to_string(), "1:7".to_string(), "aarch64".to_string()))
+        );
+
+        recipe.version = "2:1.0.0".to_string();
+        assert!(recipe.validate().is_err());
+    }
+
     #[test]
     fn test_validate_architectures() {
         assert!(BuildRecipe::validate_architectures(&["x86_64".to_string(), "aarch64".to_string()]).is_ok());
@@ -366,4 +1226,142 @@ build: |
         assert!(BuildRecipe::validate_architectures(&["i686".to_string(), "riscv64".to_string()]).is_ok());
         assert!(BuildRecipe::validate_architectures(&["invalid-arch".to_string()]).is_err());
     }
+
+    #[test]
+    fn test_anchors_and_merge_keys_are_expanded() {
+        let recipe = BuildRecipe::from_yaml(r#"
+name: anchored
+version: 1.0.0
+description: Test package
+source: https://example.com/anchored-1.0.0.tar.gz
+dependencies: &common
+  - libc>=2.31
+  - zlib
+runtime_dependencies: *common
+features:
+  base: &base
+    dependencies: [openssl-dev]
+  full:
+    <<: *base
+    runtime_dependencies: [libssl.so.3]
+"#).unwrap();
+        recipe.validate().unwrap();
+
+        assert_eq!(recipe.dependencies, vec!["libc>=2.31", "zlib"]);
+        assert_eq!(recipe.runtime_dependencies, recipe.dependencies);
+        assert_eq!(recipe.features["full"].dependencies, vec!["openssl-dev"]);
+        assert_eq!(recipe.features["full"].runtime_dependencies, vec!["libssl.so.3"]);
+    }
+
+    #[test]
+    fn test_yaml_and_toml_recipes_round_trip() {
+        let recipe = BuildRecipe::from_yaml(r#"
+name: both
+version: 1.0.0
+epoch: 2
+description: Test package
+source:
+  - https://example.com/both-1.0.0.tar.gz
+  - url: https://example.com/both-data.tar.gz
+    hash: sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855
+patches:
+  - fix.patch
+  - url: https://example.com/upstream.patch
+    strip: 0
+arch: [x86_64, aarch64]
+dependencies: [libc>=2.31]
+features:
+  ssl:
+    dependencies: [openssl-dev]
+users:
+  - name: both-svc
+    uid: 420
+file_owners:
+  var/lib/both: both-svc
+env:
+  CFLAGS: -O2
+build_system: meson
+phases:
+  prepare: ./configure
+  install: make install DESTDIR=$PAX_BUILD_ROOT
+check: make check
+"#).unwrap();
+        recipe.validate().unwrap();
+
+        let toml = recipe.to_toml().unwrap();
+        assert_eq!(BuildRecipe::from_toml(&toml).unwrap(), recipe);
+        assert_eq!(BuildRecipe::from_yaml(&recipe.to_yaml().unwrap()).unwrap(), recipe);
+        assert_eq!(BuildRecipe::from_yaml(&BuildRecipe::from_toml(&toml).unwrap().to_yaml().unwrap()).unwrap(), recipe);
+
+        // The format follows the extension, and a TOML .paxmeta still loads
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        for name in ["both.toml", "both.paxmeta"] {
+            fs::write(temp_dir.path().join(name), &toml).unwrap();
+            assert_eq!(BuildRecipe::from_file(temp_dir.path().join(name)).unwrap(), recipe);
+        }
+        let error = BuildRecipe::parse("name: [unterminated", RecipeFormat::Yaml).unwrap_err();
+        assert!(format!("{:#}", error).contains("YAML"), "{:#}", error);
+
+        // Writing the hash keeps a TOML recipe in TOML
+        let single = temp_dir.path().join("single.toml");
+        fs::write(&single, "# kept\nname = \"single\"\nversion = \"1.0\"\ndescription = \"x\"\nsource = \"https://example.com/s.tar.gz\"\n\n[env]\nCFLAGS = \"-O2\"\n").unwrap();
+        BuildRecipe::write_hash_to_file(&single, "sha256:abc").unwrap();
+        let rewritten = fs::read_to_string(&single).unwrap();
+        assert!(rewritten.starts_with("# kept\n") && rewritten.contains("hash = \"sha256:abc\"\nsource = "), "{}", rewritten);
+        assert_eq!(BuildRecipe::from_file(&single).unwrap().hash.as_deref(), Some("sha256:abc"));
+    }
+
+    #[test]
+    fn test_recursive_alias_is_rejected() {
+        let err = BuildRecipe::from_yaml(r#"
+name: loop
+version: 1.0.0
+description: Test package
+source: https://example.com/loop-1.0.0.tar.gz
+dependencies: &deps [libc, *deps]
+"#).unwrap_err();
+        assert!(format!("{:#}", err).contains("recursive YAML alias"), "{:#}", err);
+    }
+
+    #[test]
+    fn test_empty_and_duplicate_arch_lists_rejected() {
+        let recipe_with_arch = |arch: &str| BuildRecipe::from_yaml(&format!(r#"
+name: archs
+version: 1.0.0
+description: Test package
+source: https://example.com/archs-1.0.0.tar.gz
+arch: {}
+"#, arch)).unwrap();
+
+        let err = recipe_with_arch("[]").validate().unwrap_err();
+        assert!(err.to_string().contains("cannot be empty"), "{}", err);
+
+        let err = recipe_with_arch("[x86_64, aarch64, x86_64]").validate().unwrap_err();
+        assert!(err.to_string().contains("'x86_64' is listed more than once"), "{}", err);
+
+        recipe_with_arch("[x86_64, aarch64]").validate().unwrap();
+    }
+
+    #[test]
+    fn test_per_arch_sources_reject_top_level_source_fields() {
+        let recipe_with = |extra: &str| BuildRecipe::from_yaml(&format!(r#"
+name: perarch
+version: 1.0.0
+description: Test package
+arch: [x86_64]
+source:
+  x86_64:
+    url: https://example.com/perarch-x86_64.tar.gz
+    hash: sha256:{}
+{}
+"#, "0".repeat(64), extra)).unwrap();
+
+        recipe_with("").validate().unwrap();
+
+        let err = recipe_with(&format!("hash: sha256:{}", "0".repeat(64))).validate().unwrap_err();
+        assert!(err.to_string().contains("give each source its own hash"), "{}", err);
+
+        let err = recipe_with("source_filename: perarch.tar.gz").validate().unwrap_err();
+        assert!(err.to_string().contains("only be given for a single source"), "{}", err);
+    }
 }
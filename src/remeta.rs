@@ -0,0 +1,312 @@
+use anyhow::{Result, Context};
+use sha2::{Sha256, Digest};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::builder::DEFAULT_COMPRESSION_LEVEL;
+use crate::package::{
+    decompress_package, member_path, package_compression, PackageMetadata, PaxPackage, FILE_HASHES_LIST_NAME, FILES_LIST_NAME,
+    LEGACY_FILES_LIST_NAME, LEGACY_METADATA_PATH, METADATA_DIR, METADATA_PATH,
+};
+
+/// Regenerate the metadata of an existing package from its contents and repack it in place
+///
+/// The file list, file hashes and installed size are recomputed from the
+/// payload; every other field (dependencies, scripts, extra, ...) is kept.
+/// The returned metadata has the file list and hashes filled in, as
+/// `load_metadata` would, even when they are stored as separate members.
+/// Packages using the legacy root `metadata.yaml` are moved to the `.pax/` layout.
+///
+/// Payload members are copied as they are, headers included, so modes,
+/// owners, modification times and extended attributes survive without root,
+/// and the package keeps its compression algorithm, recompressed at `level`
+/// as the original level is not recorded. A new metadata member invalidates an
+/// embedded signature, so signed packages are refused unless `drop_signature`
+/// is set, in which case the signature is removed.
+pub fn remeta(package_path: &Path, drop_signature: bool, level: i32) -> Result<PackageMetadata> {
+    let mut package = PaxPackage::open(package_path)?;
+    let mut metadata = package.load_metadata()?.clone();
+    if package.has_embedded_signature()? && !drop_signature {
+        anyhow::bail!(
+            "{} has an embedded signature, which regenerating its metadata would invalidate \
+             (use --drop-signature to remove it, then sign the package again)",
+            package_path.display()
+        );
+    }
+    let compression = package_compression(package_path)?;
+    let tar_data = decompress_package(package_path)?;
+
+    // Old metadata members are dropped; they are rewritten below
+    let mut has_metadata_dir = false;
+    let mut archive = tar::Archive::new(tar_data.as_slice());
+    for entry in archive.entries().with_context(|| "Failed to read package archive")? {
+        let entry = entry.with_context(|| "Failed to read package entry")?;
+        has_metadata_dir |= member_path(&entry.path().with_context(|| "Invalid path in package entry")?) == Path::new(METADATA_PATH);
+    }
+    let is_metadata_member = |path: &Path| {
+        path.starts_with(METADATA_DIR)
+            || (!has_metadata_dir && (path == Path::new(LEGACY_METADATA_PATH) || path == Path::new(LEGACY_FILES_LIST_NAME)))
+    };
+
+    // First pass: hash the payload files
+    let mut metadata_mtime = 0;
+    let mut files = Vec::new();
+    let mut file_hashes = BTreeMap::new();
+    let mut file_sizes: HashMap<PathBuf, (String, u64)> = HashMap::new();
+    let mut installed_size = 0;
+    let mut archive = tar::Archive::new(tar_data.as_slice());
+    for entry in archive.entries().with_context(|| "Failed to read package archive")? {
+        let mut entry = entry.with_context(|| "Failed to read package entry")?;
+        let path = member_path(&entry.path().with_context(|| "Invalid path in package entry")?).to_path_buf();
+        if path == Path::new(METADATA_PATH) || (!has_metadata_dir && path == Path::new(LEGACY_METADATA_PATH)) {
+            metadata_mtime = entry.header().mtime().unwrap_or(0);
+        }
+        if is_metadata_member(&path) {
+            continue;
+        }
+        let relative = path.to_string_lossy().to_string();
+        let entry_type = entry.header().entry_type();
+        if entry_type.is_file() {
+            let mut hasher = Sha256::new();
+            let size = std::io::copy(&mut entry, &mut hasher)
+                .with_context(|| format!("Failed to hash payload file: {}", relative))?;
+            let hash = hex::encode(hasher.finalize());
+            file_sizes.insert(path.clone(), (hash.clone(), size));
+            installed_size += size;
+            file_hashes.insert(relative.clone(), hash);
+            files.push(relative);
+        } else if entry_type.is_hard_link() {
+            // A hard link installs a copy of its target's contents
+            let target = entry.link_name().ok().flatten().map(|target| member_path(&target).to_path_buf());
+            if let Some((hash, size)) = target.and_then(|target| file_sizes.get(&target).cloned()) {
+                installed_size += size;
+                file_hashes.insert(relative.clone(), hash);
+                files.push(relative);
+            }
+        }
+    }
+    files.sort();
+
+    metadata.installed_size = Some(installed_size);
+    metadata.file_hashes = file_hashes;
+    metadata.files = files;
+
+    // Keep a separate file list (and file hashes list) if the package already used one
+    let mut stored = metadata.clone();
+    let mut members = Vec::new();
+    if metadata.files_list.is_some() {
+        let mut files_list = stored.files.join("\n");
+        files_list.push('\n');
+        stored.files = Vec::new();
+        stored.files_list = Some(FILES_LIST_NAME.to_string());
+
+        let file_hashes: String = stored.file_hashes.iter()
+            .map(|(path, hash)| format!("{}  {}\n", hash, path))
+            .collect();
+        stored.file_hashes.clear();
+        stored.file_hashes_list = Some(FILE_HASHES_LIST_NAME.to_string());
+        members.push((FILES_LIST_NAME, files_list));
+        members.push((FILE_HASHES_LIST_NAME, file_hashes));
+    } else {
+        stored.file_hashes_list = None;
+    }
+    members.insert(0, (METADATA_PATH, stored.to_canonical_yaml()?));
+
+    // Second pass: the new metadata members, then every payload member as it was
+    let mut builder = tar::Builder::new(Vec::new());
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(tar::EntryType::Directory);
+    header.set_mode(0o755);
+    header.set_mtime(metadata_mtime);
+    header.set_size(0);
+    builder.append_data(&mut header, METADATA_DIR, std::io::empty())
+        .with_context(|| "Failed to write metadata directory")?;
+    for (name, contents) in &members {
+        let mut header = tar::Header::new_gnu();
+        header.set_mode(0o644);
+        header.set_mtime(metadata_mtime);
+        header.set_size(contents.len() as u64);
+        builder.append_data(&mut header, name, contents.as_bytes())
+            .with_context(|| format!("Failed to write metadata member: {}", name))?;
+    }
+    let mut archive = tar::Archive::new(tar_data.as_slice());
+    for entry in archive.entries().with_context(|| "Failed to read package archive")? {
+        let entry = entry.with_context(|| "Failed to read package entry")?;
+        let path = member_path(&entry.path().with_context(|| "Invalid path in package entry")?).to_path_buf();
+        if !is_metadata_member(&path) {
+            copy_entry(&mut builder, &path, entry)?;
+        }
+    }
+    let tar_data = builder.into_inner().with_context(|| "Failed to write package archive")?;
+
+    // Repack next to the original so the final rename cannot cross filesystems
+    let mut partial = package_path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = Path::new(&partial);
+    let written = compression.compress(&tar_data, level)
+        .and_then(|compressed| fs::write(partial, compressed)
+            .with_context(|| format!("Failed to write package: {}", partial.display())));
+    if let Err(e) = written {
+        let _ = fs::remove_file(partial);
+        return Err(e);
+    }
+    fs::rename(partial, package_path)
+        .with_context(|| format!("Failed to replace package: {}", package_path.display()))?;
+
+    Ok(metadata)
+}
+
+/// Copy an archive member into `builder` unchanged: header, PAX records
+/// (extended attributes and owner names among them) and contents
+fn copy_entry(builder: &mut tar::Builder<Vec<u8>>, path: &Path, mut entry: tar::Entry<&[u8]>) -> Result<()> {
+    let relative = path.display().to_string();
+    if let Some(extensions) = entry.pax_extensions().with_context(|| format!("Failed to read PAX headers of: {}", relative))? {
+        let records: Vec<(String, Vec<u8>)> = extensions
+            .map(|extension| {
+                let extension = extension.with_context(|| format!("Invalid PAX header in: {}", relative))?;
+                let key = extension.key().with_context(|| format!("Invalid PAX header in: {}", relative))?;
+                Ok((key.to_string(), extension.value_bytes().to_vec()))
+            })
+            .collect::<Result<_>>()?;
+        // Long paths and link targets are written again by append_data/append_link
+        let records: Vec<(&str, &[u8])> = records.iter()
+            .filter(|(key, _)| key != "path" && key != "linkpath")
+            .map(|(key, value)| (key.as_str(), value.as_slice()))
+            .collect();
+        if !records.is_empty() {
+            builder.append_pax_extensions(records)
+                .with_context(|| format!("Failed to copy PAX headers of: {}", relative))?;
+        }
+    }
+
+    let mut header = entry.header().clone();
+    let entry_type = header.entry_type();
+    if entry_type.is_symlink() || entry_type.is_hard_link() {
+        let target = entry.link_name()
+            .with_context(|| format!("Invalid link target in package entry: {}", relative))?
+            .ok_or_else(|| anyhow::anyhow!("Link without a target: {}", relative))?
+            .into_owned();
+        builder.append_link(&mut header, path, target)
+    } else {
+        builder.append_data(&mut header, path, &mut entry)
+    }
+    .with_context(|| format!("Failed to copy package entry: {}", relative))
+}
+
+/// Regenerate package metadata (CLI entry point)
+pub fn remeta_cmd(package_path: &str, drop_signature: bool, compression_level: Option<i32>) -> Result<()> {
+    println!("PAXBuild - Regenerating package metadata");
+    println!("Package: {}", package_path);
+
+    let level = compression_level.unwrap_or(DEFAULT_COMPRESSION_LEVEL);
+    let metadata = remeta(Path::new(package_path), drop_signature, level)?;
+    println!("Files: {}", metadata.file_hashes.len());
+    println!("Installed size: {} bytes", metadata.installed_size.unwrap_or(0));
+    println!("Metadata rewritten");
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::Compression;
+    use crate::test_support;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_remeta_adds_file_hashes() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut metadata = test_support::metadata("old", "1.0.0");
+        metadata.dependencies = vec!["libc>=2.31".to_string()];
+        metadata.install_script = Some("ldconfig\n".to_string());
+        metadata.files = vec!["usr/bin/stale".to_string()];
+        let package_path = test_support::write_package(temp_dir.path(), &metadata, &[
+            ("usr/bin/old", "binary"),
+            ("usr/share/doc/old/README", "docs"),
+        ]);
+
+        remeta(&package_path, false, DEFAULT_COMPRESSION_LEVEL).unwrap();
+
+        let mut package = PaxPackage::open(&package_path).unwrap();
+        let upgraded = package.load_metadata().unwrap();
+        assert_eq!(upgraded.files, vec!["usr/bin/old", "usr/share/doc/old/README"]);
+        assert_eq!(upgraded.installed_size, Some(10));
+        assert_eq!(upgraded.file_hashes.len(), 2);
+        assert_eq!(upgraded.file_hashes["usr/bin/old"], hex::encode(Sha256::digest(b"binary")));
+        assert_eq!(upgraded.dependencies, metadata.dependencies);
+        assert_eq!(upgraded.install_script, metadata.install_script);
+        assert_eq!(package.list_files().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_remeta_keeps_split_lists_of_large_packages() {
+        let tarball = test_support::source_tarball(&[("large-1.0.0/README", "large")]);
+        let url = test_support::serve(tarball, "large-1.0.0.tar.gz");
+        let recipe = crate::recipe::BuildRecipe::from_yaml(&format!(r#"
+name: large
+version: 1.0.0
+description: Test package
+source: {}
+build: |
+  mkdir -p $PAX_BUILD_ROOT/usr/share/data
+  for i in 0 1 2 3 4; do echo $i > $PAX_BUILD_ROOT/usr/share/data/file-$i.dat; done
+"#, url)).unwrap();
+        let builder = crate::builder::PackageBuilder::new().unwrap().with_files_list_threshold(Some(2));
+        let package_path = builder.build_for_architectures(&recipe, &["x86_64".to_string()]).unwrap().remove(0);
+
+        let metadata = remeta(&package_path, false, DEFAULT_COMPRESSION_LEVEL).unwrap();
+        assert_eq!(metadata.file_hashes.len(), 5);
+
+        let mut package = PaxPackage::open(&package_path).unwrap();
+        let reloaded = package.load_metadata().unwrap();
+        assert_eq!(reloaded.files_list.as_deref(), Some(FILES_LIST_NAME));
+        assert_eq!(reloaded.file_hashes_list.as_deref(), Some(FILE_HASHES_LIST_NAME));
+        assert_eq!(reloaded.files.len(), 5);
+        assert_eq!(reloaded.file_hashes, metadata.file_hashes);
+        assert!(package.file_hash_mismatches().unwrap().is_empty());
+
+        // Running it again works from the rewritten package
+        remeta(&package_path, false, DEFAULT_COMPRESSION_LEVEL).unwrap();
+        assert_eq!(PaxPackage::open(&package_path).unwrap().load_metadata().unwrap().file_hashes.len(), 5);
+    }
+
+    #[test]
+    fn test_remeta_keeps_signature_xattrs_and_compression() {
+        let temp_dir = TempDir::new().unwrap();
+        let metadata_yaml = serde_yaml::to_string(&test_support::metadata("kept", "1.0.0")).unwrap();
+
+        // A gzip package whose binary carries a capability and a named owner
+        let mut builder = tar::Builder::new(Vec::new());
+        let mut header = tar::Header::new_gnu();
+        header.set_size(metadata_yaml.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, METADATA_PATH, metadata_yaml.as_bytes()).unwrap();
+        builder.append_pax_extensions([("SCHILY.xattr.security.capability", &b"\x01\x00\x00\x02"[..])]).unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(6);
+        header.set_mode(0o755);
+        header.set_username("daemon").unwrap();
+        builder.append_data(&mut header, "usr/bin/kept", &b"binary"[..]).unwrap();
+        let package_path = temp_dir.path().join("kept-1.0.0-x86_64.pax");
+        fs::write(&package_path, Compression::Gzip.compress(&builder.into_inner().unwrap(), 6).unwrap()).unwrap();
+
+        remeta(&package_path, false, DEFAULT_COMPRESSION_LEVEL).unwrap();
+        assert_eq!(crate::package::package_compression(&package_path).unwrap(), Compression::Gzip);
+        let package = PaxPackage::open(&package_path).unwrap();
+        let entry = package.entries().unwrap().map(Result::unwrap).find(|entry| entry.path.ends_with("usr/bin/kept")).unwrap();
+        assert_eq!(entry.xattrs, vec![("security.capability".to_string(), b"\x01\x00\x00\x02".to_vec())]);
+        assert_eq!(entry.owner.as_deref(), Some("daemon"));
+
+        // Signed packages are refused unless the signature may be dropped
+        let private_key = temp_dir.path().join("private.key");
+        fs::write(&private_key, hex::encode([7; 32])).unwrap();
+        crate::crypto::embed_package_signature(&package_path, &private_key, crate::builder::DEFAULT_COMPRESSION_LEVEL).unwrap();
+        let error = remeta(&package_path, false, DEFAULT_COMPRESSION_LEVEL).unwrap_err();
+        assert!(error.to_string().contains("--drop-signature"), "{}", error);
+        assert!(PaxPackage::open(&package_path).unwrap().verify_embedded_signature().is_ok());
+
+        remeta(&package_path, true, DEFAULT_COMPRESSION_LEVEL).unwrap();
+        assert!(!PaxPackage::open(&package_path).unwrap().has_embedded_signature().unwrap());
+    }
+}
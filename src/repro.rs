@@ -0,0 +1,190 @@
+use anyhow::{Result, Context};
+use sha2::{Sha256, Digest};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use tempfile::TempDir;
+use crate::build::{build_package, BuildOptions};
+
+/// Attributes of a package archive entry that affect reproducibility
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryInfo {
+    pub mode: u32,
+    pub mtime: u64,
+    /// SHA256 of the entry's contents (or link target)
+    pub sha256: String,
+}
+
+/// How one archive entry differs between two builds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryDifference {
+    /// Package file name the entry belongs to
+    pub package: String,
+    /// Entry path
+    pub path: String,
+    /// Human-readable description of what differs
+    pub details: Vec<String>,
+}
+
+/// Read every entry of a .pax package, keyed by path
+pub fn package_entries(package_path: &Path) -> Result<BTreeMap<String, EntryInfo>> {
+    let decompressed = crate::package::decompress_package(package_path)?;
+
+    let mut archive = tar::Archive::new(decompressed.as_slice());
+    let mut entries = BTreeMap::new();
+    for entry in archive.entries().with_context(|| "Failed to read package archive")? {
+        let mut entry = entry.with_context(|| "Failed to read package entry")?;
+        let path = entry.path().with_context(|| "Invalid path in package entry")?
+            .to_string_lossy()
+            .trim_start_matches("./")
+            .to_string();
+
+        let mut hasher = Sha256::new();
+        match entry.link_name().with_context(|| "Invalid link target in package entry")? {
+            Some(target) => hasher.update(target.to_string_lossy().as_bytes()),
+            None => {
+                let mut contents = Vec::new();
+                entry.read_to_end(&mut contents)
+                    .with_context(|| format!("Failed to read package entry: {}", path))?;
+                hasher.update(&contents);
+            }
+        }
+
+        let header = entry.header();
+        entries.insert(path, EntryInfo {
+            mode: header.mode().unwrap_or(0),
+            mtime: header.mtime().unwrap_or(0),
+            sha256: hex::encode(hasher.finalize()),
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Compare the entries of two builds of the same package
+pub fn diff_packages(package: &str, first: &Path, second: &Path) -> Result<Vec<EntryDifference>> {
+    let first = package_entries(first)?;
+    let second = package_entries(second)?;
+
+    let mut paths: Vec<&String> = first.keys().chain(second.keys()).collect();
+    paths.sort();
+    paths.dedup();
+
+    let mut differences = Vec::new();
+    for path in paths {
+        let details = match (first.get(path), second.get(path)) {
+            (Some(_), None) => vec!["only in first build".to_string()],
+            (None, Some(_)) => vec!["only in second build".to_string()],
+            (Some(a), Some(b)) => {
+                let mut details = Vec::new();
+                if a.mode != b.mode {
+                    details.push(format!("mode {:o} != {:o}", a.mode, b.mode));
+                }
+                if a.mtime != b.mtime {
+                    details.push(format!("mtime {} != {}", a.mtime, b.mtime));
+                }
+                if a.sha256 != b.sha256 {
+                    details.push(format!("content {} != {}", &a.sha256[..12], &b.sha256[..12]));
+                }
+                details
+            }
+            (None, None) => unreachable!(),
+        };
+
+        if !details.is_empty() {
+            differences.push(EntryDifference { package: package.to_string(), path: path.clone(), details });
+        }
+    }
+
+    Ok(differences)
+}
+
+/// Build a recipe twice and report every archive entry that differs between the builds
+pub fn repro_check(recipe_path: &str, architectures: &[String]) -> Result<Vec<EntryDifference>> {
+    let temp_dir = TempDir::new()
+        .with_context(|| "Failed to create temporary directory")?;
+
+    let mut output_dirs = Vec::new();
+    for build in ["first", "second"] {
+        let output_dir = temp_dir.path().join(build);
+        fs::create_dir_all(&output_dir)
+            .with_context(|| "Failed to create output directory")?;
+
+        println!("Running {} build...", build);
+        let options = BuildOptions {
+            output: Some(output_dir.to_string_lossy().to_string()),
+            architectures: architectures.to_vec(),
+            ..Default::default()
+        };
+        build_package(recipe_path, &options)?;
+        output_dirs.push(output_dir);
+    }
+
+    let mut packages: Vec<String> = fs::read_dir(&output_dirs[0])
+        .with_context(|| "Failed to read build output")?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().to_string())
+        .collect();
+    packages.sort();
+
+    let mut differences = Vec::new();
+    for package in packages {
+        let second = output_dirs[1].join(&package);
+        if !second.exists() {
+            anyhow::bail!("Second build did not produce {}", package);
+        }
+        differences.extend(diff_packages(&package, &output_dirs[0].join(&package), &second)?);
+    }
+
+    Ok(differences)
+}
+
+/// Check that a recipe builds reproducibly (CLI entry point)
+pub fn repro_check_cmd(recipe_path: &str, architectures: &[String]) -> Result<()> {
+    println!("PAXBuild - Checking build reproducibility");
+    println!("Recipe: {}", recipe_path);
+
+    let differences = repro_check(recipe_path, architectures)?;
+    if differences.is_empty() {
+        println!("Builds are reproducible");
+        return Ok(());
+    }
+
+    println!("Differing entries:");
+    for difference in &differences {
+        println!("  {}: {} ({})", difference.package, difference.path, difference.details.join(", "));
+    }
+    anyhow::bail!("Build is not reproducible: {} differing entries", differences.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+
+    #[test]
+    fn test_nondeterministic_build_is_flagged() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = test_support::source_tarball(&[("clock-1.0.0/README", "clock")]);
+        let url = test_support::serve(source, "clock-1.0.0.tar.gz");
+        let recipe_path = test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: clock
+version: 1.0.0
+description: Test package
+source: {}
+arch: [x86_64]
+build: |
+  cp README $PAX_BUILD_ROOT/
+  date +%s%N > $PAX_BUILD_ROOT/build-time
+"#, url));
+
+        let differences = repro_check(recipe_path.to_str().unwrap(), &[]).unwrap();
+        let build_time = differences.iter()
+            .find(|difference| difference.path == "build-time")
+            .expect("timestamp file not flagged");
+        assert_eq!(build_time.package, "clock-1.0.0-x86_64.pax");
+        assert!(build_time.details.iter().any(|detail| detail.starts_with("content")));
+        assert!(!differences.iter().any(|difference| difference.path == "README" && difference.details.iter().any(|d| d.starts_with("content"))));
+    }
+}
@@ -1,17 +1,41 @@
 use anyhow::{Result, Context};
 use std::path::Path;
-use crate::crypto::sign_package;
+use crate::builder::DEFAULT_COMPRESSION_LEVEL;
+use crate::crypto::{embed_package_signature_with, load_package_signing_key, sign_package_with, PrivateKeySource};
+use crate::package::SIGNATURE_PATH;
+use crate::timestamp;
 
-/// Sign a .pax package
-pub fn sign_package_cmd(package_path: &str, key_path: &str, output_path: Option<&str>) -> Result<()> {
+/// Sign a .pax package, with a detached signature or one embedded in the package
+///
+/// With `tsa_url`, a detached signature is also timestamped by that RFC 3161
+/// authority and the reply saved as `<signature>.tsr`, where
+/// `verify --check-timestamp` looks for it. An embedded signature means
+/// recompressing the package, at `compression_level` or the default level.
+pub fn sign_package_cmd(
+    package_path: &str,
+    key: &PrivateKeySource,
+    output_path: Option<&str>,
+    embed: bool,
+    compression_level: Option<i32>,
+    tsa_url: Option<&str>,
+) -> Result<()> {
     println!("PAXBuild - Signing package");
     println!("Package: {}", package_path);
-    println!("Key: {}", key_path);
+    println!("Key: {}", key);
+
+    if embed && tsa_url.is_some() {
+        anyhow::bail!("Timestamps are only stored for detached signatures, not embedded ones");
+    }
+    let signing_key = load_package_signing_key(key)?;
+
+    if embed {
+        let level = compression_level.unwrap_or(DEFAULT_COMPRESSION_LEVEL);
+        embed_package_signature_with(Path::new(package_path), &signing_key, level)?;
+        println!("Signature embedded as {}", SIGNATURE_PATH);
+        return Ok(());
+    }
     
-    let signature = sign_package(
-        Path::new(package_path),
-        Path::new(key_path),
-    )?;
+    let signature = sign_package_with(Path::new(package_path), &signing_key)?;
     
     // Save signature to file
     let signature_path = if let Some(output) = output_path {
@@ -28,6 +52,14 @@ pub fn sign_package_cmd(package_path: &str, key_path: &str, output_path: Option<
     // Display signature as hex
     use hex;
     println!("Signature: {}", hex::encode(&signature));
+
+    if let Some(tsa_url) = tsa_url {
+        let reply = timestamp::request_timestamp(&signature, tsa_url)?;
+        let timestamp_path = timestamp::timestamp_path(Path::new(&signature_path));
+        std::fs::write(&timestamp_path, reply)
+            .with_context(|| format!("Failed to write timestamp to: {}", timestamp_path.display()))?;
+        println!("Timestamp saved to: {}", timestamp_path.display());
+    }
     
     Ok(())
 }
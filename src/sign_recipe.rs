@@ -0,0 +1,40 @@
+use anyhow::{Result, Context};
+use std::path::Path;
+use crate::crypto::{dearmor_signature, sign_recipe_with, PrivateKeySource};
+use crate::recipe::BuildRecipe;
+use crate::timestamp;
+
+/// Sign a .paxmeta recipe, writing an armored detached signature
+///
+/// With `tsa_url`, the signature is also timestamped by that RFC 3161
+/// authority and the reply saved as `<signature>.tsr`.
+pub fn sign_recipe_cmd(recipe_path: &str, key: &PrivateKeySource, output_path: Option<&str>, tsa_url: Option<&str>) -> Result<()> {
+    println!("PAXBuild - Signing recipe");
+    println!("Recipe: {}", recipe_path);
+    println!("Key: {}", key);
+
+    let recipe = BuildRecipe::from_file(recipe_path)?;
+    let signature = sign_recipe_with(&recipe, &key.load()?)?;
+
+    // Save signature next to the recipe by default
+    let signature_path = if let Some(output) = output_path {
+        output.to_string()
+    } else {
+        format!("{}.asc", recipe_path)
+    };
+
+    std::fs::write(&signature_path, &signature)
+        .with_context(|| format!("Failed to write signature to: {}", signature_path))?;
+
+    println!("Signature saved to: {}", signature_path);
+
+    if let Some(tsa_url) = tsa_url {
+        let reply = timestamp::request_timestamp(&dearmor_signature(&signature)?, tsa_url)?;
+        let timestamp_path = timestamp::timestamp_path(Path::new(&signature_path));
+        std::fs::write(&timestamp_path, reply)
+            .with_context(|| format!("Failed to write timestamp to: {}", timestamp_path.display()))?;
+        println!("Timestamp saved to: {}", timestamp_path.display());
+    }
+
+    Ok(())
+}
@@ -1,13 +1,154 @@
 use anyhow::{Result, Context};
-use sha2::{Sha256, Digest};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::sync::{Arc, Mutex};
 use tempfile::TempDir;
+use crate::exec;
+use crate::integrity::{ExpectedDigest, HashAlgorithm};
+use crate::recipe::{Patch, SourceSpec};
+
+/// A source archive format understood by `SourceManager`
+#[derive(Debug, Clone, Copy)]
+pub struct ArchiveFormat {
+    /// Format name
+    pub name: &'static str,
+    /// Filename suffixes recognized as this format
+    pub extensions: &'static [&'static str],
+    /// External program used for extraction, or `None` if handled natively
+    pub tool: Option<&'static str>,
+    extract: fn(&SourceManager, &Path, &Path) -> Result<bool>,
+}
+
+/// Supported source archive formats, in dispatch order
+pub const ARCHIVE_FORMATS: &[ArchiveFormat] = &[
+    ArchiveFormat { name: "tar.gz", extensions: &[".tar.gz", ".tgz"], tool: Some("tar"), extract: SourceManager::extract_tar_gz },
+    ArchiveFormat { name: "tar.xz", extensions: &[".tar.xz"], tool: Some("tar"), extract: SourceManager::extract_tar_xz },
+    ArchiveFormat { name: "tar.bz2", extensions: &[".tar.bz2"], tool: Some("tar"), extract: SourceManager::extract_tar_bz2 },
+    ArchiveFormat { name: "zip", extensions: &[".zip"], tool: Some("unzip"), extract: SourceManager::extract_zip },
+    ArchiveFormat { name: "tar", extensions: &[".tar"], tool: Some("tar"), extract: SourceManager::extract_tar },
+];
+
+impl ArchiveFormat {
+    /// Find the archive format for a filename
+    pub fn for_filename(filename: &str) -> Option<&'static ArchiveFormat> {
+        ARCHIVE_FORMATS.iter()
+            .find(|format| format.extensions.iter().any(|ext| filename.ends_with(ext)))
+    }
+}
+
+/// A location inside a git repository: `git+<url>[#<path>][@<ref>]`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitLocation {
+    /// Repository URL (without the `git+` prefix)
+    pub url: String,
+    /// Path inside the repository
+    pub path: Option<String>,
+    /// Branch, tag or commit to check out (the remote HEAD if not set)
+    pub reference: Option<String>,
+}
+
+impl GitLocation {
+    /// Parse a `git+<url>[#<path>][@<ref>]` specification
+    pub fn parse(spec: &str) -> Result<Self> {
+        let rest = spec.strip_prefix("git+")
+            .ok_or_else(|| anyhow::anyhow!("Not a git location (expected git+<url>): {}", spec))?;
+
+        // The URL itself may contain '@' (user@host), so the ref is only read from the fragment
+        let (url, fragment) = rest.split_once('#').unwrap_or((rest, ""));
+        let (path, reference) = fragment.rsplit_once('@').unwrap_or((fragment, ""));
+
+        if url.is_empty() {
+            anyhow::bail!("Missing repository URL in git location: {}", spec);
+        }
+        if path.starts_with('/') || path.split('/').any(|component| component == "..") {
+            anyhow::bail!("Invalid path '{}' in git location: it must stay inside the repository", path);
+        }
+        if fragment.contains('@') && reference.is_empty() {
+            anyhow::bail!("Empty ref in git location: {}", spec);
+        }
+        // git would read these as options
+        if url.starts_with('-') || reference.starts_with('-') {
+            anyhow::bail!("Repository URL and ref cannot start with '-' in git location: {}", spec);
+        }
+
+        Ok(GitLocation {
+            url: url.to_string(),
+            path: (!path.is_empty()).then(|| path.to_string()),
+            reference: (!reference.is_empty()).then(|| reference.to_string()),
+        })
+    }
+
+    /// The repository a source URL refers to, or `None` for an archive or file
+    ///
+    /// `git+<url>` specifications and URLs ending in `.git` are repositories;
+    /// either may pin a ref and a subdirectory as in [`GitLocation::parse`].
+    pub fn from_source_url(url: &str) -> Result<Option<Self>> {
+        if url.starts_with("git+") {
+            return Self::parse(url).map(Some);
+        }
+        let (base, _) = url.split_once('#').unwrap_or((url, ""));
+        if base.ends_with(".git") {
+            return Self::parse(&format!("git+{}", url)).map(Some);
+        }
+        Ok(None)
+    }
+}
+
+/// How to work through a source's mirrors when downloads fail
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DownloadPolicy {
+    /// Use up every attempt on one mirror before moving to the next
+    #[default]
+    Sequential,
+    /// Move to the next mirror after each failed attempt
+    Rotate,
+}
+
+impl DownloadPolicy {
+    /// The URL of every download attempt, in order, given `retries` extra attempts per URL
+    pub fn attempt_order<'a>(&self, urls: &[&'a str], retries: u32) -> Vec<&'a str> {
+        let attempts = retries as usize + 1;
+        match self {
+            DownloadPolicy::Sequential => urls.iter()
+                .flat_map(|url| std::iter::repeat_n(*url, attempts))
+                .collect(),
+            DownloadPolicy::Rotate => (0..attempts)
+                .flat_map(|_| urls.iter().copied())
+                .collect(),
+        }
+    }
+}
+
+/// Alternate locations and retry behavior for downloading a source
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DownloadPlan {
+    /// URLs serving the same file, tried after the primary URL
+    pub mirrors: Vec<String>,
+    /// Extra attempts per URL after the first one fails
+    pub retries: u32,
+    /// Order in which URLs are retried
+    pub policy: DownloadPolicy,
+}
+
+/// Where a source was downloaded to, once it has been
+type DownloadSlot = Arc<Mutex<Option<PathBuf>>>;
 
 /// Manages source code download and extraction
+///
+/// Downloads are cached by URL and filename, so builds sharing a manager
+/// fetch each source only once.
 pub struct SourceManager {
     temp_dir: TempDir,
+    /// Downloads by (URL, file name); a slot is locked while its file is being fetched
+    downloads: Mutex<HashMap<(String, String), DownloadSlot>>,
+    computed_hashes: Mutex<HashMap<String, String>>,
+    offline: bool,
+    cache_dir: Option<PathBuf>,
+    hash_algorithm: HashAlgorithm,
 }
 
 impl SourceManager {
@@ -16,54 +157,482 @@ impl SourceManager {
         let temp_dir = TempDir::new()
             .with_context(|| "Failed to create temporary directory")?;
         
-        Ok(SourceManager { temp_dir })
+        Ok(SourceManager {
+            temp_dir,
+            downloads: Mutex::new(HashMap::new()),
+            computed_hashes: Mutex::new(HashMap::new()),
+            offline: false,
+            cache_dir: None,
+            hash_algorithm: HashAlgorithm::default(),
+        })
     }
 
-    /// Download and extract source code
-    pub fn download_and_extract(&self, url: &str, expected_hash: Option<&str>) -> Result<PathBuf> {
-        println!("Downloading source from: {}", url);
-        
-        // Download the source
-        let source_file = self.download_source(url)?;
-        
-        // Verify hash if provided
-        if let Some(expected) = expected_hash {
-            self.verify_hash(&source_file, expected)?;
+    /// Keep pristine extracted source trees under `cache_dir`, keyed by source hash
+    pub fn with_cache_dir(mut self, cache_dir: Option<PathBuf>) -> Self {
+        self.cache_dir = cache_dir;
+        self
+    }
+
+    /// Default persistent cache location (`$PAX_CACHE_DIR`, else `$XDG_CACHE_HOME/paxbuild` or `~/.cache/paxbuild`)
+    pub fn default_cache_dir() -> Option<PathBuf> {
+        if let Some(dir) = std::env::var_os("PAX_CACHE_DIR") {
+            return Some(PathBuf::from(dir));
         }
-        
-        // Extract the source
-        let extracted_dir = self.extract_source(&source_file)?;
-        
-        Ok(extracted_dir)
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+            .map(|dir| dir.join("paxbuild"))
     }
 
-    /// Download source file
-    fn download_source(&self, url: &str) -> Result<PathBuf> {
-        let filename = self.get_filename_from_url(url);
-        let dest_path = self.temp_dir.path().join(&filename);
-        
-        let mut response = reqwest::blocking::get(url)
+    /// Compute hashes for sources without one with `hash_algorithm` (SHA256 by default)
+    pub fn with_hash_algorithm(mut self, hash_algorithm: HashAlgorithm) -> Self {
+        self.hash_algorithm = hash_algorithm;
+        self
+    }
+
+    /// Refuse all network access; only local sources can be used
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Local filesystem path of a source given as a `file://` URL or a plain path
+    pub fn local_source_path(url: &str) -> Option<PathBuf> {
+        if url.starts_with("git+") {
+            None
+        } else if let Some(path) = url.strip_prefix("file://") {
+            Some(PathBuf::from(path))
+        } else if url.contains("://") {
+            None
+        } else {
+            Some(PathBuf::from(url))
+        }
+    }
+
+    /// Fetch a source to `dest_path`, downloading URLs and copying local files
+    ///
+    /// A download resumes from the bytes already in `dest_path` when the server
+    /// answers the `Range` request; otherwise it starts over. When a download
+    /// fails, what was received is kept for resuming only if the server
+    /// advertised `Accept-Ranges: bytes`.
+    pub fn fetch_to(&self, url: &str, dest_path: &Path) -> Result<()> {
+        if let Some(local_path) = Self::local_source_path(url) {
+            fs::copy(&local_path, dest_path)
+                .with_context(|| format!("Failed to copy local source: {}", local_path.display()))?;
+            println!("Copied local source to: {}", dest_path.display());
+            return Ok(());
+        }
+
+        if self.offline {
+            anyhow::bail!("Refusing to download in offline mode: {}", url);
+        }
+
+        let offset = fs::metadata(dest_path).map(|metadata| metadata.len()).unwrap_or(0);
+        let mut request = reqwest::blocking::Client::new().get(url);
+        if offset > 0 {
+            request = request.header(reqwest::header::RANGE, format!("bytes={}-", offset));
+        }
+        let mut response = request.send()
             .with_context(|| format!("Failed to download from: {}", url))?;
+
+        // The kept bytes are no use to this server (larger than the file, or it changed)
+        if offset > 0 && response.status() == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+            println!("Cannot resume download, starting over: {}", url);
+            fs::remove_file(dest_path)
+                .with_context(|| format!("Failed to remove partial download: {}", dest_path.display()))?;
+            return self.fetch_to(url, dest_path);
+        }
         
         if !response.status().is_success() {
             anyhow::bail!("HTTP error {}: {}", response.status(), url);
         }
+
+        let resumed = offset > 0 && response.status() == reqwest::StatusCode::PARTIAL_CONTENT;
+        if resumed && content_range_start(&response) != Some(offset) {
+            anyhow::bail!("Server resumed at the wrong offset (expected byte {}): {}", offset, url);
+        }
+        let accepts_ranges = resumed || response.headers().get(reqwest::header::ACCEPT_RANGES)
+            .is_some_and(|value| value.as_bytes().eq_ignore_ascii_case(b"bytes"));
         
-        let mut file = fs::File::create(&dest_path)
-            .with_context(|| format!("Failed to create file: {}", dest_path.display()))?;
+        let mut file = if resumed {
+            println!("Resuming download at byte {}: {}", offset, url);
+            fs::OpenOptions::new().append(true).open(dest_path)
+        } else {
+            fs::File::create(dest_path)
+        }
+        .with_context(|| format!("Failed to create file: {}", dest_path.display()))?;
         
-        std::io::copy(&mut response, &mut file)
-            .with_context(|| "Failed to write downloaded file")?;
+        if let Err(e) = std::io::copy(&mut response, &mut file) {
+            if !accepts_ranges {
+                let _ = fs::remove_file(dest_path);
+            }
+            return Err(e).with_context(|| "Failed to write downloaded file");
+        }
         
         println!("Downloaded to: {}", dest_path.display());
+        Ok(())
+    }
+
+    /// Shallow-clone a git repository at the location's ref and return the checkout directory
+    pub fn checkout_git(&self, location: &GitLocation) -> Result<PathBuf> {
+        if self.offline && Self::local_source_path(&location.url).is_none() {
+            anyhow::bail!("Refusing to clone in offline mode: {}", location.url);
+        }
+
+        let checkout_dir = tempfile::Builder::new()
+            .prefix("git-")
+            .tempdir_in(self.temp_dir.path())
+            .with_context(|| "Failed to create checkout directory")?
+            .keep();
+        let reference = location.reference.as_deref().unwrap_or("HEAD");
+        println!("Cloning {} at {}", location.url, reference);
+
+        // Fetching a single ref works for branches, tags and (where the server allows it) commits
+        let steps: [&[&str]; 3] = [
+            &["init", "-q"],
+            &["fetch", "-q", "--depth", "1", "--", &location.url, reference],
+            &["checkout", "-q", "FETCH_HEAD"],
+        ];
+        for args in steps {
+            let output = exec::run(Command::new("git").arg("-C").arg(&checkout_dir).args(args))
+                .with_context(|| "Failed to run git command")?;
+            if !output.status.success() {
+                anyhow::bail!(
+                    "git {} failed for {}: {}",
+                    args[0], location.url, String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+        }
+
+        Ok(checkout_dir)
+    }
+
+    /// Check out a git source and return its source directory
+    ///
+    /// The checkout (or the location's subdirectory of it) is the source
+    /// tree. When `expected_commit` is given, the checked-out commit must be
+    /// exactly that one.
+    pub fn checkout_git_source(&self, location: &GitLocation, expected_commit: Option<&str>) -> Result<PathBuf> {
+        let checkout_dir = self.checkout_git(location)?;
+
+        let output = exec::run(Command::new("git").arg("-C").arg(&checkout_dir).args(["rev-parse", "HEAD"]))
+            .with_context(|| "Failed to run git command")?;
+        if !output.status.success() {
+            anyhow::bail!("git rev-parse failed for {}: {}", location.url, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        let commit = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if let Some(expected) = expected_commit {
+            if !commit.eq_ignore_ascii_case(expected.trim()) {
+                anyhow::bail!("Commit mismatch for {}! Expected: {}, Checked out: {}", location.url, expected, commit);
+            }
+        }
+        println!("Checked out commit: {}", commit);
+
+        let source_dir = match &location.path {
+            Some(path) => checkout_dir.join(path),
+            None => checkout_dir,
+        };
+        if !source_dir.is_dir() {
+            anyhow::bail!("Directory {} not found in {}", location.path.as_deref().unwrap_or("."), location.url);
+        }
+        Ok(source_dir)
+    }
+
+    /// Fetch a source to `dest_path` through a `.partial` file that is renamed only once the hash checks out
+    ///
+    /// Interrupted or corrupt downloads never leave a file at `dest_path`. An
+    /// interrupted download may leave the `.partial` file for the next attempt
+    /// to resume (see `fetch_to`); one that fails the hash check does not.
+    /// An empty download is an error even without a hash, since no source
+    /// is empty and a misconfigured server may answer 200 with no body.
+    pub fn fetch_verified(&self, url: &str, dest_path: &Path, expected_hash: Option<&str>) -> Result<()> {
+        let mut partial_name = dest_path.file_name().unwrap_or_default().to_os_string();
+        partial_name.push(".partial");
+        let partial_path = dest_path.with_file_name(partial_name);
+
+        self.fetch_to(url, &partial_path)?;
+        let is_empty = fs::metadata(&partial_path).map(|metadata| metadata.len() == 0).unwrap_or(true);
+        let verified = match expected_hash {
+            _ if is_empty => Err(anyhow::anyhow!("Downloaded 0 bytes from {}", url)),
+            Some(expected) => self.verify_hash(&partial_path, expected),
+            None => Ok(()),
+        };
+        let result = verified
+            .and_then(|()| fs::rename(&partial_path, dest_path)
+                .with_context(|| format!("Failed to move download into place: {}", dest_path.display())));
+
+        if result.is_err() {
+            let _ = fs::remove_file(&partial_path);
+        }
+        result
+    }
+
+    /// Download and extract source code
+    ///
+    /// `filename` overrides the name derived from the URL; its extension selects the archive format.
+    /// Git repositories (see [`GitLocation::from_source_url`]) are cloned instead,
+    /// and `expected_hash` is then the commit to expect.
+    pub fn download_and_extract(&self, url: &str, expected_hash: Option<&str>, filename: Option<&str>) -> Result<PathBuf> {
+        self.download_and_extract_with(url, expected_hash, filename, &DownloadPlan::default())
+    }
+
+    /// Like `download_and_extract`, falling back to mirrors and retrying as `plan` describes
+    pub fn download_and_extract_with(
+        &self,
+        url: &str,
+        expected_hash: Option<&str>,
+        filename: Option<&str>,
+        plan: &DownloadPlan,
+    ) -> Result<PathBuf> {
+        // Repositories are cloned rather than downloaded, and their hash is a commit id
+        if let Some(location) = GitLocation::from_source_url(url)? {
+            return self.checkout_git_source(&location, expected_hash);
+        }
+
+        // A known hash lets a cached tree skip the download as well as the extraction
+        if let Some(snapshot) = expected_hash.and_then(|hash| self.cached_tree(hash)) {
+            return self.restore_cached_tree(&snapshot);
+        }
+
+        println!("Downloading source from: {}", url);
+        
+        // Download the source
+        let filename = filename
+            .map(|f| f.to_string())
+            .unwrap_or_else(|| self.get_filename_from_url(url));
+        let source_file = self.download_source(url, &filename, expected_hash, plan)?;
+        let hash = match expected_hash {
+            Some(expected) => expected.to_string(),
+            None => {
+                let hash = format!("{}:{}", self.hash_algorithm.name(), self.hash_algorithm.hash_file(&source_file)?);
+                println!("Computed source hash: {}", hash);
+                self.computed_hashes.lock()
+                    .map_err(|_| anyhow::anyhow!("Computed hash lock poisoned"))?
+                    .insert(url.to_string(), hash.clone());
+                hash
+            }
+        };
+        if let Some(snapshot) = self.cached_tree(&hash) {
+            return self.restore_cached_tree(&snapshot);
+        }
+        
+        // Extract the source
+        let extract_dir = self.extract_source(&source_file)?;
+        if self.cache_dir.is_some() {
+            self.store_cached_tree(&hash, &extract_dir)?;
+        }
+
+        // Find the extracted directory (usually has package name)
+        let extracted_package_dir = self.find_extracted_dir(&extract_dir)?;
+        println!("Extracted to: {}", extracted_package_dir.display());
+        
+        Ok(extracted_package_dir)
+    }
+
+    /// Hash (in the configured algorithm, as `<algo>:<hex>`) computed for a source downloaded without an expected hash
+    pub fn computed_hash(&self, url: &str) -> Option<String> {
+        self.computed_hashes.lock().ok()?.get(url).cloned()
+    }
+
+    /// Download several sources into one source tree
+    ///
+    /// The first source is the main one and is extracted as with
+    /// `download_and_extract_with`. Every further source is verified against
+    /// its own hash; archives are extracted into the source root and plain
+    /// files are copied there under their own name.
+    pub fn download_and_extract_sources(&self, sources: &[SourceSpec], plan: &DownloadPlan) -> Result<PathBuf> {
+        let (main, extras) = sources.split_first()
+            .ok_or_else(|| anyhow::anyhow!("No sources to download"))?;
+        let source_dir = self.download_and_extract_with(&main.url, main.hash.as_deref(), None, plan)?;
+
+        for extra in extras {
+            if GitLocation::from_source_url(&extra.url)?.is_some() {
+                anyhow::bail!("Only the main source can be a git repository: {}", extra.url);
+            }
+            println!("Downloading additional source from: {}", extra.url);
+            let filename = self.get_filename_from_url(&extra.url);
+            let source_file = self.download_source(&extra.url, &filename, extra.hash.as_deref(), plan)?;
+
+            if ArchiveFormat::for_filename(&filename).is_some() {
+                let extract_dir = self.extract_source(&source_file)?;
+                copy_tree(&extract_dir, &source_dir)?;
+            } else {
+                let dest_path = source_dir.join(&filename);
+                if dest_path.exists() {
+                    anyhow::bail!("Additional source {} would overwrite {} in the source tree", extra.url, filename);
+                }
+                fs::copy(&source_file, &dest_path)
+                    .with_context(|| format!("Failed to copy {} into the source tree", filename))?;
+            }
+        }
+
+        Ok(source_dir)
+    }
+
+    /// Apply patches to an extracted source tree in order, with `patch -pN`
+    ///
+    /// Each patch is fetched like a source and verified against its hash if
+    /// one is given. The first patch that does not apply fails with `patch`'s
+    /// output.
+    pub fn apply_patches(&self, patches: &[Patch], source_dir: &Path) -> Result<()> {
+        for patch in patches {
+            let filename = self.get_filename_from_url(&patch.url);
+            println!("Applying patch: {}", filename);
+            let patch_file = self.download_source(&patch.url, &filename, patch.hash.as_deref(), &DownloadPlan::default())?;
+            let contents = fs::read(&patch_file)
+                .with_context(|| format!("Failed to read patch: {}", patch_file.display()))?;
+
+            let output = exec::run_with_input(Command::new("patch")
+                .arg(format!("-p{}", patch.strip_level()))
+                .arg("--batch")
+                .arg("--forward")
+                .current_dir(source_dir), &contents)
+                .with_context(|| "Failed to run patch command")?;
+            if !output.status.success() {
+                anyhow::bail!(
+                    "Patch {} failed to apply ({}):\n{}{}",
+                    filename,
+                    output.status,
+                    String::from_utf8_lossy(&output.stdout),
+                    String::from_utf8_lossy(&output.stderr),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Cache directory of the pristine tree extracted from a source with this hash
+    fn cached_tree_path(&self, hash: &str) -> Option<PathBuf> {
+        let digest = ExpectedDigest::parse(hash).ok()?;
+        Some(self.cache_dir.as_ref()?.join("extracted").join(hex::encode(digest.digest)))
+    }
+
+    /// Cached pristine tree for a source hash, if present
+    fn cached_tree(&self, hash: &str) -> Option<PathBuf> {
+        self.cached_tree_path(hash).filter(|path| path.is_dir())
+    }
+
+    /// Copy a cached pristine tree into a fresh directory, so builds never modify the cache
+    fn restore_cached_tree(&self, snapshot: &Path) -> Result<PathBuf> {
+        println!("Using cached source tree: {}", snapshot.display());
+
+        let extract_dir = tempfile::Builder::new()
+            .prefix("extracted-")
+            .tempdir_in(self.temp_dir.path())
+            .with_context(|| "Failed to create extract directory")?
+            .keep();
+        copy_tree(snapshot, &extract_dir)?;
+
+        let extracted_package_dir = self.find_extracted_dir(&extract_dir)?;
+        println!("Restored to: {}", extracted_package_dir.display());
+        Ok(extracted_package_dir)
+    }
+
+    /// Snapshot a freshly extracted tree into the cache
+    fn store_cached_tree(&self, hash: &str, extract_dir: &Path) -> Result<()> {
+        let Some(snapshot) = self.cached_tree_path(hash) else {
+            return Ok(());
+        };
+        let parent = snapshot.parent().unwrap_or(Path::new("."));
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create cache directory: {}", parent.display()))?;
+
+        // Populate a private directory first so other builds never see a partial tree
+        let staging = tempfile::Builder::new()
+            .prefix(".tmp-")
+            .tempdir_in(parent)
+            .with_context(|| "Failed to create cache staging directory")?;
+        copy_tree(extract_dir, staging.path())?;
+        if fs::rename(staging.path(), &snapshot).is_ok() {
+            // The staging directory now lives on as the snapshot
+            let _ = staging.keep();
+        }
+
+        Ok(())
+    }
+
+    /// Download source file, verifying it against `expected_hash` if given
+    ///
+    /// Failed attempts, including hash mismatches, move on through `plan`'s
+    /// mirrors and retries; the download fails once every attempt has.
+    fn download_source(&self, url: &str, filename: &str, expected_hash: Option<&str>, plan: &DownloadPlan) -> Result<PathBuf> {
+        // Only this file's slot stays locked during the fetch: concurrent builds
+        // wait for a download of the same file instead of repeating it, while
+        // other files download in parallel
+        let key = (url.to_string(), filename.to_string());
+        let slot = Arc::clone(self.downloads.lock()
+            .map_err(|_| anyhow::anyhow!("Download cache lock poisoned"))?
+            .entry(key.clone())
+            .or_default());
+        let mut downloaded = slot.lock()
+            .map_err(|_| anyhow::anyhow!("Download lock poisoned"))?;
+
+        if let Some(path) = downloaded.as_ref() {
+            println!("Using cached download: {}", path.display());
+            if let Some(expected) = expected_hash {
+                self.verify_hash(path, expected)?;
+            }
+            return Ok(path.clone());
+        }
+
+        let result = self.fetch_with_plan(url, filename, expected_hash, plan);
+        match &result {
+            Ok(path) => *downloaded = Some(path.clone()),
+            // A failed download is not remembered; the next attempt starts over
+            Err(_) => {
+                if let Ok(mut downloads) = self.downloads.lock() {
+                    if downloads.get(&key).is_some_and(|current| Arc::ptr_eq(current, &slot)) {
+                        downloads.remove(&key);
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Fetch a source into a fresh download directory, working through `plan`
+    fn fetch_with_plan(&self, url: &str, filename: &str, expected_hash: Option<&str>, plan: &DownloadPlan) -> Result<PathBuf> {
+        let downloads_dir = self.temp_dir.path().join("downloads");
+        fs::create_dir_all(&downloads_dir)
+            .with_context(|| "Failed to create download directory")?;
+        let download_dir = tempfile::Builder::new()
+            .tempdir_in(&downloads_dir)
+            .with_context(|| "Failed to create download directory")?
+            .keep();
+        let dest_path = download_dir.join(filename);
+
+        let urls: Vec<&str> = std::iter::once(url).chain(plan.mirrors.iter().map(String::as_str)).collect();
+        let attempts = plan.policy.attempt_order(&urls, plan.retries);
+        let mut last_error = None;
+        for (attempt, attempt_url) in attempts.iter().enumerate() {
+            if attempt > 0 {
+                println!("Retrying download from: {} (attempt {} of {})", attempt_url, attempt + 1, attempts.len());
+            }
+            match self.fetch_verified(attempt_url, &dest_path, expected_hash) {
+                Ok(()) => {
+                    last_error = None;
+                    break;
+                }
+                Err(e) => {
+                    println!("Download failed: {:#}", e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        if let Some(e) = last_error {
+            return Err(e.context(format!("All {} download attempts failed", attempts.len())));
+        }
+
         Ok(dest_path)
     }
 
     /// Extract source archive
     fn extract_source(&self, archive_path: &Path) -> Result<PathBuf> {
-        let extract_dir = self.temp_dir.path().join("extracted");
-        fs::create_dir_all(&extract_dir)
-            .with_context(|| "Failed to create extract directory")?;
+        let extract_dir = tempfile::Builder::new()
+            .prefix("extracted-")
+            .tempdir_in(self.temp_dir.path())
+            .with_context(|| "Failed to create extract directory")?
+            .keep();
         
         println!("Extracting archive...");
         
@@ -72,39 +641,24 @@ impl SourceManager {
             .and_then(|n| n.to_str())
             .ok_or_else(|| anyhow::anyhow!("Invalid filename"))?;
         
-        let success = if filename.ends_with(".tar.gz") || filename.ends_with(".tgz") {
-            self.extract_tar_gz(archive_path, &extract_dir)?
-        } else if filename.ends_with(".tar.xz") {
-            self.extract_tar_xz(archive_path, &extract_dir)?
-        } else if filename.ends_with(".tar.bz2") {
-            self.extract_tar_bz2(archive_path, &extract_dir)?
-        } else if filename.ends_with(".zip") {
-            self.extract_zip(archive_path, &extract_dir)?
-        } else if filename.ends_with(".tar") {
-            self.extract_tar(archive_path, &extract_dir)?
-        } else {
-            anyhow::bail!("Unsupported archive format: {}", filename);
-        };
+        let format = ArchiveFormat::for_filename(filename)
+            .ok_or_else(|| anyhow::anyhow!("Unsupported archive format: {}", filename))?;
+        let success = (format.extract)(self, archive_path, &extract_dir)?;
         
         if !success {
             anyhow::bail!("Failed to extract archive");
         }
         
-        // Find the extracted directory (usually has package name)
-        let extracted_package_dir = self.find_extracted_dir(&extract_dir)?;
-        println!("Extracted to: {}", extracted_package_dir.display());
-        
-        Ok(extracted_package_dir)
+        Ok(extract_dir)
     }
 
     /// Extract tar.gz archive
     fn extract_tar_gz(&self, archive_path: &Path, dest_dir: &Path) -> Result<bool> {
-        let output = Command::new("tar")
+        let output = exec::run(Command::new("tar")
             .arg("-xzf")
             .arg(archive_path)
             .arg("-C")
-            .arg(dest_dir)
-            .output()
+            .arg(dest_dir))
             .with_context(|| "Failed to run tar command")?;
         
         Ok(output.status.success())
@@ -112,12 +666,11 @@ impl SourceManager {
 
     /// Extract tar.xz archive
     fn extract_tar_xz(&self, archive_path: &Path, dest_dir: &Path) -> Result<bool> {
-        let output = Command::new("tar")
+        let output = exec::run(Command::new("tar")
             .arg("-xJf")
             .arg(archive_path)
             .arg("-C")
-            .arg(dest_dir)
-            .output()
+            .arg(dest_dir))
             .with_context(|| "Failed to run tar command")?;
         
         Ok(output.status.success())
@@ -125,12 +678,11 @@ impl SourceManager {
 
     /// Extract tar.bz2 archive
     fn extract_tar_bz2(&self, archive_path: &Path, dest_dir: &Path) -> Result<bool> {
-        let output = Command::new("tar")
+        let output = exec::run(Command::new("tar")
             .arg("-xjf")
             .arg(archive_path)
             .arg("-C")
-            .arg(dest_dir)
-            .output()
+            .arg(dest_dir))
             .with_context(|| "Failed to run tar command")?;
         
         Ok(output.status.success())
@@ -138,12 +690,11 @@ impl SourceManager {
 
     /// Extract zip archive
     fn extract_zip(&self, archive_path: &Path, dest_dir: &Path) -> Result<bool> {
-        let output = Command::new("unzip")
+        let output = exec::run(Command::new("unzip")
             .arg("-q")
             .arg(archive_path)
             .arg("-d")
-            .arg(dest_dir)
-            .output()
+            .arg(dest_dir))
             .with_context(|| "Failed to run unzip command")?;
         
         Ok(output.status.success())
@@ -151,12 +702,11 @@ impl SourceManager {
 
     /// Extract tar archive
     fn extract_tar(&self, archive_path: &Path, dest_dir: &Path) -> Result<bool> {
-        let output = Command::new("tar")
+        let output = exec::run(Command::new("tar")
             .arg("-xf")
             .arg(archive_path)
             .arg("-C")
-            .arg(dest_dir)
-            .output()
+            .arg(dest_dir))
             .with_context(|| "Failed to run tar command")?;
         
         Ok(output.status.success())
@@ -164,9 +714,12 @@ impl SourceManager {
 
     /// Find the extracted directory
     fn find_extracted_dir(&self, extract_dir: &Path) -> Result<PathBuf> {
+        // Dotfiles such as AppleDouble `._*` entries or `.git` don't count
+        // towards the archive's top level
         let entries: Vec<_> = fs::read_dir(extract_dir)
             .with_context(|| "Failed to read extract directory")?
             .filter_map(|entry| entry.ok())
+            .filter(|entry| !entry.file_name().to_string_lossy().starts_with('.'))
             .collect();
         
         if entries.len() == 1 && entries[0].path().is_dir() {
@@ -177,58 +730,118 @@ impl SourceManager {
         }
     }
 
-    /// Verify file hash
-    fn verify_hash(&self, file_path: &Path, expected_hash: &str) -> Result<()> {
+    /// Verify a file against a hex SHA256 or `blake3:` hash or an SRI string (see [`ExpectedDigest`])
+    ///
+    /// An empty file always fails, even against the hash of no bytes.
+    pub fn verify_hash(&self, file_path: &Path, expected_hash: &str) -> Result<()> {
         println!("Verifying hash...");
+        let expected = ExpectedDigest::parse(expected_hash)?;
         
         let mut file = fs::File::open(file_path)
             .with_context(|| format!("Failed to open file: {}", file_path.display()))?;
+        if file.metadata().map(|metadata| metadata.len() == 0).unwrap_or(false) {
+            anyhow::bail!("File is empty: {}", file_path.display());
+        }
         
-        let mut hasher = Sha256::new();
-        std::io::copy(&mut file, &mut hasher)
+        let calculated = expected.calculate(&mut file)
             .with_context(|| "Failed to read file for hashing")?;
         
-        let calculated_hash = hex::encode(hasher.finalize());
-        let expected_clean = expected_hash.replace("sha256:", "");
-        
-        if calculated_hash != expected_clean {
+        if !expected.matches(&calculated) {
             anyhow::bail!(
                 "Hash mismatch! Expected: {}, Calculated: {}",
-                expected_clean,
-                calculated_hash
+                expected,
+                expected.format(&calculated)
             );
         }
         
-        println!("Hash verified: {}", calculated_hash);
+        println!("Hash verified: {}", expected.format(&calculated));
         Ok(())
     }
 
     /// Get filename from URL
-    fn get_filename_from_url(&self, url: &str) -> String {
+    pub fn get_filename_from_url(&self, url: &str) -> String {
         url.split('/')
-            .last()
+            .next_back()
             .unwrap_or("source.tar.gz")
             .to_string()
     }
 
     /// Calculate SHA256 hash of a file
     pub fn calculate_hash(file_path: &Path) -> Result<String> {
-        let mut file = fs::File::open(file_path)
-            .with_context(|| format!("Failed to open file: {}", file_path.display()))?;
-        
-        let mut hasher = Sha256::new();
-        std::io::copy(&mut file, &mut hasher)
-            .with_context(|| "Failed to read file for hashing")?;
-        
-        Ok(hex::encode(hasher.finalize()))
+        HashAlgorithm::Sha256.hash_file(file_path)
     }
 }
 
+/// First byte position of a `Content-Range: bytes <start>-<end>/<size>` response
+fn content_range_start(response: &reqwest::blocking::Response) -> Option<u64> {
+    response.headers().get(reqwest::header::CONTENT_RANGE)?
+        .to_str().ok()?
+        .strip_prefix("bytes ")?
+        .split_once('-')?
+        .0.parse().ok()
+}
+
+/// Copy the contents of `src` into `dst`, preserving permissions and symlinks
+fn copy_tree(src: &Path, dst: &Path) -> Result<()> {
+    let output = exec::run(Command::new("cp")
+        .arg("-a")
+        .arg(src.join("."))
+        .arg(dst))
+        .with_context(|| "Failed to run cp command")?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "Failed to copy {} to {}: {}",
+            src.display(), dst.display(), String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use sha2::{Digest, Sha256};
     use std::fs;
 
+    #[test]
+    fn test_git_source_checks_out_pinned_ref_and_commit() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let work = temp_dir.path().join("work");
+        let repo = temp_dir.path().join("repo.git");
+        fs::create_dir_all(work.join("src")).unwrap();
+        fs::write(work.join("src/main.c"), "int main(void) { return 0; }\n").unwrap();
+        let git = |dir: &Path, args: &[&str]| {
+            let output = Command::new("git").arg("-C").arg(dir)
+                .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+                .args(args)
+                .output().unwrap();
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        };
+        git(&work, &["init", "-q"]);
+        git(&work, &["add", "."]);
+        git(&work, &["commit", "-q", "-m", "Initial"]);
+        git(&work, &["tag", "v1.0"]);
+        let commit = git(&work, &["rev-parse", "HEAD"]);
+        fs::write(work.join("src/main.c"), "changed\n").unwrap();
+        git(&work, &["commit", "-q", "-am", "Later"]);
+        git(temp_dir.path(), &["clone", "-q", "--bare", work.to_str().unwrap(), repo.to_str().unwrap()]);
+
+        let manager = SourceManager::new().unwrap().with_offline(true);
+        let url = format!("git+file://{}#@v1.0", repo.display());
+        let source_dir = manager.download_and_extract(&url, Some(&commit), None).unwrap();
+        assert_eq!(fs::read_to_string(source_dir.join("src/main.c")).unwrap(), "int main(void) { return 0; }\n");
+
+        // A plain `.git` URL is a repository too, and a subdirectory can be the source tree
+        let url = format!("file://{}#src@v1.0", repo.display());
+        let source_dir = manager.download_and_extract(&url, None, None).unwrap();
+        assert!(source_dir.join("main.c").is_file());
+
+        let error = manager.download_and_extract(&format!("git+file://{}", repo.display()), Some(&commit), None).unwrap_err();
+        assert!(error.to_string().contains("Commit mismatch"), "{}", error);
+    }
+
     #[test]
     fn test_get_filename_from_url() {
         let manager = SourceManager::new().unwrap();
@@ -247,4 +860,154 @@ mod tests {
         // This is the SHA256 of "Hello, World!"
         assert_eq!(hash, "dffd6021bb2bd5b0af676290809ec3a53191dd81c7f70a4b28688a362182986f");
     }
+
+    #[test]
+    fn test_explicit_source_filename() {
+        let tarball = crate::test_support::source_tarball(&[("hello-1.0.0/README", "hello")]);
+        let url = crate::test_support::serve(tarball, "download?id=42&format=tgz");
+
+        let manager = SourceManager::new().unwrap();
+        assert!(manager.download_and_extract(&url, None, None).is_err());
+
+        let source_dir = manager.download_and_extract(&url, None, Some("hello-1.0.0.tar.gz")).unwrap();
+        assert_eq!(fs::read_to_string(source_dir.join("README")).unwrap(), "hello");
+    }
+
+    #[test]
+    fn test_extracted_dir_ignores_dotfiles() {
+        let tarball = crate::test_support::source_tarball(&[
+            ("hello-1.0.0/README", "hello"),
+            ("._hello-1.0.0", "appledouble"),
+            (".git/HEAD", "ref: refs/heads/main"),
+        ]);
+        let url = crate::test_support::serve(tarball, "hello-1.0.0.tar.gz");
+
+        let manager = SourceManager::new().unwrap();
+        let source_dir = manager.download_and_extract(&url, None, None).unwrap();
+        assert_eq!(fs::read_to_string(source_dir.join("README")).unwrap(), "hello");
+    }
+
+    #[test]
+    fn test_cached_source_tree_skips_extraction() {
+        let cache_dir = TempDir::new().unwrap();
+        let tarball = crate::test_support::source_tarball(&[("hello-1.0.0/README", "hello")]);
+        let hash = format!("sha256:{}", hex::encode(Sha256::digest(&tarball)));
+        let (url, downloads) = crate::test_support::serve_counting(tarball, "hello-1.0.0.tar.gz");
+
+        let manager = SourceManager::new().unwrap().with_cache_dir(Some(cache_dir.path().to_path_buf()));
+        let source_dir = manager.download_and_extract(&url, Some(&hash), None).unwrap();
+        // A build dirties its tree; the cached snapshot must stay pristine
+        fs::write(source_dir.join("README"), "dirty").unwrap();
+
+        let manager = SourceManager::new().unwrap().with_cache_dir(Some(cache_dir.path().to_path_buf()));
+        let source_dir = manager.download_and_extract(&url, Some(&hash), None).unwrap();
+        assert_eq!(fs::read_to_string(source_dir.join("README")).unwrap(), "hello");
+        assert_eq!(downloads.load(std::sync::atomic::Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_interrupted_download_leaves_no_file() {
+        let tarball = crate::test_support::source_tarball(&[("hello-1.0.0/README", "hello")]);
+        let url = crate::test_support::serve_truncated(tarball.clone(), "hello-1.0.0.tar.gz");
+
+        let temp_dir = TempDir::new().unwrap();
+        let dest = temp_dir.path().join("hello-1.0.0.tar.gz");
+        let manager = SourceManager::new().unwrap();
+        assert!(manager.fetch_verified(&url, &dest, None).is_err());
+        assert_eq!(fs::read_dir(temp_dir.path()).unwrap().count(), 0);
+
+        // A truncated body that the transport accepts is still caught by the hash
+        let hash = hex::encode(Sha256::digest(&tarball));
+        let url = crate::test_support::serve(tarball[..tarball.len() / 2].to_vec(), "hello-1.0.0.tar.gz");
+        assert!(manager.download_and_extract(&url, Some(&hash), None).is_err());
+        assert!(manager.download_and_extract(&url, Some(&hash), None).is_err());
+        assert!(manager.downloads.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_different_sources_download_concurrently() {
+        // Each server answers only once both requests are in flight
+        let arrived = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let first = crate::test_support::source_tarball(&[("first-1.0.0/README", "first")]);
+        let second = crate::test_support::source_tarball(&[("second-1.0.0/README", "second")]);
+        let first_url = crate::test_support::serve_rendezvous(first, "first-1.0.0.tar.gz", Arc::clone(&arrived), 2);
+        let second_url = crate::test_support::serve_rendezvous(second, "second-1.0.0.tar.gz", Arc::clone(&arrived), 2);
+
+        let manager = SourceManager::new().unwrap();
+        std::thread::scope(|scope| {
+            let first = scope.spawn(|| manager.download_and_extract(&first_url, None, None));
+            let second = scope.spawn(|| manager.download_and_extract(&second_url, None, None));
+            let first = first.join().unwrap().unwrap();
+            let second = second.join().unwrap().unwrap();
+            assert_eq!(fs::read_to_string(first.join("README")).unwrap(), "first");
+            assert_eq!(fs::read_to_string(second.join("README")).unwrap(), "second");
+        });
+    }
+
+    #[test]
+    fn test_interrupted_download_resumes_with_range_request() {
+        let tarball = crate::test_support::source_tarball(&[("resumed-1.0.0/README", "resumed")]);
+        let hash = hex::encode(Sha256::digest(&tarball));
+        let (url, ranges) = crate::test_support::serve_resumable(tarball.clone(), "resumed-1.0.0.tar.gz");
+
+        let plan = DownloadPlan { retries: 1, ..Default::default() };
+        let manager = SourceManager::new().unwrap();
+        let source_dir = manager.download_and_extract_with(&url, Some(&hash), None, &plan).unwrap();
+        assert_eq!(fs::read_to_string(source_dir.join("README")).unwrap(), "resumed");
+
+        // The retry asked only for the bytes the first attempt did not get
+        assert_eq!(*ranges.lock().unwrap(), vec![None, Some(format!("bytes={}-", tarball.len() / 2))]);
+    }
+
+    #[test]
+    fn test_empty_download_fails_clearly_and_is_retried() {
+        use std::sync::atomic::Ordering;
+
+        let (url, downloads) = crate::test_support::serve_counting(Vec::new(), "empty-1.0.0.tar.gz");
+        let plan = DownloadPlan { retries: 1, ..Default::default() };
+        let err = SourceManager::new().unwrap().download_and_extract_with(&url, None, None, &plan).unwrap_err();
+        assert!(format!("{:#}", err).contains(&format!("Downloaded 0 bytes from {}", url)), "{:#}", err);
+        assert_eq!(downloads.load(Ordering::SeqCst), 2);
+
+        // Not even the hash of no bytes lets an empty file through
+        let empty_hash = hex::encode(Sha256::digest(b""));
+        let temp_dir = TempDir::new().unwrap();
+        let empty = temp_dir.path().join("empty");
+        fs::write(&empty, b"").unwrap();
+        let err = SourceManager::new().unwrap().verify_hash(&empty, &empty_hash).unwrap_err();
+        assert!(err.to_string().contains("File is empty"), "{}", err);
+    }
+
+    #[test]
+    fn test_download_policy_attempt_order() {
+        let urls = ["a", "b"];
+        assert_eq!(DownloadPolicy::Sequential.attempt_order(&urls, 1), vec!["a", "a", "b", "b"]);
+        assert_eq!(DownloadPolicy::Rotate.attempt_order(&urls, 1), vec!["a", "b", "a", "b"]);
+        assert_eq!(DownloadPolicy::Rotate.attempt_order(&urls, 0), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn test_download_policies_against_flaky_mirrors() {
+        use std::sync::atomic::Ordering;
+
+        let tarball = crate::test_support::source_tarball(&[("mirrored-1.0.0/README", "mirrored")]);
+        for (policy, primary_requests, mirror_requests) in [(DownloadPolicy::Sequential, 2, 0), (DownloadPolicy::Rotate, 1, 1)] {
+            // The primary fails once, then recovers; the mirror always works
+            let (primary, primary_count) = crate::test_support::serve_flaky(tarball.clone(), "mirrored-1.0.0.tar.gz", 1);
+            let (mirror, mirror_count) = crate::test_support::serve_counting(tarball.clone(), "mirrored-1.0.0.tar.gz");
+            let plan = DownloadPlan { mirrors: vec![mirror], retries: 1, policy };
+
+            let manager = SourceManager::new().unwrap();
+            let source_dir = manager.download_and_extract_with(&primary, None, None, &plan).unwrap();
+            assert!(source_dir.join("README").exists());
+            assert_eq!(primary_count.load(Ordering::SeqCst), primary_requests, "{:?}", policy);
+            assert_eq!(mirror_count.load(Ordering::SeqCst), mirror_requests, "{:?}", policy);
+        }
+
+        // Every attempt failing is an error naming the attempt count
+        let (primary, _) = crate::test_support::serve_flaky(tarball.clone(), "mirrored-1.0.0.tar.gz", usize::MAX);
+        let plan = DownloadPlan { retries: 2, ..Default::default() };
+        let err = SourceManager::new().unwrap().download_and_extract_with(&primary, None, None, &plan).unwrap_err();
+        assert!(format!("{:#}", err).contains("All 3 download attempts failed"), "{:#}", err);
+    }
 }
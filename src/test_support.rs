@@ -0,0 +1,323 @@
+//! Helpers shared by unit tests
+
+use std::io::{Read, Write};
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+/// Build a gzip-compressed tarball from `(path, contents)` pairs
+pub fn source_tarball(files: &[(&str, &str)]) -> Vec<u8> {
+    let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+
+    for (path, contents) in files {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, path, contents.as_bytes()).unwrap();
+    }
+
+    builder.into_inner().unwrap().finish().unwrap()
+}
+
+/// Serve `body` over HTTP on localhost and return a URL ending in `filename`
+pub fn serve(body: Vec<u8>, filename: &str) -> String {
+    serve_counting(body, filename).0
+}
+
+/// Like `serve`, also returning a counter of requests received
+pub fn serve_counting(body: Vec<u8>, filename: &str) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&requests);
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            read_request(&mut stream);
+            counter.fetch_add(1, Ordering::SeqCst);
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = stream.write_all(header.as_bytes());
+            let _ = stream.write_all(&body);
+        }
+    });
+
+    (format!("http://{}/{}", addr, filename), requests)
+}
+
+/// Like `serve`, but each request is held until `expected` requests have
+/// arrived across every server sharing `arrived`, and gets a 503 response if
+/// they don't within a few seconds; tests that downloads run concurrently
+pub fn serve_rendezvous(body: Vec<u8>, filename: &str, arrived: Arc<AtomicUsize>, expected: usize) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            read_request(&mut stream);
+            arrived.fetch_add(1, Ordering::SeqCst);
+            let deadline = std::time::Instant::now() + std::time::Duration::from_secs(3);
+            while arrived.load(Ordering::SeqCst) < expected && std::time::Instant::now() < deadline {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
+            let response = if arrived.load(Ordering::SeqCst) >= expected {
+                let mut response = format!("HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", body.len()).into_bytes();
+                response.extend_from_slice(&body);
+                response
+            } else {
+                b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_vec()
+            };
+            let _ = stream.write_all(&response);
+        }
+    });
+
+    format!("http://{}/{}", addr, filename)
+}
+
+/// Like `serve_counting`, but the first `failures` requests get a 503 response
+pub fn serve_flaky(body: Vec<u8>, filename: &str, failures: usize) -> (String, Arc<AtomicUsize>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let requests = Arc::new(AtomicUsize::new(0));
+    let counter = Arc::clone(&requests);
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            read_request(&mut stream);
+            if counter.fetch_add(1, Ordering::SeqCst) < failures {
+                let _ = stream.write_all(b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+                continue;
+            }
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = stream.write_all(header.as_bytes());
+            let _ = stream.write_all(&body);
+        }
+    });
+
+    (format!("http://{}/{}", addr, filename), requests)
+}
+
+/// Serve HTTP POST requests on localhost, answering each with `handler(body)`
+#[cfg(feature = "tsa")]
+pub fn serve_post(handler: impl Fn(Vec<u8>) -> Vec<u8> + Send + 'static) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            let header_end = loop {
+                if let Some(end) = request.windows(4).position(|window| window == b"\r\n\r\n") {
+                    break end + 4;
+                }
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break request.len(),
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            };
+            let headers = String::from_utf8_lossy(&request[..header_end]).to_lowercase();
+            let length: usize = headers.lines()
+                .find_map(|line| line.strip_prefix("content-length:"))
+                .and_then(|value| value.trim().parse().ok())
+                .unwrap_or(0);
+            let mut body = request[header_end..].to_vec();
+            while body.len() < length {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => body.extend_from_slice(&buf[..n]),
+                }
+            }
+
+            let response = handler(body);
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                response.len()
+            );
+            let _ = stream.write_all(header.as_bytes());
+            let _ = stream.write_all(&response);
+        }
+    });
+
+    format!("http://{}/", addr)
+}
+
+/// Like `serve`, but the connection drops halfway through the advertised body
+pub fn serve_truncated(body: Vec<u8>, filename: &str) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            read_request(&mut stream);
+            let header = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            let _ = stream.write_all(header.as_bytes());
+            let _ = stream.write_all(&body[..body.len() / 2]);
+        }
+    });
+
+    format!("http://{}/{}", addr, filename)
+}
+
+/// Serve `body` with range support; the first response drops halfway through the body
+///
+/// Also returns the `Range` header of every request received (`None` if absent).
+pub fn serve_resumable(body: Vec<u8>, filename: &str) -> (String, Arc<Mutex<Vec<Option<String>>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let ranges = Arc::new(Mutex::new(Vec::new()));
+    let received = Arc::clone(&ranges);
+
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let Ok(mut stream) = stream else { continue };
+            let request = read_request(&mut stream).to_lowercase();
+            let range = request.lines()
+                .find_map(|line| line.strip_prefix("range:"))
+                .map(|value| value.trim().to_string());
+            let first = {
+                let mut received = received.lock().unwrap();
+                received.push(range.clone());
+                received.len() == 1
+            };
+
+            let start: usize = range.as_deref()
+                .and_then(|range| range.strip_prefix("bytes="))
+                .and_then(|range| range.strip_suffix('-'))
+                .and_then(|start| start.parse().ok())
+                .unwrap_or(0);
+            let (status, content_range) = if range.is_some() {
+                ("206 Partial Content", format!("Content-Range: bytes {}-{}/{}\r\n", start, body.len() - 1, body.len()))
+            } else {
+                ("200 OK", String::new())
+            };
+            let header = format!(
+                "HTTP/1.1 {}\r\nAccept-Ranges: bytes\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+                status, content_range, body.len() - start
+            );
+            let _ = stream.write_all(header.as_bytes());
+            let end = if first { body.len() / 2 } else { body.len() };
+            let _ = stream.write_all(&body[start..end]);
+        }
+    });
+
+    (format!("http://{}/{}", addr, filename), ranges)
+}
+
+/// Read an HTTP request up to the end of its headers
+fn read_request(stream: &mut impl Read) -> String {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 1024];
+    while !request.ends_with(b"\r\n\r\n") {
+        match stream.read(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => request.extend_from_slice(&buf[..n]),
+        }
+    }
+    String::from_utf8_lossy(&request).to_string()
+}
+
+/// Write a recipe file into `dir` and return its path
+pub fn write_recipe(dir: &Path, yaml: &str) -> PathBuf {
+    let path = dir.join("recipe.paxmeta");
+    std::fs::write(&path, yaml).unwrap();
+    path
+}
+
+/// Minimal package metadata for tests
+pub fn metadata(name: &str, version: &str) -> crate::package::PackageMetadata {
+    crate::package::PackageMetadata {
+        name: name.to_string(),
+        version: version.to_string(),
+        epoch: None,
+        description: "Test package".to_string(),
+        arch: vec!["x86_64".to_string()],
+        dependencies: Vec::new(),
+        runtime_dependencies: Vec::new(),
+        provides: vec![crate::depspec::Provide::new(name)],
+        conflicts: Vec::new(),
+        replaces: Vec::new(),
+        install_script: None,
+        uninstall_script: None,
+        files: Vec::new(),
+        files_list: None,
+        extra: Default::default(),
+        installed_size: None,
+        file_hashes: Default::default(),
+        file_hashes_list: None,
+        capabilities: Default::default(),
+        users: Vec::new(),
+        groups: Vec::new(),
+        file_owners: Default::default(),
+        prefix_map: Vec::new(),
+    }
+}
+
+/// Write a `<name>-<version>-<arch>.pax` package into `dir` from metadata and `(path, contents)` pairs
+pub fn write_package(
+    dir: &Path,
+    metadata: &crate::package::PackageMetadata,
+    files: &[(&str, &str)],
+) -> PathBuf {
+    let files: Vec<(&str, &[u8])> = files.iter().map(|(path, contents)| (*path, contents.as_bytes())).collect();
+    write_package_bytes(dir, metadata, &files)
+}
+
+/// Like `write_package`, for binary file contents
+pub fn write_package_bytes(
+    dir: &Path,
+    metadata: &crate::package::PackageMetadata,
+    files: &[(&str, &[u8])],
+) -> PathBuf {
+    let filename = format!("{}-{}-{}.pax", metadata.name, metadata.full_version(), metadata.arch[0]);
+    let members: Vec<(&str, &[u8], u32)> = files.iter().map(|(path, contents)| (*path, *contents, 0o644)).collect();
+    write_package_members(dir, &filename, Some(metadata), &members)
+}
+
+/// Write a package called `filename` into `dir` from `(path, contents, mode)` members
+///
+/// With `metadata`, `.pax/metadata.yaml` comes first; without it the archive
+/// holds only the given members, as a legacy or metadata-less package does.
+pub fn write_package_members(
+    dir: &Path,
+    filename: &str,
+    metadata: Option<&crate::package::PackageMetadata>,
+    members: &[(&str, &[u8], u32)],
+) -> PathBuf {
+    let metadata_yaml = metadata.map(|metadata| serde_yaml::to_string(metadata).unwrap());
+    let metadata_member = metadata_yaml.as_ref().map(|yaml| (crate::package::METADATA_PATH, yaml.as_bytes(), 0o644));
+    let members: Vec<(&str, &[u8], u32)> = metadata_member.into_iter().chain(members.iter().copied()).collect();
+
+    let path = dir.join(filename);
+    std::fs::write(&path, zstd::encode_all(tar_archive(&members).as_slice(), 3).unwrap()).unwrap();
+    path
+}
+
+/// Uncompressed tar data holding `(path, contents, mode)` members, in order
+pub fn tar_archive(members: &[(&str, &[u8], u32)]) -> Vec<u8> {
+    let mut builder = tar::Builder::new(Vec::new());
+    for (path, contents, mode) in members {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(*mode);
+        header.set_cksum();
+        builder.append_data(&mut header, path, *contents).unwrap();
+    }
+    builder.into_inner().unwrap()
+}
@@ -0,0 +1,203 @@
+use anyhow::Result;
+use std::path::{Path, PathBuf};
+#[cfg(feature = "tsa")]
+use anyhow::Context;
+#[cfg(feature = "tsa")]
+use sha2::{Digest, Sha256};
+#[cfg(feature = "tsa")]
+use std::process::Command;
+#[cfg(feature = "tsa")]
+use crate::exec;
+
+/// Where the RFC 3161 timestamp reply for a signature file is stored
+pub fn timestamp_path(signature_path: &Path) -> PathBuf {
+    let mut path = signature_path.as_os_str().to_owned();
+    path.push(".tsr");
+    PathBuf::from(path)
+}
+
+/// Ask a timestamp authority to timestamp a signature, returning the DER timestamp reply
+///
+/// The request covers the SHA256 of the raw signature bytes and asks the TSA
+/// to embed its certificate, so the reply verifies against just the CA.
+#[cfg(feature = "tsa")]
+pub fn request_timestamp(signature: &[u8], tsa_url: &str) -> Result<Vec<u8>> {
+    let digest = hex::encode(Sha256::digest(signature));
+    let query = exec::run(Command::new("openssl")
+        .args(["ts", "-query", "-sha256", "-cert", "-digest"])
+        .arg(&digest))
+        .with_context(|| "Failed to run openssl ts -query")?;
+    if !query.status.success() {
+        anyhow::bail!("Failed to create timestamp request: {}", String::from_utf8_lossy(&query.stderr).trim());
+    }
+
+    let response = reqwest::blocking::Client::new()
+        .post(tsa_url)
+        .header("Content-Type", "application/timestamp-query")
+        .body(query.stdout)
+        .send()
+        .with_context(|| format!("Failed to contact timestamp authority: {}", tsa_url))?;
+    if !response.status().is_success() {
+        anyhow::bail!("Timestamp authority returned HTTP {}: {}", response.status(), tsa_url);
+    }
+    let reply = response.bytes()
+        .with_context(|| format!("Failed to read timestamp reply from: {}", tsa_url))?
+        .to_vec();
+
+    let status = reply_text(&reply)?;
+    if !status.lines().any(|line| line.trim_start().starts_with("Status: Granted")) {
+        anyhow::bail!("Timestamp authority did not grant the request:\n{}", status.trim());
+    }
+    Ok(reply)
+}
+
+/// Check that a timestamp reply covers `signature` and chains to the CA in `ca_file`,
+/// returning the time it attests
+#[cfg(feature = "tsa")]
+pub fn verify_timestamp(signature: &[u8], reply: &[u8], ca_file: &Path) -> Result<String> {
+    let reply_file = tempfile::NamedTempFile::new()
+        .with_context(|| "Failed to create temporary file")?;
+    std::fs::write(reply_file.path(), reply)
+        .with_context(|| "Failed to write timestamp reply")?;
+
+    let digest = hex::encode(Sha256::digest(signature));
+    let output = exec::run(Command::new("openssl")
+        .args(["ts", "-verify", "-digest"])
+        .arg(&digest)
+        .arg("-in")
+        .arg(reply_file.path())
+        .arg("-CAfile")
+        .arg(ca_file))
+        .with_context(|| "Failed to run openssl ts -verify")?;
+    if !output.status.success() {
+        anyhow::bail!("Timestamp verification FAILED: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+
+    reply_text(reply)?.lines()
+        .find_map(|line| line.trim_start().strip_prefix("Time stamp: "))
+        .map(str::to_string)
+        .ok_or_else(|| anyhow::anyhow!("Timestamp reply has no time"))
+}
+
+/// Human-readable dump of a timestamp reply
+#[cfg(feature = "tsa")]
+fn reply_text(reply: &[u8]) -> Result<String> {
+    let reply_file = tempfile::NamedTempFile::new()
+        .with_context(|| "Failed to create temporary file")?;
+    std::fs::write(reply_file.path(), reply)
+        .with_context(|| "Failed to write timestamp reply")?;
+    let output = exec::run(Command::new("openssl")
+        .args(["ts", "-reply", "-text", "-in"])
+        .arg(reply_file.path()))
+        .with_context(|| "Failed to run openssl ts -reply")?;
+    if !output.status.success() {
+        anyhow::bail!("Malformed timestamp reply: {}", String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// Ask a timestamp authority to timestamp a signature, returning the DER timestamp reply
+#[cfg(not(feature = "tsa"))]
+pub fn request_timestamp(_signature: &[u8], _tsa_url: &str) -> Result<Vec<u8>> {
+    anyhow::bail!("paxbuild was built without timestamp support. Rebuild with --features tsa")
+}
+
+/// Check that a timestamp reply covers `signature` and chains to the CA in `ca_file`
+#[cfg(not(feature = "tsa"))]
+pub fn verify_timestamp(_signature: &[u8], _reply: &[u8], _ca_file: &Path) -> Result<String> {
+    anyhow::bail!("paxbuild was built without timestamp support. Rebuild with --features tsa")
+}
+
+#[cfg(all(test, feature = "tsa"))]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use crate::verify::{verify_package, VerifyOptions};
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// Create a CA and a TSA certificate signed by it; returns the CA certificate path
+    fn create_tsa(dir: &Path) -> PathBuf {
+        let openssl = |args: &[&str]| {
+            let status = Command::new("openssl").args(args).current_dir(dir).output().unwrap().status;
+            assert!(status.success(), "openssl {:?} failed", args);
+        };
+        openssl(&["req", "-x509", "-newkey", "ec", "-pkeyopt", "ec_paramgen_curve:prime256v1", "-nodes",
+            "-keyout", "ca.key", "-out", "ca.pem", "-days", "2", "-subj", "/CN=Test CA",
+            "-addext", "basicConstraints=critical,CA:TRUE", "-addext", "keyUsage=critical,keyCertSign"]);
+        openssl(&["req", "-newkey", "ec", "-pkeyopt", "ec_paramgen_curve:prime256v1", "-nodes",
+            "-keyout", "tsa.key", "-out", "tsa.csr", "-subj", "/CN=Test TSA"]);
+        fs::write(dir.join("ext.cnf"), "extendedKeyUsage=critical,timeStamping\nkeyUsage=critical,digitalSignature\n").unwrap();
+        openssl(&["x509", "-req", "-in", "tsa.csr", "-CA", "ca.pem", "-CAkey", "ca.key", "-CAcreateserial",
+            "-out", "tsa.pem", "-days", "2", "-extfile", "ext.cnf"]);
+        fs::write(dir.join("ts.cnf"), "[tsa]\nserial = ./serial\nsigner_digest = sha256\ndefault_policy = 1.2.3.4.1\ndigests = sha256\n").unwrap();
+        fs::write(dir.join("serial"), "01\n").unwrap();
+        dir.join("ca.pem")
+    }
+
+    #[test]
+    fn test_timestamp_from_mock_tsa_is_stored_and_validated() {
+        if Command::new("openssl").arg("version").output().is_err() {
+            return;
+        }
+        let temp_dir = TempDir::new().unwrap();
+        let tsa_dir = temp_dir.path().join("tsa");
+        fs::create_dir_all(&tsa_dir).unwrap();
+        let ca = create_tsa(&tsa_dir);
+
+        // The mock TSA answers every query with `openssl ts -reply`
+        let tsa_url = test_support::serve_post(move |query| {
+            fs::write(tsa_dir.join("query.tsq"), query).unwrap();
+            Command::new("openssl")
+                .args(["ts", "-reply", "-queryfile", "query.tsq", "-signer", "tsa.pem", "-inkey", "tsa.key",
+                    "-config", "ts.cnf", "-section", "tsa"])
+                .current_dir(&tsa_dir)
+                .output()
+                .unwrap()
+                .stdout
+        });
+
+        let package_path = test_support::write_package(temp_dir.path(), &test_support::metadata("stamped", "1.0.0"), &[]);
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let private_key = temp_dir.path().join("private.key");
+        let public_key = temp_dir.path().join("public.key");
+        fs::write(&private_key, hex::encode(signing_key.to_bytes())).unwrap();
+        fs::write(&public_key, hex::encode(signing_key.verifying_key().to_bytes())).unwrap();
+
+        // `sign --tsa` stores the reply where `verify --check-timestamp` reads it
+        let key = crate::crypto::PrivateKeySource::File(private_key.clone());
+        crate::sign::sign_package_cmd(package_path.to_str().unwrap(), &key, None, false, None, Some(&tsa_url)).unwrap();
+        let signature_path = PathBuf::from(format!("{}.sig", package_path.display()));
+        let signature = fs::read(&signature_path).unwrap();
+        let reply = fs::read(format!("{}.sig.tsr", package_path.display())).unwrap();
+
+        assert!(verify_timestamp(&signature, &reply, &ca).unwrap().contains("GMT"));
+        assert!(verify_timestamp(b"another signature", &reply, &ca).is_err());
+
+        let options = VerifyOptions {
+            key: Some(public_key.to_str().unwrap().to_string()),
+            signature_file: Some(signature_path.to_str().unwrap().to_string()),
+            check_timestamp: true,
+            tsa_ca: Some(ca.to_str().unwrap().to_string()),
+            ..Default::default()
+        };
+        verify_package(package_path.to_str().unwrap(), &options).unwrap();
+
+        // Recipe signatures get their timestamp stored next to the armored signature
+        let recipe_path = test_support::write_recipe(temp_dir.path(), r#"
+name: stamped
+version: 1.0.0
+description: Test package
+source: https://example.com/stamped-1.0.0.tar.gz
+"#);
+        let key = crate::crypto::PrivateKeySource::File(private_key);
+        crate::sign_recipe::sign_recipe_cmd(recipe_path.to_str().unwrap(), &key, None, Some(&tsa_url)).unwrap();
+        let armored_path = PathBuf::from(format!("{}.asc", recipe_path.display()));
+        let armored = fs::read_to_string(&armored_path).unwrap();
+        let recipe_reply = fs::read(timestamp_path(&armored_path)).unwrap();
+        let recipe_signature = crate::crypto::dearmor_signature(&armored).unwrap();
+        verify_timestamp(&recipe_signature, &recipe_reply, &ca).unwrap();
+    }
+}
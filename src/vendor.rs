@@ -0,0 +1,201 @@
+use anyhow::{Result, Context};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use crate::integrity::HashAlgorithm;
+use crate::recipe::{BuildRecipe, RecipeFormat, Source};
+use crate::source::{GitLocation, SourceManager};
+
+/// Fetch everything a recipe needs into `output_dir` and write a recipe that uses the local copies
+///
+/// The vendored recipe references its source by a path relative to itself
+/// and always carries a hash, so it can be built with `--offline`. Sources
+/// without a hash are pinned with `hash_algorithm`. Per-architecture sources
+/// go in a subdirectory named after their architecture; any other two
+/// sources or patches with the same file name are an error.
+pub fn vendor_recipe(recipe_path: &str, output_dir: &Path, hash_algorithm: HashAlgorithm) -> Result<PathBuf> {
+    let is_url = recipe_path.starts_with("http://") || recipe_path.starts_with("https://");
+    let mut recipe = if is_url {
+        BuildRecipe::from_url(recipe_path)?
+    } else {
+        let mut recipe = BuildRecipe::from_file(recipe_path)?;
+        recipe.resolve_local_source(Path::new(recipe_path).parent().unwrap_or(Path::new(".")));
+        recipe
+    };
+    recipe.validate()?;
+
+    fs::create_dir_all(output_dir)
+        .with_context(|| format!("Failed to create vendor directory: {}", output_dir.display()))?;
+
+    let source_mgr = SourceManager::new()?;
+    let mut vendor = Vendor { source_mgr: &source_mgr, output_dir, hash_algorithm, vendored: HashSet::new() };
+    match &mut recipe.source {
+        Source::Url(url) => {
+            let filename = recipe.source_filename.clone()
+                .unwrap_or_else(|| source_mgr.get_filename_from_url(url));
+            vendor.file(url, &filename, &mut recipe.hash)?;
+        }
+        Source::List(sources) => {
+            for source in sources.iter_mut() {
+                let filename = source_mgr.get_filename_from_url(&source.url);
+                vendor.file(&mut source.url, &filename, &mut source.hash)?;
+            }
+        }
+        Source::PerArch(sources) => {
+            for (arch, source) in sources.iter_mut() {
+                let filename = format!("{}/{}", arch, source_mgr.get_filename_from_url(&source.url));
+                vendor.file(&mut source.url, &filename, &mut source.hash)?;
+            }
+        }
+    }
+
+    for patch in &mut recipe.patches {
+        let filename = source_mgr.get_filename_from_url(&patch.url);
+        vendor.file(&mut patch.url, &filename, &mut patch.hash)?;
+    }
+
+    let recipe_filename = if is_url {
+        format!("{}.paxmeta", recipe.name)
+    } else {
+        Path::new(recipe_path).file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| format!("{}.paxmeta", recipe.name))
+    };
+    let format = RecipeFormat::for_path(&recipe_filename);
+    let vendored_path = output_dir.join(recipe_filename);
+    fs::write(&vendored_path, recipe.serialize(format)?)
+        .with_context(|| format!("Failed to write vendored recipe: {}", vendored_path.display()))?;
+
+    Ok(vendored_path)
+}
+
+/// Sources fetched into a vendor directory so far
+struct Vendor<'a> {
+    source_mgr: &'a SourceManager,
+    output_dir: &'a Path,
+    hash_algorithm: HashAlgorithm,
+    /// Paths taken, relative to `output_dir`
+    vendored: HashSet<String>,
+}
+
+impl Vendor<'_> {
+    /// Fetch one source into `output_dir` as `filename`, pin its hash and point `url` at the local copy
+    fn file(&mut self, url: &mut String, filename: &str, hash: &mut Option<String>) -> Result<()> {
+        if GitLocation::from_source_url(url)?.is_some() {
+            anyhow::bail!("Git sources cannot be vendored: {}", url);
+        }
+        if !self.vendored.insert(filename.to_string()) {
+            anyhow::bail!("Two sources would both be vendored as {}; give them different file names", filename);
+        }
+        let source_path = self.output_dir.join(filename);
+        if let Some(parent) = source_path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create vendor directory: {}", parent.display()))?;
+        }
+        self.source_mgr.fetch_verified(url, &source_path, hash.as_deref())?;
+
+        // Pin the source so offline builds are verified too
+        if hash.is_none() {
+            *hash = Some(format!("{}:{}", self.hash_algorithm.name(), self.hash_algorithm.hash_file(&source_path)?));
+        }
+        *url = filename.to_string();
+
+        Ok(())
+    }
+}
+
+/// Vendor a recipe's sources into a directory (CLI entry point)
+pub fn vendor_cmd(recipe_path: &str, output_dir: &str, hash_algorithm: HashAlgorithm) -> Result<()> {
+    println!("PAXBuild - Vendoring recipe sources");
+    println!("Recipe: {}", recipe_path);
+
+    let vendored_path = vendor_recipe(recipe_path, Path::new(output_dir), hash_algorithm)?;
+
+    println!("Vendored recipe written to: {}", vendored_path.display());
+    println!("Build it offline with: paxbuild build {} --offline", vendored_path.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build::{build_package, BuildOptions};
+    use crate::test_support;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_vendor_then_build_offline() {
+        let temp_dir = TempDir::new().unwrap();
+        let source = test_support::source_tarball(&[("hello-1.0.0/README", "hello")]);
+        let url = test_support::serve(source, "hello-1.0.0.tar.gz");
+        let recipe_path = test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: hello
+version: 1.0.0
+description: Test package
+source: {}
+arch: [x86_64]
+build: cp README $PAX_BUILD_ROOT/
+"#, url));
+
+        let options = BuildOptions {
+            output: Some(temp_dir.path().join("hello.pax").to_str().unwrap().to_string()),
+            offline: true,
+            ..Default::default()
+        };
+        assert!(build_package(recipe_path.to_str().unwrap(), &options).is_err());
+
+        let vendor_dir = temp_dir.path().join("vendor");
+        let vendored = vendor_recipe(recipe_path.to_str().unwrap(), &vendor_dir, HashAlgorithm::Sha256).unwrap();
+        assert!(vendor_dir.join("hello-1.0.0.tar.gz").exists());
+
+        let recipe = BuildRecipe::from_file(&vendored).unwrap();
+        assert_eq!(recipe.source, "hello-1.0.0.tar.gz".into());
+        assert!(recipe.hash.as_deref().is_some_and(|hash| hash.starts_with("sha256:")));
+
+        build_package(vendored.to_str().unwrap(), &options).unwrap();
+        assert!(temp_dir.path().join("hello.pax").exists());
+    }
+
+    #[test]
+    fn test_vendor_keeps_same_named_sources_apart() {
+        let temp_dir = TempDir::new().unwrap();
+        let x86_64 = test_support::source_tarball(&[("tool/tool", "x86_64 build")]);
+        let aarch64 = test_support::source_tarball(&[("tool/tool", "aarch64 build")]);
+        let x86_64_url = test_support::serve(x86_64.clone(), "tool.tar.gz");
+        let aarch64_url = test_support::serve(aarch64.clone(), "tool.tar.gz");
+        let recipe_path = test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: tool
+version: 1.0.0
+description: Test package
+source:
+  x86_64: {}
+  aarch64: {}
+arch: [x86_64, aarch64]
+build: cp tool $PAX_BUILD_ROOT/
+"#, x86_64_url, aarch64_url));
+
+        // Per-architecture copies go in their own directories, pinned with the chosen algorithm
+        let vendor_dir = temp_dir.path().join("vendor");
+        let vendored = vendor_recipe(recipe_path.to_str().unwrap(), &vendor_dir, HashAlgorithm::Blake3).unwrap();
+        assert_eq!(fs::read(vendor_dir.join("x86_64/tool.tar.gz")).unwrap(), x86_64);
+        assert_eq!(fs::read(vendor_dir.join("aarch64/tool.tar.gz")).unwrap(), aarch64);
+        let recipe = BuildRecipe::from_file(&vendored).unwrap();
+        let Source::PerArch(sources) = &recipe.source else { panic!("expected per-architecture sources") };
+        assert_eq!(sources["x86_64"].url, "x86_64/tool.tar.gz");
+        assert_eq!(sources["x86_64"].hash, Some(format!("blake3:{}", blake3::hash(&x86_64).to_hex())));
+
+        // In a source list, the second copy would overwrite the first
+        let recipe_path = test_support::write_recipe(temp_dir.path(), &format!(r#"
+name: tool
+version: 1.0.0
+description: Test package
+source:
+  - {}
+  - {}
+build: cp tool $PAX_BUILD_ROOT/
+"#, x86_64_url, aarch64_url));
+        let error = vendor_recipe(recipe_path.to_str().unwrap(), &temp_dir.path().join("list"), HashAlgorithm::Sha256).unwrap_err();
+        assert!(error.to_string().contains("would both be vendored as tool.tar.gz"), "{:#}", error);
+    }
+}
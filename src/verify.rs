@@ -1,27 +1,165 @@
-use anyhow::Result;
-use crate::package::PaxPackage;
+use anyhow::{Result, Context};
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::process::Command;
+use crate::crypto;
+use crate::depspec::Constraint;
+use crate::elf;
+use crate::exec;
+use crate::integrity::{ExpectedDigest, HashAlgorithm};
+use crate::package::{PackageMetadata, PaxPackage};
+use crate::timestamp;
+
+/// Options for package verification
+#[derive(Debug, Clone, Default)]
+pub struct VerifyOptions {
+    /// Public key file for signature verification
+    pub key: Option<String>,
+    /// Check that every dependency constraint in the metadata is well-formed
+    pub check_deps: bool,
+    /// Syntax-check the install and uninstall scripts without running them
+    pub check_scripts: bool,
+    /// Shell used to check scripts (defaults to bash)
+    pub shell: Option<String>,
+    /// Published `.sha256` file the package hash must match
+    pub checksum_file: Option<String>,
+    /// Published SHA256 of the package (hex, optionally `sha256:`-prefixed)
+    pub expected_hash: Option<String>,
+    /// Detached signature over the package file, checked with `key` (defaults to `<package>.sig`)
+    pub signature_file: Option<String>,
+    /// Check that ELF binaries in the package were built for the metadata architecture
+    pub check_binary_arch: bool,
+    /// Fail if any packaged file or directory is writable by group or others
+    pub check_permissions: bool,
+    /// Validate the RFC 3161 timestamp stored next to `signature_file` (`<signature>.tsr`)
+    pub check_timestamp: bool,
+    /// CA certificate (PEM) the timestamp authority must chain to
+    pub tsa_ca: Option<String>,
+}
 
 /// Verify a .pax package
-pub fn verify_package(package_path: &str, _key_path: Option<&str>) -> Result<()> {
+pub fn verify_package(package_path: &str, options: &VerifyOptions) -> Result<()> {
     println!("PAXBuild - Verifying package");
     println!("Package: {}", package_path);
     
     let mut package = PaxPackage::open(package_path)?;
+
+    // Published checksum and signature are checked before the package is unpacked
+    if let Some(checksum_file) = &options.checksum_file {
+        println!("Checking package hash against {}...", checksum_file);
+        check_checksum_file(Path::new(package_path), Path::new(checksum_file))?;
+        println!("Package hash matches checksum file");
+    }
+    if let Some(expected_hash) = &options.expected_hash {
+        println!("Checking package hash against {}...", expected_hash);
+        check_expected_hash(&package, expected_hash)?;
+        println!("Package hash matches the expected hash");
+    }
+
+    // A package carrying its own signature needs no <package>.sig
+    let embedded = package.has_embedded_signature()?;
+    let signature_file = options.signature_file.clone().or_else(|| {
+        let sidecar = format!("{}.sig", package_path);
+        options.key.as_ref()
+            .filter(|_| !embedded || Path::new(&sidecar).exists())
+            .map(|_| sidecar)
+    });
+    if let Some(signature_file) = &signature_file {
+        let key = options.key.as_deref()
+            .ok_or_else(|| anyhow::anyhow!("--signature-file requires --key"))?;
+        println!("Checking signature {} with key {}...", signature_file, key);
+        check_signature_file(Path::new(package_path), Path::new(signature_file), Path::new(key))?;
+        println!("Package signature verified");
+
+        if options.check_timestamp {
+            let ca = options.tsa_ca.as_deref()
+                .ok_or_else(|| anyhow::anyhow!("--check-timestamp requires --tsa-ca"))?;
+            let timestamp_path = timestamp::timestamp_path(Path::new(signature_file));
+            println!("Checking timestamp {}...", timestamp_path.display());
+            let reply = fs::read(&timestamp_path)
+                .with_context(|| format!("Failed to read timestamp: {}", timestamp_path.display()))?;
+            let time = timestamp::verify_timestamp(&read_signature_file(Path::new(signature_file))?, &reply, Path::new(ca))?;
+            println!("Signature timestamp verified: {}", time);
+        }
+    } else if options.check_timestamp {
+        anyhow::bail!("--check-timestamp requires --signature-file");
+    }
+
+    if embedded {
+        println!("Checking embedded signature...");
+        let signer = package.verify_embedded_signature()?;
+        let fingerprint = crypto::get_key_fingerprint(&signer.to_bytes())?;
+        if let Some(key) = options.key.as_deref().filter(|_| signature_file.is_none()) {
+            if crypto::load_verifying_key(Path::new(key))? != signer {
+                anyhow::bail!("Embedded signature was made by {}, not by {}", fingerprint, key);
+            }
+        }
+        println!("Embedded signature verified (signer {})", fingerprint);
+    }
     
     // Verify package integrity
     println!("Verifying package integrity...");
     package.verify()?;
+    check_file_hashes(&mut package)?;
     println!("Package integrity verified");
     
     // Load metadata
-    let mut package = package;
     let metadata = package.load_metadata()?;
     println!("Package metadata:");
     println!("  Name: {}", metadata.name);
-    println!("  Version: {}", metadata.version);
+    println!("  Version: {}", metadata.full_version());
     println!("  Description: {}", metadata.description);
+
+    if options.check_deps {
+        println!("Checking dependency constraints...");
+        let malformed = malformed_dependencies(metadata);
+        if !malformed.is_empty() {
+            for error in &malformed {
+                println!("  {}", error);
+            }
+            anyhow::bail!("Package has {} malformed dependency constraint(s)", malformed.len());
+        }
+        println!("Dependency constraints are well-formed");
+    }
     
-    // Note: Signature verification removed - only hash verification is used
+    if options.check_scripts {
+        let shell = options.shell.as_deref().unwrap_or("bash");
+        println!("Checking install scripts with {} -n...", shell);
+        let errors = script_syntax_errors(metadata, shell)?;
+        if !errors.is_empty() {
+            for error in &errors {
+                println!("  {}", error);
+            }
+            anyhow::bail!("Package has {} script(s) with syntax errors", errors.len());
+        }
+        println!("Install scripts are syntactically valid");
+    }
+
+    if options.check_binary_arch {
+        println!("Checking binary architectures against {:?}...", metadata.arch);
+        let arch = metadata.arch.clone();
+        let mismatches = binary_arch_mismatches(&package, &arch)?;
+        if !mismatches.is_empty() {
+            for mismatch in &mismatches {
+                println!("  {}", mismatch);
+            }
+            anyhow::bail!("Package has {} binary(ies) built for a different architecture", mismatches.len());
+        }
+        println!("Binary architectures match the metadata");
+    }
+
+    if options.check_permissions {
+        println!("Checking file permissions...");
+        let writable = writable_entries(&package)?;
+        if !writable.is_empty() {
+            for entry in &writable {
+                println!("  {}", entry);
+            }
+            anyhow::bail!("Package has {} entry(ies) writable by group or others", writable.len());
+        }
+        println!("No group- or world-writable files");
+    }
     
     // Calculate and display hash
     let hash = package.calculate_hash()?;
@@ -33,3 +171,466 @@ pub fn verify_package(package_path: &str, _key_path: Option<&str>) -> Result<()>
     
     Ok(())
 }
+
+/// Check a freshly built package: metadata, file list and any recorded file hashes
+pub fn verify_built_package(package_path: &Path) -> Result<()> {
+    let mut package = PaxPackage::open(package_path)?;
+    package.verify()?;
+    check_file_hashes(&mut package)
+}
+
+/// Fail, listing the paths, if any payload file differs from its recorded `file_hashes` entry
+fn check_file_hashes(package: &mut PaxPackage) -> Result<()> {
+    let mismatches = package.file_hash_mismatches()?;
+    if !mismatches.is_empty() {
+        for mismatch in &mismatches {
+            println!("  {}", mismatch);
+        }
+        anyhow::bail!("{} file(s) do not match their recorded hashes", mismatches.len());
+    }
+    Ok(())
+}
+
+/// Check a package against a `sha256sum`-style checksum file
+///
+/// Each line is `<hash>  <file>`, and the line whose file name matches the
+/// package's is used; a file holding nothing but a hash applies to any
+/// package. The hash is hex SHA256 (optionally prefixed with `sha256:`) or an
+/// SRI string such as `sha384-<base64>`.
+pub fn check_checksum_file(package_path: &Path, checksum_file: &Path) -> Result<()> {
+    let contents = fs::read_to_string(checksum_file)
+        .with_context(|| format!("Failed to read checksum file: {}", checksum_file.display()))?;
+    let expected = checksum_for(&contents, package_path)
+        .with_context(|| format!("Invalid checksum file: {}", checksum_file.display()))?;
+    let expected = ExpectedDigest::parse(expected)
+        .with_context(|| format!("Invalid checksum file: {}", checksum_file.display()))?;
+
+    let mut package = fs::File::open(package_path)
+        .with_context(|| format!("Failed to open package: {}", package_path.display()))?;
+    let calculated = expected.calculate(&mut package)
+        .with_context(|| format!("Failed to read package: {}", package_path.display()))?;
+    if !expected.matches(&calculated) {
+        anyhow::bail!("Checksum mismatch! Expected: {}, Calculated: {}", expected, expected.format(&calculated));
+    }
+    Ok(())
+}
+
+/// The hash a checksum file lists for a package, found by its file name
+fn checksum_for<'a>(contents: &'a str, package_path: &Path) -> Result<&'a str> {
+    let lines: Vec<&str> = contents.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .collect();
+    if let [line] = lines.as_slice() {
+        if !line.contains(char::is_whitespace) {
+            return Ok(line);
+        }
+    }
+
+    let filename = package_path.file_name()
+        .ok_or_else(|| anyhow::anyhow!("Package path has no file name: {}", package_path.display()))?;
+    for line in &lines {
+        let (hash, name) = line.split_once(char::is_whitespace)
+            .ok_or_else(|| anyhow::anyhow!("Expected '<hash>  <file>', found: {}", line))?;
+        // sha256sum marks files hashed in binary mode with a leading '*'
+        let name = name.trim_start();
+        let name = name.strip_prefix('*').unwrap_or(name);
+        if Path::new(name).file_name() == Some(filename) {
+            return Ok(hash);
+        }
+    }
+    anyhow::bail!("No checksum listed for {}", filename.to_string_lossy())
+}
+
+/// Check a package against a published SHA256, as hex with an optional `sha256:` prefix
+pub fn check_expected_hash(package: &PaxPackage, expected_hash: &str) -> Result<()> {
+    let expected = ExpectedDigest::parse(expected_hash)
+        .with_context(|| "Invalid --expected-hash")?;
+    if expected.algorithm != HashAlgorithm::Sha256 {
+        anyhow::bail!("--expected-hash takes a SHA256 digest, not {} (use --checksum-file for other algorithms)", expected.algorithm.name());
+    }
+
+    let calculated = package.calculate_hash()?;
+    if !expected.matches(&hex::decode(&calculated)?) {
+        anyhow::bail!(
+            "Package hash mismatch for {}! Expected: {}, Calculated: {}",
+            package.path().display(), expected, calculated
+        );
+    }
+    Ok(())
+}
+
+/// Read a detached signature file, raw or ASCII-armored, as raw signature bytes
+pub fn read_signature_file(signature_file: &Path) -> Result<Vec<u8>> {
+    let signature = fs::read(signature_file)
+        .with_context(|| format!("Failed to read signature file: {}", signature_file.display()))?;
+    match std::str::from_utf8(&signature) {
+        Ok(text) if text.trim_start().starts_with("-----BEGIN") => crypto::dearmor_signature(text),
+        _ => Ok(signature),
+    }
+}
+
+/// Verify a detached package signature, raw or ASCII-armored
+pub fn check_signature_file(package_path: &Path, signature_file: &Path, key_path: &Path) -> Result<()> {
+    if !signature_file.exists() {
+        anyhow::bail!("Signature file not found: {}", signature_file.display());
+    }
+    let signature = read_signature_file(signature_file)?;
+    crypto::verify_package_signature(package_path, &signature, key_path)
+}
+
+/// Describe every dependency or runtime dependency that fails to parse
+pub fn malformed_dependencies(metadata: &PackageMetadata) -> Vec<String> {
+    let dependencies = metadata.dependencies.iter().map(|dep| ("dependency", dep));
+    let runtime_dependencies = metadata.runtime_dependencies.iter().map(|dep| ("runtime dependency", dep));
+
+    dependencies
+        .chain(runtime_dependencies)
+        .filter_map(|(kind, dep)| {
+            Constraint::parse(dep).err().map(|e| format!("{}: {}", kind, e))
+        })
+        .collect()
+}
+
+/// Describe every ELF file whose machine type is not one of the package's architectures
+///
+/// Non-ELF files are skipped, as are architectures without a known ELF machine type.
+pub fn binary_arch_mismatches(package: &PaxPackage, archs: &[String]) -> Result<Vec<String>> {
+    let expected: Vec<u16> = archs.iter().filter_map(|arch| elf::arch_machine(arch)).collect();
+    if expected.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut mismatches = Vec::new();
+    for entry in package.entries()? {
+        let mut entry = entry?;
+        if !entry.is_file() {
+            continue;
+        }
+
+        let mut header = Vec::with_capacity(20);
+        (&mut entry).take(20).read_to_end(&mut header)
+            .with_context(|| format!("Failed to read package entry: {}", entry.path.display()))?;
+        let Some(machine) = elf::machine(&header) else { continue };
+        if !expected.contains(&machine) {
+            let found = elf::machine_arch(machine)
+                .map(str::to_string)
+                .unwrap_or_else(|| format!("ELF machine {}", machine));
+            mismatches.push(format!("{}: built for {}, package is {}", entry.path.display(), found, archs.join(", ")));
+        }
+    }
+
+    Ok(mismatches)
+}
+
+/// Describe every package entry whose mode lets group or others write to it
+///
+/// Symlinks are skipped: their mode bits are never used.
+pub fn writable_entries(package: &PaxPackage) -> Result<Vec<String>> {
+    let mut writable = Vec::new();
+    for entry in package.entries()? {
+        let entry = entry?;
+        if !entry.is_symlink() && entry.mode & 0o022 != 0 {
+            writable.push(format!("{}: mode {:04o}", entry.path.display(), entry.mode & 0o7777));
+        }
+    }
+    Ok(writable)
+}
+
+/// Run `<shell> -n` on the install and uninstall scripts and describe each one that fails
+pub fn script_syntax_errors(metadata: &PackageMetadata, shell: &str) -> Result<Vec<String>> {
+    let scripts = [
+        ("install script", &metadata.install_script),
+        ("uninstall script", &metadata.uninstall_script),
+    ];
+
+    let mut errors = Vec::new();
+    for (kind, script) in scripts {
+        let Some(script) = script else { continue };
+
+        let output = exec::run_with_input(Command::new(shell).arg("-n"), script.as_bytes())
+            .with_context(|| format!("Failed to run {} -n", shell))?;
+        if !output.status.success() {
+            errors.push(format!("{}: {}", kind, String::from_utf8_lossy(&output.stderr).trim()));
+        }
+    }
+
+    Ok(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support;
+    use ed25519_dalek::SigningKey;
+    use rand::rngs::OsRng;
+    use sha2::{Digest, Sha256};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_check_deps_flags_malformed_constraint() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut metadata = test_support::metadata("broken", "1.0.0");
+        metadata.dependencies = vec!["make>=4.0".to_string()];
+        metadata.runtime_dependencies = vec!["libc>>2".to_string(), "libssl.so.1.1".to_string()];
+        let package_path = test_support::write_package(temp_dir.path(), &metadata, &[]);
+
+        let malformed = malformed_dependencies(&metadata);
+        assert_eq!(malformed.len(), 1);
+        assert!(malformed[0].contains("libc>>2"));
+
+        let options = VerifyOptions { check_deps: true, ..Default::default() };
+        assert!(verify_package(package_path.to_str().unwrap(), &options).is_err());
+        assert!(verify_package(package_path.to_str().unwrap(), &VerifyOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_check_scripts_flags_syntax_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut metadata = test_support::metadata("scripted", "1.0.0");
+        metadata.install_script = Some("if [ -d /etc ]; then\n  ldconfig\n".to_string());
+        metadata.uninstall_script = Some("ldconfig\n".to_string());
+        let package_path = test_support::write_package(temp_dir.path(), &metadata, &[]);
+
+        let errors = script_syntax_errors(&metadata, "bash").unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("install script:"));
+
+        let options = VerifyOptions { check_scripts: true, ..Default::default() };
+        assert!(verify_package(package_path.to_str().unwrap(), &options).is_err());
+        assert!(verify_package(package_path.to_str().unwrap(), &VerifyOptions::default()).is_ok());
+    }
+
+    #[test]
+    fn test_checksum_file_accepts_sri() {
+        use base64::Engine;
+        use sha2::Sha384;
+
+        let temp_dir = TempDir::new().unwrap();
+        let package_path = test_support::write_package(temp_dir.path(), &test_support::metadata("sri", "1.0.0"), &[("usr/bin/sri", "s")]);
+        let checksum_file = temp_dir.path().join("sri.pax.sha384");
+        let sri = format!("sha384-{}", base64::engine::general_purpose::STANDARD.encode(Sha384::digest(fs::read(&package_path).unwrap())));
+
+        fs::write(&checksum_file, format!("{}  sri-1.0.0-x86_64.pax\n", sri)).unwrap();
+        check_checksum_file(&package_path, &checksum_file).unwrap();
+
+        let mismatched = format!("sha384-{}", base64::engine::general_purpose::STANDARD.encode(Sha384::digest(b"other")));
+        fs::write(&checksum_file, format!("{}  sri-1.0.0-x86_64.pax\n", mismatched)).unwrap();
+        let error = check_checksum_file(&package_path, &checksum_file).unwrap_err();
+        assert!(error.to_string().contains(&format!("Expected: {}, Calculated: {}", mismatched, sri)), "{}", error);
+    }
+
+    #[test]
+    fn test_expected_hash() {
+        let temp_dir = TempDir::new().unwrap();
+        let package_path = test_support::write_package(temp_dir.path(), &test_support::metadata("expected", "1.0.0"), &[("usr/bin/expected", "e")]);
+        let package = package_path.to_str().unwrap();
+        let hash = hex::encode(Sha256::digest(fs::read(&package_path).unwrap()));
+
+        for expected in [hash.clone(), format!("sha256:{}", hash), hash.to_uppercase()] {
+            let options = VerifyOptions { expected_hash: Some(expected), ..Default::default() };
+            verify_package(package, &options).unwrap();
+        }
+
+        let mismatched = format!("sha256:{}", "0".repeat(64));
+        let options = VerifyOptions { expected_hash: Some(mismatched), ..Default::default() };
+        let error = verify_package(package, &options).unwrap_err();
+        assert!(error.to_string().contains(&format!("Expected: {}, Calculated: {}", "0".repeat(64), hash)), "{}", error);
+
+        let options = VerifyOptions { expected_hash: Some("sha256:not-hex".to_string()), ..Default::default() };
+        assert!(verify_package(package, &options).is_err());
+    }
+
+    #[test]
+    fn test_checksum_file_lists_several_packages() {
+        let temp_dir = TempDir::new().unwrap();
+        let package_path = test_support::write_package(temp_dir.path(), &test_support::metadata("listed", "1.0.0"), &[("usr/bin/listed", "l")]);
+        let hash = PaxPackage::open(&package_path).unwrap().calculate_hash().unwrap();
+        let checksum_file = temp_dir.path().join("SHA256SUMS");
+
+        fs::write(&checksum_file, format!(
+            "{}  other-1.0.0-x86_64.pax\n{} *dist/listed-1.0.0-x86_64.pax\n",
+            "0".repeat(64), hash,
+        )).unwrap();
+        check_checksum_file(&package_path, &checksum_file).unwrap();
+
+        // The first line belongs to another package, so it is not used
+        fs::write(&checksum_file, format!("{}  listed-1.0.0-x86_64.pax\n{}  other-1.0.0-x86_64.pax\n", "0".repeat(64), hash)).unwrap();
+        let error = check_checksum_file(&package_path, &checksum_file).unwrap_err();
+        assert!(error.to_string().contains("Checksum mismatch"), "{:#}", error);
+
+        fs::write(&checksum_file, format!("{}  other-1.0.0-x86_64.pax\n", hash)).unwrap();
+        let error = check_checksum_file(&package_path, &checksum_file).unwrap_err();
+        assert!(format!("{:#}", error).contains("No checksum listed for listed-1.0.0-x86_64.pax"), "{:#}", error);
+    }
+
+    #[test]
+    fn test_checksum_and_signature_bundle() {
+        let temp_dir = TempDir::new().unwrap();
+        let package_path = test_support::write_package(temp_dir.path(), &test_support::metadata("bundle", "1.0.0"), &[("usr/bin/bundle", "b")]);
+        let package = package_path.to_str().unwrap().to_string();
+
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let private_key = temp_dir.path().join("private.key");
+        let public_key = temp_dir.path().join("public.key");
+        fs::write(&private_key, hex::encode(signing_key.to_bytes())).unwrap();
+        fs::write(&public_key, hex::encode(signing_key.verifying_key().to_bytes())).unwrap();
+
+        let checksum_file = temp_dir.path().join("bundle.pax.sha256");
+        let signature_file = temp_dir.path().join("bundle.pax.sig");
+        let publish = |data: &[u8]| {
+            fs::write(&package_path, data).unwrap();
+            let hash = PaxPackage::open(&package).unwrap().calculate_hash().unwrap();
+            fs::write(&checksum_file, format!("{}  bundle-1.0.0-x86_64.pax\n", hash)).unwrap();
+            fs::write(&signature_file, crypto::sign_bytes(data, &private_key).unwrap()).unwrap();
+        };
+        let options = VerifyOptions {
+            key: Some(public_key.to_string_lossy().to_string()),
+            checksum_file: Some(checksum_file.to_string_lossy().to_string()),
+            signature_file: Some(signature_file.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+        let original = fs::read(&package_path).unwrap();
+
+        publish(&original);
+        verify_package(&package, &options).unwrap();
+
+        // An armored signature is accepted too
+        let raw = fs::read(&signature_file).unwrap();
+        fs::write(&signature_file, crypto::armor_signature(&raw)).unwrap();
+        verify_package(&package, &options).unwrap();
+
+        publish(&original);
+        fs::write(&checksum_file, format!("{}  bundle-1.0.0-x86_64.pax\n", "0".repeat(64))).unwrap();
+        let error = verify_package(&package, &options).unwrap_err();
+        assert!(error.to_string().contains("Checksum mismatch"), "{:#}", error);
+
+        publish(&original);
+        let mut signature = fs::read(&signature_file).unwrap();
+        signature[0] ^= 1;
+        fs::write(&signature_file, signature).unwrap();
+        let error = verify_package(&package, &options).unwrap_err();
+        assert!(format!("{:#}", error).contains("Signature verification FAILED"), "{:#}", error);
+
+        // A modified package no longer matches the published hash
+        publish(&original);
+        let mut tampered = original.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        fs::write(&package_path, &tampered).unwrap();
+        let error = verify_package(&package, &options).unwrap_err();
+        assert!(error.to_string().contains("Checksum mismatch"), "{:#}", error);
+
+        // Republished with a matching hash and signature, the corrupt archive fails integrity
+        publish(b"not a package");
+        assert!(verify_package(&package, &options).is_err());
+        check_checksum_file(&package_path, &checksum_file).unwrap();
+        check_signature_file(&package_path, &signature_file, &public_key).unwrap();
+    }
+
+    #[test]
+    fn test_key_checks_package_signature() {
+        let temp_dir = TempDir::new().unwrap();
+        let package_path = test_support::write_package(temp_dir.path(), &test_support::metadata("signed", "1.0.0"), &[("usr/bin/signed", "s")]);
+        let package = package_path.to_str().unwrap().to_string();
+
+        let signing_key = SigningKey::generate(&mut OsRng);
+        let private_key = temp_dir.path().join("private.key");
+        let public_key = temp_dir.path().join("public.key");
+        fs::write(&private_key, hex::encode(signing_key.to_bytes())).unwrap();
+        fs::write(&public_key, hex::encode(signing_key.verifying_key().to_bytes())).unwrap();
+        let options = VerifyOptions {
+            key: Some(public_key.to_string_lossy().to_string()),
+            ..Default::default()
+        };
+
+        let error = verify_package(&package, &options).unwrap_err();
+        assert!(error.to_string().contains("Signature file not found"), "{:#}", error);
+
+        let signature_file = format!("{}.sig", package);
+        fs::write(&signature_file, crypto::sign_package(&package_path, &private_key).unwrap()).unwrap();
+        verify_package(&package, &options).unwrap();
+
+        fs::write(&signature_file, b"short").unwrap();
+        let error = verify_package(&package, &options).unwrap_err();
+        assert!(format!("{:#}", error).contains("Malformed signature"), "{:#}", error);
+
+        // A rebuilt package passes integrity on its own, but not the old signature
+        fs::write(&signature_file, crypto::sign_package(&package_path, &private_key).unwrap()).unwrap();
+        test_support::write_package(temp_dir.path(), &test_support::metadata("signed", "1.0.0"), &[("usr/bin/signed", "tampered")]);
+        let error = verify_package(&package, &options).unwrap_err();
+        assert!(format!("{:#}", error).contains("Signature verification FAILED"), "{:#}", error);
+    }
+
+    #[test]
+    fn test_post_build_verify_catches_corruption() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut metadata = test_support::metadata("hashed", "1.0.0");
+        metadata.file_hashes.insert("usr/bin/hashed".to_string(), hex::encode(Sha256::digest(b"binary")));
+        let package_path = test_support::write_package(temp_dir.path(), &metadata, &[("usr/bin/hashed", "binary")]);
+        verify_built_package(&package_path).unwrap();
+
+        // Contents that no longer match the recorded hash
+        let corrupt_dir = temp_dir.path().join("corrupt");
+        fs::create_dir_all(&corrupt_dir).unwrap();
+        let corrupt_path = test_support::write_package(&corrupt_dir, &metadata, &[("usr/bin/hashed", "tampered")]);
+        let error = verify_built_package(&corrupt_path).unwrap_err();
+        assert!(error.to_string().contains("recorded hashes"), "{:#}", error);
+
+        // `verify` catches the corrupted member as well
+        verify_package(package_path.to_str().unwrap(), &VerifyOptions::default()).unwrap();
+        let error = verify_package(corrupt_path.to_str().unwrap(), &VerifyOptions::default()).unwrap_err();
+        assert!(error.to_string().contains("1 file(s) do not match their recorded hashes"), "{:#}", error);
+
+        // A truncated archive
+        let data = fs::read(&package_path).unwrap();
+        fs::write(&package_path, &data[..data.len() / 2]).unwrap();
+        assert!(verify_built_package(&package_path).is_err());
+    }
+
+    #[test]
+    fn test_check_permissions_flags_writable_file() {
+        let temp_dir = TempDir::new().unwrap();
+        let metadata = test_support::metadata("writable", "1.0.0");
+        let package_path = test_support::write_package_members(temp_dir.path(), "writable-1.0.0-x86_64.pax", Some(&metadata), &[
+            ("usr/bin/safe", b"safe", 0o755),
+            ("var/lib/writable/state", b"open", 0o666),
+        ]);
+
+        let package = PaxPackage::open(&package_path).unwrap();
+        assert_eq!(writable_entries(&package).unwrap(), vec!["var/lib/writable/state: mode 0666"]);
+
+        let options = VerifyOptions { check_permissions: true, ..Default::default() };
+        let err = verify_package(package_path.to_str().unwrap(), &options).unwrap_err();
+        assert!(err.to_string().contains("1 entry(ies) writable by group or others"), "{}", err);
+    }
+
+    #[test]
+    fn test_check_binary_arch_flags_mislabeled_binary() {
+        let temp_dir = TempDir::new().unwrap();
+        let elf_for = |machine: u16| {
+            let mut header = vec![0u8; 64];
+            header[..4].copy_from_slice(b"\x7fELF");
+            header[4] = 2;
+            header[5] = 1;
+            header[18..20].copy_from_slice(&machine.to_le_bytes());
+            header
+        };
+        let (x86_64, aarch64) = (elf_for(62), elf_for(183));
+        let metadata = test_support::metadata("mislabeled", "1.0.0");
+        let package_path = test_support::write_package_bytes(temp_dir.path(), &metadata, &[
+            ("usr/bin/tool", &aarch64),
+            ("usr/lib/libtool.so", &x86_64),
+            ("usr/bin/wrapper", b"#!/bin/sh\nexec tool\n"),
+        ]);
+
+        let package = PaxPackage::open(&package_path).unwrap();
+        let mismatches = binary_arch_mismatches(&package, &metadata.arch).unwrap();
+        assert_eq!(mismatches, vec!["usr/bin/tool: built for aarch64, package is x86_64"]);
+
+        let options = VerifyOptions { check_binary_arch: true, ..Default::default() };
+        let err = verify_package(package_path.to_str().unwrap(), &options).unwrap_err();
+        assert!(err.to_string().contains("1 binary(ies) built for a different architecture"), "{}", err);
+        verify_package(package_path.to_str().unwrap(), &VerifyOptions::default()).unwrap();
+    }
+}
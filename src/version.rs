@@ -0,0 +1,153 @@
+use std::cmp::Ordering;
+
+/// Compare two version strings
+///
+/// Versions are split into runs of digits and runs of letters; separators
+/// (`.`, `-`, `_`, `+`, `~`) only delimit runs. Numeric runs compare by value
+/// (so leading zeros don't matter), alphabetic runs lexically, and a numeric
+/// run sorts after an alphabetic one. When all shared runs are equal, the
+/// version with more runs is newer (`1.0.1` > `1.0`, `1.0a` > `1.0`) unless
+/// its next run is a pre-release tag: letters after `-` or `~`, so
+/// `1.0.0-rc1` < `1.0.0`.
+///
+/// An `epoch:` prefix (e.g. `2:1.0.0`) outranks everything after it; a
+/// version without one has epoch 0.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    let (a_epoch, a) = split_epoch(a);
+    let (b_epoch, b) = split_epoch(b);
+    if a_epoch != b_epoch {
+        return a_epoch.cmp(&b_epoch);
+    }
+
+    let a = segments(a);
+    let b = segments(b);
+
+    for (x, y) in a.iter().zip(&b) {
+        let ordering = match (x, y) {
+            (Segment::Numeric(x), Segment::Numeric(y)) => compare_numeric(x, y),
+            (Segment::Alpha(x, _), Segment::Alpha(y, _)) => x.cmp(y),
+            (Segment::Numeric(_), Segment::Alpha(..)) => Ordering::Greater,
+            (Segment::Alpha(..), Segment::Numeric(_)) => Ordering::Less,
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+
+    match (a.get(b.len()), b.get(a.len())) {
+        (Some(Segment::Alpha(_, true)), _) => Ordering::Less,
+        (_, Some(Segment::Alpha(_, true))) => Ordering::Greater,
+        _ => a.len().cmp(&b.len()),
+    }
+}
+
+/// Prefix a version with its epoch, if it has one
+pub fn with_epoch(epoch: Option<u32>, version: &str) -> String {
+    match epoch {
+        Some(epoch) => format!("{}:{}", epoch, version),
+        None => version.to_string(),
+    }
+}
+
+/// Split a version into its epoch (0 when absent) and the rest
+pub fn split_epoch(version: &str) -> (u32, &str) {
+    version.split_once(':')
+        .and_then(|(epoch, rest)| epoch.parse().ok().map(|epoch| (epoch, rest)))
+        .unwrap_or((0, version))
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment<'a> {
+    Numeric(&'a str),
+    /// Letters, and whether they follow `-` or `~` (a pre-release tag such as `-rc1`)
+    Alpha(&'a str, bool),
+}
+
+/// Split a version into alternating numeric and alphabetic runs
+fn segments(version: &str) -> Vec<Segment<'_>> {
+    let mut segments = Vec::new();
+    let mut rest = version;
+
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphanumeric()) {
+        let pre_release = rest[..start].ends_with(['-', '~']);
+        rest = &rest[start..];
+        let numeric = rest.starts_with(|c: char| c.is_ascii_digit());
+        let end = rest
+            .find(|c: char| !c.is_ascii_alphanumeric() || c.is_ascii_digit() != numeric)
+            .unwrap_or(rest.len());
+
+        let (run, tail) = rest.split_at(end);
+        segments.push(if numeric { Segment::Numeric(run) } else { Segment::Alpha(run, pre_release) });
+        rest = tail;
+    }
+
+    segments
+}
+
+/// Compare digit strings by value without overflowing on long runs
+fn compare_numeric(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_versions() {
+        assert_eq!(compare("1.10.0", "1.9.0"), Ordering::Greater);
+        assert_eq!(compare("1.0", "1.0.0"), Ordering::Less);
+        assert_eq!(compare("2.01", "2.1"), Ordering::Equal);
+        assert_eq!(compare("1.0a", "1.0b"), Ordering::Less);
+        assert_eq!(compare("1.0.1", "1.0a"), Ordering::Greater);
+    }
+
+    #[test]
+    fn test_compare_version_edge_cases() {
+        use Ordering::*;
+        let cases = [
+            // Pre-release tags sort before the release, and by tag
+            ("1.0.0-rc1", "1.0.0", Less),
+            ("1.0.0-beta1", "1.0.0-rc1", Less),
+            ("1.0.0-rc2", "1.0.0-rc10", Less),
+            ("1.0~rc1", "1.0", Less),
+            ("1.0.0-rc1", "1.0.0.1", Less),
+            ("2.0.0-alpha", "1.9.9", Greater),
+            // Post-release suffixes and revisions sort after it
+            ("1.0.1a", "1.0.1", Greater),
+            ("1.0-1", "1.0", Greater),
+            ("1.0+git20240101", "1.0", Greater),
+            // Leading zeros are ignored, long runs don't overflow
+            ("1.010", "1.9", Greater),
+            ("1.001", "1.1", Equal),
+            ("007", "7", Equal),
+            ("1.100000000000000000000000000", "1.99999999999999999999", Greater),
+            // Differing component counts
+            ("1.2", "1.2.0.0", Less),
+            ("1.2.1", "1.2", Greater),
+            ("10", "9.9.9", Greater),
+            // Mixed numeric and alphabetic runs, Debian-style
+            ("2.4.1b3", "2.4.1b12", Less),
+            ("1.0a", "1.0.0", Less),
+            ("r100", "r99", Greater),
+        ];
+        for (a, b, expected) in cases {
+            assert_eq!(compare(a, b), expected, "{} vs {}", a, b);
+            assert_eq!(compare(b, a), expected.reverse(), "{} vs {}", b, a);
+        }
+    }
+
+    #[test]
+    fn test_epoch_outranks_version() {
+        assert_eq!(compare("1:1.0", "9.9"), Ordering::Greater);
+        assert_eq!(compare("0:2.0", "2.0"), Ordering::Equal);
+        assert_eq!(compare("2:1.0", "10:0.1"), Ordering::Less);
+        assert_eq!(compare("1:1.10", "1:1.9"), Ordering::Greater);
+        assert_eq!(split_epoch("3:1.0"), (3, "1.0"));
+        assert_eq!(split_epoch("1.0"), (0, "1.0"));
+        assert_eq!(with_epoch(Some(2), "1.0.0"), "2:1.0.0");
+        assert_eq!(with_epoch(None, "1.0.0"), "1.0.0");
+    }
+}
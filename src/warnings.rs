@@ -0,0 +1,37 @@
+use anyhow::Result;
+use std::sync::Mutex;
+
+/// Collects the warnings emitted while building, optionally promoting them to errors
+///
+/// Every paxbuild warning goes through `warn`, so `--strict` applies to all of them.
+#[derive(Debug, Default)]
+pub struct Warnings {
+    strict: bool,
+    messages: Mutex<Vec<String>>,
+}
+
+impl Warnings {
+    /// Create a collector; in strict mode every warning is an error
+    pub fn new(strict: bool) -> Self {
+        Warnings { strict, messages: Mutex::new(Vec::new()) }
+    }
+
+    /// Emit a warning, or fail with it in strict mode
+    pub fn warn(&self, message: impl Into<String>) -> Result<()> {
+        let message = message.into();
+        if self.strict {
+            anyhow::bail!("{} (warning treated as error by --strict)", message);
+        }
+
+        println!("Warning: {}", message);
+        if let Ok(mut messages) = self.messages.lock() {
+            messages.push(message);
+        }
+        Ok(())
+    }
+
+    /// Warnings emitted so far, in order
+    pub fn messages(&self) -> Vec<String> {
+        self.messages.lock().map(|messages| messages.clone()).unwrap_or_default()
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{Result, Context};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use crate::build::{self, BuildOptions};
+
+/// Quiet period after the last change before a rebuild starts
+pub const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Collapses a burst of change events into a single rebuild
+#[derive(Debug, Clone)]
+pub struct Debouncer {
+    delay: Duration,
+    last_change: Option<Instant>,
+}
+
+impl Debouncer {
+    pub fn new(delay: Duration) -> Self {
+        Debouncer { delay, last_change: None }
+    }
+
+    /// Note a change at `now`, pushing back any pending rebuild
+    pub fn record(&mut self, now: Instant) {
+        self.last_change = Some(now);
+    }
+
+    /// Time left until a pending rebuild is due, or `None` if nothing changed
+    pub fn wait_time(&self, now: Instant) -> Option<Duration> {
+        self.last_change.map(|last| (last + self.delay).saturating_duration_since(now))
+    }
+
+    /// Whether a rebuild is due at `now`; clears the pending change when it is
+    pub fn take_due(&mut self, now: Instant) -> bool {
+        if self.wait_time(now).is_some_and(|wait| wait.is_zero()) {
+            self.last_change = None;
+            return true;
+        }
+        false
+    }
+}
+
+/// Whether a filesystem event changes the source tree (reads and opens don't)
+///
+/// Events that only touch the packages written to `output` are ignored, so
+/// an output directory inside the source tree doesn't rebuild forever.
+pub fn triggers_rebuild(event: &notify::Event, output: &Path) -> bool {
+    matches!(event.kind, EventKind::Any | EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_))
+        && (event.paths.is_empty() || !event.paths.iter().all(|path| is_build_output(path, output)))
+}
+
+/// Whether `path` is a package a build writes to `output` (a package file or a directory)
+fn is_build_output(path: &Path, output: &Path) -> bool {
+    path == output || (path.parent() == Some(output) && path.extension().is_some_and(|ext| ext == "pax"))
+}
+
+/// Absolute form of `path`, resolving symlinks in the part that exists
+fn resolve(path: &Path) -> PathBuf {
+    if let Ok(resolved) = path.canonicalize() {
+        return resolved;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => resolve(parent).join(name),
+        (_, Some(name)) => resolve(Path::new(".")).join(name),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// Build once and print a one-line result; failures are reported, not returned
+fn rebuild(recipe_path: &str, options: &BuildOptions) {
+    let started = Instant::now();
+    match build::build_recipe_report(recipe_path, options) {
+        Ok(report) => {
+            let paths: Vec<String> = report.packages.iter().map(|package| package.path.display().to_string()).collect();
+            println!("==> ok: {} {} in {:.1}s: {}", report.name, report.version, started.elapsed().as_secs_f64(), paths.join(", "));
+        }
+        Err(e) => println!("==> FAILED in {:.1}s: {:#}", started.elapsed().as_secs_f64(), e),
+    }
+}
+
+/// Rebuild a recipe from a local source tree whenever the tree or the recipe changes (CLI entry point)
+pub fn watch_cmd(recipe_path: &str, source_dir: &str, options: &BuildOptions) -> Result<()> {
+    println!("PAXBuild - Watching for changes");
+    println!("Recipe: {}", recipe_path);
+    println!("Source directory: {}", source_dir);
+
+    // Packages left in the builder's temporary directory would vanish after each build
+    let options = BuildOptions {
+        from_directory: Some(source_dir.to_string()),
+        output: Some(options.output.clone().unwrap_or_else(|| ".".to_string())),
+        ..options.clone()
+    };
+
+    // Events carry absolute paths once the tree is watched by its resolved path
+    let output = resolve(Path::new(options.output.as_deref().unwrap_or(".")));
+
+    let (sender, events) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender)
+        .with_context(|| "Failed to start file watcher")?;
+    watcher.watch(&resolve(Path::new(source_dir)), RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch: {}", source_dir))?;
+    if Path::new(recipe_path).is_file() {
+        watcher.watch(Path::new(recipe_path), RecursiveMode::NonRecursive)
+            .with_context(|| format!("Failed to watch: {}", recipe_path))?;
+    }
+
+    rebuild(recipe_path, &options);
+    println!("Waiting for changes (Ctrl-C to stop)...");
+
+    let mut debouncer = Debouncer::new(DEBOUNCE);
+    loop {
+        let timeout = debouncer.wait_time(Instant::now()).unwrap_or(Duration::from_secs(3600));
+        match events.recv_timeout(timeout) {
+            Ok(Ok(event)) if triggers_rebuild(&event, &output) => debouncer.record(Instant::now()),
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => println!("Watch error: {}", e),
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => anyhow::bail!("File watcher stopped"),
+        }
+
+        if debouncer.take_due(Instant::now()) {
+            rebuild(recipe_path, &options);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use notify::event::{AccessKind, CreateKind, ModifyKind};
+
+    #[test]
+    fn test_change_events_are_debounced_into_one_rebuild() {
+        let modify = notify::Event::new(EventKind::Modify(ModifyKind::Any)).add_path("src/main.c".into());
+        let create = notify::Event::new(EventKind::Create(CreateKind::File));
+        let access = notify::Event::new(EventKind::Access(AccessKind::Read));
+        let output = Path::new("/work/out");
+        assert!(triggers_rebuild(&modify, output) && triggers_rebuild(&create, output));
+        assert!(!triggers_rebuild(&access, output));
+
+        let start = Instant::now();
+        let mut debouncer = Debouncer::new(Duration::from_millis(500));
+        assert!(!debouncer.take_due(start));
+        assert_eq!(debouncer.wait_time(start), None);
+
+        // A burst of saves keeps pushing the rebuild back
+        debouncer.record(start);
+        debouncer.record(start + Duration::from_millis(300));
+        assert!(!debouncer.take_due(start + Duration::from_millis(600)));
+        assert_eq!(debouncer.wait_time(start + Duration::from_millis(600)), Some(Duration::from_millis(200)));
+
+        assert!(debouncer.take_due(start + Duration::from_millis(800)));
+        assert!(!debouncer.take_due(start + Duration::from_millis(900)));
+    }
+
+    #[test]
+    fn test_written_packages_do_not_trigger_rebuild() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let source = resolve(temp_dir.path());
+        let package = notify::Event::new(EventKind::Create(CreateKind::File)).add_path(source.join("pkg-1.0-x86_64.pax"));
+        let source_file = notify::Event::new(EventKind::Modify(ModifyKind::Any)).add_path(source.join("src/pkg.pax"));
+
+        // The default output is the current directory, here the source tree itself
+        assert!(!triggers_rebuild(&package, &source));
+        assert!(triggers_rebuild(&source_file, &source));
+        assert!(triggers_rebuild(&package, &source.join("out")));
+
+        // A single-architecture build can write to a file path instead
+        let output_file = resolve(&temp_dir.path().join("pkg.pax"));
+        let written = notify::Event::new(EventKind::Modify(ModifyKind::Any)).add_path(output_file.clone());
+        assert_eq!(output_file, source.join("pkg.pax"));
+        assert!(!triggers_rebuild(&written, &output_file));
+    }
+}